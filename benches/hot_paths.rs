@@ -0,0 +1,80 @@
+//! Benchmarks for the paths the module runs on every tick and every command
+//!
+//! Run with `cargo bench --features test-support --bench hot_paths`, and to compare a change
+//! against the code before it, add `-- --save-baseline main` on the old code and
+//! `-- --baseline main` on the new.
+
+use std::{hint::black_box, time::Duration};
+
+use clap::Parser;
+use criterion::{criterion_group, criterion_main, Criterion};
+use waybar_module_pomodoro::{
+    cli::ModuleCli,
+    models::{config::Config, message::Message},
+    test_support::Harness,
+};
+
+fn config(args: &[&str]) -> Config {
+    let cli = ModuleCli::parse_from(["waybar-module-pomodoro"].iter().chain(args));
+    Config::from_module_cli(&cli)
+}
+
+fn decode(c: &mut Criterion) {
+    let mut group = c.benchmark_group("decode");
+    for (name, input) in [
+        ("bare", "toggle\n"),
+        ("json", r#""start""#),
+        ("set-work", r#"{"set-work":{"time":"+5"}}"#),
+        (
+            "set-task",
+            r#"{"set-task":{"name":"Write the RFC","estimate":4}}"#,
+        ),
+    ] {
+        group.bench_function(name, |b| b.iter(|| Message::decode(black_box(input))));
+    }
+    group.finish();
+}
+
+fn render(c: &mut Criterion) {
+    let mut group = c.benchmark_group("render");
+    let mut plain = Harness::new(config(&[]));
+    group.bench_function("default", |b| b.iter(|| plain.render()));
+
+    let mut busy = Harness::new(config(&[
+        "--format",
+        "{icon} {time} {progress} {focus_today} {cycle_icon}",
+        "--stale-after",
+        "5",
+        "--stale-tooltip",
+    ]));
+    busy.send(&Message::Start).unwrap();
+    busy.advance(Duration::from_secs(60));
+    group.bench_function("all-placeholders", |b| b.iter(|| busy.render()));
+    group.finish();
+}
+
+fn tick(c: &mut Criterion) {
+    let mut group = c.benchmark_group("tick");
+    let mut running = Harness::new(config(&[]));
+    running.send(&Message::Start).unwrap();
+    // a second at a time, cycles rolling over now and then as they would for real
+    group.bench_function("one-second", |b| {
+        b.iter(|| running.advance(Duration::from_secs(1)))
+    });
+
+    let mut paused = Harness::new(config(&[]));
+    group.bench_function("paused", |b| {
+        b.iter(|| paused.advance(Duration::from_secs(1)))
+    });
+    group.finish();
+}
+
+fn request(c: &mut Criterion) {
+    let mut harness = Harness::new(config(&[]));
+    c.bench_function("request/toggle", |b| {
+        b.iter(|| harness.request(black_box("toggle")).unwrap())
+    });
+}
+
+criterion_group!(benches, decode, render, tick, request);
+criterion_main!(benches);
@@ -0,0 +1,14 @@
+//! Anything a client writes to the socket must decode or fail cleanly, and what decodes
+//! must survive a round trip through `encode`
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use waybar_module_pomodoro::models::message::Message;
+
+fuzz_target!(|input: &str| {
+    if let Ok(message) = Message::decode(input) {
+        let encoded = message.encode();
+        assert_eq!(Message::decode(&encoded).ok(), Some(message), "{encoded}");
+    }
+});
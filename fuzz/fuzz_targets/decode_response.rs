@@ -0,0 +1,14 @@
+//! Whatever a module, or something pretending to be one, replies must not panic a client
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use waybar_module_pomodoro::models::message::Response;
+
+fuzz_target!(|input: &str| {
+    if let Ok(response) = Response::decode(input) {
+        // numbers in `data` may come back rounded, so only check it still decodes
+        let encoded = response.encode();
+        assert!(Response::decode(&encoded).is_ok(), "{encoded}");
+    }
+});
@@ -0,0 +1,17 @@
+//! Commands, however malformed or extreme, are answered without panicking the module, and
+//! it keeps ticking afterwards
+
+#![no_main]
+
+use std::time::Duration;
+
+use libfuzzer_sys::fuzz_target;
+use waybar_module_pomodoro::{models::config::Config, test_support::Harness};
+
+fuzz_target!(|input: &str| {
+    let mut harness = Harness::new(Config::default());
+    for command in input.split('\n') {
+        harness.request(command).expect("the module always replies");
+    }
+    harness.advance(Duration::from_secs(90));
+});
@@ -1,39 +1,49 @@
 use clap::Parser;
 use signal_hook::{
-    consts::{SIGHUP, SIGINT, SIGTERM},
+    consts::{SIGHUP, SIGINT, SIGTERM, SIGUSR1, SIGUSR2},
     iterator::Signals,
 };
-use std::thread;
-use tracing::info;
-use tracing_subscriber::EnvFilter;
-use waybar_module_pomodoro::cli::{LogOption, ModuleCli};
+use std::path::Path;
+use std::{env, fs};
+use std::{process, thread};
+use tracing::{error, info};
+use tracing_appender::rolling::{RollingFileAppender, Rotation};
+use tracing_subscriber::{reload, EnvFilter, Registry};
+use waybar_module_pomodoro::cli::{LogFormat, LogOption, LogRotation, ModuleCli};
 use waybar_module_pomodoro::models::config::Config;
+use waybar_module_pomodoro::models::message::{LogLevel, Message};
+use waybar_module_pomodoro::services::logging::{self, SizeRotatingWriter};
 use waybar_module_pomodoro::services::module::{
     find_next_instance_number, send_message_socket, spawn_module,
 };
-use xdg::BaseDirectories;
+use waybar_module_pomodoro::services::session;
+use waybar_module_pomodoro::services::{config_file, lock};
 
-fn setup_tracing(log_option: Option<LogOption>) {
-    let env_filter = EnvFilter::from_default_env()
-        .add_directive("waybar_module_pomodoro=debug".parse().unwrap());
+fn setup_tracing(cli: &ModuleCli) {
+    use tracing_subscriber::prelude::*;
+
+    let Some(log_option) = cli.log.clone() else {
+        // No logging - just return without initializing tracing
+        return;
+    };
+
+    // wrap the filter so `log-level` can swap it while the module is running
+    let (env_filter, reload_handle) = reload::Layer::new(logging::env_filter(LogLevel::Debug));
 
     match log_option {
-        None => {
-            // No logging - just return without initializing tracing
-        }
-        Some(LogOption::Journald) => {
+        LogOption::Journald => {
             // Log to journald
             if let Ok(journald_layer) = tracing_journald::layer() {
-                use tracing_subscriber::prelude::*;
                 tracing_subscriber::registry()
                     .with(env_filter)
                     .with(journald_layer)
                     .init();
             } else {
                 eprintln!("Failed to initialize journald logging");
+                return;
             }
         }
-        Some(LogOption::File { path }) => {
+        LogOption::File { path } => {
             // Log to file
             // Extract directory and filename
             let log_dir = path
@@ -44,50 +54,143 @@ fn setup_tracing(log_option: Option<LogOption>) {
                 .and_then(|name| name.to_str())
                 .unwrap_or("waybar-pomodoro.log");
 
-            let file_appender = tracing_appender::rolling::daily(log_dir, log_filename);
-            let (non_blocking, _guard) = tracing_appender::non_blocking(file_appender);
-
-            tracing_subscriber::fmt()
-                .with_writer(non_blocking)
-                .with_env_filter(env_filter)
-                .init();
-
-            // Prevent the guard from being dropped
-            std::mem::forget(_guard);
+            let writer: Box<dyn std::io::Write + Send> = match cli.log_rotation {
+                LogRotation::Size(max_bytes) => {
+                    match SizeRotatingWriter::new(&path, max_bytes, cli.log_keep) {
+                        Ok(writer) => Box::new(writer),
+                        Err(e) => {
+                            eprintln!("Failed to open log file {}: {}", path.display(), e);
+                            return;
+                        }
+                    }
+                }
+                age => {
+                    let rotation = match age {
+                        LogRotation::Hourly => Rotation::HOURLY,
+                        LogRotation::Daily => Rotation::DAILY,
+                        _ => Rotation::NEVER,
+                    };
+                    match RollingFileAppender::builder()
+                        .rotation(rotation)
+                        .filename_prefix(log_filename)
+                        .max_log_files(cli.log_keep.max(1))
+                        .build(log_dir)
+                    {
+                        Ok(appender) => Box::new(appender),
+                        Err(e) => {
+                            eprintln!("Failed to initialize file logging: {}", e);
+                            return;
+                        }
+                    }
+                }
+            };
+            init_file_logging(env_filter, writer, cli.log_format);
         }
     }
+
+    logging::install_reload_handle(reload_handle);
 }
 
-fn main() -> std::io::Result<()> {
-    let cli = ModuleCli::parse();
+fn init_file_logging<W>(
+    env_filter: reload::Layer<EnvFilter, Registry>,
+    writer: W,
+    log_format: LogFormat,
+) where
+    W: std::io::Write + Send + 'static,
+{
+    use tracing_subscriber::prelude::*;
+
+    let (non_blocking, _guard) = tracing_appender::non_blocking(writer);
+
+    let fmt_layer = tracing_subscriber::fmt::layer().with_writer(non_blocking);
+    match log_format {
+        LogFormat::Text => tracing_subscriber::registry()
+            .with(env_filter)
+            .with(fmt_layer)
+            .init(),
+        LogFormat::Json => tracing_subscriber::registry()
+            .with(env_filter)
+            .with(fmt_layer.json().with_current_span(true))
+            .init(),
+    }
 
-    setup_tracing(cli.log.clone());
+    // Prevent the guard from being dropped
+    std::mem::forget(_guard);
+}
 
-    // Debug output of CLI arguments
-    tracing::debug!("Parsed CLI arguments: {:#?}", cli);
+/// Parses the command line again with the config file's options for `instance` in front,
+/// so the ones given on the command line win
+fn with_config_file(cli: ModuleCli, instance: u16) -> ModuleCli {
+    let Some(path) = cli.config.clone().or_else(config_file::default_path) else {
+        return cli;
+    };
+    let file = match config_file::load(&path) {
+        Ok(Some(file)) => file,
+        Ok(None) => return cli,
+        Err(e) => {
+            eprintln!("{e}");
+            process::exit(1);
+        }
+    };
+    let settings = config_file::settings(&file, instance, cli.name.as_deref());
+    let file_args = config_file::to_args(&settings).unwrap_or_else(|e| {
+        eprintln!("{e}");
+        process::exit(1);
+    });
+    let mut args = env::args();
+    let binary = args.next().unwrap_or_default();
+    ModuleCli::try_parse_from(std::iter::once(binary).chain(file_args).chain(args)).unwrap_or_else(
+        |e| {
+            eprintln!("In {}:", path.display());
+            e.exit()
+        },
+    )
+}
 
-    let config = Config::from_module_cli(&cli);
+fn main() -> std::io::Result<()> {
+    let cli = ModuleCli::parse();
 
-    // Use XDG runtime directory for socket
-    let xdg_dirs = BaseDirectories::with_prefix("waybar-module-pomodoro");
+    // Use XDG runtime directory for socket, one directory per session
+    let session = cli.session.clone().or_else(session::current);
+    let socket_dir = session::socket_dir("waybar-module-pomodoro", session.as_deref())
+        .expect("No runtime directory for the socket, is XDG_RUNTIME_DIR set?");
 
     // Determine instance number
     let instance = match cli.instance {
         Some(num) => num,
-        None => find_next_instance_number("waybar-module-pomodoro"),
+        None => find_next_instance_number("waybar-module-pomodoro", session.as_deref()),
     };
 
+    let cli = with_config_file(cli, instance);
+
+    setup_tracing(&cli);
+
+    // Debug output of CLI arguments
+    tracing::debug!("Parsed CLI arguments: {:#?}", cli);
+
+    let config = Config::from_module_cli(&cli);
+
     let socket_filename = format!("module{instance}.socket");
-    let socket_path = xdg_dirs
-        .place_runtime_file(&socket_filename)
-        .expect("Failed to create socket path in runtime directory")
+    fs::create_dir_all(&socket_dir).expect("Failed to create socket path in runtime directory");
+    let socket_path = socket_dir
+        .join(socket_filename)
         .to_string_lossy()
         .to_string();
 
     info!("Starting module");
     info!("Socket path: {}", socket_path);
 
-    process_signals(socket_path.clone());
+    // held until the module exits
+    let _lock = match lock::acquire(Path::new(&socket_path), cli.replace) {
+        Ok(lock) => lock,
+        Err(e) => {
+            error!("{}", e);
+            eprintln!("{e}");
+            process::exit(1);
+        }
+    };
+
+    process_signals(socket_path.clone(), &cli);
     spawn_module(&socket_path, config);
 
     Ok(())
@@ -95,7 +198,8 @@ fn main() -> std::io::Result<()> {
 
 // we need to handle signals to ensure a graceful exit
 // this is important because we need to remove the sockets on exit
-fn process_signals(socket_path: String) {
+// SIGUSR1 and SIGUSR2 run a command, for setups without ctl
+fn process_signals(socket_path: String, cli: &ModuleCli) {
     // all possible realtime UNIX signals
     let sigrt = 34..64;
 
@@ -103,10 +207,29 @@ fn process_signals(socket_path: String) {
     // if we don't do this, the process will terminate if the user sends SIGRTMIN+N to the bar
     let _dont_handle = Signals::new(sigrt.collect::<Vec<i32>>()).unwrap();
 
-    let mut signals = Signals::new([SIGINT, SIGTERM, SIGHUP]).unwrap();
+    let on_sigusr1 = cli.on_sigusr1.clone();
+    let on_sigusr2 = cli.on_sigusr2.clone();
+    let mut signals = Signals::new([SIGINT, SIGTERM, SIGHUP, SIGUSR1, SIGUSR2]).unwrap();
     thread::spawn(move || {
-        for _ in signals.forever() {
-            send_message_socket(&socket_path, "exit").expect("unable to send message to module");
+        for signal in signals.forever() {
+            let command = match signal {
+                SIGUSR1 => &on_sigusr1,
+                SIGUSR2 => &on_sigusr2,
+                _ => {
+                    // a second signal may come in after the module removed its socket
+                    if let Err(e) = send_message_socket(&socket_path, &Message::Exit.encode()) {
+                        error!("Failed to send exit for signal {}: {}", signal, e);
+                    }
+                    break;
+                }
+            };
+            if command == "none" {
+                continue;
+            }
+            info!("Received signal {}, sending '{}'", signal, command);
+            if let Err(e) = send_message_socket(&socket_path, command) {
+                error!("Failed to send '{}' for signal {}: {}", command, signal, e);
+            }
         }
     });
 }
@@ -1,10 +1,30 @@
+use chrono::{Days, Local, NaiveDate};
 use clap::Parser;
 use std::env;
+use std::fs;
+use std::io::{ErrorKind, Write};
+use std::path::Path;
+use std::process::ExitCode;
+use std::time::Duration;
 use tracing::{debug, warn};
 use tracing_subscriber::EnvFilter;
 
-use waybar_module_pomodoro::control_cli::ControlCli;
-use waybar_module_pomodoro::services::module::{get_existing_sockets, send_message_socket};
+use waybar_module_pomodoro::client::PomodoroClient;
+use waybar_module_pomodoro::control_cli::{
+    config_path, config_toml, menu_action, prompt_segment, run_menu, waybar_menu_config,
+    waybar_menu_xml, ConfigAction, ConfigChange, ConfigFormat, ControlCli, Operation, SegmentStyle,
+    Target,
+};
+use waybar_module_pomodoro::models::message::{protocol_schema, Message, Response};
+use waybar_module_pomodoro::services::cache::Snapshot;
+use waybar_module_pomodoro::services::config_file;
+use waybar_module_pomodoro::services::history::{self, HistoryEvent};
+use waybar_module_pomodoro::services::ical;
+use waybar_module_pomodoro::services::import::{self, ImportSource};
+use waybar_module_pomodoro::services::report::{self, Report, ReportFormat};
+use waybar_module_pomodoro::services::session;
+use waybar_module_pomodoro::services::stats;
+use waybar_module_pomodoro::services::storage::Storage;
 
 fn setup_tracing() {
     // Client: log to console, respecting RUST_LOG environment variable
@@ -13,10 +33,439 @@ fn setup_tracing() {
         .init();
 }
 
-fn main() -> std::io::Result<()> {
+/// Sends `message` to every module, returning how many were reached and how many of those
+/// refused it
+fn send_to_modules(
+    modules: &[PomodoroClient],
+    message: &Message,
+) -> std::io::Result<(usize, usize)> {
+    let mut success_count = 0;
+    let mut refused = 0;
+    for module in modules {
+        let socket_str = module.socket().to_string_lossy();
+        debug!("Sending message '{:?}' to socket '{}'", message, socket_str);
+        match module.send(message) {
+            Ok(Response::Error { kind, message }) => {
+                // the module was reached, it just didn't like the command
+                eprintln!("{}: {:?}: {}", socket_str, kind, message);
+                success_count += 1;
+                refused += 1;
+            }
+            Ok(Response::Ok { data }) => {
+                debug!("Message sent successfully to {}", socket_str);
+                if let Some(data) = data {
+                    println!("{}", serde_json::to_string_pretty(&data)?);
+                }
+                success_count += 1;
+            }
+            Err(e) if e.kind() == ErrorKind::InvalidData => {
+                // older modules close the connection without replying
+                debug!("{} from {}", e, socket_str);
+                success_count += 1;
+            }
+            Err(e) => {
+                warn!("Failed to send message to {}: {}", socket_str, e);
+            }
+        }
+    }
+    Ok((success_count, refused))
+}
+
+/// Writes a report of the last `days` days of history, which doesn't need a running module
+fn write_report(
+    storage: &Storage,
+    format: ReportFormat,
+    days: u32,
+    path: Option<&Path>,
+) -> std::io::Result<()> {
+    let events = read_history(storage)?;
+    let since = Local::now().date_naive() - Days::new(days as u64 - 1);
+    let report = Report::new(&events, since).render(format);
+
+    match path {
+        Some(path) => fs::write(path, report),
+        None => {
+            print!("{report}");
+            Ok(())
+        }
+    }
+}
+
+/// Writes the pomodoros per day of the last `days` days as an SVG calendar
+fn write_heatmap(storage: &Storage, days: u32, path: &Path) -> std::io::Result<()> {
+    let events = read_history(storage)?;
+    let today = Local::now().date_naive();
+    let since = today - Days::new(days as u64 - 1);
+    fs::write(path, Report::new(&events, since).heatmap_svg(since, today))
+}
+
+/// Writes the work cycles of the last `days` days, or all of them, as calendar events
+fn write_ics(storage: &Storage, days: Option<u32>, path: &Path) -> std::io::Result<()> {
+    let events = read_history(storage)?;
+    let since = match days {
+        Some(days) => Local::now().date_naive() - Days::new(days as u64 - 1),
+        None => NaiveDate::MIN,
+    };
+    fs::write(path, ical::to_ics(&events, since))
+}
+
+/// Adds another timer's cycles to this machine's history, leaving out ones already there
+fn import_history(
+    storage: &Storage,
+    source: ImportSource,
+    path: Option<&Path>,
+) -> std::io::Result<()> {
+    let path = path
+        .map(Path::to_path_buf)
+        .or_else(|| source.default_path())
+        .ok_or_else(|| std::io::Error::other("Give the path of the file to import"))?;
+    let imported = source.read(&path).map_err(std::io::Error::other)?;
+    let count = imported.len();
+    let events = import::new_events(imported, &read_history(storage)?);
+    let added = events.len();
+
+    storage
+        .history_path()
+        .and_then(|own| history::merge(&own, events))
+        .map_err(|e| std::io::Error::other(format!("Cannot write history: {e}")))?;
+    println!(
+        "Imported {added} cycles, {} were already in the history",
+        count - added
+    );
+    Ok(())
+}
+
+fn read_history(storage: &Storage) -> std::io::Result<Vec<HistoryEvent>> {
+    storage
+        .history_paths()
+        .and_then(|paths| history::read_all(&paths))
+        .map_err(|e| std::io::Error::other(format!("Cannot read history: {e}")))
+}
+
+/// Lists achievements from the stats file, unlocked ones first
+fn list_achievements(storage: &Storage) -> std::io::Result<()> {
+    let stats = storage
+        .stats_paths()
+        .and_then(|paths| stats::load_all(&paths))
+        .map_err(|e| std::io::Error::other(format!("Cannot read stats: {e}")))?;
+
+    let (unlocked, locked): (Vec<_>, Vec<_>) = stats::ACHIEVEMENTS
+        .iter()
+        .partition(|achievement| stats.achievements.contains_key(achievement.id));
+    for achievement in unlocked {
+        let day = report::local_date(stats.achievements[achievement.id])
+            .map(|day| day.to_string())
+            .unwrap_or_default();
+        println!("[x] {} ({})", achievement.title, day);
+    }
+    for achievement in locked {
+        println!("[ ] {}: {}", achievement.title, achievement.description);
+    }
+    Ok(())
+}
+
+/// Sends `message` to `module` and returns the data it replied with, `None` if it refused
+fn request_data(
+    module: &PomodoroClient,
+    message: &Message,
+) -> std::io::Result<Option<serde_json::Value>> {
+    let socket_str = module.socket().to_string_lossy();
+    match module.send(message)? {
+        Response::Ok { data: Some(data) } => Ok(Some(data)),
+        Response::Error { kind, message } => {
+            eprintln!("{}: {:?}: {}", socket_str, kind, message);
+            Ok(None)
+        }
+        Response::Ok { data: None } => Err(std::io::Error::other(format!(
+            "No data in the reply from {socket_str}"
+        ))),
+    }
+}
+
+/// Runs `command` against the one module targeted, for commands that read a single reply
+fn single_target(
+    modules: &[PomodoroClient],
+    command: impl FnOnce(&PomodoroClient) -> std::io::Result<ExitCode>,
+) -> std::io::Result<ExitCode> {
+    match modules {
+        [module] => command(module),
+        _ => {
+            eprintln!("Several modules are running, pick one with --instance or --group");
+            Ok(ExitCode::FAILURE)
+        }
+    }
+}
+
+/// Asks `module` for its state and writes it to `path`
+fn export_state(module: &PomodoroClient, path: &Path) -> std::io::Result<ExitCode> {
+    match request_data(module, &Message::ExportState)? {
+        Some(snapshot) => {
+            fs::write(path, serde_json::to_string_pretty(&snapshot)?)?;
+            Ok(ExitCode::SUCCESS)
+        }
+        None => Ok(ExitCode::FAILURE),
+    }
+}
+
+/// Prints the configuration `module` runs with
+fn show_config(module: &PomodoroClient, format: ConfigFormat) -> std::io::Result<ExitCode> {
+    let Some(config) = request_data(module, &Message::ShowConfig)? else {
+        return Ok(ExitCode::FAILURE);
+    };
+    match format {
+        ConfigFormat::Json => println!("{}", serde_json::to_string_pretty(&config)?),
+        ConfigFormat::Toml => print!("{}", config_toml(&config).map_err(std::io::Error::other)?),
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+/// Writes a `config set` change to the config file, for the --instance given or for all
+fn save_change(change: &ConfigChange, cli: &ControlCli) -> std::io::Result<ExitCode> {
+    let Some(path) = config_path() else {
+        eprintln!("No config directory to save the setting in");
+        return Ok(ExitCode::FAILURE);
+    };
+    let section = cli.instance.as_ref().map(Target::to_string);
+    match config_file::save_setting(&path, section.as_deref(), change.key, &change.value) {
+        Ok(()) => {
+            eprintln!("Saved {} to {}", change.key, path.display());
+            Ok(ExitCode::SUCCESS)
+        }
+        Err(e) => {
+            eprintln!("{e}");
+            Ok(ExitCode::FAILURE)
+        }
+    }
+}
+
+/// Prints the `unit` field of `module`'s `remaining` reply, nothing else
+fn print_remaining(module: &PomodoroClient, unit: &str) -> std::io::Result<ExitCode> {
+    match request_data(module, &Message::Remaining)? {
+        Some(remaining) => {
+            println!("{}", remaining[unit]);
+            Ok(ExitCode::SUCCESS)
+        }
+        None => Ok(ExitCode::FAILURE),
+    }
+}
+
+/// Prints each line `module` renders until either side hangs up
+fn print_subscription(module: &PomodoroClient) -> std::io::Result<ExitCode> {
+    let mut stdout = std::io::stdout().lock();
+    for line in module.subscribe()? {
+        // whoever reads the lines may go away first, as `| head` does
+        if writeln!(stdout, "{}", line?)
+            .and_then(|_| stdout.flush())
+            .is_err()
+        {
+            break;
+        }
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+/// The modules of `binary_name` in the `preferred` session, reached with --timeout and
+/// --retries
+fn discover(
+    binary_name: &str,
+    preferred: Option<String>,
+    cli: &ControlCli,
+) -> Result<Vec<PomodoroClient>, String> {
+    let modules = PomodoroClient::discover_in(binary_name, preferred)?;
+    Ok(modules
+        .into_iter()
+        .map(|module| {
+            module
+                .with_timeout(Duration::from_millis(cli.timeout))
+                .with_retries(cli.retries)
+        })
+        .collect())
+}
+
+/// Whether `module` is the instance `target` picks
+fn is_target(module: &PomodoroClient, target: &Target) -> bool {
+    match target {
+        Target::Number(number) => module.instance() == Some(*number),
+        Target::Name(name) => match module.name() {
+            Ok(module_name) => module_name.as_ref() == Some(name),
+            Err(e) => {
+                warn!(
+                    "Failed to ask {} for its name: {}",
+                    module.socket().display(),
+                    e
+                );
+                false
+            }
+        },
+    }
+}
+
+/// Whether `module` was started with `--group group`
+fn in_group(module: &PomodoroClient, group: &str) -> bool {
+    match module.groups() {
+        Ok(groups) => groups.iter().any(|name| name == group),
+        Err(e) => {
+            warn!(
+                "Failed to ask {} for its groups: {}",
+                module.socket().display(),
+                e
+            );
+            false
+        }
+    }
+}
+
+/// Prints the `prompt` or `tmux-status` line of the targeted module, the first one unless
+/// --instance or --group picks another
+///
+/// Prompts are drawn all the time, so this stays silent when there is nothing to show.
+fn print_prompt(
+    binary_name: &str,
+    preferred: Option<String>,
+    cli: &ControlCli,
+    style: SegmentStyle,
+) {
+    let Ok(modules) = discover(binary_name, preferred, cli) else {
+        return;
+    };
+    let target = cli.target();
+    let module = modules.into_iter().find(|module| {
+        target
+            .as_ref()
+            .is_none_or(|target| is_target(module, target))
+            && cli
+                .group
+                .as_deref()
+                .is_none_or(|group| in_group(module, group))
+    });
+    let remaining = module.and_then(|module| module.request(&Message::Remaining).ok()?);
+    if let Some(remaining) = remaining {
+        println!("{}", prompt_segment(&remaining, style));
+    }
+}
+
+/// The state of `module`, as a message importing it into another
+///
+/// Stats are left out, the instances already share the stats file.
+fn mirror_state(module: &PomodoroClient) -> std::io::Result<Option<Message>> {
+    let Some(snapshot) = request_data(module, &Message::ExportState)? else {
+        return Ok(None);
+    };
+    let mut snapshot: Snapshot = serde_json::from_value(snapshot)
+        .map_err(|e| std::io::Error::other(format!("Unreadable state: {e}")))?;
+    snapshot.stats = None;
+    Ok(Some(Message::ImportState {
+        snapshot: Box::new(snapshot),
+    }))
+}
+
+fn read_snapshot(path: &Path) -> std::io::Result<Message> {
+    let snapshot: Snapshot = serde_json::from_str(&fs::read_to_string(path)?).map_err(|e| {
+        std::io::Error::other(format!("{} is not a state export: {e}", path.display()))
+    })?;
+    Ok(Message::ImportState {
+        snapshot: Box::new(snapshot),
+    })
+}
+
+/// Writes the Waybar menu file and prints the config snippet that wires it up
+fn write_waybar_menu(path: Option<&Path>, cli: &ControlCli) -> std::io::Result<()> {
+    let path = match path {
+        Some(path) => std::path::absolute(path)?,
+        None => dirs::config_dir()
+            .ok_or_else(|| std::io::Error::other("Unable to get config dir"))?
+            .join("waybar")
+            .join("pomodoro-menu.xml"),
+    };
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(&path, waybar_menu_xml())?;
+
+    let mut ctl = env::current_exe()
+        .ok()
+        .and_then(|exe| exe.file_name()?.to_str().map(str::to_string))
+        .unwrap_or_else(|| "waybar-module-pomodoro-ctl".to_string());
+    if let Some(instance) = &cli.instance {
+        ctl.push_str(&format!(" -i {instance}"));
+    }
+    if let Some(group) = &cli.group {
+        ctl.push_str(&format!(" -g {group}"));
+    }
+    if cli.all {
+        ctl.push_str(" --all");
+    }
+    eprintln!(
+        "Wrote {}, add this to the module's Waybar config:",
+        path.display()
+    );
+    println!(
+        "{}",
+        serde_json::to_string_pretty(&waybar_menu_config(&path, &ctl))?
+    );
+    Ok(())
+}
+
+/// Exits with a failure when no module was reached, or one refused the command, so
+/// keybindings and scripts can tell
+fn main() -> std::io::Result<ExitCode> {
     let cli = ControlCli::parse();
     setup_tracing();
 
+    // these only read local files, no module has to be running
+    let storage = Storage::new(cli.sync_dir.as_deref());
+    match &cli.operation {
+        Operation::Report {
+            days,
+            heatmap: Some(heatmap),
+            ..
+        } => {
+            write_heatmap(&storage, days.unwrap_or(365), heatmap)?;
+            return Ok(ExitCode::SUCCESS);
+        }
+        Operation::Report {
+            format, days, path, ..
+        } => {
+            write_report(&storage, *format, days.unwrap_or(7), path.as_deref())?;
+            return Ok(ExitCode::SUCCESS);
+        }
+        Operation::Export { ics, days } => {
+            write_ics(&storage, *days, ics)?;
+            return Ok(ExitCode::SUCCESS);
+        }
+        Operation::Import { from, path } => {
+            import_history(&storage, *from, path.as_deref())?;
+            return Ok(ExitCode::SUCCESS);
+        }
+        Operation::Achievements => {
+            list_achievements(&storage)?;
+            return Ok(ExitCode::SUCCESS);
+        }
+        Operation::Schema => {
+            println!("{}", serde_json::to_string_pretty(&protocol_schema())?);
+            return Ok(ExitCode::SUCCESS);
+        }
+        Operation::WaybarMenu { path } => {
+            write_waybar_menu(path.as_deref(), &cli)?;
+            return Ok(ExitCode::SUCCESS);
+        }
+        _ => {}
+    }
+
+    // checked before bothering the modules with it
+    let change = match &cli.operation {
+        Operation::Config {
+            action: ConfigAction::Set { key, value, .. },
+        } => match ConfigChange::parse(key, value) {
+            Ok(change) => Some(change),
+            Err(e) => {
+                eprintln!("{e}");
+                return Ok(ExitCode::FAILURE);
+            }
+        },
+        _ => None,
+    };
+
     let binary_name = env::current_exe()
         .ok()
         .and_then(|path| path.file_name().map(|s| s.to_owned()))
@@ -24,59 +473,153 @@ fn main() -> std::io::Result<()> {
         .unwrap_or_else(|| "waybar-module-pomodoro".to_string())
         .replace("-ctl", ""); // Remove -ctl to match module socket names
 
-    let mut sockets = get_existing_sockets(&binary_name);
-    debug!("Found {} existing sockets", sockets.len());
-
-    // Filter by instance if specified
-    if let Some(instance) = cli.instance {
-        let target_socket_name = format!("module{instance}.socket");
-        sockets.retain(|socket| {
-            socket
-                .file_name()
-                .and_then(|name| name.to_str())
-                .map(|name| name == target_socket_name)
-                .unwrap_or(false)
-        });
-
-        if sockets.is_empty() {
+    let preferred = cli.session.clone().or_else(session::current);
+    let style = match cli.operation {
+        Operation::Prompt { no_color: true } => Some(SegmentStyle::Plain),
+        Operation::Prompt { no_color: false } => Some(SegmentStyle::Ansi),
+        Operation::TmuxStatus => Some(SegmentStyle::Tmux),
+        _ => None,
+    };
+    if let Some(style) = style {
+        // prompts stay quiet, and so does their exit status
+        print_prompt(&binary_name, preferred, &cli, style);
+        return Ok(ExitCode::SUCCESS);
+    }
+    let mut modules = match discover(&binary_name, preferred, &cli) {
+        Ok(modules) => modules,
+        Err(e) => {
+            eprintln!("{e}");
+            return Ok(ExitCode::FAILURE);
+        }
+    };
+    debug!("Found {} existing sockets", modules.len());
+
+    // the instance to mirror, which the other filters must not take away
+    let source = match &cli.operation {
+        Operation::Mirror { from } => {
+            let Some(index) = modules.iter().position(|m| m.instance() == Some(*from)) else {
+                eprintln!("No running waybar-module-pomodoro instance {} found", from);
+                return Ok(ExitCode::FAILURE);
+            };
+            Some(modules.remove(index))
+        }
+        _ => None,
+    };
+
+    // Filter by instance if specified, or by the default one
+    let target = cli.target();
+    if let Some(target) = &target {
+        modules.retain(|module| is_target(module, target));
+
+        if modules.is_empty() {
             eprintln!(
                 "No running waybar-module-pomodoro instance {} found",
-                instance
+                target
+            );
+            return Ok(ExitCode::FAILURE);
+        }
+        debug!("Targeting instance {}", target);
+    }
+
+    if let Some(group) = &cli.group {
+        modules.retain(|module| in_group(module, group));
+
+        if modules.is_empty() {
+            eprintln!(
+                "No running waybar-module-pomodoro instance in group {}",
+                group
             );
-            return Ok(());
+            return Ok(ExitCode::FAILURE);
         }
-        debug!("Targeting instance {}", instance);
+        debug!("Targeting group {}", group);
     }
 
-    if sockets.is_empty() {
+    if modules.is_empty() {
         eprintln!("No running waybar-module-pomodoro module found");
-        return Ok(());
+        return Ok(ExitCode::FAILURE);
     }
 
-    for socket in &sockets {
-        debug!("Socket path: {}", socket.display());
+    // broadcasting to timers kept apart would be a surprise, that takes --all
+    if modules.len() > 1 && target.is_none() && cli.group.is_none() && !cli.all {
+        let instances: Vec<_> = modules
+            .iter()
+            .filter_map(|module| module.instance().map(|instance| instance.to_string()))
+            .collect();
+        eprintln!(
+            "Several modules are running ({}), pick one with --instance or --group, or address them all with --all",
+            instances.join(", ")
+        );
+        return Ok(ExitCode::FAILURE);
     }
 
-    let message = cli.operation.to_message().encode();
+    for module in &modules {
+        debug!("Socket path: {}", module.socket().display());
+    }
 
-    let mut success_count = 0;
-    for socket in sockets {
-        let socket_str = socket.to_string_lossy();
-        debug!("Sending message '{}' to socket '{}'", message, socket_str);
-        match send_message_socket(&socket_str, &message) {
-            Ok(_) => {
-                debug!("Message sent successfully to {}", socket_str);
-                success_count += 1;
-            }
-            Err(e) => {
-                warn!("Failed to send message to {}: {}", socket_str, e);
+    let messages = match &cli.operation {
+        Operation::Menu { launcher } => match run_menu(*launcher)? {
+            Some(messages) => messages,
+            // dismissed without picking anything
+            None => return Ok(ExitCode::SUCCESS),
+        },
+        Operation::MenuAction { id } => menu_action(id)?,
+        Operation::ExportState { path } => {
+            return single_target(&modules, |module| export_state(module, path))
+        }
+        Operation::Config {
+            action: ConfigAction::Show { format },
+        } => return single_target(&modules, |module| show_config(module, *format)),
+        Operation::Remaining { seconds, percent } => {
+            let unit = match (seconds, percent) {
+                (true, _) => "seconds",
+                (_, true) => "percent",
+                _ => "minutes",
+            };
+            return single_target(&modules, |module| print_remaining(module, unit));
+        }
+        Operation::Subscribe => return single_target(&modules, print_subscription),
+        Operation::ImportState { path } => vec![read_snapshot(path)?],
+        Operation::Config { .. } => change.iter().map(ConfigChange::message).collect(),
+        Operation::Mirror { .. } => match source.as_ref().map(mirror_state).transpose()? {
+            Some(Some(message)) => vec![message],
+            _ => return Ok(ExitCode::FAILURE),
+        },
+        operation => {
+            let mut messages = vec![operation
+                .to_message()
+                .expect("only client-side operations have no message")];
+            if operation.resets() {
+                messages.push(Message::Reset);
             }
+            messages
         }
+    };
+
+    let mut success_count = 0;
+    let mut refused = 0;
+    for message in &messages {
+        let (reached, refusals) = send_to_modules(&modules, message)?;
+        success_count += reached;
+        refused += refusals;
     }
 
     if success_count == 0 {
         eprintln!("Failed to send message to any running modules");
+        return Ok(ExitCode::FAILURE);
+    }
+    if refused > 0 {
+        return Ok(ExitCode::FAILURE);
     }
 
-    Ok(())
+    if let (
+        Some(change),
+        Operation::Config {
+            action: ConfigAction::Set { save: true, .. },
+        },
+    ) = (&change, &cli.operation)
+    {
+        return save_change(change, &cli);
+    }
+
+    Ok(ExitCode::SUCCESS)
 }
@@ -1,8 +1,17 @@
+use crate::models::{
+    config::{
+        ActivityOrder, AutosaveEvent, BreakRatio, CompletionCount, DurationBounds, IconPreset,
+        LongBreakCurve, ProgressGlyphs,
+    },
+    message::{AutoTarget, DurationValue, Message},
+};
+use crate::services::{hooks::TintTool, reminders::ReminderSpec, sink::OutputFormat};
 use crate::utils::consts::{
-    BREAK_ICON, LONG_BREAK_TIME, MINUTE, PAUSE_ICON, PLAY_ICON, SHORT_BREAK_TIME, WORK_ICON,
+    BREAK_ICON, DEFAULT_FORMAT, LONG_BREAK_ICON, LONG_BREAK_TIME, MAX_MINUTES, MICRO_BREAK_LENGTH,
+    MINUTE, PAUSE_ICON, PLAY_ICON, PROGRESS_WIDTH, SHORT_BREAK_TIME, TICK_RATE, WORK_ICON,
     WORK_TIME,
 };
-use clap::Parser;
+use clap::{Parser, ValueEnum};
 use std::env;
 use std::fs;
 use std::path::PathBuf;
@@ -71,6 +80,60 @@ impl std::str::FromStr for LogOption {
     }
 }
 
+/// When the file logger starts a new file
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LogRotation {
+    Hourly,
+    Daily,
+    Never,
+    /// Rotate once the file grows past this many bytes
+    Size(u64),
+}
+
+impl std::str::FromStr for LogRotation {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "hourly" => return Ok(LogRotation::Hourly),
+            "daily" => return Ok(LogRotation::Daily),
+            "never" => return Ok(LogRotation::Never),
+            _ => {}
+        }
+
+        let (number, multiplier) = match s.char_indices().last() {
+            Some((i, 'K' | 'k')) => (&s[..i], 1024),
+            Some((i, 'M' | 'm')) => (&s[..i], 1024 * 1024),
+            Some((i, 'G' | 'g')) => (&s[..i], 1024 * 1024 * 1024),
+            _ => (s, 1),
+        };
+        match number.parse::<u64>() {
+            Ok(size) if size > 0 => Ok(LogRotation::Size(size * multiplier)),
+            _ => Err(format!(
+                "Invalid log rotation: {s} (expected hourly, daily, never or a size like 10M)"
+            )),
+        }
+    }
+}
+
+/// Line format used by the file logger
+#[derive(Debug, Clone, Copy, PartialEq, Default, ValueEnum)]
+pub enum LogFormat {
+    #[default]
+    Text,
+    Json,
+}
+
+/// A command for a signal to send the module, or `none` to ignore the signal
+fn validate_signal_command(command: &str) -> Result<String, String> {
+    if command == "none" {
+        return Ok(command.to_string());
+    }
+    Message::decode(command)
+        .map(|_| command.to_string())
+        .map_err(|_| format!("Not a command the module takes: {command}"))
+}
+
 fn validate_sound_file_path(path: &str) -> Result<String, String> {
     let path_buf = PathBuf::from(path);
 
@@ -96,6 +159,8 @@ fn validate_sound_file_path(path: &str) -> Result<String, String> {
 #[command(about = "A pomodoro timer module for your system bar")]
 #[command(long_about = None)]
 #[command(version)]
+// options on the command line win over the ones read from the config file
+#[command(args_override_self = true)]
 pub struct ModuleCli {
     /// Sets how long a work cycle is, in minutes
     #[arg(short = 'w', long = "work", value_name = "value", help = format!("Sets how long a work cycle is, in minutes. default: {}", WORK_TIME / MINUTE))]
@@ -106,9 +171,69 @@ pub struct ModuleCli {
     pub shortbreak: Option<u16>,
 
     /// Sets how long a long break is, in minutes
-    #[arg(short = 'l', long = "longbreak", value_name = "value", help = format!("Sets how long a long break is, in minutes. default: {}", LONG_BREAK_TIME / MINUTE))]
+    #[arg(short = 'l', long = "longbreak", value_name = "value", help = format!("Sets how long a long break is, in minutes, 0 for no long breaks. default: {}", LONG_BREAK_TIME / MINUTE))]
     pub longbreak: Option<u16>,
 
+    /// Work length of each iteration before the long break, in minutes
+    #[arg(
+        long = "work-sequence",
+        value_name = "minutes,...",
+        value_delimiter = ',',
+        value_parser = clap::value_parser!(u16).range(1..=MAX_MINUTES as i64),
+        help = "Work length of each iteration before the long break, e.g. 25,25,30,35. The last one repeats if there are fewer than iterations. Overrides --work"
+    )]
+    pub work_sequence: Vec<u16>,
+
+    /// Randomly lengthen or shorten each cycle by up to this many minutes
+    #[arg(
+        long = "jitter",
+        value_name = "minutes",
+        value_parser = clap::value_parser!(u16).range(1..=60),
+        help = "Randomly lengthen or shorten each cycle by up to this many minutes, to keep from watching the clock. The drawn length is shown in the tooltip"
+    )]
+    pub jitter: Option<u16>,
+
+    /// Stop auto-starting cycles once this many pomodoros were completed today
+    #[arg(
+        long = "daily-cap",
+        value_name = "pomodoros",
+        value_parser = clap::value_parser!(u16).range(1..),
+        help = "Stop auto-starting cycles once this many pomodoros were completed today, adding the done-for-today class"
+    )]
+    pub daily_cap: Option<u16>,
+
+    /// Size breaks from the work length, also when set-work changes it
+    #[arg(
+        long = "break-ratio",
+        value_name = "short:long",
+        num_args = 0..=1,
+        default_missing_value = "5:2",
+        help = "Size breaks from the work length, also when set-work changes it: short = work/short, long = work/long. Overrides --shortbreak and --longbreak (default 5:2)"
+    )]
+    pub break_ratio: Option<BreakRatio>,
+
+    /// Lengthen long breaks after long streaks of pomodoros
+    #[arg(
+        long = "adaptive-long-break",
+        value_name = "pomodoros:minutes,...",
+        num_args = 0..=1,
+        default_missing_value = "8:20,12:25,16:30",
+        help = "Lengthen a long break once this many pomodoros in a row were completed today, e.g. 8:20,12:30 for 20 minutes after 8 and 30 after 12 (default 8:20,12:25,16:30)"
+    )]
+    pub adaptive_long_break: Option<LongBreakCurve>,
+
+    /// Shortest and longest a work cycle may be set to from ctl, in minutes
+    #[arg(long = "work-bounds", value_name = "min-max", help = format!("Shortest and longest a work cycle may be set to from ctl or scrolling, in minutes, e.g. 5-90. default: 1-{}", MAX_MINUTES))]
+    pub work_bounds: Option<DurationBounds>,
+
+    /// Shortest and longest a short break may be set to from ctl, in minutes
+    #[arg(long = "short-bounds", value_name = "min-max", help = format!("Shortest and longest a short break may be set to from ctl or scrolling, in minutes. default: 1-{}", MAX_MINUTES))]
+    pub short_bounds: Option<DurationBounds>,
+
+    /// Shortest and longest a long break may be set to from ctl, in minutes
+    #[arg(long = "long-bounds", value_name = "min-max", help = format!("Shortest and longest a long break may be set to from ctl or scrolling, in minutes. default: 1-{}", MAX_MINUTES))]
+    pub long_bounds: Option<DurationBounds>,
+
     /// Sets custom play icon/text
     #[arg(short = 'p', long = "play", value_name = "value", help = format!("Sets custom play icon/text. default: {}", PLAY_ICON))]
     pub play: Option<String>,
@@ -125,6 +250,70 @@ pub struct ModuleCli {
     #[arg(short = 'b', long = "break-icon", value_name = "value", help = format!("Sets custom break icon/text. default: {}", BREAK_ICON))]
     pub break_icon: Option<String>,
 
+    /// Sets custom long break icon/text
+    #[arg(long = "long-break-icon", value_name = "value", help = format!("Sets custom long break icon/text. Falls back to --break-icon. default: {}", LONG_BREAK_ICON))]
+    pub long_break_icon: Option<String>,
+
+    /// Selects the built-in icon set used for any icon not set explicitly
+    #[arg(
+        long = "icon-preset",
+        value_name = "preset",
+        value_enum,
+        default_value_t = IconPreset::NerdFont,
+        help = "Selects the built-in icon set used for any icon not set explicitly"
+    )]
+    pub icon_preset: IconPreset,
+
+    /// Sets the text format
+    #[arg(long = "format", value_name = "template", help = format!("Sets the text format. Placeholders: {{icon}}, {{time}}, {{cycle_icon}}, {{progress}}, {{focus_today}}, {{sprint}}, {{until_long}}, {{next}}, {{task}}. default: \"{}\"", DEFAULT_FORMAT))]
+    pub format: Option<String>,
+
+    /// Sets the tooltip format
+    #[arg(
+        long = "tooltip-format",
+        value_name = "template",
+        help = "Sets the tooltip from a template that may use Pango markup, \\n separating lines. A line is left out when all of its placeholders are empty. Takes the --format placeholders and {cycle}, {completed}, {pomodoros_today}, {interruptions}, {task}, {task_progress}, {activity} and {pause_cause}"
+    )]
+    pub tooltip_format: Option<String>,
+
+    /// Glyphs used to draw the {progress} placeholder
+    #[arg(
+        long = "progress-glyphs",
+        value_name = "glyphs",
+        value_enum,
+        default_value_t = ProgressGlyphs::Bar,
+        help = "Glyphs used to draw the {progress} placeholder, the current cycle's completion"
+    )]
+    pub progress_glyphs: ProgressGlyphs,
+
+    /// How many glyphs wide the {progress} bar is
+    #[arg(
+        long = "progress-width",
+        value_name = "glyphs",
+        default_value_t = PROGRESS_WIDTH,
+        value_parser = clap::value_parser!(u8).range(1..=50),
+        help = "How many glyphs wide the {progress} bar is, ignored by the pie"
+    )]
+    pub progress_width: u8,
+
+    /// Longest the text may get, in characters
+    #[arg(
+        long = "max-length",
+        value_name = "chars",
+        value_parser = clap::value_parser!(u16).range(2..),
+        help = "Longest the text may get, in characters. A long {task} is shortened first, ending in …"
+    )]
+    pub max_length: Option<u16>,
+
+    /// Shortest the text may get, in characters
+    #[arg(
+        long = "min-length",
+        value_name = "chars",
+        default_value_t = 0,
+        help = "Pads the text with spaces to at least this many characters, so the module keeps its width as the text changes"
+    )]
+    pub min_length: u16,
+
     /// Sound to play at the end of a work period
     #[arg(
         short = 'O',
@@ -145,6 +334,17 @@ pub struct ModuleCli {
     )]
     pub break_sound: Option<String>,
 
+    /// Turn other apps down while the end-of-cycle sound plays
+    #[arg(
+        long = "duck",
+        value_name = "percent",
+        num_args = 0..=1,
+        default_missing_value = "30",
+        value_parser = clap::value_parser!(u8).range(0..=100),
+        help = "Turn the audio of other apps down to this percentage of its volume while the end-of-cycle sound plays (default 30), restoring it afterwards. Needs PipeWire's pw-dump and wpctl"
+    )]
+    pub duck: Option<u8>,
+
     /// Disable the pause/play icon
     #[arg(long = "no-icons", help = "Disable the pause/play icon")]
     pub no_icons: bool,
@@ -153,6 +353,13 @@ pub struct ModuleCli {
     #[arg(long = "no-work-icons", help = "Disable the work/break icon")]
     pub no_work_icons: bool,
 
+    /// Show the time spent in the current cycle instead of the time left
+    #[arg(
+        long = "count-up",
+        help = "Show the time spent in the current cycle instead of the time left. Switch at runtime with `toggle-display`"
+    )]
+    pub count_up: bool,
+
     /// Starts a work cycle automatically after a break
     #[arg(
         long = "autow",
@@ -164,19 +371,427 @@ pub struct ModuleCli {
     #[arg(long = "autob", help = "Starts a break cycle automatically after work")]
     pub autob: bool,
 
+    /// Auto-start a specific transition, may be repeated
+    #[arg(
+        long = "auto",
+        value_name = "rule",
+        help = "Auto-start a specific transition: work, break, short-break, long-break, work-after-short or work-after-long. May be repeated"
+    )]
+    pub auto: Vec<AutoTarget>,
+
+    /// Seconds to count down before an auto-start, during which it can be cancelled
+    #[arg(
+        long = "auto-grace",
+        value_name = "seconds",
+        default_value_t = 0,
+        help = "Seconds to count down before an auto-start, during which `cancel-auto` can stop it"
+    )]
+    pub auto_grace: u16,
+
     /// Persist timer state between sessions
     #[arg(long = "persist", help = "Persist timer state between sessions")]
     pub persist: bool,
 
+    /// How often a persisting module saves its state
+    #[arg(
+        long = "autosave",
+        value_name = "interval",
+        requires = "persist",
+        help = "How often --persist saves the state, e.g. 30s or 5m, instead of on every tick. It is still saved on the --autosave-on events and when the module exits"
+    )]
+    pub autosave: Option<DurationValue>,
+
+    /// Events that save the state straight away between autosaves, may be repeated
+    #[arg(
+        long = "autosave-on",
+        value_name = "event",
+        value_enum,
+        default_values_t = [AutosaveEvent::Cycle, AutosaveEvent::Command],
+        help = "Save the state straight away, between --autosave intervals, when a cycle ends (cycle) or a command arrives (command). May be repeated"
+    )]
+    pub autosave_on: Vec<AutosaveEvent>,
+
+    /// Keep a log of finished cycles and tasks
+    #[arg(
+        long = "history",
+        help = "Keep a log of finished cycles and tasks in the state directory"
+    )]
+    pub history: bool,
+
+    /// Keep the timer in a state.env file for tools that can only source files
+    #[arg(
+        long = "state-env",
+        help = "Keep CYCLE, REMAINING, TIME, RUNNING and COMPLETED in state.env next to the socket, for hyprlock and scripts"
+    )]
+    pub state_env: bool,
+
+    /// What to print on standard output
+    #[arg(
+        long = "output",
+        value_name = "format",
+        value_enum,
+        default_value_t = OutputFormat::Waybar,
+        help = "What to print on standard output: waybar's JSON, or plain text for a polybar custom/script with tail = true"
+    )]
+    pub output: OutputFormat,
+
+    /// Record which apps were focused during work cycles in the history
+    #[arg(
+        long = "track-apps",
+        requires = "history",
+        help = "Record which apps were focused during work cycles in the history (Hyprland and Sway)"
+    )]
+    pub track_apps: bool,
+
+    /// Track milestones like the 100th pomodoro
+    #[arg(
+        long = "achievements",
+        help = "Track milestones like the 100th pomodoro, announced with --with-notifications and listed by `achievements`"
+    )]
+    pub achievements: bool,
+
+    /// Keep state, history and stats in a directory synced between machines
+    #[arg(
+        long = "sync-dir",
+        value_name = "path",
+        env = "WAYBAR_POMODORO_SYNC_DIR",
+        help = "Keep persisted state, history and stats in a directory synced between machines, e.g. by Syncthing. Each machine writes its own files and reads everyone's"
+    )]
+    pub sync_dir: Option<PathBuf>,
+
     /// Enable desktop notifications
     #[arg(long = "with-notifications", help = "Enable desktop notifications")]
     pub with_notifications: bool,
 
+    /// Only notify on these transitions, may be repeated
+    #[arg(
+        long = "notify-on",
+        value_name = "rule",
+        help = "Enable desktop notifications for a single transition only, named like --auto: work (a break ended), break (work ended), short-break, long-break, work-after-short or work-after-long. May be repeated"
+    )]
+    pub notify_on: Vec<AutoTarget>,
+
+    /// Start with sounds muted (they can be enabled at runtime)
+    #[arg(
+        long = "no-sounds",
+        help = "Start with sounds muted. They can be re-enabled with `sounds on`"
+    )]
+    pub no_sounds: bool,
+
+    /// Flash the urgent class every second while a finished cycle waits to be started
+    #[arg(
+        long = "blink",
+        help = "Flash the urgent class every second while a finished cycle waits to be started"
+    )]
+    pub blink: bool,
+
+    /// How often the module re-renders, in milliseconds
+    #[arg(
+        long = "tick-rate",
+        value_name = "ms",
+        default_value_t = TICK_RATE,
+        value_parser = clap::value_parser!(u16).range(1..),
+        help = "How often the module re-renders, in milliseconds. Ticks are aligned to the timer's seconds"
+    )]
+    pub tick_rate: u16,
+
+    /// Render whole minutes, once a minute, while on battery
+    #[arg(
+        long = "power-save",
+        help = "While on battery (UPower), render whole minutes once a minute, keep the audio device closed between sounds and add the power-save class"
+    )]
+    pub power_save: bool,
+
+    /// Switch to the stale class once paused for this many minutes
+    #[arg(
+        long = "stale-after",
+        value_name = "minutes",
+        help = "Switch to the stale class once the timer has been paused for this many minutes"
+    )]
+    pub stale_after: Option<u16>,
+
+    /// Mention how long the timer has been paused in the tooltip once stale
+    #[arg(
+        long = "stale-tooltip",
+        help = "Mention how long the timer has been paused in the tooltip once stale"
+    )]
+    pub stale_tooltip: bool,
+
+    /// Resume a timer paused by `idle` when `active` follows within this many minutes
+    #[arg(
+        long = "idle-resume",
+        value_name = "minutes",
+        value_parser = clap::value_parser!(u16).range(1..),
+        help = "Resume a timer paused by `idle` when `active` follows within this many minutes, e.g. from swayidle's timeout and resume commands"
+    )]
+    pub idle_resume: Option<u16>,
+
+    /// Pause and mute the timer while the microphone is in use
+    #[arg(
+        long = "call-pause",
+        help = "Pause the timer and hold back notifications and sounds while an app records from the microphone (PipeWire), resuming once the call ends"
+    )]
+    pub call_pause: bool,
+
+    /// Pause the work cycles of other instances whenever this one starts one
+    #[arg(
+        long = "exclusive-focus",
+        help = "Pause work cycles running on the session's other instances whenever this one starts one, so only one timer is focused at a time"
+    )]
+    pub exclusive_focus: bool,
+
+    /// Give up on a work cycle paused for this many minutes
+    #[arg(
+        long = "abandon-after",
+        value_name = "minutes",
+        value_parser = clap::value_parser!(u16).range(1..),
+        help = "Give up on a work cycle paused for this many minutes, recording it as abandoned in the history and starting a fresh one"
+    )]
+    pub abandon_after: Option<u16>,
+
+    /// Reset the timer when a new day starts at this hour
+    #[arg(
+        long = "day-start",
+        value_name = "hour",
+        num_args = 0..=1,
+        default_missing_value = "0",
+        value_parser = clap::value_parser!(u8).range(0..24),
+        help = "Reset the timer every day at this local hour (default midnight). Pomodoros before it count towards the previous day's stats"
+    )]
+    pub day_start: Option<u8>,
+
+    /// Send a reminder once a work cycle has been paused for this many minutes
+    #[arg(
+        long = "pause-reminder",
+        value_name = "minutes",
+        value_parser = clap::value_parser!(u16).range(1..),
+        help = "Send a reminder, with buttons to resume or reset, once a work cycle has been paused for this many minutes"
+    )]
+    pub pause_reminder: Option<u16>,
+
+    /// Remind to look away from the screen every so many minutes of work
+    #[arg(
+        long = "micro-break",
+        value_name = "minutes",
+        num_args = 0..=1,
+        default_missing_value = "20",
+        value_parser = clap::value_parser!(u16).range(1..),
+        help = "Remind to look away from the screen every so many minutes of work (default 20), without touching the pomodoro cycles"
+    )]
+    pub micro_break: Option<u16>,
+
+    /// How long a micro-break lasts, in seconds
+    #[arg(
+        long = "micro-break-length",
+        value_name = "seconds",
+        default_value_t = MICRO_BREAK_LENGTH,
+        value_parser = clap::value_parser!(u16).range(1..),
+        help = "How long a micro-break lasts, in seconds"
+    )]
+    pub micro_break_length: u16,
+
+    /// Percentage of a work cycle at which to give a pacing cue, may be repeated
+    #[arg(
+        long = "checkpoint",
+        value_name = "percent",
+        value_parser = clap::value_parser!(u8).range(1..100),
+        help = "Give a quiet cue once this percentage of a work cycle has passed, e.g. 50. May be repeated"
+    )]
+    pub checkpoints: Vec<u8>,
+
+    /// Sound to play at a checkpoint
+    #[arg(
+        long = "checkpoint-sound",
+        value_name = "value",
+        value_parser = validate_sound_file_path,
+        help = "Sound to play at a --checkpoint. Omit for silence."
+    )]
+    pub checkpoint_sound: Option<String>,
+
+    /// A recurring reminder, may be repeated
+    #[arg(
+        long = "reminder",
+        value_name = "name:every[:message]",
+        help = "A recurring reminder shown with --with-notifications, e.g. water:45m:Drink some water. The message may use {name} and {every}. May be repeated"
+    )]
+    pub reminders: Vec<ReminderSpec>,
+
+    /// Something to do during breaks, may be repeated
+    #[arg(
+        long = "break-activity",
+        value_name = "activity",
+        help = "Something to do during breaks, e.g. \"stretch\". May be repeated, one is suggested per break in the notification and tooltip"
+    )]
+    pub break_activities: Vec<String>,
+
+    /// How the break activity is picked
+    #[arg(
+        long = "activity-order",
+        value_name = "order",
+        value_enum,
+        default_value_t = ActivityOrder::RoundRobin,
+        help = "How the break activity is picked: in turn (round-robin) or at random"
+    )]
+    pub activity_order: ActivityOrder,
+
+    /// What counts as a completed pomodoro
+    #[arg(
+        long = "count-completed",
+        value_name = "unit",
+        value_enum,
+        default_value_t = CompletionCount::Set,
+        help = "What counts as a completed pomodoro in the tooltip: each set ending with its long break (set), or each work cycle run to its end (work)"
+    )]
+    pub count_completed: CompletionCount,
+
+    /// Shell command to run when a break begins
+    #[arg(
+        long = "on-break-start",
+        value_name = "command",
+        help = "Shell command to run when a break begins, e.g. to dim or warm the screen"
+    )]
+    pub on_break_start: Option<String>,
+
+    /// Shell command to run when a break ends, undoing --on-break-start
+    #[arg(
+        long = "on-break-end",
+        value_name = "command",
+        help = "Shell command to run when a break ends or the module exits mid-break, to undo --on-break-start"
+    )]
+    pub on_break_end: Option<String>,
+
+    /// Tint the screen to this colour temperature during breaks
+    #[arg(
+        long = "break-temperature",
+        value_name = "kelvin",
+        value_parser = clap::value_parser!(u16).range(1000..=25000),
+        help = "Tint the screen to this colour temperature during breaks using gammastep or wlsunset, e.g. 3500"
+    )]
+    pub break_temperature: Option<u16>,
+
+    /// Program --break-temperature tints the screen with
+    #[arg(
+        long = "break-temperature-tool",
+        value_name = "tool",
+        value_enum,
+        default_value_t = TintTool::Gammastep,
+        help = "Program --break-temperature tints the screen with. wlsunset is killed when the break ends, so don't use it if wlsunset also runs for the night"
+    )]
+    pub break_temperature_tool: TintTool,
+
+    /// Show a Hyprland special workspace during breaks
+    #[arg(
+        long = "break-workspace",
+        value_name = "name",
+        help = "Show this Hyprland special workspace during breaks, e.g. one holding a break checklist, and hide it when the break ends"
+    )]
+    pub break_workspace: Option<String>,
+
+    /// Run the timer faster than real time to try out a configuration
+    #[arg(
+        long = "simulate",
+        value_name = "speed",
+        num_args = 0..=1,
+        default_missing_value = "60",
+        value_parser = clap::value_parser!(u16).range(1..),
+        help = "Run the timer this many times faster than real time (default 60) to try out a configuration. Disables --persist"
+    )]
+    pub simulate: Option<u16>,
+
     /// Enable logging to file or journald
     #[arg(long = "log", value_name = "destination", num_args = 0..=1, default_missing_value = "journald", help = "Enable logging. Optionally specify a log file path. If no path is provided, logs to journald")]
     pub log: Option<LogOption>,
 
+    /// Line format for file logging
+    #[arg(
+        long = "log-format",
+        value_name = "format",
+        value_enum,
+        default_value_t = LogFormat::Text,
+        help = "Line format for file logging. json writes one machine-parseable object per line"
+    )]
+    pub log_format: LogFormat,
+
+    /// When to start a new log file
+    #[arg(
+        long = "log-rotation",
+        value_name = "rotation",
+        default_value = "daily",
+        help = "When to start a new log file: hourly, daily, never, or once it reaches a size like 10M"
+    )]
+    pub log_rotation: LogRotation,
+
+    /// How many rotated log files to keep
+    #[arg(
+        long = "log-keep",
+        value_name = "count",
+        default_value_t = 7,
+        help = "How many rotated log files to keep, older ones are deleted"
+    )]
+    pub log_keep: usize,
+
+    /// Take over from a module already running with the same instance number
+    #[arg(
+        long = "replace",
+        help = "Take over from a module already running with the same instance number instead of exiting"
+    )]
+    pub replace: bool,
+
+    /// Command SIGUSR1 sends the module
+    #[arg(
+        long = "on-sigusr1",
+        value_name = "command",
+        default_value = "toggle",
+        value_parser = validate_signal_command,
+        help = "Command to run on SIGUSR1, as sent by ctl, e.g. pkill -USR1 waybar-module-pomodoro to toggle. none ignores the signal"
+    )]
+    pub on_sigusr1: String,
+
+    /// Command SIGUSR2 sends the module
+    #[arg(
+        long = "on-sigusr2",
+        value_name = "command",
+        default_value = "next-state",
+        value_parser = validate_signal_command,
+        help = "Command to run on SIGUSR2, as sent by ctl. none ignores the signal"
+    )]
+    pub on_sigusr2: String,
+
     /// Specify instance number (defaults to next available)
     #[arg(short = 'i', long = "instance", value_name = "NUM")]
     pub instance: Option<u16>,
+
+    /// Session the module's socket belongs to
+    #[arg(
+        long = "session",
+        value_name = "name",
+        env = "WAYBAR_POMODORO_SESSION",
+        help = "Session the module belongs to, instances are numbered per session. default: $WAYLAND_DISPLAY, or the login session id"
+    )]
+    pub session: Option<String>,
+
+    /// Config file with the same options as the command line
+    #[arg(
+        long = "config",
+        value_name = "path",
+        env = "WAYBAR_POMODORO_CONFIG",
+        help = "Config file holding options by their long name, e.g. work = 30, with [instance.<number>] and [instance.<name>] sections overriding them per instance. default: $XDG_CONFIG_HOME/waybar-module-pomodoro/config.toml"
+    )]
+    pub config: Option<PathBuf>,
+
+    /// Name picking the module's [instance.<name>] section of the config file
+    #[arg(
+        long = "name",
+        value_name = "name",
+        help = "Name picking the module's [instance.<name>] section of the config file, e.g. coding"
+    )]
+    pub name: Option<String>,
+
+    /// Groups ctl --group can address the module by
+    #[arg(
+        long = "group",
+        value_name = "name",
+        help = "Group ctl --group can address the module by, e.g. monitors for the timers mirrored on each bar. May be repeated"
+    )]
+    pub group: Vec<String>,
 }
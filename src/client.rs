@@ -0,0 +1,266 @@
+//! Controlling running modules from other programs, the way `waybar-module-pomodoro-ctl`
+//! does
+//!
+//! ```no_run
+//! use waybar_module_pomodoro::{client::PomodoroClient, models::message::Message};
+//!
+//! for module in PomodoroClient::discover().unwrap() {
+//!     module.send(&Message::Toggle).unwrap();
+//! }
+//! ```
+
+use std::{
+    io::{self, BufRead, BufReader, Write},
+    net::Shutdown,
+    os::unix::net::UnixStream,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use serde_json::Value;
+
+use crate::{
+    models::message::{Message, Response},
+    services::{
+        module::{get_existing_sockets, send_message_retrying, socket_number},
+        session,
+    },
+    utils::consts::CLIENT_READ_TIMEOUT,
+};
+
+const MODULE: &str = env!("CARGO_PKG_NAME");
+
+/// One running module, reached through its socket
+#[derive(Debug, Clone, PartialEq)]
+pub struct PomodoroClient {
+    socket: PathBuf,
+    timeout: Duration,
+    retries: u32,
+}
+
+impl PomodoroClient {
+    pub fn new(socket: impl Into<PathBuf>) -> Self {
+        Self {
+            socket: socket.into(),
+            timeout: CLIENT_READ_TIMEOUT,
+            retries: 0,
+        }
+    }
+
+    /// How long to wait for the module's reply, 2s by default
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// How many more times to try connecting to a module that isn't accepting
+    /// connections, none by default
+    pub fn with_retries(mut self, retries: u32) -> Self {
+        self.retries = retries;
+        self
+    }
+
+    /// Every module of the current desktop session, or of the only session running any,
+    /// lowest instance first
+    pub fn discover() -> Result<Vec<Self>, String> {
+        Self::discover_in(MODULE, session::current())
+    }
+
+    /// Every module of `binary_name` in the `preferred` session, or in the only session
+    /// running any
+    pub fn discover_in(binary_name: &str, preferred: Option<String>) -> Result<Vec<Self>, String> {
+        let session = session::resolve(preferred, session::with_sockets(binary_name))?;
+        let mut modules: Vec<_> = get_existing_sockets(binary_name, session.as_deref())
+            .into_iter()
+            .map(Self::new)
+            .collect();
+        modules.sort_by_key(Self::instance);
+        Ok(modules)
+    }
+
+    pub fn socket(&self) -> &Path {
+        &self.socket
+    }
+
+    /// The module's instance number, read from the socket's name
+    pub fn instance(&self) -> Option<u16> {
+        self.socket
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .and_then(socket_number)
+    }
+
+    /// The module's --name, `None` without one or for modules too old to say
+    pub fn name(&self) -> io::Result<Option<String>> {
+        let ping = self.request(&Message::Ping)?.unwrap_or_default();
+        Ok(ping["name"].as_str().map(str::to_string))
+    }
+
+    /// The groups the module was given with --group, none for modules too old to have any
+    pub fn groups(&self) -> io::Result<Vec<String>> {
+        let ping = self.request(&Message::Ping)?.unwrap_or_default();
+        Ok(serde_json::from_value(ping["groups"].clone()).unwrap_or_default())
+    }
+
+    /// Sends `message` and returns the module's reply, an error of kind `InvalidData` if
+    /// the reply can't be read, as with modules too old to reply
+    pub fn send(&self, message: &Message) -> io::Result<Response> {
+        let reply = send_message_retrying(
+            &self.socket.to_string_lossy(),
+            &message.encode(),
+            self.timeout,
+            self.retries,
+        )?;
+        Response::decode(&reply).map_err(|e| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Unexpected reply '{reply}': {e}"),
+            )
+        })
+    }
+
+    /// Sends `message` and returns the data the module replied with, the module refusing
+    /// the command being an error
+    pub fn request(&self, message: &Message) -> io::Result<Option<Value>> {
+        match self.send(message)? {
+            Response::Ok { data } => Ok(data),
+            Response::Error { kind, message } => {
+                Err(io::Error::other(format!("{kind:?}: {message}")))
+            }
+        }
+    }
+
+    /// Every line the module renders from now on, the JSON Waybar would read, ending when
+    /// the module exits
+    pub fn subscribe(&self) -> io::Result<impl Iterator<Item = io::Result<String>>> {
+        let mut stream = UnixStream::connect(&self.socket)?;
+        stream.write_all(Message::Subscribe.encode().as_bytes())?;
+        stream.shutdown(Shutdown::Write)?;
+        Ok(BufReader::new(stream).lines())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::message::ErrorKind;
+    use std::{
+        io::{Read, Write},
+        os::unix::net::UnixListener,
+        thread,
+    };
+    use tempfile::tempdir;
+
+    /// Serves one connection, answering with `reply` whatever was asked
+    fn serve(listener: UnixListener, reply: Response) -> thread::JoinHandle<String> {
+        thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = String::new();
+            stream.read_to_string(&mut request).unwrap();
+            stream.write_all(reply.encode().as_bytes()).unwrap();
+            request
+        })
+    }
+
+    #[test]
+    fn test_request() {
+        let dir = tempdir().unwrap();
+        let socket = dir.path().join("module3.socket");
+        let listener = UnixListener::bind(&socket).unwrap();
+        let module = PomodoroClient::new(&socket);
+        assert_eq!(module.instance(), Some(3));
+
+        let server = serve(
+            listener.try_clone().unwrap(),
+            Response::with_data(serde_json::json!({"seconds": 90})),
+        );
+        let data = module.request(&Message::Remaining).unwrap().unwrap();
+        assert_eq!(data["seconds"], 90);
+        assert_eq!(server.join().unwrap(), r#""remaining""#);
+
+        let server = serve(
+            listener,
+            Response::error(
+                ErrorKind::InvalidValue,
+                "A sprint needs at least one pomodoro",
+            ),
+        );
+        let error = module
+            .request(&Message::Sprint { pomodoros: 0 })
+            .unwrap_err();
+        assert!(error.to_string().contains("at least one pomodoro"));
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn test_subscribe() {
+        let dir = tempdir().unwrap();
+        let socket = dir.path().join("module0.socket");
+        let listener = UnixListener::bind(&socket).unwrap();
+        let server = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = String::new();
+            stream.read_to_string(&mut request).unwrap();
+            stream
+                .write_all(b"{\"text\": \"24:59\"}\n{\"text\": \"24:58\"}\n")
+                .unwrap();
+            request
+        });
+
+        let lines: Vec<String> = PomodoroClient::new(&socket)
+            .subscribe()
+            .unwrap()
+            .map(Result::unwrap)
+            .collect();
+        assert_eq!(lines, [r#"{"text": "24:59"}"#, r#"{"text": "24:58"}"#]);
+        assert_eq!(server.join().unwrap(), r#""subscribe""#);
+    }
+
+    #[test]
+    fn test_retries() {
+        let dir = tempdir().unwrap();
+        let socket = dir.path().join("module0.socket");
+        let module = PomodoroClient::new(&socket)
+            .with_timeout(Duration::from_millis(500))
+            .with_retries(3);
+        assert_eq!(
+            PomodoroClient::new(&socket)
+                .send(&Message::Ping)
+                .unwrap_err()
+                .kind(),
+            io::ErrorKind::NotFound
+        );
+
+        // the module comes up while the client backs off
+        let server = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(150));
+            serve(UnixListener::bind(&socket).unwrap(), Response::ok())
+                .join()
+                .unwrap()
+        });
+        assert_eq!(module.send(&Message::Ping).unwrap(), Response::ok());
+        assert_eq!(server.join().unwrap(), r#""ping""#);
+    }
+
+    #[test]
+    fn test_groups() {
+        let dir = tempdir().unwrap();
+        let socket = dir.path().join("module0.socket");
+        let listener = UnixListener::bind(&socket).unwrap();
+        let module = PomodoroClient::new(&socket);
+
+        let server = serve(
+            listener.try_clone().unwrap(),
+            Response::with_data(serde_json::json!({"instance": 0, "groups": ["monitors"]})),
+        );
+        assert_eq!(module.groups().unwrap(), ["monitors"]);
+        assert_eq!(server.join().unwrap(), r#""ping""#);
+
+        let server = serve(
+            listener,
+            Response::with_data(serde_json::json!({"instance": 0})),
+        );
+        assert!(module.groups().unwrap().is_empty());
+        server.join().unwrap();
+    }
+}
@@ -1,6 +1,17 @@
-use crate::models::message::{Message, TimeValue};
-use crate::services::timer::CycleType;
-use clap::{Parser, Subcommand};
+use crate::models::message::{
+    AutoTarget, ClockTime, DurationValue, IconKind, LogLevel, Message, ScrollDirection,
+    SeekPosition, Switch, TimeValue,
+};
+use crate::services::{config_file, import::ImportSource, report::ReportFormat, timer::CycleType};
+use clap::{Parser, Subcommand, ValueEnum};
+use std::{
+    env, fmt,
+    io::{self, Write},
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+    str::FromStr,
+};
+use toml::Table;
 
 #[derive(Parser)]
 #[command(name = "waybar-module-pomodoro-ctl")]
@@ -8,9 +19,55 @@ use clap::{Parser, Subcommand};
 #[command(long_about = None)]
 #[command(version)]
 pub struct ControlCli {
-    /// Target a specific instance number (e.g., 0, 1, 2)
-    #[arg(short = 'i', long = "instance", value_name = "NUM")]
-    pub instance: Option<u16>,
+    /// Target a specific instance by number (e.g., 0, 1, 2) or by its --name
+    #[arg(short = 'i', long = "instance", value_name = "NUM|name")]
+    pub instance: Option<Target>,
+
+    /// Target the instances started with this --group
+    #[arg(short = 'g', long = "group", value_name = "name")]
+    pub group: Option<String>,
+
+    /// Target every running instance, instead of the default one
+    #[arg(long = "all", conflicts_with_all = ["instance", "group"])]
+    pub all: bool,
+
+    /// How long to wait for a module's reply, in milliseconds
+    #[arg(
+        long = "timeout",
+        value_name = "ms",
+        default_value_t = 2000,
+        value_parser = clap::value_parser!(u64).range(1..),
+        global = true
+    )]
+    pub timeout: u64,
+
+    /// How many more times to try reaching a module that isn't accepting connections,
+    /// waiting twice as long each time from 100ms
+    #[arg(
+        long = "retries",
+        value_name = "count",
+        default_value_t = 2,
+        global = true
+    )]
+    pub retries: u32,
+
+    /// Read history and stats from a directory synced between machines
+    #[arg(
+        long = "sync-dir",
+        value_name = "path",
+        env = "WAYBAR_POMODORO_SYNC_DIR",
+        global = true
+    )]
+    pub sync_dir: Option<PathBuf>,
+
+    /// Talk to the modules of this session instead of the current one
+    #[arg(
+        long = "session",
+        value_name = "name",
+        env = "WAYBAR_POMODORO_SESSION",
+        global = true
+    )]
+    pub session: Option<String>,
 
     #[command(subcommand)]
     pub operation: Operation,
@@ -27,33 +84,712 @@ pub enum Operation {
     /// Reset timer to initial state
     Reset,
     /// Set new work time [supports: 25, 5+, 3-]
-    SetWork { value: TimeValue },
+    SetWork {
+        value: TimeValue,
+        /// Reset the timer too instead of keeping the running cycle's progress
+        #[arg(long = "reset")]
+        reset: bool,
+    },
     /// Set new short break time [supports: 5, 2+, 1-]
-    SetShort { value: TimeValue },
+    SetShort {
+        value: TimeValue,
+        /// Reset the timer too instead of keeping the running cycle's progress
+        #[arg(long = "reset")]
+        reset: bool,
+    },
     /// Set new long break time [supports: 15, 5+, 2-]
-    SetLong { value: TimeValue },
+    SetLong {
+        value: TimeValue,
+        /// Reset the timer too instead of keeping the running cycle's progress
+        #[arg(long = "reset")]
+        reset: bool,
+    },
     /// Set duration for current timer state [supports: 25, 5+, 3-]
     SetCurrent { value: TimeValue },
     /// Move to the next state (skip current timer)
     NextState,
+    /// Cancel a pending auto-start while its grace countdown is running
+    CancelAuto,
+    /// Pause because the user went idle, e.g. from swayidle
+    Idle,
+    /// Pause because the screen was locked, e.g. from swayidle's lock event
+    Lock,
+    /// Report returning activity, resuming an idle pause within --idle-resume
+    Active,
+    /// Enable or disable desktop notifications [on, off, toggle]
+    Notifications { state: Switch },
+    /// Enable or disable sounds, or stop the one playing [on, off, toggle, stop]
+    Sounds { state: SoundsState },
+    /// Enable or disable auto-starting cycles [work, break, short-break, long-break, work-after-short, work-after-long]
+    SetAuto { cycle: AutoTarget, state: Switch },
+    /// Change an icon [play, pause, work, break, long-break]
+    SetIcon { icon: IconKind, value: String },
+    /// Change the text format [placeholders: {icon}, {time}, {cycle_icon}, {progress}, {focus_today}, {sprint}, {until_long}, {next}]
+    SetFormat { format: String },
+    /// Switch the text between the time left and the time spent in the current cycle
+    ToggleDisplay,
+    /// Change the module's log level [trace, debug, info, warn, error]
+    LogLevel { level: LogLevel },
+    /// Enable or disable the module's logging [on, off, toggle]
+    Logging { state: Switch },
+    /// Print a diagnostic snapshot of the module as JSON
+    Dump,
+    /// Check the module is alive, printing its version, protocol version, uptime and instance
+    Ping,
+    /// Inspect the module's configuration
+    Config {
+        #[command(subcommand)]
+        action: ConfigAction,
+    },
+    /// Print the time left in the current cycle as a bare number of minutes, for scripts
+    Remaining {
+        /// Print seconds instead
+        #[arg(long = "seconds", conflicts_with = "percent")]
+        seconds: bool,
+        /// Print the percentage of the cycle left instead
+        #[arg(long = "percent")]
+        percent: bool,
+    },
+    /// Print a short coloured line like `<icon> 12:34` for a shell prompt, nothing when no
+    /// module runs
+    Prompt {
+        /// Leave out the colour escapes
+        #[arg(long = "no-color")]
+        no_color: bool,
+    },
+    /// Print a `#[fg=...]` coloured line like `<icon> 12:34` for tmux's status-right, nothing
+    /// when no module runs
+    TmuxStatus,
+    /// Print each line the module renders as it does, until it exits, e.g. for eww or scripts
+    Subscribe,
+    /// Skip ahead in the timer, e.g. 90s, 10m or 1h (plain numbers are minutes)
+    FastForward { time: DurationValue },
+    /// Set how far into the current cycle the timer is, e.g. 10:00 when started late, or 50%
+    Seek { to: SeekPosition },
+    /// Run a sprint of this many pomodoros, stopping the timer after the last
+    Sprint {
+        #[arg(value_parser = clap::value_parser!(u16).range(1..))]
+        pomodoros: u16,
+    },
+    /// Run pomodoros back to back until a time of day, e.g. 17:00, cutting the last one short
+    FocusUntil { until: ClockTime },
+    /// Pause until a time of day, e.g. 14:00, then resume where the timer stood, with a
+    /// notification a minute before
+    SnoozeUntil { until: ClockTime },
+    /// Lengthen or shorten the current cycle, for Waybar's on-scroll-up/down [up, down]
+    Scroll {
+        direction: ScrollDirection,
+        /// Minutes to move by
+        #[arg(long = "step", default_value_t = 1)]
+        step: u16,
+    },
+    /// Set the task being worked on, optionally with an estimate in pomodoros
+    SetTask {
+        name: String,
+        #[arg(long = "estimate", value_name = "pomodoros")]
+        estimate: Option<u16>,
+    },
+    /// Clear the current task
+    ClearTask,
+    /// Add a note on what the current work cycle is spent on, kept in the history
+    Annotate { note: String },
+    /// Enable or disable a --reminder by name [on, off, toggle]
+    Reminder { name: String, state: Switch },
+    /// Go back to the cycle a reset or restart cut short, as recorded by --history
+    ResumeLast,
+    /// Save the module's timer, task and stats to a file
+    ExportState { path: PathBuf },
+    /// Restore the module's timer, task and stats from a file written by export-state
+    ImportState { path: PathBuf },
+    /// Copy the timer of another instance into this one, e.g. after starting a second bar
+    Mirror {
+        /// Instance to copy from
+        from: u16,
+    },
+    /// Summarise the history log as Markdown or HTML, for reviews and standups
+    Report {
+        #[arg(long = "export", value_enum, default_value_t = ReportFormat::Md)]
+        format: ReportFormat,
+        /// How many days back to cover, including today. default: 7, or a year for --heatmap
+        #[arg(long = "days", value_parser = clap::value_parser!(u32).range(1..))]
+        days: Option<u32>,
+        /// Write a calendar of pomodoros per day as an SVG to this file instead
+        #[arg(long = "heatmap", value_name = "path", conflicts_with_all = ["format", "path"])]
+        heatmap: Option<PathBuf>,
+        /// File to write, standard output if omitted
+        path: Option<PathBuf>,
+    },
+    /// Write the completed work cycles in the history log as calendar events
+    Export {
+        /// iCalendar file to write, for importing into a calendar app
+        #[arg(long = "ics", value_name = "path")]
+        ics: PathBuf,
+        /// Only cover this many days back, including today, instead of the whole log
+        #[arg(long = "days", value_parser = clap::value_parser!(u32).range(1..))]
+        days: Option<u32>,
+    },
+    /// Add the cycles logged by another pomodoro timer to the history
+    Import {
+        #[arg(long = "from", value_enum)]
+        from: ImportSource,
+        /// The timer's log, GNOME Pomodoro's database by default
+        path: Option<PathBuf>,
+    },
+    /// List unlocked and remaining achievements
+    Achievements,
+    /// Print the JSON Schema of the socket protocol, for clients in other languages
+    Schema,
+    /// Pick an action from a rofi/fuzzel/wofi menu, e.g. for Waybar's on-click-right
+    Menu {
+        /// Menu program to use, defaults to the first one installed
+        #[arg(long = "launcher", value_enum)]
+        launcher: Option<MenuLauncher>,
+    },
+    /// Write a menu file for Waybar's native right-click menu and print the config to use it
+    WaybarMenu {
+        /// Where to write the menu definition, defaults to waybar/pomodoro-menu.xml in the
+        /// config directory
+        path: Option<PathBuf>,
+    },
+    /// Run an entry of the menu by id, used by the generated Waybar menu
+    MenuAction { id: String },
 }
 
-impl Operation {
-    pub fn to_message(&self) -> Message {
+impl ControlCli {
+    /// The instance to address: --instance, else the default target, which --group and
+    /// --all leave out
+    pub fn target(&self) -> Option<Target> {
+        if self.instance.is_some() || self.group.is_some() || self.all {
+            return self.instance.clone();
+        }
+        let file = config_path().and_then(|path| {
+            config_file::load(&path)
+                .inspect_err(|e| tracing::warn!("{e}"))
+                .ok()
+                .flatten()
+        });
+        default_target(env::var("POMODORO_DEFAULT_INSTANCE").ok(), file.as_ref())
+    }
+}
+
+/// The module's config file: `WAYBAR_POMODORO_CONFIG`, else the default one
+pub fn config_path() -> Option<PathBuf> {
+    env::var_os("WAYBAR_POMODORO_CONFIG")
+        .map(PathBuf::from)
+        .or_else(config_file::default_path)
+}
+
+/// The instance ctl addresses without --instance: `POMODORO_DEFAULT_INSTANCE`, else
+/// `instance` under `[ctl]` in the config file
+pub fn default_target(var: Option<String>, file: Option<&Table>) -> Option<Target> {
+    if let Some(target) = var.and_then(|var| var.parse().ok()) {
+        return Some(target);
+    }
+    match file?.get("ctl")?.get("instance")? {
+        toml::Value::Integer(number) => u16::try_from(*number).ok().map(Target::Number),
+        toml::Value::String(name) => name.parse().ok(),
+        _ => None,
+    }
+}
+
+/// An instance, picked by its number or by the module's --name
+#[derive(Debug, Clone, PartialEq)]
+pub enum Target {
+    Number(u16),
+    Name(String),
+}
+
+impl FromStr for Target {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.parse() {
+            Ok(number) => Ok(Target::Number(number)),
+            Err(_) if s.is_empty() => Err("The instance needs a number or a name".to_string()),
+            Err(_) => Ok(Target::Name(s.to_string())),
+        }
+    }
+}
+
+impl fmt::Display for Target {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
+            Target::Number(number) => write!(f, "{number}"),
+            Target::Name(name) => f.write_str(name),
+        }
+    }
+}
+
+impl Operation {
+    /// Whether `--reset` asked for the timer to be reset after the operation's message
+    pub fn resets(&self) -> bool {
+        matches!(
+            self,
+            Operation::SetWork { reset: true, .. }
+                | Operation::SetShort { reset: true, .. }
+                | Operation::SetLong { reset: true, .. }
+        )
+    }
+
+    /// The message to send for this operation, `None` for ones handled by the client itself
+    pub fn to_message(&self) -> Option<Message> {
+        let message = match self {
             Operation::Toggle => Message::Toggle,
             Operation::Start => Message::Start,
             Operation::Stop => Message::Stop,
             Operation::Reset => Message::Reset,
-            Operation::SetWork { value } => time_value_to_message(value, Some(CycleType::Work)),
-            Operation::SetShort { value } => {
+            Operation::SetWork { value, .. } => time_value_to_message(value, Some(CycleType::Work)),
+            Operation::SetShort { value, .. } => {
                 time_value_to_message(value, Some(CycleType::ShortBreak))
             }
-            Operation::SetLong { value } => {
+            Operation::SetLong { value, .. } => {
                 time_value_to_message(value, Some(CycleType::LongBreak))
             }
             Operation::SetCurrent { value } => time_value_to_message(value, None),
             Operation::NextState => Message::NextState,
+            Operation::CancelAuto => Message::CancelAuto,
+            Operation::Idle => Message::Idle,
+            Operation::Lock => Message::Lock,
+            Operation::Active => Message::Active,
+            Operation::Notifications { state } => Message::Notifications { state: *state },
+            Operation::Sounds {
+                state: SoundsState::Switch(state),
+            } => Message::Sounds { state: *state },
+            Operation::Sounds {
+                state: SoundsState::Stop,
+            } => Message::StopSound,
+            Operation::SetAuto { cycle, state } => Message::SetAuto {
+                cycle: *cycle,
+                state: *state,
+            },
+            Operation::SetIcon { icon, value } => Message::SetIcon {
+                icon: *icon,
+                value: value.clone(),
+            },
+            Operation::SetFormat { format } => Message::SetFormat {
+                format: format.clone(),
+            },
+            Operation::ToggleDisplay => Message::ToggleDisplay,
+            Operation::LogLevel { level } => Message::LogLevel { level: *level },
+            Operation::Logging { state } => Message::Logging { state: *state },
+            Operation::Dump => Message::Dump,
+            Operation::Ping => Message::Ping,
+            Operation::Config {
+                action: ConfigAction::Show { .. },
+            } => Message::ShowConfig,
+            Operation::Config {
+                action: ConfigAction::Set { .. },
+            } => return None,
+            Operation::Remaining { .. } | Operation::Prompt { .. } | Operation::TmuxStatus => {
+                Message::Remaining
+            }
+            Operation::FastForward { time } => Message::FastForward { time: *time },
+            Operation::Seek { to } => Message::Seek { to: *to },
+            Operation::Sprint { pomodoros } => Message::Sprint {
+                pomodoros: *pomodoros,
+            },
+            Operation::FocusUntil { until } => Message::FocusUntil { until: *until },
+            Operation::SnoozeUntil { until } => Message::SnoozeUntil { until: *until },
+            Operation::Scroll { direction, step } => Message::Scroll {
+                direction: *direction,
+                step: *step,
+            },
+            Operation::SetTask { name, estimate } => Message::SetTask {
+                name: name.clone(),
+                estimate: *estimate,
+            },
+            Operation::ClearTask => Message::ClearTask,
+            Operation::ResumeLast => Message::ResumeLast,
+            Operation::Annotate { note } => Message::Annotate { note: note.clone() },
+            Operation::Reminder { name, state } => Message::Reminder {
+                name: name.clone(),
+                state: *state,
+            },
+            Operation::Menu { .. }
+            | Operation::WaybarMenu { .. }
+            | Operation::MenuAction { .. }
+            | Operation::ExportState { .. }
+            | Operation::ImportState { .. }
+            | Operation::Mirror { .. }
+            | Operation::Report { .. }
+            | Operation::Export { .. }
+            | Operation::Import { .. }
+            | Operation::Achievements
+            | Operation::Schema
+            | Operation::Subscribe => return None,
+        };
+        Some(message)
+    }
+}
+
+#[derive(Subcommand, Clone)]
+pub enum ConfigAction {
+    /// Print the configuration in effect, after defaults, the config file and the command
+    /// line, to find out why an option isn't taking effect
+    Show {
+        #[arg(long = "format", value_enum, default_value_t = ConfigFormat::Toml)]
+        format: ConfigFormat,
+    },
+    /// Change a setting of the running module [work, shortbreak, longbreak, format, play,
+    /// pause, work-icon, break-icon, long-break-icon]
+    Set {
+        key: String,
+        value: String,
+        /// Write the setting to the config file too, under the --instance's section if given
+        #[arg(long = "save")]
+        save: bool,
+    },
+}
+
+/// A setting `config set` changes, by its config file key
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConfigChange {
+    pub key: &'static str,
+    pub value: toml::Value,
+}
+
+impl ConfigChange {
+    /// Reads `key` as a config file key or a `config show` field, e.g. `work` or
+    /// `work_time`, times being in minutes
+    pub fn parse(key: &str, value: &str) -> Result<Self, String> {
+        let key = match key.replace('_', "-").as_str() {
+            "work" | "work-time" => "work",
+            "shortbreak" | "short-break" | "short-break-time" => "shortbreak",
+            "longbreak" | "long-break" | "long-break-time" => "longbreak",
+            "format" => "format",
+            "play" | "play-icon" => "play",
+            "pause" | "pause-icon" => "pause",
+            "work-icon" => "work-icon",
+            "break-icon" => "break-icon",
+            "long-break-icon" => "long-break-icon",
+            _ => {
+                return Err(format!(
+                "{key} can't be changed on a running module, edit the config file and restart it"
+            ))
+            }
+        };
+        let value = match key {
+            "work" | "shortbreak" | "longbreak" => value
+                .parse::<u16>()
+                .map(|minutes| toml::Value::Integer(minutes.into()))
+                .map_err(|_| format!("{key} takes a number of minutes, not {value}"))?,
+            _ => toml::Value::String(value.to_string()),
+        };
+        Ok(Self { key, value })
+    }
+
+    /// The message making the change on a running module
+    pub fn message(&self) -> Message {
+        let minutes = || TimeValue::Set(self.value.as_integer().unwrap_or_default() as u16);
+        let text = || self.value.as_str().unwrap_or_default().to_string();
+        let icon = |icon| Message::SetIcon {
+            icon,
+            value: text(),
+        };
+        match self.key {
+            "work" => Message::SetWork { time: minutes() },
+            "shortbreak" => Message::SetShort { time: minutes() },
+            "longbreak" => Message::SetLong { time: minutes() },
+            "format" => Message::SetFormat { format: text() },
+            "play" => icon(IconKind::Play),
+            "pause" => icon(IconKind::Pause),
+            "work-icon" => icon(IconKind::Work),
+            "break-icon" => icon(IconKind::Break),
+            _ => icon(IconKind::LongBreak),
+        }
+    }
+}
+
+/// How `config show` prints the configuration
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum ConfigFormat {
+    Toml,
+    Json,
+}
+
+/// `config` as TOML, leaving out the options that aren't set, which TOML has no value for
+pub fn config_toml(config: &serde_json::Value) -> Result<String, String> {
+    fn strip_nulls(value: &serde_json::Value) -> serde_json::Value {
+        match value {
+            serde_json::Value::Object(map) => map
+                .iter()
+                .filter(|(_, value)| !value.is_null())
+                .map(|(key, value)| (key.clone(), strip_nulls(value)))
+                .collect(),
+            serde_json::Value::Array(values) => values.iter().map(strip_nulls).collect(),
+            value => value.clone(),
+        }
+    }
+    toml::to_string(&strip_nulls(config)).map_err(|e| format!("Cannot print the config: {e}"))
+}
+
+/// dmenu-style programs `menu` can drive
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum MenuLauncher {
+    Rofi,
+    Fuzzel,
+    Wofi,
+}
+
+impl MenuLauncher {
+    const ALL: [MenuLauncher; 3] = [MenuLauncher::Fuzzel, MenuLauncher::Rofi, MenuLauncher::Wofi];
+
+    fn program(self) -> &'static str {
+        match self {
+            MenuLauncher::Rofi => "rofi",
+            MenuLauncher::Fuzzel => "fuzzel",
+            MenuLauncher::Wofi => "wofi",
+        }
+    }
+
+    fn command(self) -> Command {
+        let mut command = Command::new(self.program());
+        match self {
+            MenuLauncher::Rofi => command.args(["-dmenu", "-i", "-p", "pomodoro"]),
+            MenuLauncher::Fuzzel => command.args(["--dmenu", "--prompt", "pomodoro: "]),
+            MenuLauncher::Wofi => command.args(["--dmenu", "--prompt", "pomodoro"]),
+        };
+        command
+    }
+
+    /// The first launcher found on PATH
+    fn detect() -> Option<MenuLauncher> {
+        let path = env::var_os("PATH")?;
+        MenuLauncher::ALL.into_iter().find(|launcher| {
+            env::split_paths(&path).any(|dir| dir.join(launcher.program()).is_file())
+        })
+    }
+}
+
+/// What picking a menu entry does
+#[derive(Debug, PartialEq)]
+pub enum MenuAction {
+    Send(Vec<Message>),
+    /// Ask for a task name with a second menu
+    SetTask,
+}
+
+/// An entry of the `menu` and of the generated Waybar menu
+pub struct MenuEntry {
+    /// Stable name used by `menu-action`
+    pub id: &'static str,
+    pub label: &'static str,
+    pub action: MenuAction,
+}
+
+/// Menu entries and what each one does
+pub fn menu_entries() -> Vec<MenuEntry> {
+    let entry = |id, label, action| MenuEntry { id, label, action };
+    let preset = |work: u16, short: u16, long: u16| {
+        MenuAction::Send(vec![
+            Message::SetWork {
+                time: TimeValue::Set(work),
+            },
+            Message::SetShort {
+                time: TimeValue::Set(short),
+            },
+            Message::SetLong {
+                time: TimeValue::Set(long),
+            },
+        ])
+    };
+
+    vec![
+        entry(
+            "toggle",
+            "Start / pause",
+            MenuAction::Send(vec![Message::Toggle]),
+        ),
+        entry(
+            "skip",
+            "Skip to the next cycle",
+            MenuAction::Send(vec![Message::NextState]),
+        ),
+        entry(
+            "add-5",
+            "Add 5 minutes",
+            MenuAction::Send(vec![Message::SetCurrent {
+                time: TimeValue::Add(5),
+            }]),
+        ),
+        entry("reset", "Reset", MenuAction::Send(vec![Message::Reset])),
+        entry("set-task", "Set task…", MenuAction::SetTask),
+        entry(
+            "clear-task",
+            "Clear task",
+            MenuAction::Send(vec![Message::ClearTask]),
+        ),
+        entry("preset-25", "Preset: 25/5/15", preset(25, 5, 15)),
+        entry("preset-50", "Preset: 50/10/30", preset(50, 10, 30)),
+        entry("preset-15", "Preset: 15/3/10", preset(15, 3, 10)),
+    ]
+}
+
+/// The messages of the entry called `id`, for `menu-action`
+pub fn menu_action(id: &str) -> io::Result<Vec<Message>> {
+    let entry = menu_entries().into_iter().find(|entry| entry.id == id);
+    match entry.map(|entry| entry.action) {
+        Some(MenuAction::Send(messages)) => Ok(messages),
+        // Waybar menus can't take text input
+        Some(MenuAction::SetTask) | None => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("Unknown menu action '{id}'"),
+        )),
+    }
+}
+
+/// Entries a Waybar menu can offer, leaving out those that need typing
+fn waybar_menu_entries() -> impl Iterator<Item = MenuEntry> {
+    menu_entries()
+        .into_iter()
+        .filter(|entry| matches!(entry.action, MenuAction::Send(_)))
+}
+
+/// GtkBuilder definition for Waybar's `menu-file`
+pub fn waybar_menu_xml() -> String {
+    let mut xml = String::from(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<interface>\n  <object class=\"GtkMenu\" id=\"menu\">\n",
+    );
+    for entry in waybar_menu_entries() {
+        xml.push_str(&format!(
+            "    <child>\n      <object class=\"GtkMenuItem\" id=\"{}\">\n        \
+             <property name=\"label\">{}</property>\n      </object>\n    </child>\n",
+            entry.id,
+            entry.label.replace('&', "&amp;").replace('<', "&lt;")
+        ));
+    }
+    xml.push_str("  </object>\n</interface>\n");
+    xml
+}
+
+/// The `menu`, `menu-file` and `menu-actions` settings for the module's Waybar config,
+/// with actions running `ctl`
+pub fn waybar_menu_config(menu_file: &Path, ctl: &str) -> serde_json::Value {
+    let actions: serde_json::Map<String, serde_json::Value> = waybar_menu_entries()
+        .map(|entry| {
+            (
+                entry.id.to_string(),
+                format!("{ctl} menu-action {}", entry.id).into(),
+            )
+        })
+        .collect();
+    serde_json::json!({
+        "menu": "on-click-right",
+        "menu-file": menu_file,
+        "menu-actions": actions,
+    })
+}
+
+/// Shows the menu and returns the messages for the chosen entry, `None` if it was dismissed
+pub fn run_menu(launcher: Option<MenuLauncher>) -> io::Result<Option<Vec<Message>>> {
+    let launcher = launcher.or_else(MenuLauncher::detect).ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::NotFound,
+            "No menu program found, install rofi, fuzzel or wofi",
+        )
+    })?;
+
+    let entries = menu_entries();
+    let labels: Vec<&str> = entries.iter().map(|entry| entry.label).collect();
+    let Some(choice) = prompt(launcher, &labels)? else {
+        return Ok(None);
+    };
+
+    match chosen(entries, &choice) {
+        Some(MenuAction::Send(messages)) => Ok(Some(messages)),
+        Some(MenuAction::SetTask) => Ok(prompt(launcher, &[])?.map(|name| {
+            vec![Message::SetTask {
+                name,
+                estimate: None,
+            }]
+        })),
+        None => Ok(None),
+    }
+}
+
+/// What the entry the launcher printed does, `None` for typed text that isn't an entry
+fn chosen(entries: Vec<MenuEntry>, choice: &str) -> Option<MenuAction> {
+    entries
+        .into_iter()
+        .find(|entry| entry.label == choice)
+        .map(|entry| entry.action)
+}
+
+/// Offers `lines` in the launcher and returns what was picked or typed
+fn prompt(launcher: MenuLauncher, lines: &[&str]) -> io::Result<Option<String>> {
+    let mut child = launcher
+        .command()
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()?;
+    {
+        let mut stdin = child.stdin.take().expect("stdin is piped");
+        for line in lines {
+            writeln!(stdin, "{line}")?;
+        }
+    }
+    let output = child.wait_with_output()?;
+
+    let choice = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    Ok(Some(choice).filter(|choice| !choice.is_empty()))
+}
+
+/// Argument of `sounds`, a switch or `stop`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SoundsState {
+    Switch(Switch),
+    Stop,
+}
+
+impl FromStr for SoundsState {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "stop" => Ok(SoundsState::Stop),
+            _ => s.parse().map(SoundsState::Switch).map_err(|_| {
+                format!("Invalid sounds value: {s} (expected on, off, toggle or stop)")
+            }),
+        }
+    }
+}
+
+/// How `prompt` and `tmux-status` colour their line
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SegmentStyle {
+    Plain,
+    /// Terminal escape codes, for shell prompts
+    Ansi,
+    /// `#[fg=...]` markup for tmux's status line
+    Tmux,
+}
+
+/// The `prompt` line for a `remaining` reply: red while working, green on a break and
+/// dimmed while paused
+pub fn prompt_segment(remaining: &serde_json::Value, style: SegmentStyle) -> String {
+    let icon = remaining["icon"].as_str().unwrap_or_default();
+    let time = remaining["time"].as_str().unwrap_or_default();
+    let text = format!("{icon} {time}").trim().to_string();
+    let paused = remaining["running"].as_bool() == Some(false);
+    let working = remaining["cycle"].as_str() == Some("work");
+    match style {
+        SegmentStyle::Plain => text,
+        SegmentStyle::Ansi => {
+            let code = match (paused, working) {
+                (true, _) => "2",
+                (_, true) => "31",
+                _ => "32",
+            };
+            format!("\x1b[{code}m{text}\x1b[0m")
+        }
+        SegmentStyle::Tmux => {
+            let colour = match (paused, working) {
+                (true, _) => "colour244",
+                (_, true) => "red",
+                _ => "green",
+            };
+            // a lone # would start a tmux format
+            format!("#[fg={colour}]{}#[default]", text.replace('#', "##"))
         }
     }
 }
@@ -74,3 +810,189 @@ fn time_value_to_message(value: &TimeValue, cycle_type: Option<CycleType>) -> Me
         },
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_menu_choice() {
+        let labels: Vec<&str> = menu_entries().iter().map(|entry| entry.label).collect();
+        for (i, label) in labels.iter().enumerate() {
+            assert!(
+                !labels[i + 1..].contains(label),
+                "{label} is in the menu twice"
+            );
+        }
+
+        assert_eq!(
+            chosen(menu_entries(), "Start / pause"),
+            Some(MenuAction::Send(vec![Message::Toggle]))
+        );
+        assert_eq!(
+            chosen(menu_entries(), "Set task…"),
+            Some(MenuAction::SetTask)
+        );
+        assert_eq!(
+            chosen(menu_entries(), "Preset: 50/10/30"),
+            Some(MenuAction::Send(vec![
+                Message::SetWork {
+                    time: TimeValue::Set(50)
+                },
+                Message::SetShort {
+                    time: TimeValue::Set(10)
+                },
+                Message::SetLong {
+                    time: TimeValue::Set(30)
+                },
+            ]))
+        );
+        // something typed that isn't an entry
+        assert_eq!(chosen(menu_entries(), "Lunch"), None);
+    }
+
+    #[test]
+    fn test_config_change() {
+        let change = ConfigChange::parse("work_time", "50").unwrap();
+        assert_eq!(change.key, "work");
+        assert_eq!(change.value, toml::Value::Integer(50));
+        assert_eq!(
+            change.message(),
+            Message::SetWork {
+                time: TimeValue::Set(50)
+            }
+        );
+
+        let change = ConfigChange::parse("break-icon", "B").unwrap();
+        assert_eq!(
+            change.message(),
+            Message::SetIcon {
+                icon: IconKind::Break,
+                value: "B".to_string()
+            }
+        );
+
+        assert!(ConfigChange::parse("work", "5+").is_err());
+        assert!(ConfigChange::parse("history", "/tmp/history").is_err());
+    }
+
+    #[test]
+    fn test_config_toml() {
+        let config = serde_json::json!({
+            "work_time": 1500,
+            "work_sound": null,
+            "autosave": {"secs": 30, "nanos": 0},
+            "checkpoints": [25, 75],
+        });
+        let table: Table = config_toml(&config).unwrap().parse().unwrap();
+        assert_eq!(table["work_time"].as_integer(), Some(1500));
+        assert_eq!(table["checkpoints"].as_array().unwrap().len(), 2);
+        assert_eq!(table["autosave"]["secs"].as_integer(), Some(30));
+        assert!(!table.contains_key("work_sound"));
+    }
+
+    #[test]
+    fn test_default_target() {
+        assert_eq!("2".parse(), Ok(Target::Number(2)));
+        assert_eq!("coding".parse(), Ok(Target::Name("coding".to_string())));
+        assert!("".parse::<Target>().is_err());
+
+        let file: Table = "[ctl]\ninstance = 1".parse().unwrap();
+        assert_eq!(default_target(None, None), None);
+        assert_eq!(default_target(None, Some(&file)), Some(Target::Number(1)));
+        assert_eq!(
+            default_target(Some("coding".to_string()), Some(&file)),
+            Some(Target::Name("coding".to_string()))
+        );
+        // an empty variable doesn't hide the config file
+        assert_eq!(
+            default_target(Some(String::new()), Some(&file)),
+            Some(Target::Number(1))
+        );
+
+        let file: Table = "[ctl]\ninstance = \"coding\"".parse().unwrap();
+        assert_eq!(
+            default_target(None, Some(&file)),
+            Some(Target::Name("coding".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_prompt_segment() {
+        let remaining = serde_json::json!({
+            "time": "12:34",
+            "cycle": "work",
+            "icon": "W",
+            "running": true,
+        });
+        assert_eq!(prompt_segment(&remaining, SegmentStyle::Plain), "W 12:34");
+        assert_eq!(
+            prompt_segment(&remaining, SegmentStyle::Ansi),
+            "\x1b[31mW 12:34\x1b[0m"
+        );
+        assert_eq!(
+            prompt_segment(&remaining, SegmentStyle::Tmux),
+            "#[fg=red]W 12:34#[default]"
+        );
+
+        let paused = serde_json::json!({
+            "time": "04:00",
+            "cycle": "short-break",
+            "icon": "",
+            "running": false,
+        });
+        assert_eq!(
+            prompt_segment(&paused, SegmentStyle::Ansi),
+            "\x1b[2m04:00\x1b[0m"
+        );
+        assert_eq!(
+            prompt_segment(&paused, SegmentStyle::Tmux),
+            "#[fg=colour244]04:00#[default]"
+        );
+    }
+
+    #[test]
+    fn test_set_time_reset() {
+        let cli = ControlCli::try_parse_from(["ctl", "set-long", "30"]).unwrap();
+        assert!(!cli.operation.resets());
+        let cli = ControlCli::try_parse_from(["ctl", "set-long", "30", "--reset"]).unwrap();
+        assert!(cli.operation.resets());
+        assert_eq!(
+            cli.operation.to_message(),
+            Some(Message::SetLong {
+                time: TimeValue::Set(30)
+            })
+        );
+    }
+
+    #[test]
+    fn test_sounds_state() {
+        let cli = ControlCli::try_parse_from(["ctl", "sounds", "stop"]).unwrap();
+        assert_eq!(cli.operation.to_message(), Some(Message::StopSound));
+        let cli = ControlCli::try_parse_from(["ctl", "sounds", "off"]).unwrap();
+        assert_eq!(
+            cli.operation.to_message(),
+            Some(Message::Sounds { state: Switch::Off })
+        );
+        assert!(ControlCli::try_parse_from(["ctl", "sounds", "pause"]).is_err());
+    }
+
+    #[test]
+    fn test_waybar_menu_matches_actions() {
+        let xml = waybar_menu_xml();
+        let config = waybar_menu_config(Path::new("/tmp/menu.xml"), "ctl -i 1");
+
+        assert!(xml.contains(r#"<object class="GtkMenuItem" id="toggle">"#));
+        assert!(xml.contains(r#"<property name="label">Start / pause</property>"#));
+        // typing a task name needs a launcher
+        assert!(!xml.contains("set-task"));
+
+        let actions = config["menu-actions"].as_object().unwrap();
+        assert_eq!(actions["toggle"], "ctl -i 1 menu-action toggle");
+        for id in actions.keys() {
+            assert!(xml.contains(&format!(r#"id="{id}""#)));
+            assert!(menu_action(id).is_ok());
+        }
+        assert!(menu_action("set-task").is_err());
+    }
+}
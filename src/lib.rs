@@ -1,5 +1,8 @@
 pub mod cli;
+pub mod client;
 pub mod control_cli;
 pub mod models;
 pub mod services;
+#[cfg(any(test, feature = "test-support"))]
+pub mod test_support;
 pub mod utils;
@@ -1,29 +1,431 @@
 use crate::{
     cli::ModuleCli,
+    models::message::{AutoTarget, IconKind, Switch},
+    services::{reminders::ReminderSpec, sink::OutputFormat, timer::CycleType},
     utils::consts::{
-        BREAK_ICON, LONG_BREAK_TIME, MINUTE, PAUSE_ICON, PLAY_ICON, SHORT_BREAK_TIME, WORK_ICON,
-        WORK_TIME,
+        BREAK_ICON, DEFAULT_FORMAT, LONG_BREAK_ICON, LONG_BREAK_TIME, MAX_MINUTES,
+        MICRO_BREAK_LENGTH, MINUTE, PAUSE_ICON, PLAY_ICON, PROGRESS_WIDTH, SHORT_BREAK_TIME,
+        TICK_RATE, WORK_ICON, WORK_TIME,
     },
 };
-use std::env;
+use clap::ValueEnum;
+use serde::Serialize;
+use std::{env, path::PathBuf, str::FromStr, time::Duration};
 
-#[derive(Debug)]
+/// Built-in icon sets, for users without a patched (nerd) font
+#[derive(Debug, Clone, Copy, PartialEq, Default, ValueEnum)]
+pub enum IconPreset {
+    #[default]
+    NerdFont,
+    Emoji,
+    Ascii,
+}
+
+/// How the break activity is picked from `--break-activity`
+#[derive(Debug, Clone, Copy, PartialEq, Default, ValueEnum, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ActivityOrder {
+    #[default]
+    RoundRobin,
+    Random,
+}
+
+/// What counts as a completed pomodoro in the tooltip
+#[derive(Debug, Clone, Copy, PartialEq, Default, ValueEnum, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum CompletionCount {
+    /// A set of work cycles ending with its long break
+    #[default]
+    Set,
+    /// Every work cycle that ran to its end
+    Work,
+}
+
+/// What saves a persisting module's state between `--autosave` intervals
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum AutosaveEvent {
+    /// A cycle ended and the next one came up
+    Cycle,
+    /// A command arrived over the socket
+    Command,
+}
+
+/// Glyphs drawing the `{progress}` placeholder
+#[derive(Debug, Clone, Copy, PartialEq, Default, ValueEnum, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ProgressGlyphs {
+    /// ▰▰▰▱▱
+    #[default]
+    Bar,
+    /// ███░░
+    Blocks,
+    /// ●●●○○
+    Dots,
+    /// A single glyph filling up: ○◔◑◕●
+    Pie,
+}
+
+impl ProgressGlyphs {
+    /// Draws `fraction` of the cycle done, `width` glyphs wide except for the pie
+    pub fn render(self, fraction: f64, width: u8) -> String {
+        let fraction = fraction.clamp(0.0, 1.0);
+        let (full, empty) = match self {
+            ProgressGlyphs::Bar => ('▰', '▱'),
+            ProgressGlyphs::Blocks => ('█', '░'),
+            ProgressGlyphs::Dots => ('●', '○'),
+            ProgressGlyphs::Pie => {
+                const PIE: [char; 5] = ['○', '◔', '◑', '◕', '●'];
+                // only a finished cycle shows the full circle
+                let step = (fraction * (PIE.len() - 1) as f64).floor() as usize;
+                return PIE[step].to_string();
+            }
+        };
+        let filled = (fraction * width as f64).floor() as usize;
+        let mut bar = full.to_string().repeat(filled);
+        bar.push_str(&empty.to_string().repeat(width as usize - filled));
+        bar
+    }
+}
+
+/// The shortest and longest, in minutes, a cycle may be set to while running
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct DurationBounds {
+    pub min: u16,
+    pub max: u16,
+}
+
+impl Default for DurationBounds {
+    fn default() -> Self {
+        Self {
+            min: 1,
+            max: MAX_MINUTES,
+        }
+    }
+}
+
+impl DurationBounds {
+    /// Checks that `cycle` may last `seconds`
+    pub fn check(self, cycle: CycleType, seconds: i32) -> Result<u16, String> {
+        let min = self.min as i32 * MINUTE as i32;
+        let max = self.max as i32 * MINUTE as i32;
+        if (min..=max).contains(&seconds) {
+            return Ok(seconds as u16);
+        }
+        Err(format!(
+            "Duration out of range: {} minutes of {} (allowed {} to {})",
+            seconds.div_euclid(MINUTE as i32),
+            cycle.name(),
+            self.min,
+            self.max
+        ))
+    }
+}
+
+impl FromStr for DurationBounds {
+    type Err = String;
+
+    /// `min-max`, either side may be left out to keep its default
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("Invalid bounds: {s} (expected min-max in minutes, e.g. 5-90)");
+        let (min, max) = s.split_once('-').ok_or_else(invalid)?;
+        let parse = |value: &str, default: u16| match value.trim() {
+            "" => Ok(default),
+            value => value.parse::<u16>().map_err(|_| invalid()),
+        };
+        let bounds = Self {
+            min: parse(min, Self::default().min)?,
+            max: parse(max, Self::default().max)?,
+        };
+        if bounds.max > MAX_MINUTES {
+            return Err(format!(
+                "Invalid bounds: {s} (at most {MAX_MINUTES} minutes)"
+            ));
+        }
+        if bounds.min > bounds.max {
+            return Err(format!("Invalid bounds: {s} (min is above max)"));
+        }
+        Ok(bounds)
+    }
+}
+
+/// Breaks sized from the work cycle, `short` and `long` divide the work length
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct BreakRatio {
+    pub short: u16,
+    pub long: u16,
+}
+
+impl Default for BreakRatio {
+    fn default() -> Self {
+        Self { short: 5, long: 2 }
+    }
+}
+
+impl BreakRatio {
+    /// Short and long break lengths, in seconds, for `work` seconds of work
+    pub fn breaks(self, work: u16) -> (u16, u16) {
+        (work / self.short, work / self.long)
+    }
+}
+
+impl FromStr for BreakRatio {
+    type Err = String;
+
+    /// `short:long`, e.g. `5:2` for breaks a fifth and half as long as work
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("Invalid break ratio: {s} (expected short:long, e.g. 5:2)");
+        let (short, long) = s.split_once(':').ok_or_else(invalid)?;
+        let parse = |value: &str| match value.trim().parse::<u16>() {
+            Ok(divisor) if divisor > 0 => Ok(divisor),
+            _ => Err(invalid()),
+        };
+        Ok(Self {
+            short: parse(short)?,
+            long: parse(long)?,
+        })
+    }
+}
+
+/// Long breaks that grow with the streak of pomodoros behind them, as steps of
+/// `pomodoros:minutes` sorted by pomodoros
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct LongBreakCurve(pub Vec<(u16, u16)>);
+
+impl LongBreakCurve {
+    /// Seconds of long break after `streak` pomodoros in a row, `base` until the first step
+    pub fn length(&self, streak: u16, base: u16) -> u16 {
+        self.0
+            .iter()
+            .rev()
+            .find(|(pomodoros, _)| streak >= *pomodoros)
+            .map_or(base, |(_, minutes)| minutes * MINUTE)
+    }
+}
+
+impl FromStr for LongBreakCurve {
+    type Err = String;
+
+    /// Comma separated `pomodoros:minutes` steps, e.g. `8:20,12:30`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || {
+            format!(
+                "Invalid long break curve: {s} (expected pomodoros:minutes steps, e.g. 8:20,12:30)"
+            )
+        };
+        let mut steps = s
+            .split(',')
+            .map(|step| {
+                let (pomodoros, minutes) = step.split_once(':').ok_or_else(invalid)?;
+                let pomodoros = pomodoros.trim().parse::<u16>().map_err(|_| invalid())?;
+                match minutes.trim().parse::<u16>() {
+                    Ok(minutes) if (1..=MAX_MINUTES).contains(&minutes) => Ok((pomodoros, minutes)),
+                    _ => Err(invalid()),
+                }
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        steps.sort();
+        Ok(Self(steps))
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct IconSet {
+    pub play: &'static str,
+    pub pause: &'static str,
+    pub work: &'static str,
+    pub short_break: &'static str,
+    pub long_break: &'static str,
+}
+
+impl IconPreset {
+    pub fn icons(self) -> IconSet {
+        match self {
+            IconPreset::NerdFont => IconSet {
+                play: PLAY_ICON,
+                pause: PAUSE_ICON,
+                work: WORK_ICON,
+                short_break: BREAK_ICON,
+                long_break: LONG_BREAK_ICON,
+            },
+            IconPreset::Emoji => IconSet {
+                play: "▶️",
+                pause: "⏸️",
+                work: "🍅",
+                short_break: "☕",
+                long_break: "🌴",
+            },
+            IconPreset::Ascii => IconSet {
+                play: ">",
+                pause: "||",
+                work: "W",
+                short_break: "B",
+                long_break: "LB",
+            },
+        }
+    }
+}
+
+/// A set of cycle transitions, such as the ones that start the next cycle without user
+/// interaction
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize)]
+pub struct Transitions {
+    /// work -> short break
+    pub short_break: bool,
+    /// work -> long break
+    pub long_break: bool,
+    /// short break -> work
+    pub work_after_short: bool,
+    /// long break -> work
+    pub work_after_long: bool,
+}
+
+impl Transitions {
+    pub const ALL: Transitions = Transitions {
+        short_break: true,
+        long_break: true,
+        work_after_short: true,
+        work_after_long: true,
+    };
+
+    pub fn covers(&self, from: CycleType, to: CycleType) -> bool {
+        match (from, to) {
+            (_, CycleType::ShortBreak) => self.short_break,
+            (_, CycleType::LongBreak) => self.long_break,
+            (CycleType::LongBreak, CycleType::Work) => self.work_after_long,
+            (_, CycleType::Work) => self.work_after_short,
+        }
+    }
+
+    pub fn enable(&mut self, target: AutoTarget) {
+        self.switch(target, Switch::On);
+    }
+
+    /// Applies `switch` to every rule covered by `target`, toggling a group sets all of
+    /// its rules to the opposite of whether they are currently all enabled
+    pub fn switch(&mut self, target: AutoTarget, switch: Switch) {
+        let rules: Vec<&mut bool> = match target {
+            AutoTarget::Work => vec![&mut self.work_after_short, &mut self.work_after_long],
+            AutoTarget::Break => vec![&mut self.short_break, &mut self.long_break],
+            AutoTarget::ShortBreak => vec![&mut self.short_break],
+            AutoTarget::LongBreak => vec![&mut self.long_break],
+            AutoTarget::WorkAfterShort => vec![&mut self.work_after_short],
+            AutoTarget::WorkAfterLong => vec![&mut self.work_after_long],
+        };
+        let value = switch.apply(rules.iter().all(|rule| **rule));
+        for rule in rules {
+            *rule = value;
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
 pub struct Config {
     pub work_time: u16,
     pub short_break: u16,
     pub long_break: u16,
+    /// What set and delta commands may change each cycle's duration to, by cycle index
+    pub bounds: [DurationBounds; 3],
+    /// Sizes breaks from the work length whenever it changes, `None` unless --break-ratio
+    pub break_ratio: Option<BreakRatio>,
+    /// Lengthens long breaks after long streaks, `None` unless --adaptive-long-break
+    pub long_break_curve: Option<LongBreakCurve>,
+    /// Work length of each iteration before the long break, empty unless --work-sequence
+    pub work_sequence: Vec<u16>,
+    /// Most seconds each cycle's length is drawn away from its own, 0 unless --jitter
+    pub jitter: u16,
+    /// Pomodoros a day after which nothing starts by itself, `None` unless --daily-cap
+    pub daily_cap: Option<u16>,
     pub no_icons: bool,
     pub no_work_icons: bool,
+    /// Show elapsed rather than remaining time
+    pub count_up: bool,
     pub play_icon: String,
     pub pause_icon: String,
     pub work_icon: String,
     pub break_icon: String,
+    pub long_break_icon: String,
+    pub format: String,
+    /// Template the tooltip is filled from, line by line, `None` for the built-in one
+    pub tooltip_format: Option<String>,
+    pub progress_glyphs: ProgressGlyphs,
+    pub progress_width: u8,
+    /// Characters the text is cut down to, ending in an ellipsis
+    pub max_length: Option<u16>,
+    /// Characters the text is padded out to
+    pub min_length: u16,
     pub work_sound: Option<String>,
     pub break_sound: Option<String>,
-    pub autow: bool,
-    pub autob: bool,
+    /// Percentage other apps are turned down to while a cycle's sound plays
+    pub duck: Option<u8>,
+    pub auto_start: Transitions,
+    /// Seconds to wait before an auto-start, during which it can be cancelled
+    pub auto_start_grace: u16,
     pub persist: bool,
+    /// How long the state may go unsaved, `None` to save it on every tick
+    pub autosave: Option<Duration>,
+    /// Events that save the state before the autosave interval is up
+    pub autosave_on: Vec<AutosaveEvent>,
+    /// Append finished cycles and tasks to the history log
+    pub history: bool,
+    /// Keep the timer in a `state.env` file next to the socket
+    pub state_env: bool,
+    /// What to print on standard output
+    pub output: OutputFormat,
+    /// Attribute work time to the focused app in the history
+    pub track_apps: bool,
+    /// Keep totals in the stats file and unlock achievements
+    pub achievements: bool,
+    /// Directory shared with other machines for state, history and stats
+    pub sync_dir: Option<PathBuf>,
     pub with_notifications: bool,
+    /// Transitions the desktop notifications are sent for
+    pub notify_on: Transitions,
+    pub with_sounds: bool,
+    pub blink: bool,
+    /// Upper bound on how long the module sleeps between renders
+    pub tick_rate: Duration,
+    /// Save power while on battery
+    pub power_save: bool,
+    /// On battery with --power-save, rendering whole minutes
+    pub saving_power: bool,
+    pub stale_after: Option<Duration>,
+    pub stale_tooltip: bool,
+    /// How long after `idle` the `active` command still resumes the timer
+    pub idle_resume: Option<Duration>,
+    /// Pause and mute while a call has the microphone
+    pub call_pause: bool,
+    /// Pause the other instances' work cycles when this one starts working
+    pub exclusive_focus: bool,
+    /// The module's --name, which ctl can address it by
+    pub name: Option<String>,
+    /// Groups the module can be addressed by
+    pub groups: Vec<String>,
+    /// How long a work cycle may sit paused before it is abandoned
+    pub abandon_after: Option<Duration>,
+    /// Hour the day starts at, to reset the timer every day
+    pub day_start: Option<u8>,
+    /// How long a work cycle may sit paused before a reminder is sent
+    pub pause_reminder: Option<Duration>,
+    /// How many times faster than real time the timer runs, 1 outside of `--simulate`
+    pub speed: u16,
+    /// How much work between micro-breaks, `None` when they are off
+    pub micro_break_every: Option<Duration>,
+    pub micro_break_length: Duration,
+    /// Percentages of a work cycle that get a quiet cue
+    pub checkpoints: Vec<u8>,
+    pub checkpoint_sound: Option<String>,
+    /// Recurring reminders running alongside the timer
+    pub reminders: Vec<ReminderSpec>,
+    /// Suggestions for what to do during a break, one picked per break
+    pub break_activities: Vec<String>,
+    pub activity_order: ActivityOrder,
+    pub count_completed: CompletionCount,
+    /// Shell commands run when a break begins and when it ends
+    pub break_start_command: Option<String>,
+    pub break_end_command: Option<String>,
+    /// Hyprland special workspace shown during breaks
+    pub break_workspace: Option<String>,
     pub binary_name: String,
 }
 
@@ -33,23 +435,100 @@ impl Default for Config {
             work_time: Default::default(),
             short_break: Default::default(),
             long_break: Default::default(),
+            bounds: Default::default(),
+            break_ratio: Default::default(),
+            long_break_curve: Default::default(),
+            work_sequence: Default::default(),
+            jitter: Default::default(),
+            daily_cap: Default::default(),
             no_icons: Default::default(),
             no_work_icons: Default::default(),
+            count_up: Default::default(),
             play_icon: PLAY_ICON.to_string(),
             pause_icon: PAUSE_ICON.to_string(),
             work_icon: WORK_ICON.to_string(),
             break_icon: BREAK_ICON.to_string(),
+            long_break_icon: LONG_BREAK_ICON.to_string(),
+            format: DEFAULT_FORMAT.to_string(),
+            tooltip_format: Default::default(),
+            progress_glyphs: Default::default(),
+            progress_width: PROGRESS_WIDTH,
+            max_length: Default::default(),
+            min_length: Default::default(),
             work_sound: Default::default(),
             break_sound: Default::default(),
-            autow: Default::default(),
-            autob: Default::default(),
+            duck: Default::default(),
+            auto_start: Default::default(),
+            auto_start_grace: Default::default(),
             persist: Default::default(),
+            autosave: Default::default(),
+            autosave_on: Default::default(),
+            history: Default::default(),
+            state_env: Default::default(),
+            output: Default::default(),
+            track_apps: Default::default(),
+            achievements: Default::default(),
+            sync_dir: Default::default(),
             with_notifications: Default::default(),
+            notify_on: Transitions::ALL,
+            with_sounds: true,
+            blink: Default::default(),
+            tick_rate: Duration::from_millis(TICK_RATE as u64),
+            power_save: Default::default(),
+            saving_power: Default::default(),
+            stale_after: Default::default(),
+            stale_tooltip: Default::default(),
+            idle_resume: Default::default(),
+            call_pause: Default::default(),
+            exclusive_focus: Default::default(),
+            name: Default::default(),
+            groups: Default::default(),
+            abandon_after: Default::default(),
+            day_start: Default::default(),
+            pause_reminder: Default::default(),
+            speed: 1,
+            micro_break_every: Default::default(),
+            micro_break_length: Duration::from_secs(MICRO_BREAK_LENGTH as u64),
+            checkpoints: Default::default(),
+            checkpoint_sound: Default::default(),
+            reminders: Default::default(),
+            break_activities: Default::default(),
+            activity_order: Default::default(),
+            count_completed: Default::default(),
+            break_start_command: Default::default(),
+            break_end_command: Default::default(),
+            break_workspace: Default::default(),
             binary_name: Default::default(),
         }
     }
 }
 
+fn auto_start_from_cli(cli: &ModuleCli) -> Transitions {
+    let mut auto_start = Transitions::default();
+    if cli.autow {
+        auto_start.enable(AutoTarget::Work);
+    }
+    if cli.autob {
+        auto_start.enable(AutoTarget::Break);
+    }
+    for target in &cli.auto {
+        auto_start.enable(*target);
+    }
+    auto_start
+}
+
+/// Every transition unless --notify-on picks some
+fn notify_on_from_cli(cli: &ModuleCli) -> Transitions {
+    if cli.notify_on.is_empty() {
+        return Transitions::ALL;
+    }
+    let mut notify_on = Transitions::default();
+    for target in &cli.notify_on {
+        notify_on.enable(*target);
+    }
+    notify_on
+}
+
 impl Config {
     pub fn from_module_cli(cli: &ModuleCli) -> Self {
         let binary_name = env::current_exe()
@@ -58,31 +537,125 @@ impl Config {
             .and_then(|s| s.to_str().map(|s| s.to_string()))
             .unwrap_or_else(|| "waybar-module-pomodoro".to_string());
 
+        let icons = cli.icon_preset.icons();
+
+        // explicit commands win over the built-in --break-temperature ones
+        let (temperature_start, temperature_end) = cli
+            .break_temperature
+            .map(|kelvin| cli.break_temperature_tool.commands(kelvin))
+            .unzip();
+
+        let work_time = cli.work.map(|w| w * MINUTE).unwrap_or(WORK_TIME);
+        let long_break = cli.longbreak.map(|l| l * MINUTE).unwrap_or(LONG_BREAK_TIME);
+        let (short_break, long_break) = match cli.break_ratio {
+            Some(ratio) => {
+                let (short, long) = ratio.breaks(work_time);
+                // --longbreak 0 still turns long breaks off
+                (short, if long_break == 0 { 0 } else { long })
+            }
+            None => (
+                cli.shortbreak
+                    .map(|s| s * MINUTE)
+                    .unwrap_or(SHORT_BREAK_TIME),
+                long_break,
+            ),
+        };
+
         let config = Self {
-            work_time: cli.work.map(|w| w * MINUTE).unwrap_or(WORK_TIME),
-            short_break: cli
-                .shortbreak
-                .map(|s| s * MINUTE)
-                .unwrap_or(SHORT_BREAK_TIME),
-            long_break: cli.longbreak.map(|l| l * MINUTE).unwrap_or(LONG_BREAK_TIME),
+            work_time,
+            short_break,
+            long_break,
+            bounds: [cli.work_bounds, cli.short_bounds, cli.long_bounds]
+                .map(Option::unwrap_or_default),
+            break_ratio: cli.break_ratio,
+            long_break_curve: cli.adaptive_long_break.clone(),
+            work_sequence: cli.work_sequence.iter().map(|m| m * MINUTE).collect(),
+            jitter: cli.jitter.map(|m| m * MINUTE).unwrap_or(0),
+            daily_cap: cli.daily_cap,
             no_icons: cli.no_icons,
             no_work_icons: cli.no_work_icons,
-            play_icon: cli.play.clone().unwrap_or_else(|| PLAY_ICON.to_string()),
-            pause_icon: cli.pause.clone().unwrap_or_else(|| PAUSE_ICON.to_string()),
+            count_up: cli.count_up,
+            play_icon: cli.play.clone().unwrap_or_else(|| icons.play.to_string()),
+            pause_icon: cli.pause.clone().unwrap_or_else(|| icons.pause.to_string()),
             work_icon: cli
                 .work_icon
                 .clone()
-                .unwrap_or_else(|| WORK_ICON.to_string()),
+                .unwrap_or_else(|| icons.work.to_string()),
             break_icon: cli
                 .break_icon
                 .clone()
-                .unwrap_or_else(|| BREAK_ICON.to_string()),
+                .unwrap_or_else(|| icons.short_break.to_string()),
+            // --break-icon applies to both breaks unless the long break is given its own icon
+            long_break_icon: cli
+                .long_break_icon
+                .clone()
+                .or_else(|| cli.break_icon.clone())
+                .unwrap_or_else(|| icons.long_break.to_string()),
+            format: cli
+                .format
+                .clone()
+                .unwrap_or_else(|| DEFAULT_FORMAT.to_string()),
+            tooltip_format: cli.tooltip_format.clone(),
+            progress_glyphs: cli.progress_glyphs,
+            progress_width: cli.progress_width,
+            max_length: cli.max_length,
+            min_length: cli.min_length,
             work_sound: cli.work_sound.clone(),
             break_sound: cli.break_sound.clone(),
-            autow: cli.autow,
-            autob: cli.autob,
-            persist: cli.persist,
-            with_notifications: cli.with_notifications,
+            duck: cli.duck,
+            auto_start: auto_start_from_cli(cli),
+            auto_start_grace: cli.auto_grace,
+            // a simulated run must not overwrite the real persisted state, history or stats
+            persist: cli.persist && cli.simulate.is_none(),
+            autosave: cli
+                .autosave
+                .map(|interval| Duration::from_millis(interval.as_millis())),
+            autosave_on: cli.autosave_on.clone(),
+            history: cli.history && cli.simulate.is_none(),
+            state_env: cli.state_env,
+            output: cli.output,
+            track_apps: cli.track_apps,
+            achievements: cli.achievements && cli.simulate.is_none(),
+            sync_dir: cli.sync_dir.clone(),
+            with_notifications: cli.with_notifications || !cli.notify_on.is_empty(),
+            notify_on: notify_on_from_cli(cli),
+            with_sounds: !cli.no_sounds,
+            blink: cli.blink,
+            tick_rate: Duration::from_millis(cli.tick_rate as u64),
+            power_save: cli.power_save,
+            saving_power: false,
+            stale_after: cli
+                .stale_after
+                .map(|m| Duration::from_secs(m as u64 * MINUTE as u64)),
+            stale_tooltip: cli.stale_tooltip,
+            idle_resume: cli
+                .idle_resume
+                .map(|m| Duration::from_secs(m as u64 * MINUTE as u64)),
+            call_pause: cli.call_pause,
+            exclusive_focus: cli.exclusive_focus,
+            name: cli.name.clone(),
+            groups: cli.group.clone(),
+            abandon_after: cli
+                .abandon_after
+                .map(|m| Duration::from_secs(m as u64 * MINUTE as u64)),
+            day_start: cli.day_start,
+            pause_reminder: cli
+                .pause_reminder
+                .map(|m| Duration::from_secs(m as u64 * MINUTE as u64)),
+            speed: cli.simulate.unwrap_or(1),
+            micro_break_every: cli
+                .micro_break
+                .map(|m| Duration::from_secs(m as u64 * MINUTE as u64)),
+            micro_break_length: Duration::from_secs(cli.micro_break_length as u64),
+            checkpoints: cli.checkpoints.clone(),
+            checkpoint_sound: cli.checkpoint_sound.clone(),
+            reminders: cli.reminders.clone(),
+            break_activities: cli.break_activities.clone(),
+            activity_order: cli.activity_order,
+            count_completed: cli.count_completed,
+            break_start_command: cli.on_break_start.clone().or(temperature_start),
+            break_end_command: cli.on_break_end.clone().or(temperature_end),
+            break_workspace: cli.break_workspace.clone(),
             binary_name,
         };
 
@@ -90,6 +663,10 @@ impl Config {
         config
     }
 
+    pub fn bounds(&self, cycle: CycleType) -> DurationBounds {
+        self.bounds[cycle as usize]
+    }
+
     pub fn get_play_pause_icon(&self, running: bool) -> &str {
         if self.no_icons {
             return "";
@@ -102,15 +679,25 @@ impl Config {
         }
     }
 
-    pub fn get_cycle_icon(&self, is_break: bool) -> &str {
+    pub fn set_icon(&mut self, icon: IconKind, value: String) {
+        match icon {
+            IconKind::Play => self.play_icon = value,
+            IconKind::Pause => self.pause_icon = value,
+            IconKind::Work => self.work_icon = value,
+            IconKind::Break => self.break_icon = value,
+            IconKind::LongBreak => self.long_break_icon = value,
+        }
+    }
+
+    pub fn get_cycle_icon(&self, cycle: CycleType) -> &str {
         if self.no_work_icons {
             return "";
         }
 
-        if !is_break {
-            &self.work_icon
-        } else {
-            &self.break_icon
+        match cycle {
+            CycleType::Work => &self.work_icon,
+            CycleType::ShortBreak => &self.break_icon,
+            CycleType::LongBreak => &self.long_break_icon,
         }
     }
 }
@@ -163,8 +750,8 @@ mod tests {
         assert_eq!(config.pause_icon, PAUSE_ICON.to_string());
         assert_eq!(config.work_icon, WORK_ICON.to_string());
         assert_eq!(config.break_icon, BREAK_ICON.to_string());
-        assert!(!config.autow);
-        assert!(!config.autob);
+        assert_eq!(config.long_break_icon, LONG_BREAK_ICON.to_string());
+        assert_eq!(config.auto_start, Transitions::default());
         assert!(!config.persist);
     }
 
@@ -188,8 +775,289 @@ mod tests {
         assert_eq!(config.work_time, 30 * MINUTE);
         assert_eq!(config.short_break, 10 * MINUTE);
         assert_eq!(config.long_break, LONG_BREAK_TIME);
-        assert!(config.autow);
-        assert!(!config.autob);
+        assert!(config.auto_start.work_after_short);
+        assert!(config.auto_start.work_after_long);
+        assert!(!config.auto_start.short_break);
+        assert!(!config.auto_start.long_break);
         assert!(config.persist);
     }
+
+    #[test]
+    fn test_config_from_module_cli_autosave() {
+        use crate::cli::ModuleCli;
+        use clap::Parser;
+
+        let cli = ModuleCli::try_parse_from(["waybar-module-pomodoro", "--persist"]).unwrap();
+        let config = Config::from_module_cli(&cli);
+        assert_eq!(config.autosave, None);
+        assert_eq!(
+            config.autosave_on,
+            [AutosaveEvent::Cycle, AutosaveEvent::Command]
+        );
+
+        let cli = ModuleCli::try_parse_from([
+            "waybar-module-pomodoro",
+            "--persist",
+            "--autosave",
+            "30s",
+            "--autosave-on",
+            "cycle",
+        ])
+        .unwrap();
+        let config = Config::from_module_cli(&cli);
+        assert_eq!(config.autosave, Some(Duration::from_secs(30)));
+        assert_eq!(config.autosave_on, [AutosaveEvent::Cycle]);
+
+        // there is nothing to save without --persist
+        assert!(
+            ModuleCli::try_parse_from(["waybar-module-pomodoro", "--autosave", "30s"]).is_err()
+        );
+    }
+
+    #[test]
+    fn test_signal_commands() {
+        use crate::cli::ModuleCli;
+        use clap::Parser;
+
+        let cli = ModuleCli::try_parse_from(["waybar-module-pomodoro"]).unwrap();
+        assert_eq!(cli.on_sigusr1, "toggle");
+        assert_eq!(cli.on_sigusr2, "next-state");
+
+        let cli = ModuleCli::try_parse_from([
+            "waybar-module-pomodoro",
+            "--on-sigusr1",
+            "reset",
+            "--on-sigusr2",
+            "none",
+        ])
+        .unwrap();
+        assert_eq!(cli.on_sigusr1, "reset");
+        assert_eq!(cli.on_sigusr2, "none");
+        assert!(
+            ModuleCli::try_parse_from(["waybar-module-pomodoro", "--on-sigusr1", "explode"])
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_duration_bounds() {
+        let bounds: DurationBounds = "5-90".parse().unwrap();
+        assert_eq!(bounds, DurationBounds { min: 5, max: 90 });
+        assert_eq!(
+            "-45".parse::<DurationBounds>().unwrap(),
+            DurationBounds { min: 1, max: 45 }
+        );
+        assert_eq!(
+            "0-".parse::<DurationBounds>().unwrap(),
+            DurationBounds {
+                min: 0,
+                max: MAX_MINUTES
+            }
+        );
+        assert!("90-5".parse::<DurationBounds>().is_err());
+        assert!("5".parse::<DurationBounds>().is_err());
+        assert!("5-2000".parse::<DurationBounds>().is_err());
+
+        assert_eq!(bounds.check(CycleType::Work, 5 * MINUTE as i32), Ok(300));
+        assert_eq!(
+            bounds.check(CycleType::Work, 0),
+            Err("Duration out of range: 0 minutes of work (allowed 5 to 90)".to_string())
+        );
+        assert!(bounds.check(CycleType::Work, -(MINUTE as i32)).is_err());
+        assert!(bounds.check(CycleType::Work, 91 * MINUTE as i32).is_err());
+    }
+
+    #[test]
+    fn test_break_ratio() {
+        use crate::cli::ModuleCli;
+        use clap::Parser;
+
+        assert_eq!(
+            "4:1".parse::<BreakRatio>().unwrap(),
+            BreakRatio { short: 4, long: 1 }
+        );
+        assert!("5".parse::<BreakRatio>().is_err());
+        assert!("0:2".parse::<BreakRatio>().is_err());
+        assert_eq!(BreakRatio::default().breaks(30 * MINUTE), (360, 900));
+
+        let cli = ModuleCli::try_parse_from(vec![
+            "waybar-module-pomodoro",
+            "--work",
+            "40",
+            "--break-ratio",
+        ])
+        .unwrap();
+        let config = Config::from_module_cli(&cli);
+        assert_eq!(config.short_break, 8 * MINUTE);
+        assert_eq!(config.long_break, 20 * MINUTE);
+    }
+
+    #[test]
+    fn test_long_break_curve() {
+        let curve: LongBreakCurve = "12:30, 8:20".parse().unwrap();
+        assert_eq!(curve, LongBreakCurve(vec![(8, 20), (12, 30)]));
+        assert_eq!(curve.length(4, LONG_BREAK_TIME), LONG_BREAK_TIME);
+        assert_eq!(curve.length(8, LONG_BREAK_TIME), 20 * MINUTE);
+        assert_eq!(curve.length(11, LONG_BREAK_TIME), 20 * MINUTE);
+        assert_eq!(curve.length(16, LONG_BREAK_TIME), 30 * MINUTE);
+        assert!("8".parse::<LongBreakCurve>().is_err());
+        assert!("8:0".parse::<LongBreakCurve>().is_err());
+        assert!("8:20,x:30".parse::<LongBreakCurve>().is_err());
+    }
+
+    #[test]
+    fn test_progress_glyphs() {
+        assert_eq!(ProgressGlyphs::Bar.render(0.0, 5), "▱▱▱▱▱");
+        assert_eq!(ProgressGlyphs::Bar.render(0.65, 5), "▰▰▰▱▱");
+        assert_eq!(ProgressGlyphs::Blocks.render(1.0, 3), "███");
+        assert_eq!(ProgressGlyphs::Dots.render(0.5, 4), "●●○○");
+        assert_eq!(ProgressGlyphs::Pie.render(0.3, 5), "◔");
+        assert_eq!(ProgressGlyphs::Pie.render(0.99, 5), "◕");
+        assert_eq!(ProgressGlyphs::Pie.render(1.0, 5), "●");
+    }
+
+    #[test]
+    fn test_get_cycle_icon_per_cycle() {
+        let config = Config::default();
+
+        assert_eq!(config.get_cycle_icon(CycleType::Work), WORK_ICON);
+        assert_eq!(config.get_cycle_icon(CycleType::ShortBreak), BREAK_ICON);
+        assert_eq!(config.get_cycle_icon(CycleType::LongBreak), LONG_BREAK_ICON);
+    }
+
+    #[test]
+    fn test_auto_start_rules() {
+        let auto_start = Transitions {
+            short_break: true,
+            work_after_short: true,
+            ..Default::default()
+        };
+
+        assert!(auto_start.covers(CycleType::Work, CycleType::ShortBreak));
+        assert!(!auto_start.covers(CycleType::Work, CycleType::LongBreak));
+        assert!(auto_start.covers(CycleType::ShortBreak, CycleType::Work));
+        assert!(!auto_start.covers(CycleType::LongBreak, CycleType::Work));
+    }
+
+    #[test]
+    fn test_notify_on() {
+        use clap::Parser;
+        let config = Config::from_module_cli(&ModuleCli::parse_from(["test"]));
+        assert!(!config.with_notifications);
+        assert_eq!(config.notify_on, Transitions::ALL);
+
+        // a nudge back to work, but none to stop
+        let cli = ModuleCli::parse_from(["test", "--notify-on", "work"]);
+        let config = Config::from_module_cli(&cli);
+        assert!(config.with_notifications);
+        assert!(config
+            .notify_on
+            .covers(CycleType::ShortBreak, CycleType::Work));
+        assert!(config
+            .notify_on
+            .covers(CycleType::LongBreak, CycleType::Work));
+        assert!(!config
+            .notify_on
+            .covers(CycleType::Work, CycleType::ShortBreak));
+    }
+
+    #[test]
+    fn test_auto_start_switch() {
+        let mut auto_start = Transitions::default();
+
+        auto_start.switch(AutoTarget::Break, Switch::On);
+        assert!(auto_start.short_break && auto_start.long_break);
+
+        auto_start.switch(AutoTarget::LongBreak, Switch::Off);
+        assert!(auto_start.short_break && !auto_start.long_break);
+
+        // a partially enabled group toggles to fully enabled
+        auto_start.switch(AutoTarget::Break, Switch::Toggle);
+        assert!(auto_start.short_break && auto_start.long_break);
+        auto_start.switch(AutoTarget::Break, Switch::Toggle);
+        assert!(!auto_start.short_break && !auto_start.long_break);
+    }
+
+    #[test]
+    fn test_config_from_module_cli_auto_rules() {
+        use crate::cli::ModuleCli;
+        use clap::Parser;
+
+        let cli = ModuleCli::try_parse_from(vec![
+            "waybar-module-pomodoro",
+            "--auto",
+            "short-break",
+            "--auto",
+            "work-after-short",
+        ])
+        .unwrap();
+        let config = Config::from_module_cli(&cli);
+
+        assert_eq!(
+            config.auto_start,
+            Transitions {
+                short_break: true,
+                work_after_short: true,
+                ..Default::default()
+            }
+        );
+    }
+
+    #[test]
+    fn test_set_icon() {
+        let mut config = Config::default();
+        config.set_icon(IconKind::Play, "go".to_string());
+        config.set_icon(IconKind::LongBreak, "zz".to_string());
+
+        assert_eq!(config.get_play_pause_icon(false), "go");
+        assert_eq!(config.get_cycle_icon(CycleType::LongBreak), "zz");
+        assert_eq!(config.get_cycle_icon(CycleType::ShortBreak), BREAK_ICON);
+    }
+
+    #[test]
+    fn test_config_from_module_cli_icon_preset() {
+        use crate::cli::ModuleCli;
+        use clap::Parser;
+
+        let cli = ModuleCli::try_parse_from(vec![
+            "waybar-module-pomodoro",
+            "--icon-preset",
+            "ascii",
+            "--play",
+            "go",
+        ])
+        .unwrap();
+        let config = Config::from_module_cli(&cli);
+
+        // explicit icons win over the preset
+        assert_eq!(config.play_icon, "go");
+        assert_eq!(config.pause_icon, "||");
+        assert_eq!(config.work_icon, "W");
+        assert_eq!(config.break_icon, "B");
+        assert_eq!(config.long_break_icon, "LB");
+    }
+
+    #[test]
+    fn test_config_from_module_cli_break_icon_applies_to_long_break() {
+        use crate::cli::ModuleCli;
+        use clap::Parser;
+
+        let cli =
+            ModuleCli::try_parse_from(vec!["waybar-module-pomodoro", "--break-icon", "b"]).unwrap();
+        let config = Config::from_module_cli(&cli);
+        assert_eq!(config.break_icon, "b");
+        assert_eq!(config.long_break_icon, "b");
+
+        let cli = ModuleCli::try_parse_from(vec![
+            "waybar-module-pomodoro",
+            "--break-icon",
+            "b",
+            "--long-break-icon",
+            "lb",
+        ])
+        .unwrap();
+        let config = Config::from_module_cli(&cli);
+        assert_eq!(config.break_icon, "b");
+        assert_eq!(config.long_break_icon, "lb");
+    }
 }
@@ -1,13 +1,19 @@
-use regex::Regex;
+use chrono::{DateTime, Days, Local, NaiveTime};
+use schemars::{json_schema, JsonSchema, Schema, SchemaGenerator};
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use serde_json;
-use std::str::FromStr;
-use std::sync::LazyLock;
+use std::{borrow::Cow, str::FromStr};
 use tracing::debug;
 
-static TIME_VALUE_REGEX: LazyLock<Regex> = LazyLock::new(|| {
-    Regex::new(r"^([+-])?(\d+)([+-])?$").expect("Invalid regex for time value parsing")
-});
+use crate::services::cache::Snapshot;
+
+/// Version of the socket protocol, bumped when a change breaks older clients
+pub const PROTOCOL_VERSION: u32 = 1;
+
+/// Whether `s` is a non-empty run of ASCII digits
+fn is_digits(s: &str) -> bool {
+    !s.is_empty() && s.bytes().all(|b| b.is_ascii_digit())
+}
 
 #[derive(Debug, PartialEq, Clone)]
 pub enum TimeValue {
@@ -20,29 +26,33 @@ impl FromStr for TimeValue {
     type Err = String;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let captures = TIME_VALUE_REGEX
-            .captures(s)
-            .ok_or_else(|| format!("Invalid time value format: {s}"))?;
+        // a number with an optional sign on either side, e.g. 5, +5 or 5-
+        let sign = |c: char| c == '+' || c == '-';
+        let (prefix, rest) = match s.strip_prefix(sign) {
+            Some(rest) => (s.chars().next(), rest),
+            None => (None, s),
+        };
+        let (number_str, suffix) = match rest.strip_suffix(sign) {
+            Some(number_str) => (number_str, rest.chars().last()),
+            None => (rest, None),
+        };
+        if !is_digits(number_str) {
+            return Err(format!("Invalid time value format: {s}"));
+        }
 
-        let number_str = captures.get(2).unwrap().as_str();
         let number: u16 = number_str
             .parse()
             .map_err(|_| format!("Invalid number: {number_str}"))?;
 
-        // Check for prefix and suffix
-        let prefix = captures.get(1).map(|m| m.as_str());
-        let suffix = captures.get(3).map(|m| m.as_str());
-
         if prefix.is_some() && suffix.is_some() {
             return Err(format!("Invalid time value format {s}"));
         }
 
+        let delta = || i16::try_from(number).map_err(|_| format!("Value out of range: {s}"));
         match prefix.or(suffix) {
-            Some("+") => Ok(TimeValue::Add(number as i16)),
-            Some("-") => Ok(TimeValue::Subtract(number as i16)),
-            None => Ok(TimeValue::Set(number)),
-            // This shouldn't happen with our regex, but just in case
-            _ => Err(format!("Invalid time value format: {s}")),
+            Some('+') => Ok(TimeValue::Add(delta()?)),
+            Some('-') => Ok(TimeValue::Subtract(delta()?)),
+            _ => Ok(TimeValue::Set(number)),
         }
     }
 }
@@ -60,6 +70,20 @@ impl Serialize for TimeValue {
     }
 }
 
+impl JsonSchema for TimeValue {
+    fn schema_name() -> Cow<'static, str> {
+        "TimeValue".into()
+    }
+
+    fn json_schema(_: &mut SchemaGenerator) -> Schema {
+        json_schema!({
+            "type": "string",
+            "pattern": "^([+-]?[0-9]+|[0-9]+[+-])$",
+            "description": "Minutes to set, or to add or take away with a + or - sign, e.g. 25, +5 or 5-"
+        })
+    }
+}
+
 impl<'de> Deserialize<'de> for TimeValue {
     fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
     where
@@ -70,7 +94,391 @@ impl<'de> Deserialize<'de> for TimeValue {
     }
 }
 
-#[derive(Debug, PartialEq, Serialize, Deserialize)]
+/// A span of time like `90s`, `10m` or `1h`, plain numbers are minutes
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct DurationValue(pub u32);
+
+impl DurationValue {
+    pub fn as_millis(self) -> u64 {
+        self.0 as u64 * 1000
+    }
+}
+
+impl FromStr for DurationValue {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (number_str, unit) = match s.strip_suffix(['s', 'm', 'h']) {
+            Some(number_str) => (number_str, s.chars().last()),
+            None => (s, None),
+        };
+        if !is_digits(number_str) {
+            return Err(format!(
+                "Invalid duration: {s} (expected e.g. 90s, 10m or 1h)"
+            ));
+        }
+
+        let number: u32 = number_str
+            .parse()
+            .map_err(|_| format!("Invalid number: {number_str}"))?;
+        let unit = match unit {
+            Some('s') => 1,
+            Some('h') => 3600,
+            _ => 60,
+        };
+        number
+            .checked_mul(unit)
+            .map(DurationValue)
+            .ok_or_else(|| format!("Duration out of range: {s}"))
+    }
+}
+
+impl Serialize for DurationValue {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_str(&format!("{}s", self.0))
+    }
+}
+
+impl JsonSchema for DurationValue {
+    fn schema_name() -> Cow<'static, str> {
+        "DurationValue".into()
+    }
+
+    fn json_schema(_: &mut SchemaGenerator) -> Schema {
+        json_schema!({
+            "type": "string",
+            "pattern": "^[0-9]+[smh]?$",
+            "description": "A span of time like 90s, 10m or 1h, plain numbers are minutes"
+        })
+    }
+}
+
+impl<'de> Deserialize<'de> for DurationValue {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let s = String::deserialize(deserializer)?;
+        DurationValue::from_str(&s).map_err(serde::de::Error::custom)
+    }
+}
+
+/// A time of day like `17:00`
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct ClockTime(pub NaiveTime);
+
+impl ClockTime {
+    /// The next moment after `now` the clock shows this time, tomorrow if it has passed
+    pub fn next_after(self, now: DateTime<Local>) -> DateTime<Local> {
+        let mut day = now.date_naive();
+        if day.and_time(self.0) <= now.naive_local() {
+            day = day + Days::new(1);
+        }
+        // a time skipped by a DST change falls back to the hour after
+        let time = day.and_time(self.0);
+        time.and_local_timezone(Local)
+            .earliest()
+            .or_else(|| {
+                (time + chrono::TimeDelta::hours(1))
+                    .and_local_timezone(Local)
+                    .earliest()
+            })
+            .unwrap_or(now)
+    }
+}
+
+impl FromStr for ClockTime {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        NaiveTime::parse_from_str(s, "%H:%M")
+            .map(ClockTime)
+            .map_err(|_| format!("Invalid time of day: {s} (expected e.g. 17:00)"))
+    }
+}
+
+impl Serialize for ClockTime {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_str(&self.0.format("%H:%M").to_string())
+    }
+}
+
+impl JsonSchema for ClockTime {
+    fn schema_name() -> Cow<'static, str> {
+        "ClockTime".into()
+    }
+
+    fn json_schema(_: &mut SchemaGenerator) -> Schema {
+        json_schema!({
+            "type": "string",
+            "pattern": "^[0-9]{1,2}:[0-9]{2}$",
+            "description": "A time of day like 17:00"
+        })
+    }
+}
+
+impl<'de> Deserialize<'de> for ClockTime {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let s = String::deserialize(deserializer)?;
+        ClockTime::from_str(&s).map_err(serde::de::Error::custom)
+    }
+}
+
+/// A point in the current cycle, `10:00` or `1:05:00` in or `50%` of the way through
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum SeekPosition {
+    /// Seconds since the cycle started
+    Elapsed(u32),
+    Percent(u8),
+}
+
+impl SeekPosition {
+    /// Seconds into a cycle lasting `total` seconds, at most `total`
+    pub fn seconds(self, total: u16) -> u16 {
+        match self {
+            SeekPosition::Elapsed(seconds) => seconds.min(total as u32) as u16,
+            SeekPosition::Percent(percent) => (total as u32 * percent as u32 / 100) as u16,
+        }
+    }
+}
+
+impl FromStr for SeekPosition {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("Invalid position: {s} (expected e.g. 10:00 or 50%)");
+        if let Some(percent) = s.strip_suffix('%') {
+            return match percent.parse::<u8>() {
+                Ok(number) if is_digits(percent) && number <= 100 => {
+                    Ok(SeekPosition::Percent(number))
+                }
+                _ => Err(invalid()),
+            };
+        }
+
+        let parts: Vec<&str> = s.split(':').collect();
+        if !(2..=3).contains(&parts.len())
+            || !parts.iter().all(|part| is_digits(part))
+            || parts[1..].iter().any(|part| part.len() != 2)
+        {
+            return Err(invalid());
+        }
+        let mut seconds: u32 = 0;
+        for (i, part) in parts.iter().enumerate() {
+            let number: u32 = part.parse().map_err(|_| invalid())?;
+            if i > 0 && number >= 60 {
+                return Err(invalid());
+            }
+            seconds = seconds
+                .checked_mul(60)
+                .and_then(|seconds| seconds.checked_add(number))
+                .ok_or_else(|| format!("Position out of range: {s}"))?;
+        }
+        Ok(SeekPosition::Elapsed(seconds))
+    }
+}
+
+impl Serialize for SeekPosition {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let text = match self {
+            SeekPosition::Elapsed(seconds) => format!("{}:{:02}", seconds / 60, seconds % 60),
+            SeekPosition::Percent(percent) => format!("{percent}%"),
+        };
+        serializer.serialize_str(&text)
+    }
+}
+
+impl JsonSchema for SeekPosition {
+    fn schema_name() -> Cow<'static, str> {
+        "SeekPosition".into()
+    }
+
+    fn json_schema(_: &mut SchemaGenerator) -> Schema {
+        json_schema!({
+            "type": "string",
+            "pattern": "^([0-9]+(:[0-9]{2}){1,2}|[0-9]{1,3}%)$",
+            "description": "A point in the current cycle, 10:00 in or 50% of the way through"
+        })
+    }
+}
+
+impl<'de> Deserialize<'de> for SeekPosition {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let s = String::deserialize(deserializer)?;
+        SeekPosition::from_str(&s).map_err(serde::de::Error::custom)
+    }
+}
+
+/// On/off/toggle argument for runtime switches
+#[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "kebab-case")]
+pub enum Switch {
+    On,
+    Off,
+    Toggle,
+}
+
+impl Switch {
+    pub fn apply(self, current: bool) -> bool {
+        match self {
+            Switch::On => true,
+            Switch::Off => false,
+            Switch::Toggle => !current,
+        }
+    }
+}
+
+impl FromStr for Switch {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "on" => Ok(Switch::On),
+            "off" => Ok(Switch::Off),
+            "toggle" => Ok(Switch::Toggle),
+            _ => Err(format!(
+                "Invalid switch value: {s} (expected on, off or toggle)"
+            )),
+        }
+    }
+}
+
+/// Icons that can be replaced at runtime
+#[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "kebab-case")]
+pub enum IconKind {
+    Play,
+    Pause,
+    Work,
+    Break,
+    LongBreak,
+}
+
+impl FromStr for IconKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "play" => Ok(IconKind::Play),
+            "pause" => Ok(IconKind::Pause),
+            "work" => Ok(IconKind::Work),
+            "break" => Ok(IconKind::Break),
+            "long-break" => Ok(IconKind::LongBreak),
+            _ => Err(format!(
+                "Invalid icon: {s} (expected play, pause, work, break or long-break)"
+            )),
+        }
+    }
+}
+
+/// Which transitions an auto-start switch applies to, `work` and `break` cover both of
+/// their finer-grained variants
+#[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "kebab-case")]
+pub enum AutoTarget {
+    Work,
+    Break,
+    ShortBreak,
+    LongBreak,
+    WorkAfterShort,
+    WorkAfterLong,
+}
+
+impl FromStr for AutoTarget {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "work" => Ok(AutoTarget::Work),
+            "break" => Ok(AutoTarget::Break),
+            "short-break" => Ok(AutoTarget::ShortBreak),
+            "long-break" => Ok(AutoTarget::LongBreak),
+            "work-after-short" => Ok(AutoTarget::WorkAfterShort),
+            "work-after-long" => Ok(AutoTarget::WorkAfterLong),
+            _ => Err(format!(
+                "Invalid auto target: {s} (expected work, break, short-break, long-break, work-after-short or work-after-long)"
+            )),
+        }
+    }
+}
+
+/// Which way a scroll nudges the current cycle
+#[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "kebab-case")]
+pub enum ScrollDirection {
+    Up,
+    Down,
+}
+
+impl FromStr for ScrollDirection {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "up" => Ok(ScrollDirection::Up),
+            "down" => Ok(ScrollDirection::Down),
+            _ => Err(format!(
+                "Invalid scroll direction: {s} (expected up or down)"
+            )),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "kebab-case")]
+pub enum LogLevel {
+    Trace,
+    Debug,
+    Info,
+    Warn,
+    Error,
+}
+
+impl LogLevel {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LogLevel::Trace => "trace",
+            LogLevel::Debug => "debug",
+            LogLevel::Info => "info",
+            LogLevel::Warn => "warn",
+            LogLevel::Error => "error",
+        }
+    }
+}
+
+impl FromStr for LogLevel {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "trace" => Ok(LogLevel::Trace),
+            "debug" => Ok(LogLevel::Debug),
+            "info" => Ok(LogLevel::Info),
+            "warn" => Ok(LogLevel::Warn),
+            "error" => Ok(LogLevel::Error),
+            _ => Err(format!(
+                "Invalid log level: {s} (expected trace, debug, info, warn or error)"
+            )),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize, JsonSchema)]
 #[serde(rename_all = "kebab-case")]
 pub enum Message {
     // Simple commands
@@ -79,11 +487,186 @@ pub enum Message {
     Toggle,
     Reset,
     NextState,
+    CancelAuto,
+    Idle,
+    /// Pause because the screen was locked, resumed by `active` like `idle`
+    Lock,
+    Active,
+    /// Pause a running work cycle because another instance started one, with
+    /// --exclusive-focus
+    YieldFocus,
     // Duration commands
-    SetWork { time: TimeValue },
-    SetShort { time: TimeValue },
-    SetLong { time: TimeValue },
-    SetCurrent { time: TimeValue },
+    SetWork {
+        time: TimeValue,
+    },
+    SetShort {
+        time: TimeValue,
+    },
+    SetLong {
+        time: TimeValue,
+    },
+    SetCurrent {
+        time: TimeValue,
+    },
+    // Runtime switches
+    Notifications {
+        state: Switch,
+    },
+    Sounds {
+        state: Switch,
+    },
+    /// Cut off the sound that is playing
+    StopSound,
+    SetAuto {
+        cycle: AutoTarget,
+        state: Switch,
+    },
+    // Appearance commands
+    SetIcon {
+        icon: IconKind,
+        value: String,
+    },
+    SetFormat {
+        format: String,
+    },
+    ToggleDisplay,
+    // Diagnostics
+    LogLevel {
+        level: LogLevel,
+    },
+    Logging {
+        state: Switch,
+    },
+    Dump,
+    /// Reply with version, uptime and instance, to check the module is alive
+    Ping,
+    /// Reply with the time left in the current cycle, for scripts
+    Remaining,
+    /// Reply with the configuration in effect, defaults, config file and command line
+    /// taken together
+    ShowConfig,
+    FastForward {
+        time: DurationValue,
+    },
+    /// Set how far into the current cycle the timer is, keeping its length
+    Seek {
+        to: SeekPosition,
+    },
+    /// Run a block of pomodoros, stopping after the last
+    Sprint {
+        pomodoros: u16,
+    },
+    /// Run pomodoros back to back, ending the session at `until`
+    FocusUntil {
+        until: ClockTime,
+    },
+    /// Pause until `until`, then carry on where the timer stood
+    SnoozeUntil {
+        until: ClockTime,
+    },
+    Scroll {
+        direction: ScrollDirection,
+        step: u16,
+    },
+    SetTask {
+        name: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        estimate: Option<u16>,
+    },
+    ClearTask,
+    Annotate {
+        note: String,
+    },
+    Reminder {
+        name: String,
+        state: Switch,
+    },
+    /// Go back to the latest cycle the history shows was cut short by a reset or restart
+    ResumeLast,
+    ExportState,
+    ImportState {
+        /// A state written by `export-state`
+        #[schemars(with = "serde_json::Map<String, serde_json::Value>")]
+        snapshot: Box<Snapshot>,
+    },
+    /// Reply with the JSON Schema of the protocol
+    Schema,
+    /// Shut the module down, only accepted from the user running it
+    Exit,
+    /// Keep the connection open and send every line the module renders, until either side
+    /// hangs up
+    Subscribe,
+}
+
+/// Why the daemon rejected a command
+#[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "kebab-case")]
+pub enum ErrorKind {
+    UnknownCommand,
+    InvalidValue,
+    /// The command isn't allowed from the client that sent it
+    PermissionDenied,
+}
+
+impl ErrorKind {
+    /// Malformed input or an unknown command name is an unknown command, anything else
+    /// means a known command carried a bad argument
+    pub fn from_decode_error(error: &serde_json::Error) -> Self {
+        if error.is_data() && !error.to_string().starts_with("unknown variant") {
+            ErrorKind::InvalidValue
+        } else {
+            ErrorKind::UnknownCommand
+        }
+    }
+}
+
+/// Reply written back to the client for every command
+#[derive(Debug, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(tag = "status", rename_all = "kebab-case")]
+pub enum Response {
+    Ok {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        data: Option<serde_json::Value>,
+    },
+    Error {
+        kind: ErrorKind,
+        message: String,
+    },
+}
+
+impl Response {
+    pub fn ok() -> Self {
+        Response::Ok { data: None }
+    }
+
+    pub fn with_data(data: serde_json::Value) -> Self {
+        Response::Ok { data: Some(data) }
+    }
+
+    pub fn error(kind: ErrorKind, message: impl Into<String>) -> Self {
+        Response::Error {
+            kind,
+            message: message.into(),
+        }
+    }
+
+    pub fn decode(input: &str) -> Result<Self, serde_json::Error> {
+        serde_json::from_str(input)
+    }
+
+    pub fn encode(&self) -> String {
+        serde_json::to_string(self).unwrap()
+    }
+}
+
+/// JSON Schemas of the messages the module takes and of its replies, for clients in other
+/// languages to check theirs against
+pub fn protocol_schema() -> serde_json::Value {
+    serde_json::json!({
+        "protocol": PROTOCOL_VERSION,
+        "message": schemars::schema_for!(Message),
+        "response": schemars::schema_for!(Response),
+    })
 }
 
 impl Message {
@@ -138,6 +721,239 @@ mod tests {
         assert!(TimeValue::from_str("-5-").is_err());
         assert!(TimeValue::from_str("++5").is_err());
         assert!(TimeValue::from_str("--5").is_err());
+
+        // deltas must fit an i16 instead of wrapping around
+        assert!(TimeValue::from_str("+40000").is_err());
+        assert_eq!(TimeValue::from_str("40000").unwrap(), TimeValue::Set(40000));
+    }
+
+    #[test]
+    fn test_response_roundtrip() {
+        assert_eq!(Response::ok().encode(), r#"{"status":"ok"}"#);
+        assert_eq!(
+            Response::decode(r#"{"status":"ok"}"#).unwrap(),
+            Response::ok()
+        );
+
+        let response = Response::with_data(serde_json::json!({"instance": 0}));
+        assert_eq!(
+            response.encode(),
+            r#"{"status":"ok","data":{"instance":0}}"#
+        );
+        assert_eq!(Response::decode(&response.encode()).unwrap(), response);
+
+        let response = Response::error(ErrorKind::UnknownCommand, "Unknown command: foo");
+        let encoded = response.encode();
+        assert_eq!(
+            encoded,
+            r#"{"status":"error","kind":"unknown-command","message":"Unknown command: foo"}"#
+        );
+        assert_eq!(Response::decode(&encoded).unwrap(), response);
+    }
+
+    #[test]
+    fn test_error_kind_from_decode_error() {
+        let kind = |input| ErrorKind::from_decode_error(&Message::decode(input).unwrap_err());
+
+        assert_eq!(kind("invalid"), ErrorKind::UnknownCommand);
+        assert_eq!(
+            kind(r#"{"launch":{"time":"5"}}"#),
+            ErrorKind::UnknownCommand
+        );
+        assert_eq!(
+            kind(r#"{"set-work":{"time":"abc"}}"#),
+            ErrorKind::InvalidValue
+        );
+        assert_eq!(kind(r#"{"set-work":{}}"#), ErrorKind::InvalidValue);
+    }
+
+    #[test]
+    fn test_duration_value() {
+        assert_eq!(DurationValue::from_str("90s").unwrap(), DurationValue(90));
+        assert_eq!(DurationValue::from_str("10m").unwrap(), DurationValue(600));
+        assert_eq!(DurationValue::from_str("10").unwrap(), DurationValue(600));
+        assert_eq!(DurationValue::from_str("2h").unwrap(), DurationValue(7200));
+        assert!(DurationValue::from_str("-5m").is_err());
+        assert!(DurationValue::from_str("5d").is_err());
+        assert!(DurationValue::from_str("9999999999h").is_err());
+
+        let message = Message::FastForward {
+            time: DurationValue(600),
+        };
+        assert_eq!(message.encode(), r#"{"fast-forward":{"time":"600s"}}"#);
+        assert_eq!(Message::decode(&message.encode()).unwrap(), message);
+    }
+
+    #[test]
+    fn test_seek_position() {
+        let parse = |s| SeekPosition::from_str(s);
+        assert_eq!(parse("10:00").unwrap(), SeekPosition::Elapsed(600));
+        assert_eq!(parse("1:05:30").unwrap(), SeekPosition::Elapsed(3930));
+        assert_eq!(parse("50%").unwrap(), SeekPosition::Percent(50));
+        assert!(parse("10:60").is_err());
+        assert!(parse("10:5").is_err());
+        assert!(parse("101%").is_err());
+        assert!(parse("+5%").is_err());
+        assert!(parse("10").is_err());
+
+        assert_eq!(SeekPosition::Elapsed(600).seconds(1500), 600);
+        assert_eq!(SeekPosition::Elapsed(2000).seconds(1500), 1500);
+        assert_eq!(SeekPosition::Percent(50).seconds(1500), 750);
+
+        let message = Message::Seek {
+            to: SeekPosition::Elapsed(630),
+        };
+        assert_eq!(message.encode(), r#"{"seek":{"to":"10:30"}}"#);
+        assert_eq!(Message::decode(&message.encode()).unwrap(), message);
+    }
+
+    #[test]
+    fn test_clock_time() {
+        let at = |h, m| NaiveTime::from_hms_opt(h, m, 0).unwrap();
+        assert_eq!(ClockTime::from_str("17:00").unwrap(), ClockTime(at(17, 0)));
+        assert!(ClockTime::from_str("25:00").is_err());
+        assert!(ClockTime::from_str("5pm").is_err());
+
+        let now = chrono::NaiveDate::from_ymd_opt(2025, 3, 2)
+            .unwrap()
+            .and_time(at(16, 0))
+            .and_local_timezone(Local)
+            .unwrap();
+        assert_eq!(
+            ClockTime(at(17, 0)).next_after(now) - now,
+            chrono::TimeDelta::hours(1)
+        );
+        assert_eq!(
+            ClockTime(at(9, 0)).next_after(now) - now,
+            chrono::TimeDelta::hours(17)
+        );
+
+        let message = Message::FocusUntil {
+            until: ClockTime(at(17, 30)),
+        };
+        assert_eq!(message.encode(), r#"{"focus-until":{"until":"17:30"}}"#);
+        assert_eq!(Message::decode(&message.encode()).unwrap(), message);
+
+        let message = Message::SnoozeUntil {
+            until: ClockTime(at(14, 0)),
+        };
+        assert_eq!(message.encode(), r#"{"snooze-until":{"until":"14:00"}}"#);
+    }
+
+    #[test]
+    fn test_encode_scroll() {
+        let message = Message::Scroll {
+            direction: ScrollDirection::Down,
+            step: 5,
+        };
+        assert_eq!(
+            message.encode(),
+            r#"{"scroll":{"direction":"down","step":5}}"#
+        );
+        assert_eq!(
+            ScrollDirection::from_str("up").unwrap(),
+            ScrollDirection::Up
+        );
+        assert!(ScrollDirection::from_str("left").is_err());
+    }
+
+    #[test]
+    fn test_encode_set_task() {
+        let message = Message::SetTask {
+            name: "RFC".to_string(),
+            estimate: Some(4),
+        };
+        assert_eq!(
+            message.encode(),
+            r#"{"set-task":{"name":"RFC","estimate":4}}"#
+        );
+        assert_eq!(
+            Message::decode(r#"{"set-task":{"name":"RFC"}}"#).unwrap(),
+            Message::SetTask {
+                name: "RFC".to_string(),
+                estimate: None
+            }
+        );
+        assert_eq!(Message::ClearTask.encode(), r#""clear-task""#);
+    }
+
+    #[test]
+    fn test_switch() {
+        assert_eq!(Switch::from_str("on").unwrap(), Switch::On);
+        assert_eq!(Switch::from_str("off").unwrap(), Switch::Off);
+        assert_eq!(Switch::from_str("toggle").unwrap(), Switch::Toggle);
+        assert!(Switch::from_str("yes").is_err());
+
+        assert!(Switch::On.apply(false));
+        assert!(!Switch::Off.apply(true));
+        assert!(Switch::Toggle.apply(false));
+        assert!(!Switch::Toggle.apply(true));
+    }
+
+    #[test]
+    fn test_encode_switches() {
+        let message = Message::Notifications { state: Switch::Off };
+        assert_eq!(message.encode(), r#"{"notifications":{"state":"off"}}"#);
+
+        let message = Message::Sounds {
+            state: Switch::Toggle,
+        };
+        assert_eq!(message.encode(), r#"{"sounds":{"state":"toggle"}}"#);
+    }
+
+    #[test]
+    fn test_icon_kind_from_str() {
+        assert_eq!(IconKind::from_str("play").unwrap(), IconKind::Play);
+        assert_eq!(
+            IconKind::from_str("long-break").unwrap(),
+            IconKind::LongBreak
+        );
+        assert!(IconKind::from_str("coffee").is_err());
+    }
+
+    #[test]
+    fn test_encode_set_icon() {
+        let message = Message::SetIcon {
+            icon: IconKind::Play,
+            value: "go".to_string(),
+        };
+        assert_eq!(
+            message.encode(),
+            r#"{"set-icon":{"icon":"play","value":"go"}}"#
+        );
+    }
+
+    #[test]
+    fn test_encode_set_auto() {
+        let message = Message::SetAuto {
+            cycle: AutoTarget::Break,
+            state: Switch::On,
+        };
+        assert_eq!(
+            message.encode(),
+            r#"{"set-auto":{"cycle":"break","state":"on"}}"#
+        );
+        assert_eq!(
+            AutoTarget::from_str("work-after-long").unwrap(),
+            AutoTarget::WorkAfterLong
+        );
+        assert!(AutoTarget::from_str("lunch").is_err());
+    }
+
+    #[test]
+    fn test_log_level() {
+        for level in ["trace", "debug", "info", "warn", "error"] {
+            assert_eq!(LogLevel::from_str(level).unwrap().as_str(), level);
+        }
+        assert!(LogLevel::from_str("loud").is_err());
+
+        let message = Message::LogLevel {
+            level: LogLevel::Warn,
+        };
+        assert_eq!(message.encode(), r#"{"log-level":{"level":"warn"}}"#);
+
+        let message = Message::Logging { state: Switch::Off };
+        assert_eq!(message.encode(), r#"{"logging":{"state":"off"}}"#);
     }
 
     #[test]
@@ -220,6 +1036,8 @@ mod tests {
         assert_eq!(Message::decode("next-state\n").unwrap(), Message::NextState);
         assert_eq!(Message::decode("  start  \n").unwrap(), Message::Start);
 
+        assert_eq!(Message::decode("exit\n").unwrap(), Message::Exit);
+
         // Invalid commands should still fail
         assert!(Message::decode("invalid").is_err());
         assert!(Message::decode("invalid\n").is_err());
@@ -239,6 +1057,28 @@ mod tests {
         assert!(result.is_err());
     }
 
+    #[test]
+    fn test_protocol_schema() {
+        let schema = protocol_schema();
+        assert_eq!(schema["protocol"], PROTOCOL_VERSION);
+        let messages = schema["message"].to_string();
+        for name in [
+            "\"start\"",
+            "\"set-work\"",
+            "\"import-state\"",
+            "\"schema\"",
+        ] {
+            assert!(messages.contains(name), "{name} missing from the schema");
+        }
+        assert_eq!(
+            schema["message"]["$defs"]["DurationValue"]["pattern"],
+            "^[0-9]+[smh]?$"
+        );
+        assert!(schema["response"]
+            .to_string()
+            .contains("\"unknown-command\""));
+    }
+
     #[test]
     fn test_encode_simple_commands() {
         assert_eq!(Message::Start.encode(), r#""start""#);
@@ -246,6 +1086,14 @@ mod tests {
         assert_eq!(Message::Toggle.encode(), r#""toggle""#);
         assert_eq!(Message::Reset.encode(), r#""reset""#);
         assert_eq!(Message::NextState.encode(), r#""next-state""#);
+        assert_eq!(Message::CancelAuto.encode(), r#""cancel-auto""#);
+        assert_eq!(Message::Dump.encode(), r#""dump""#);
+        assert_eq!(Message::Ping.encode(), r#""ping""#);
+        assert_eq!(Message::Remaining.encode(), r#""remaining""#);
+        assert_eq!(Message::ShowConfig.encode(), r#""show-config""#);
+        assert_eq!(Message::Exit.encode(), r#""exit""#);
+        assert_eq!(Message::Subscribe.encode(), r#""subscribe""#);
+        assert_eq!(Message::ResumeLast.encode(), r#""resume-last""#);
     }
 
     #[test]
@@ -359,6 +1207,22 @@ mod tests {
             Message::SetCurrent {
                 time: TimeValue::Add(5),
             },
+            Message::Notifications { state: Switch::On },
+            Message::Sounds {
+                state: Switch::Toggle,
+            },
+            Message::StopSound,
+            Message::SetAuto {
+                cycle: AutoTarget::Work,
+                state: Switch::Off,
+            },
+            Message::SetIcon {
+                icon: IconKind::LongBreak,
+                value: "zz".to_string(),
+            },
+            Message::SetFormat {
+                format: "{time}".to_string(),
+            },
         ];
 
         for msg in messages {
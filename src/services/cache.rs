@@ -1,22 +1,73 @@
 use std::{
     env,
     error::Error,
-    fs::File,
+    fs::{self, File},
     io::Write,
-    path::{Path, PathBuf},
+    path::Path,
+    time::{Duration, Instant},
 };
 
-use tracing::error;
+use crate::models::config::{AutosaveEvent, Config};
+use serde::{Deserialize, Serialize};
 
-use crate::models::config::Config;
-
-use super::timer::Timer;
+use super::{stats::Stats, storage, timer::Timer};
 
 const MODULE: &str = env!("CARGO_PKG_NAME");
 const VERSION: &str = env!("CARGO_PKG_VERSION");
 
+/// A module's full state as written by `export-state`, to back up or move to another machine
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Snapshot {
+    /// Version of the module that took the snapshot
+    pub version: String,
+    pub timer: Timer,
+    /// Totals and achievements, present when the module runs with --achievements
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stats: Option<Stats>,
+}
+
+/// When a persisting module writes its state: every tick, or with --autosave once the
+/// interval is up or one of its events happened
+#[derive(Debug, Default)]
+pub struct Autosave {
+    every: Option<Duration>,
+    events: Vec<AutosaveEvent>,
+    last: Option<Instant>,
+    pending: bool,
+}
+
+impl Autosave {
+    pub fn new(config: &Config) -> Self {
+        Self {
+            every: config.autosave,
+            events: config.autosave_on.clone(),
+            ..Default::default()
+        }
+    }
+
+    /// Notes that `event` happened, making the state due if it is one to save on
+    pub fn event(&mut self, event: AutosaveEvent) {
+        if self.events.contains(&event) {
+            self.pending = true;
+        }
+    }
+
+    /// Whether the state should be written at `now`
+    pub fn due(&self, now: Instant) -> bool {
+        match (self.every, self.last) {
+            (Some(every), Some(last)) => self.pending || now.duration_since(last) >= every,
+            _ => true,
+        }
+    }
+
+    pub fn saved(&mut self, now: Instant) {
+        self.last = Some(now);
+        self.pending = false;
+    }
+}
+
 pub fn store(state: &Timer) -> Result<(), Box<dyn Error>> {
-    let mut filepath = cache_dir()?;
+    let mut filepath = storage::local_dir()?;
     let output_name = format!("{MODULE}-{VERSION}");
     filepath.push(output_name);
 
@@ -24,19 +75,23 @@ pub fn store(state: &Timer) -> Result<(), Box<dyn Error>> {
 }
 
 pub fn restore(state: &mut Timer, config: &Config) -> Result<(), Box<dyn Error>> {
-    let mut filepath = cache_dir()?;
+    let mut filepath = storage::local_dir()?;
     let output_name = format!("{MODULE}-{VERSION}");
     filepath.push(output_name);
 
     restore_from_path(state, config, &filepath)
 }
 
-fn store_to_path(state: &Timer, filepath: &std::path::Path) -> Result<(), Box<dyn Error>> {
+pub fn store_to_path(state: &Timer, filepath: &std::path::Path) -> Result<(), Box<dyn Error>> {
     let data = serde_json::to_string(&state).expect("Not a serializable type");
-    Ok(File::create(filepath)?.write_all(data.as_bytes())?)
+    // write then rename so neither a crash nor a sync tool ever sees half a file
+    let mut tmp = filepath.as_os_str().to_owned();
+    tmp.push(".tmp");
+    File::create(&tmp)?.write_all(data.as_bytes())?;
+    Ok(fs::rename(tmp, filepath)?)
 }
 
-fn restore_from_path(
+pub fn restore_from_path(
     state: &mut Timer,
     config: &Config,
     filepath: &Path,
@@ -48,18 +103,44 @@ fn restore_from_path(
     let restored: Timer = serde_json::from_str(&content)?;
 
     if match_timers(config, &restored.times) {
-        state.current_index = restored.current_index;
-        state.elapsed_millis = restored.elapsed_millis;
-        state.elapsed_time = restored.elapsed_time;
-        state.times = restored.times;
-        state.iterations = restored.iterations;
-        state.session_completed = restored.session_completed;
-        state.running = restored.running;
+        // other instances share the local state file, their running doesn't count
+        state.dirty = restored.dirty && restored.socket_nr == state.socket_nr;
+        apply(state, restored);
+    } else {
+        // the task doesn't depend on the cycle lengths
+        state.task = restored.task;
     }
 
     Ok(())
 }
 
+/// Takes over the progress of `restored`, keeping `state`'s instance number
+pub fn apply(state: &mut Timer, restored: Timer) {
+    state.current_index = restored.current_index;
+    state.elapsed_millis = restored.elapsed_millis;
+    state.elapsed_time = restored.elapsed_time;
+    state.times = restored.times;
+    state.iterations = restored.iterations;
+    state.session_completed = restored.session_completed;
+    state.running = restored.running;
+    state.awaiting_start = restored.awaiting_start;
+    state.task = restored.task;
+    state.activity = restored.activity;
+    state.activities_given = restored.activities_given;
+    state.notes = restored.notes;
+    state.focus_millis = restored.focus_millis;
+    state.focus_day = restored.focus_day;
+    state.pomodoros_today = restored.pomodoros_today;
+    state.interruptions_today = restored.interruptions_today;
+    state.streak = restored.streak;
+    state.focus = restored.focus;
+    state.sprint = restored.sprint;
+    state.day = restored.day;
+    state.set_tally = restored.set_tally;
+    state.current_override = None;
+    state.grace_millis = None;
+}
+
 fn match_timers(config: &Config, times: &[u16; 3]) -> bool {
     let work_time: u16 = times[0];
     let short_break: u16 = times[1];
@@ -75,20 +156,6 @@ fn match_timers(config: &Config, times: &[u16; 3]) -> bool {
     true
 }
 
-fn cache_dir() -> Result<PathBuf, Box<dyn Error>> {
-    let mut dir = if let Some(dir) = dirs::cache_dir() {
-        dir
-    } else {
-        return Err("unable to get cache dir".into());
-    };
-
-    dir.push(MODULE);
-    if let Err(e) = std::fs::create_dir_all(&dir) {
-        error!("create_dir: path == {dir:?}, err == {e}");
-    }
-    Ok(dir)
-}
-
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -115,6 +182,27 @@ mod tests {
             running: false, // Default to false, we'll set it explicitly in tests when needed
             socket_nr: 0,
             current_override: None,
+            awaiting_start: false,
+            paused_since: None,
+            pause_cause: None,
+            pauses: Vec::new(),
+            grace_millis: None,
+            task: None,
+            activity: None,
+            activities_given: 0,
+            notes: Vec::new(),
+            focus_millis: 0,
+            focus_day: None,
+            pomodoros_today: 0,
+            interruptions_today: 0,
+            streak: 0,
+            focus: None,
+            sprint: None,
+            day: None,
+            dirty: false,
+            set_tally: Default::default(),
+            completed: Vec::new(),
+            micro_break: Default::default(),
         }
     }
 
@@ -227,17 +315,106 @@ mod tests {
     }
 
     #[test]
-    fn test_cache_dir_creation() -> Result<(), Box<dyn Error>> {
-        // We don't need to set env vars as we're not testing the cache path directly
-        let result = cache_dir()?;
+    fn test_restore_dirty_state() -> Result<(), Box<dyn Error>> {
+        let temp_file = NamedTempFile::new()?;
+        let config = Config {
+            work_time: 25,
+            short_break: 5,
+            long_break: 15,
+            ..Default::default()
+        };
+
+        let mut timer = create_timer(None, None, None);
+        timer.dirty = true;
+        store_to_path(&timer, temp_file.path())?;
+        let mut restored = create_timer(None, None, None);
+        restore_from_path(&mut restored, &config, temp_file.path())?;
+        assert!(restored.dirty);
+
+        // left behind by another instance that is still running
+        timer.socket_nr = 1;
+        store_to_path(&timer, temp_file.path())?;
+        let mut restored = create_timer(None, None, None);
+        restore_from_path(&mut restored, &config, temp_file.path())?;
+        assert!(!restored.dirty);
+
+        // a clean exit doesn't write the flag at all
+        timer.dirty = false;
+        store_to_path(&timer, temp_file.path())?;
+        assert!(!std::fs::read_to_string(temp_file.path())?.contains("dirty"));
+
+        Ok(())
+    }
 
-        // Just verify the cache directory exists and is a directory
+    #[test]
+    fn test_restore_near_the_end_with_jitter() -> Result<(), Box<dyn Error>> {
+        let temp_file = NamedTempFile::new()?;
+        let config = Config {
+            work_time: 1500,
+            short_break: 300,
+            long_break: 900,
+            jitter: 120,
+            ..Default::default()
+        };
+
+        let mut timer = create_timer(None, None, None);
+        timer.current_index = 0;
+        timer.times = [1500, 300, 900];
+        timer.elapsed_time = 1490;
+        timer.current_override = Some(1560);
+        store_to_path(&timer, temp_file.path())?;
+
+        for _ in 0..20 {
+            let mut restored = create_timer(None, None, None);
+            restore_from_path(&mut restored, &config, temp_file.path())?;
+            // the cycle is drawn again, never ending before where it already is
+            restored.plan_cycle(&config);
+            assert!(restored.get_current_time() >= 1490);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_state_dir_creation() -> Result<(), Box<dyn Error>> {
+        // We don't need to set env vars as we're not testing the state path directly
+        let result = storage::local_dir()?;
+
+        // Just verify the state directory exists and is a directory
         assert!(result.exists());
         assert!(result.is_dir());
 
         Ok(())
     }
 
+    #[test]
+    fn test_autosave() {
+        let start = Instant::now();
+        let mut autosave = Autosave::new(&Config::default());
+        autosave.saved(start);
+        assert!(
+            autosave.due(start),
+            "saves on every tick without --autosave"
+        );
+
+        let mut autosave = Autosave::new(&Config {
+            autosave: Some(Duration::from_secs(30)),
+            autosave_on: vec![AutosaveEvent::Cycle],
+            ..Default::default()
+        });
+        assert!(autosave.due(start), "the first tick always saves");
+        autosave.saved(start);
+        assert!(!autosave.due(start + Duration::from_secs(29)));
+        assert!(autosave.due(start + Duration::from_secs(30)));
+
+        autosave.event(AutosaveEvent::Command);
+        assert!(!autosave.due(start + Duration::from_secs(1)));
+        autosave.event(AutosaveEvent::Cycle);
+        assert!(autosave.due(start + Duration::from_secs(1)));
+        autosave.saved(start + Duration::from_secs(1));
+        assert!(!autosave.due(start + Duration::from_secs(2)));
+    }
+
     #[test]
     fn test_match_timers_match() {
         let config = Config {
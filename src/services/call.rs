@@ -0,0 +1,93 @@
+use std::{
+    process::Command,
+    time::{Duration, Instant},
+};
+
+use serde_json::Value;
+use tracing::debug;
+
+/// How often PipeWire is asked whether the microphone is in use
+const SAMPLE_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Whether an app is recording from a microphone, `None` if PipeWire can't be asked
+pub fn microphone_in_use() -> Option<bool> {
+    let output = Command::new("pw-dump").output().ok()?;
+    if !output.status.success() {
+        debug!("pw-dump exited with {}", output.status);
+        return None;
+    }
+    let objects: Value = serde_json::from_slice(&output.stdout).ok()?;
+    Some(recording(&objects))
+}
+
+/// Whether any of PipeWire's objects is an audio capture stream that is running
+fn recording(objects: &Value) -> bool {
+    objects
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter(|object| object["type"] == "PipeWire:Interface:Node")
+        .any(|node| {
+            node["info"]["props"]["media.class"] == "Stream/Input/Audio"
+                && node["info"]["state"] == "running"
+        })
+}
+
+/// Follows whether a call is going on, judging by the microphone
+#[derive(Debug, Default)]
+pub struct CallWatch {
+    last_sample: Option<Instant>,
+    in_call: bool,
+}
+
+impl CallWatch {
+    /// Looks at the microphone once the sample interval has passed, returning whether a
+    /// call is on when that changed
+    pub fn sample(&mut self) -> Option<bool> {
+        let now = Instant::now();
+        if self
+            .last_sample
+            .is_some_and(|last| now.duration_since(last) < SAMPLE_INTERVAL)
+        {
+            return None;
+        }
+        self.last_sample = Some(now);
+
+        let in_call = microphone_in_use().unwrap_or(false);
+        if in_call == self.in_call {
+            return None;
+        }
+        self.in_call = in_call;
+        Some(in_call)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_recording() {
+        let node = |class: &str, state: &str| {
+            json!({
+                "type": "PipeWire:Interface:Node",
+                "info": {"state": state, "props": {"media.class": class}}
+            })
+        };
+        let idle = json!([
+            {"type": "PipeWire:Interface:Client", "info": {}},
+            node("Audio/Source", "running"),
+            node("Stream/Output/Audio", "running"),
+            node("Stream/Input/Audio", "suspended"),
+        ]);
+        assert!(!recording(&idle));
+
+        let call = json!([
+            node("Audio/Source", "running"),
+            node("Stream/Input/Audio", "running")
+        ]);
+        assert!(recording(&call));
+        assert!(!recording(&json!({})));
+    }
+}
@@ -0,0 +1,121 @@
+use std::{
+    fs, panic,
+    path::{Path, PathBuf},
+    sync::{Mutex, OnceLock},
+    thread,
+};
+
+use tracing::error;
+
+use super::{cache, timer::Timer};
+
+static SOCKET_PATH: OnceLock<PathBuf> = OnceLock::new();
+
+/// Where the state goes with --sync-dir, the local versioned file when unset
+static STATE_PATH: OnceLock<PathBuf> = OnceLock::new();
+
+/// Name of the thread running the timer loop
+pub const TIMER_THREAD: &str = "timer";
+
+/// Threads whose panic brings the whole module down; a panicking client thread doesn't
+const FATAL_THREADS: [&str; 2] = ["main", TIMER_THREAD];
+
+/// Latest state of a persisting module, saved again if the process panics
+static LAST_STATE: Mutex<Option<Timer>> = Mutex::new(None);
+
+/// Removes the socket and persists the last known state when a fatal thread panics, so a
+/// crash doesn't leave a stale socket behind for `find_next_instance_number` to trip on
+pub fn install_panic_hook(socket_path: &Path) {
+    let _ = SOCKET_PATH.set(socket_path.to_owned());
+
+    let previous = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        error!("Module panicked: {}", info);
+        if thread::current()
+            .name()
+            .is_some_and(|name| FATAL_THREADS.contains(&name))
+        {
+            run();
+        }
+        previous(info);
+    }));
+}
+
+/// Persists the state to `path` rather than the local file should the module crash
+pub fn persist_to(path: &Path) {
+    let _ = STATE_PATH.set(path.to_owned());
+}
+
+/// Records the state to persist should the module crash
+pub fn remember_state(state: &Timer) {
+    *lock_state() = Some(state.clone());
+}
+
+/// Cleans up after a crash, safe to call more than once
+pub fn run() {
+    let state = lock_state().clone();
+    if let Some(socket_path) = SOCKET_PATH.get() {
+        clean_up(
+            socket_path,
+            state.as_ref(),
+            STATE_PATH.get().map(PathBuf::as_path),
+        );
+    }
+}
+
+fn clean_up(socket_path: &Path, state: Option<&Timer>, state_path: Option<&Path>) {
+    if let Some(state) = state {
+        let stored = match state_path {
+            Some(path) => cache::store_to_path(state, path),
+            None => cache::store(state),
+        };
+        if let Err(e) = stored {
+            error!("Failed to persist state: {}", e);
+        }
+    }
+    if socket_path.exists() {
+        let _ = fs::remove_file(socket_path);
+    }
+}
+
+// a panic while the lock is held must not stop the cleanup
+fn lock_state() -> std::sync::MutexGuard<'static, Option<Timer>> {
+    LAST_STATE.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::consts::{LONG_BREAK_TIME, SHORT_BREAK_TIME, WORK_TIME};
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_clean_up_removes_socket() {
+        let dir = tempdir().unwrap();
+        let socket_path = dir.path().join("module0.socket");
+        fs::write(&socket_path, "").unwrap();
+
+        clean_up(&socket_path, None, None);
+        assert!(!socket_path.exists());
+
+        // a second run finds nothing left to do
+        clean_up(&socket_path, None, None);
+    }
+
+    #[test]
+    fn test_clean_up_persists_to_state_path() {
+        let dir = tempdir().unwrap();
+        let state_path = dir.path().join("state-laptop.json");
+        let mut state = Timer::new(WORK_TIME, SHORT_BREAK_TIME, LONG_BREAK_TIME, 0);
+        state.elapsed_time = 90;
+
+        clean_up(
+            &dir.path().join("module0.socket"),
+            Some(&state),
+            Some(&state_path),
+        );
+        let stored: Timer =
+            serde_json::from_str(&fs::read_to_string(&state_path).unwrap()).unwrap();
+        assert_eq!(stored.elapsed_time, 90);
+    }
+}
@@ -0,0 +1,221 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use toml::{Table, Value};
+
+const MODULE: &str = env!("CARGO_PKG_NAME");
+
+/// `$XDG_CONFIG_HOME/waybar-module-pomodoro/config.toml`
+pub fn default_path() -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| dir.join(MODULE).join("config.toml"))
+}
+
+/// Reads the config file, `None` if there is none
+pub fn load(path: &Path) -> Result<Option<Table>, String> {
+    let content = match fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(format!("Cannot read {}: {}", path.display(), e)),
+    };
+    content
+        .parse()
+        .map(Some)
+        .map_err(|e| format!("Invalid config file {}: {}", path.display(), e))
+}
+
+/// The settings for one instance: the top level of the file, overridden by its
+/// `[instance.<number>]` section and then by its `[instance.<name>]` section
+///
+/// The `[ctl]` section is for `waybar-module-pomodoro-ctl` and left out.
+pub fn settings(file: &Table, instance: u16, name: Option<&str>) -> Table {
+    let mut settings = file.clone();
+    settings.remove("ctl");
+    let sections = settings.remove("instance");
+    let sections = sections.as_ref().and_then(Value::as_table);
+    let keys = [Some(instance.to_string()), name.map(str::to_string)];
+    for key in keys.iter().flatten() {
+        if let Some(section) = sections.and_then(|s| s.get(key)).and_then(Value::as_table) {
+            settings.extend(section.clone());
+        }
+    }
+    settings
+}
+
+/// Sets `key` to `value` in the config file at `path`, under `[instance.<section>]` if
+/// given, keeping the rest of the file and its comments as they are
+pub fn save_setting(
+    path: &Path,
+    section: Option<&str>,
+    key: &str,
+    value: &Value,
+) -> Result<(), String> {
+    let content = match fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+        Err(e) => return Err(format!("Cannot read {}: {}", path.display(), e)),
+    };
+    let mut document: toml_edit::DocumentMut = content
+        .parse()
+        .map_err(|e| format!("Invalid config file {}: {}", path.display(), e))?;
+
+    let mut value = match value {
+        Value::Integer(n) => toml_edit::Value::from(*n),
+        Value::String(s) => toml_edit::Value::from(s.as_str()),
+        Value::Boolean(b) => toml_edit::Value::from(*b),
+        _ => return Err(format!("Unsupported value for {key}")),
+    };
+    let table = match section {
+        Some(section) => {
+            let instances = document
+                .entry("instance")
+                .or_insert_with(|| {
+                    let mut table = toml_edit::Table::new();
+                    table.set_implicit(true);
+                    toml_edit::Item::Table(table)
+                })
+                .as_table_mut()
+                .ok_or("instance in the config file isn't a table")?;
+            instances
+                .entry(section)
+                .or_insert(toml_edit::table())
+                .as_table_mut()
+                .ok_or_else(|| format!("instance.{section} in the config file isn't a table"))?
+        }
+        None => document.as_table_mut(),
+    };
+    match table.get_mut(key).and_then(toml_edit::Item::as_value_mut) {
+        // in place, so the comments around it stay
+        Some(existing) => {
+            *value.decor_mut() = existing.decor().clone();
+            *existing = value;
+        }
+        None => {
+            table.insert(key, toml_edit::Item::Value(value));
+        }
+    }
+
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).map_err(|e| format!("Cannot create {}: {}", dir.display(), e))?;
+    }
+    fs::write(path, document.to_string())
+        .map_err(|e| format!("Cannot write {}: {}", path.display(), e))
+}
+
+/// Turns settings into the command line options they stand for, keys being the long
+/// option names: `work = 30` is `--work 30`, `autow = true` is `--autow` and a list
+/// repeats the option
+pub fn to_args(settings: &Table) -> Result<Vec<String>, String> {
+    let mut args = Vec::new();
+    for (key, value) in settings {
+        let values = match value {
+            Value::Array(values) => values.iter().collect(),
+            value => vec![value],
+        };
+        for value in values {
+            match value {
+                Value::Boolean(true) => args.push(format!("--{key}")),
+                Value::Boolean(false) => {}
+                Value::String(s) => args.extend([format!("--{key}"), s.clone()]),
+                Value::Integer(n) => args.extend([format!("--{key}"), n.to_string()]),
+                Value::Float(n) => args.extend([format!("--{key}"), n.to_string()]),
+                _ => return Err(format!("Unsupported value for {key} in the config file")),
+            }
+        }
+    }
+    Ok(args)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_settings_per_instance() {
+        let file: Table = r#"
+            work = 25
+            autow = true
+            checkpoint = [25, 75]
+
+            [instance.1]
+            work = 50
+            autow = false
+
+            [instance.coding]
+            work-icon = "C"
+
+            [ctl]
+            instance = "coding"
+        "#
+        .parse()
+        .unwrap();
+
+        let args = |instance, name| to_args(&settings(&file, instance, name)).unwrap();
+        assert_eq!(
+            args(0, None),
+            [
+                "--autow",
+                "--checkpoint",
+                "25",
+                "--checkpoint",
+                "75",
+                "--work",
+                "25"
+            ]
+        );
+        assert_eq!(
+            args(1, Some("coding")),
+            [
+                "--checkpoint",
+                "25",
+                "--checkpoint",
+                "75",
+                "--work",
+                "50",
+                "--work-icon",
+                "C"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_load() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+        assert_eq!(load(&path), Ok(None));
+
+        fs::write(&path, "work = ").unwrap();
+        assert!(load(&path).is_err());
+
+        fs::write(&path, "work = 30").unwrap();
+        assert_eq!(load(&path).unwrap().unwrap()["work"].as_integer(), Some(30));
+
+        let table: Table = "when = 1979-05-27".parse().unwrap();
+        assert!(to_args(&table).is_err());
+    }
+
+    #[test]
+    fn test_save_setting() {
+        let dir = tempdir().unwrap();
+        let path = dir
+            .path()
+            .join("waybar-module-pomodoro")
+            .join("config.toml");
+        save_setting(&path, None, "work", &Value::Integer(50)).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "work = 50\n");
+
+        fs::write(&path, "# my timers\nwork = 25\n\n[instance.1]\nwork = 40\n").unwrap();
+        save_setting(&path, None, "work", &Value::Integer(50)).unwrap();
+        save_setting(&path, Some("1"), "format", &Value::String("{time}".into())).unwrap();
+        save_setting(&path, Some("coding"), "work", &Value::Integer(90)).unwrap();
+        let content = fs::read_to_string(&path).unwrap();
+        assert!(content.starts_with("# my timers\nwork = 50\n"));
+
+        let file = load(&path).unwrap().unwrap();
+        let args = |instance, name| to_args(&settings(&file, instance, name)).unwrap();
+        assert_eq!(args(1, None), ["--format", "{time}", "--work", "40"]);
+        assert_eq!(args(0, Some("coding")), ["--work", "90"]);
+    }
+}
@@ -0,0 +1,134 @@
+use std::process::Command;
+
+use serde_json::Value;
+use tracing::{debug, warn};
+
+/// Other apps' audio turned down while a sound plays, turned back up when dropped
+#[derive(Debug, Default)]
+pub struct Ducked {
+    /// PipeWire node ids and the volume each had
+    volumes: Vec<(u32, f32)>,
+}
+
+/// Turns the audio other apps are playing down to `percent` of its volume, with wpctl
+pub fn duck(percent: u8) -> Ducked {
+    let mut ducked = Ducked::default();
+    let Some(objects) = pw_dump() else {
+        return ducked;
+    };
+    for id in other_streams(&objects, std::process::id()) {
+        let Some(volume) = get_volume(id) else {
+            continue;
+        };
+        if set_volume(id, volume * percent as f32 / 100.0) {
+            ducked.volumes.push((id, volume));
+        }
+    }
+    debug!("Ducked {} streams to {}%", ducked.volumes.len(), percent);
+    ducked
+}
+
+impl Drop for Ducked {
+    fn drop(&mut self) {
+        for (id, volume) in self.volumes.drain(..) {
+            set_volume(id, volume);
+        }
+    }
+}
+
+fn pw_dump() -> Option<Value> {
+    let output = Command::new("pw-dump").output().ok()?;
+    if !output.status.success() {
+        debug!("pw-dump exited with {}", output.status);
+        return None;
+    }
+    serde_json::from_slice(&output.stdout).ok()
+}
+
+/// The audio playback streams among PipeWire's objects, leaving out the ones of `own_pid`
+fn other_streams(objects: &Value, own_pid: u32) -> Vec<u32> {
+    objects
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter(|object| object["type"] == "PipeWire:Interface:Node")
+        .filter(|node| node["info"]["props"]["media.class"] == "Stream/Output/Audio")
+        .filter(|node| {
+            let pid = &node["info"]["props"]["application.process.id"];
+            let pid = pid
+                .as_u64()
+                .or_else(|| pid.as_str().and_then(|pid| pid.parse().ok()));
+            pid != Some(own_pid as u64)
+        })
+        .filter_map(|node| node["id"].as_u64()?.try_into().ok())
+        .collect()
+}
+
+fn get_volume(id: u32) -> Option<f32> {
+    let output = Command::new("wpctl")
+        .args(["get-volume", &id.to_string()])
+        .output()
+        .ok()?;
+    parse_volume(&String::from_utf8_lossy(&output.stdout))
+}
+
+/// The volume in wpctl's `Volume: 0.40 [MUTED]`
+fn parse_volume(output: &str) -> Option<f32> {
+    output
+        .trim()
+        .strip_prefix("Volume:")?
+        .split_whitespace()
+        .next()?
+        .parse()
+        .ok()
+}
+
+fn set_volume(id: u32, volume: f32) -> bool {
+    let status = Command::new("wpctl")
+        .args(["set-volume", &id.to_string(), &format!("{volume:.2}")])
+        .status();
+    match status {
+        Ok(status) if status.success() => true,
+        Ok(status) => {
+            debug!("wpctl set-volume {} exited with {}", id, status);
+            false
+        }
+        Err(e) => {
+            warn!("Failed to run wpctl: {}", e);
+            false
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_other_streams() {
+        let node = |id: u32, class: &str, pid: Value| {
+            json!({
+                "id": id,
+                "type": "PipeWire:Interface:Node",
+                "info": {"props": {"media.class": class, "application.process.id": pid}}
+            })
+        };
+        let objects = json!([
+            {"id": 30, "type": "PipeWire:Interface:Client", "info": {}},
+            node(40, "Audio/Sink", json!(null)),
+            node(50, "Stream/Output/Audio", json!(1234)),
+            node(51, "Stream/Output/Audio", json!("99")),
+            node(52, "Stream/Input/Audio", json!(1234)),
+        ]);
+        assert_eq!(other_streams(&objects, 99), [50]);
+        assert_eq!(other_streams(&objects, 1), [50, 51]);
+    }
+
+    #[test]
+    fn test_parse_volume() {
+        assert_eq!(parse_volume("Volume: 0.40\n"), Some(0.4));
+        assert_eq!(parse_volume("Volume: 1.00 [MUTED]\n"), Some(1.0));
+        assert_eq!(parse_volume("Object not found\n"), None);
+    }
+}
@@ -0,0 +1,164 @@
+use std::{
+    collections::BTreeMap,
+    env,
+    process::Command,
+    time::{Duration, Instant},
+};
+
+use serde_json::Value;
+use tracing::debug;
+
+/// How often the focused window is looked up during work cycles
+const SAMPLE_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Compositors whose IPC can tell us the focused window
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Compositor {
+    Hyprland,
+    Sway,
+}
+
+impl Compositor {
+    pub fn detect() -> Option<Compositor> {
+        if env::var_os("HYPRLAND_INSTANCE_SIGNATURE").is_some() {
+            Some(Compositor::Hyprland)
+        } else if env::var_os("SWAYSOCK").is_some() {
+            Some(Compositor::Sway)
+        } else {
+            None
+        }
+    }
+
+    /// Class (or app id) of the focused window
+    pub fn focused_app(self) -> Option<String> {
+        let (program, args): (&str, &[&str]) = match self {
+            Compositor::Hyprland => ("hyprctl", &["activewindow", "-j"]),
+            Compositor::Sway => ("swaymsg", &["-t", "get_tree"]),
+        };
+        let output = Command::new(program).args(args).output().ok()?;
+        if !output.status.success() {
+            debug!("{} exited with {}", program, output.status);
+            return None;
+        }
+
+        let tree: Value = serde_json::from_slice(&output.stdout).ok()?;
+        match self {
+            Compositor::Hyprland => hyprland_app(&tree),
+            Compositor::Sway => sway_app(&tree),
+        }
+    }
+}
+
+fn hyprland_app(window: &Value) -> Option<String> {
+    window["class"]
+        .as_str()
+        .filter(|class| !class.is_empty())
+        .map(str::to_string)
+}
+
+fn sway_app(node: &Value) -> Option<String> {
+    if node["focused"].as_bool() == Some(true) {
+        // native Wayland windows have an app id, Xwayland ones a class
+        return node["app_id"]
+            .as_str()
+            .or_else(|| node["window_properties"]["class"].as_str())
+            .map(str::to_string);
+    }
+    ["nodes", "floating_nodes"]
+        .iter()
+        .filter_map(|key| node[key].as_array())
+        .flatten()
+        .find_map(sway_app)
+}
+
+/// Adds up how long each app was focused while a work cycle was running
+#[derive(Debug)]
+pub struct AppTracker {
+    compositor: Compositor,
+    last_sample: Option<Instant>,
+    totals: BTreeMap<String, u64>,
+}
+
+impl AppTracker {
+    pub fn new(compositor: Compositor) -> Self {
+        Self {
+            compositor,
+            last_sample: None,
+            totals: BTreeMap::new(),
+        }
+    }
+
+    /// Samples the focused app if `active` and the sample interval has passed
+    pub fn sample(&mut self, active: bool) {
+        if !active {
+            self.last_sample = None;
+            return;
+        }
+
+        let now = Instant::now();
+        let since = match self.last_sample {
+            Some(last) if now.duration_since(last) < SAMPLE_INTERVAL => return,
+            Some(last) => now.duration_since(last),
+            None => Duration::ZERO,
+        };
+        self.last_sample = Some(now);
+
+        if let Some(app) = self.compositor.focused_app() {
+            self.add(app, since);
+        }
+    }
+
+    fn add(&mut self, app: String, focused_for: Duration) {
+        *self.totals.entry(app).or_default() += focused_for.as_secs();
+    }
+
+    /// Seconds per app since the last call
+    pub fn take(&mut self) -> BTreeMap<String, u64> {
+        std::mem::take(&mut self.totals)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_hyprland_app() {
+        assert_eq!(
+            hyprland_app(&json!({"class": "kitty", "title": "vim"})),
+            Some("kitty".to_string())
+        );
+        assert_eq!(hyprland_app(&json!({})), None);
+    }
+
+    #[test]
+    fn test_sway_app() {
+        let tree = json!({
+            "focused": false,
+            "nodes": [{
+                "focused": false,
+                "nodes": [
+                    {"focused": false, "app_id": "firefox"},
+                    {"focused": true, "app_id": null, "window_properties": {"class": "Code"}}
+                ]
+            }],
+            "floating_nodes": []
+        });
+        assert_eq!(sway_app(&tree), Some("Code".to_string()));
+        assert_eq!(sway_app(&json!({"focused": false, "nodes": []})), None);
+    }
+
+    #[test]
+    fn test_tracker_totals() {
+        let mut tracker = AppTracker::new(Compositor::Sway);
+        tracker.add("kitty".to_string(), Duration::from_secs(5));
+        tracker.add("kitty".to_string(), Duration::from_secs(10));
+        tracker.add("firefox".to_string(), Duration::from_secs(3));
+
+        let totals = tracker.take();
+        assert_eq!(totals["kitty"], 15);
+        assert_eq!(totals["firefox"], 3);
+        assert!(tracker.take().is_empty());
+    }
+}
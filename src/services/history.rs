@@ -0,0 +1,296 @@
+use std::{
+    collections::BTreeMap,
+    error::Error,
+    fs::{File, OpenOptions},
+    io::{BufRead, BufReader, Write},
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use super::timer::{CompletedCycle, CycleType, PauseCause, Task};
+
+/// One line of the history log
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "event", rename_all = "kebab-case")]
+pub enum HistoryEvent {
+    /// A cycle that ran to its end, times are unix seconds
+    Cycle {
+        cycle: CycleType,
+        started: u64,
+        ended: u64,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        task: Option<String>,
+        /// Seconds each app was focused, with --track-apps
+        #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+        apps: BTreeMap<String, u64>,
+        /// Activity suggested for a break, with --break-activity
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        activity: Option<String>,
+        /// Notes added with `annotate`
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        notes: Vec<String>,
+    },
+    /// A work cycle left paused for too long and given up on, with --abandon-after
+    Abandoned {
+        cycle: CycleType,
+        /// Seconds worked before the pause
+        worked: u64,
+        /// Seconds it sat paused
+        paused: u64,
+        ended: u64,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        task: Option<String>,
+    },
+    /// A work cycle was paused part way through and later resumed
+    Pause {
+        cause: PauseCause,
+        /// Seconds it was paused for
+        paused: u64,
+        ended: u64,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        task: Option<String>,
+    },
+    /// A cycle cut short by `reset` or by the module exiting, for `resume-last`
+    Interrupted {
+        cycle: CycleType,
+        /// Seconds of the cycle that had passed
+        elapsed: u64,
+        /// Seconds the cycle was set to last
+        duration: u64,
+        /// Work cycles done towards the long break
+        #[serde(default)]
+        iterations: u8,
+        ended: u64,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        task: Option<String>,
+    },
+    /// A task was replaced or cleared, recording how it went against its estimate
+    Task {
+        name: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        estimate: Option<u16>,
+        completed: u16,
+        ended: u64,
+    },
+}
+
+impl HistoryEvent {
+    pub fn cycle(
+        completed: CompletedCycle,
+        ended: u64,
+        task: Option<&Task>,
+        apps: BTreeMap<String, u64>,
+    ) -> Self {
+        HistoryEvent::Cycle {
+            cycle: completed.cycle,
+            started: ended.saturating_sub(completed.duration as u64),
+            ended,
+            task: task.map(|task| task.name.clone()),
+            apps,
+            activity: completed.activity,
+            notes: completed.notes,
+        }
+    }
+
+    pub fn task(task: &Task, ended: u64) -> Self {
+        HistoryEvent::Task {
+            name: task.name.clone(),
+            estimate: task.estimate,
+            completed: task.completed,
+            ended,
+        }
+    }
+
+    /// When the event happened, in unix seconds
+    pub fn ended(&self) -> u64 {
+        match self {
+            HistoryEvent::Cycle { ended, .. }
+            | HistoryEvent::Abandoned { ended, .. }
+            | HistoryEvent::Pause { ended, .. }
+            | HistoryEvent::Interrupted { ended, .. }
+            | HistoryEvent::Task { ended, .. } => *ended,
+        }
+    }
+}
+
+/// The latest interrupted cycle, unless a cycle has run to its end since
+pub fn last_interrupted(events: &[HistoryEvent]) -> Option<&HistoryEvent> {
+    events
+        .iter()
+        .rev()
+        .find(|event| {
+            matches!(
+                event,
+                HistoryEvent::Interrupted { .. } | HistoryEvent::Cycle { .. }
+            )
+        })
+        .filter(|event| matches!(event, HistoryEvent::Interrupted { .. }))
+}
+
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+pub fn append(path: &Path, event: &HistoryEvent) -> Result<(), Box<dyn Error>> {
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    writeln!(file, "{}", serde_json::to_string(event)?)?;
+    Ok(())
+}
+
+/// Adds older events to the log, rewriting it so it stays oldest first
+pub fn merge(path: &Path, events: Vec<HistoryEvent>) -> Result<(), Box<dyn Error>> {
+    let mut merged = read(path)?;
+    merged.extend(events);
+    merged.sort_by_key(HistoryEvent::ended);
+
+    let mut content = String::new();
+    for event in &merged {
+        content.push_str(&serde_json::to_string(event)?);
+        content.push('\n');
+    }
+    let temp = path.with_extension("jsonl.tmp");
+    std::fs::write(&temp, content)?;
+    std::fs::rename(&temp, path)?;
+    Ok(())
+}
+
+/// Reads every event in the log, skipping lines that can't be parsed
+pub fn read(path: &Path) -> Result<Vec<HistoryEvent>, Box<dyn Error>> {
+    let file = match File::open(path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e.into()),
+    };
+
+    let mut events = Vec::new();
+    for line in BufReader::new(file).lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        match serde_json::from_str(&line) {
+            Ok(event) => events.push(event),
+            Err(e) => warn!("Skipping history line '{}': {}", line, e),
+        }
+    }
+    Ok(events)
+}
+
+/// Reads and interleaves the logs of several machines, oldest event first
+pub fn read_all(paths: &[PathBuf]) -> Result<Vec<HistoryEvent>, Box<dyn Error>> {
+    let mut events = Vec::new();
+    for path in paths {
+        events.extend(read(path)?);
+    }
+    // stable, so events of the same second keep their order within a log
+    events.sort_by_key(HistoryEvent::ended);
+    Ok(events)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_append_and_read() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("history.jsonl");
+        assert_eq!(read(&path).unwrap(), vec![]);
+
+        let mut task = Task::new("RFC".to_string(), Some(4));
+        let cycle = HistoryEvent::cycle(
+            CompletedCycle {
+                cycle: CycleType::Work,
+                duration: 1500,
+                activity: None,
+                notes: vec!["outline".to_string()],
+            },
+            10_000,
+            Some(&task),
+            BTreeMap::from([("kitty".to_string(), 1200)]),
+        );
+        task.completed = 2;
+        let summary = HistoryEvent::task(&task, 10_100);
+
+        append(&path, &cycle).unwrap();
+        fs::OpenOptions::new()
+            .append(true)
+            .open(&path)
+            .unwrap()
+            .write_all(b"not json\n")
+            .unwrap();
+        append(&path, &summary).unwrap();
+
+        assert_eq!(read(&path).unwrap(), vec![cycle.clone(), summary.clone()]);
+        let content = fs::read_to_string(&path).unwrap();
+        assert!(content.starts_with(
+            r#"{"event":"cycle","cycle":"work","started":8500,"ended":10000,"task":"RFC","apps":{"kitty":1200},"notes":["outline"]}"#
+        ));
+
+        // another machine's log is merged in by time
+        let other = dir.path().join("history-laptop.jsonl");
+        let between = HistoryEvent::task(&task, 10_050);
+        append(&other, &between).unwrap();
+        assert_eq!(
+            read_all(&[path, other]).unwrap(),
+            vec![cycle, between, summary]
+        );
+    }
+
+    #[test]
+    fn test_merge() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("history.jsonl");
+        let task = |ended| HistoryEvent::task(&Task::new("RFC".to_string(), None), ended);
+
+        append(&path, &task(100)).unwrap();
+        append(&path, &task(300)).unwrap();
+        merge(&path, vec![task(200), task(50)]).unwrap();
+
+        assert_eq!(
+            read(&path).unwrap(),
+            vec![task(50), task(100), task(200), task(300)]
+        );
+    }
+
+    #[test]
+    fn test_last_interrupted() {
+        let interrupted = HistoryEvent::Interrupted {
+            cycle: CycleType::Work,
+            elapsed: 600,
+            duration: 1500,
+            iterations: 1,
+            ended: 10_000,
+            task: None,
+        };
+        let task = HistoryEvent::task(&Task::new("RFC".to_string(), None), 10_100);
+        let cycle = HistoryEvent::cycle(
+            CompletedCycle {
+                cycle: CycleType::Work,
+                duration: 1500,
+                activity: None,
+                notes: Vec::new(),
+            },
+            11_600,
+            None,
+            BTreeMap::new(),
+        );
+
+        assert_eq!(last_interrupted(&[]), None);
+        let events = [interrupted.clone(), task];
+        assert_eq!(last_interrupted(&events), Some(&interrupted));
+        // too late to go back once another cycle has finished
+        let events = [interrupted.clone(), cycle.clone()];
+        assert_eq!(last_interrupted(&events), None);
+        let events = [cycle, interrupted.clone()];
+        assert_eq!(last_interrupted(&events), Some(&interrupted));
+    }
+}
@@ -0,0 +1,177 @@
+use std::{
+    process::{Command, Stdio},
+    thread,
+};
+
+use clap::ValueEnum;
+use tracing::{debug, warn};
+
+use crate::models::config::Config;
+
+use super::workspace::SpecialWorkspace;
+
+/// Runs a shell command in the background, reaping it once it exits
+pub fn spawn_command(command: &str) {
+    debug!("Running hook: {}", command);
+    match shell(command).spawn() {
+        Ok(mut child) => {
+            let command = command.to_string();
+            thread::spawn(move || match child.wait() {
+                Ok(status) if !status.success() => {
+                    warn!("Hook '{}' exited with {}", command, status)
+                }
+                Ok(_) => {}
+                Err(e) => warn!("Failed to wait for hook '{}': {}", command, e),
+            });
+        }
+        Err(e) => warn!("Failed to run hook '{}': {}", command, e),
+    }
+}
+
+/// Runs a shell command and waits for it, for hooks that must finish before the module exits
+pub fn run_command(command: &str) {
+    debug!("Running hook: {}", command);
+    match shell(command).status() {
+        Ok(status) if !status.success() => warn!("Hook '{}' exited with {}", command, status),
+        Ok(_) => {}
+        Err(e) => warn!("Failed to run hook '{}': {}", command, e),
+    }
+}
+
+fn shell(command: &str) -> Command {
+    let mut cmd = Command::new("sh");
+    cmd.arg("-c")
+        .arg(command)
+        .stdin(Stdio::null())
+        // stdout belongs to Waybar
+        .stdout(Stdio::null());
+    cmd
+}
+
+/// Programs `--break-temperature` can tint the screen with
+#[derive(Debug, Clone, Copy, PartialEq, Default, ValueEnum)]
+pub enum TintTool {
+    #[default]
+    Gammastep,
+    /// Kept running for the break and killed at its end, along with any other wlsunset
+    Wlsunset,
+}
+
+impl TintTool {
+    /// Commands that tint the screen to `kelvin` at break start and undo it at break end
+    pub fn commands(self, kelvin: u16) -> (String, String) {
+        match self {
+            TintTool::Gammastep => (
+                format!("gammastep -P -O {kelvin}"),
+                "gammastep -x".to_string(),
+            ),
+            // wlsunset wants a day temperature above the night one, a kelvin apart holds it
+            // at `kelvin` whatever the time of day
+            TintTool::Wlsunset => (
+                format!("wlsunset -t {kelvin} -T {} -S 06:00 -s 18:00", kelvin + 1),
+                "pkill -x wlsunset".to_string(),
+            ),
+        }
+    }
+}
+
+/// Fires the break start/end commands as the timer moves in and out of breaks
+#[derive(Debug, Default)]
+pub struct BreakHooks {
+    in_break: bool,
+}
+
+impl BreakHooks {
+    pub fn update(&mut self, in_break: bool, config: &Config) {
+        if in_break == self.in_break {
+            return;
+        }
+        self.in_break = in_break;
+
+        let command = if in_break {
+            &config.break_start_command
+        } else {
+            &config.break_end_command
+        };
+        if let Some(command) = command {
+            spawn_command(command);
+        }
+        if let Some(name) = &config.break_workspace {
+            let workspace = SpecialWorkspace::new(name);
+            // asks Hyprland first, so keep it off the timer thread
+            thread::spawn(move || {
+                if in_break {
+                    workspace.show()
+                } else {
+                    workspace.hide()
+                }
+            });
+        }
+    }
+
+    /// Restores whatever the break start command changed before the module exits
+    pub fn finish(&mut self, config: &Config) {
+        if std::mem::take(&mut self.in_break) {
+            if let Some(command) = &config.break_end_command {
+                run_command(command);
+            }
+            if let Some(name) = &config.break_workspace {
+                SpecialWorkspace::new(name).hide();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{
+        fs,
+        thread::sleep,
+        time::{Duration, Instant},
+    };
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_break_hooks_fire_on_edges() {
+        let dir = tempdir().unwrap();
+        let log = dir.path().join("hooks.log");
+        let config = Config {
+            break_start_command: Some(format!("echo start >> {}", log.display())),
+            break_end_command: Some(format!("echo end >> {}", log.display())),
+            ..Default::default()
+        };
+
+        let mut hooks = BreakHooks::default();
+        hooks.update(false, &config);
+        assert!(!log.exists());
+        hooks.update(true, &config);
+        hooks.update(true, &config);
+        // the start hook runs in the background, wait for it before the end hook races it
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while !log.exists() && Instant::now() < deadline {
+            sleep(Duration::from_millis(10));
+        }
+        hooks.finish(&config);
+
+        assert_eq!(fs::read_to_string(&log).unwrap(), "start\nend\n");
+        // nothing left to undo
+        hooks.finish(&config);
+        assert_eq!(fs::read_to_string(&log).unwrap(), "start\nend\n");
+    }
+
+    #[test]
+    fn test_tint_commands() {
+        assert_eq!(
+            TintTool::Gammastep.commands(3500),
+            (
+                "gammastep -P -O 3500".to_string(),
+                "gammastep -x".to_string()
+            )
+        );
+        assert_eq!(
+            TintTool::Wlsunset.commands(3500).0,
+            "wlsunset -t 3500 -T 3501 -S 06:00 -s 18:00"
+        );
+    }
+}
@@ -0,0 +1,152 @@
+use chrono::{DateTime, NaiveDate, Utc};
+
+use super::{
+    history::HistoryEvent,
+    report::{self, format_duration},
+    timer::CycleType,
+};
+
+/// iCalendar lines may be at most this many bytes, longer ones are folded
+const MAX_LINE: usize = 75;
+
+/// The completed work cycles that ended on or after `since` as an iCalendar file, one
+/// event each with the task as its summary
+pub fn to_ics(events: &[HistoryEvent], since: NaiveDate) -> String {
+    let mut out = String::new();
+    push_line(&mut out, "BEGIN:VCALENDAR");
+    push_line(&mut out, "VERSION:2.0");
+    push_line(&mut out, "PRODID:-//sw1nn//waybar-module-pomodoro//EN");
+    push_line(&mut out, "CALSCALE:GREGORIAN");
+
+    for event in events {
+        let HistoryEvent::Cycle {
+            cycle: CycleType::Work,
+            started,
+            ended,
+            task,
+            notes,
+            ..
+        } = event
+        else {
+            continue;
+        };
+        if report::local_date(*ended).is_none_or(|day| day < since) {
+            continue;
+        }
+        let (Some(start), Some(end)) = (utc_time(*started), utc_time(*ended)) else {
+            continue;
+        };
+        let summary = task.as_deref().unwrap_or("Pomodoro");
+
+        push_line(&mut out, "BEGIN:VEVENT");
+        push_line(
+            &mut out,
+            &format!("UID:{started}-{ended}@waybar-module-pomodoro"),
+        );
+        push_line(&mut out, &format!("DTSTAMP:{end}"));
+        push_line(&mut out, &format!("DTSTART:{start}"));
+        push_line(&mut out, &format!("DTEND:{end}"));
+        push_line(&mut out, &format!("SUMMARY:{}", escape_text(summary)));
+        let mut description = format!(
+            "{} of focus",
+            format_duration(ended.saturating_sub(*started))
+        );
+        for note in notes {
+            description.push_str(&format!("\n{note}"));
+        }
+        push_line(
+            &mut out,
+            &format!("DESCRIPTION:{}", escape_text(&description)),
+        );
+        push_line(&mut out, "END:VEVENT");
+    }
+
+    push_line(&mut out, "END:VCALENDAR");
+    out
+}
+
+/// `20231114T221320Z`
+fn utc_time(timestamp: u64) -> Option<String> {
+    DateTime::<Utc>::from_timestamp(timestamp as i64, 0)
+        .map(|time| time.format("%Y%m%dT%H%M%SZ").to_string())
+}
+
+fn escape_text(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace(';', "\\;")
+        .replace(',', "\\,")
+        .replace('\n', "\\n")
+}
+
+/// Appends a content line ending in CRLF, folded onto continuation lines that start with
+/// a space when it's too long
+fn push_line(out: &mut String, line: &str) {
+    let mut width = 0;
+    for c in line.chars() {
+        if width + c.len_utf8() > MAX_LINE {
+            out.push_str("\r\n ");
+            // the space counts towards the continuation line
+            width = 1;
+        }
+        out.push(c);
+        width += c.len_utf8();
+    }
+    out.push_str("\r\n");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn work(ended: u64, task: Option<&str>, notes: &[&str]) -> HistoryEvent {
+        HistoryEvent::Cycle {
+            cycle: CycleType::Work,
+            started: ended - 1500,
+            ended,
+            task: task.map(str::to_string),
+            apps: Default::default(),
+            activity: None,
+            notes: notes.iter().map(|note| note.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn test_to_ics() {
+        let events = vec![
+            work(1_700_000_000, Some("RFC, draft; v2"), &["parser"]),
+            HistoryEvent::Cycle {
+                cycle: CycleType::ShortBreak,
+                started: 1_700_000_000,
+                ended: 1_700_000_300,
+                task: None,
+                apps: Default::default(),
+                activity: None,
+                notes: Vec::new(),
+            },
+            work(1_700_002_000, None, &[]),
+        ];
+        let ics = to_ics(&events, NaiveDate::MIN);
+
+        assert!(ics.starts_with("BEGIN:VCALENDAR\r\nVERSION:2.0\r\n"));
+        assert!(ics.ends_with("END:VCALENDAR\r\n"));
+        assert_eq!(ics.matches("BEGIN:VEVENT").count(), 2);
+        assert!(ics.contains("DTSTART:20231114T214820Z\r\nDTEND:20231114T221320Z\r\n"));
+        assert!(ics.contains("SUMMARY:RFC\\, draft\\; v2\r\n"));
+        assert!(ics.contains("DESCRIPTION:25m of focus\\nparser\r\n"));
+        assert!(ics.contains("SUMMARY:Pomodoro\r\n"));
+
+        assert!(!to_ics(&events, NaiveDate::MAX).contains("BEGIN:VEVENT"));
+    }
+
+    #[test]
+    fn test_push_line_folds() {
+        let mut out = String::new();
+        push_line(&mut out, &format!("SUMMARY:{}", "é".repeat(40)));
+
+        let lines: Vec<&str> = out.split("\r\n").collect();
+        assert_eq!(lines.len(), 3);
+        assert!(lines.iter().all(|line| line.len() <= MAX_LINE));
+        assert!(lines[1].starts_with(' '));
+        assert_eq!(lines.concat().replace(" é", "é").matches('é').count(), 40);
+    }
+}
@@ -0,0 +1,306 @@
+use std::{
+    collections::{BTreeMap, HashSet},
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use chrono::{DateTime, Local, NaiveDateTime, TimeZone};
+use clap::ValueEnum;
+use serde_json::Value;
+
+use super::{history::HistoryEvent, timer::CycleType};
+
+/// Other timers whose logs `import --from` understands
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum ImportSource {
+    /// GNOME Pomodoro's database, read with the sqlite3 command
+    GnomePomodoro,
+    /// A CSV file with `start` and `end` or `duration` columns, `task` and `type` optional
+    Csv,
+}
+
+impl ImportSource {
+    /// Where the timer keeps its log when no path is given
+    pub fn default_path(self) -> Option<PathBuf> {
+        match self {
+            ImportSource::GnomePomodoro => {
+                Some(dirs::data_dir()?.join("gnome-pomodoro/database.sqlite"))
+            }
+            ImportSource::Csv => None,
+        }
+    }
+
+    /// The completed cycles in the log at `path`
+    pub fn read(self, path: &Path) -> Result<Vec<HistoryEvent>, String> {
+        match self {
+            ImportSource::GnomePomodoro => from_gnome_pomodoro(&gnome_pomodoro_entries(path)?),
+            ImportSource::Csv => {
+                let text = std::fs::read_to_string(path)
+                    .map_err(|e| format!("Cannot read {}: {e}", path.display()))?;
+                from_csv(&text)
+            }
+        }
+    }
+}
+
+fn gnome_pomodoro_entries(path: &Path) -> Result<Value, String> {
+    let output = Command::new("sqlite3")
+        .arg("-json")
+        .arg(path)
+        .arg("SELECT state_name, state_duration, elapsed, datetime_string FROM entries")
+        .output()
+        .map_err(|e| format!("Cannot run sqlite3: {e}"))?;
+    if !output.status.success() {
+        return Err(format!(
+            "sqlite3 cannot read {}: {}",
+            path.display(),
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    // sqlite3 prints nothing at all for an empty table
+    if output.stdout.iter().all(u8::is_ascii_whitespace) {
+        return Ok(Value::Array(Vec::new()));
+    }
+    serde_json::from_slice(&output.stdout).map_err(|e| format!("Unexpected sqlite3 output: {e}"))
+}
+
+/// GNOME Pomodoro's `entries` rows, skipping the cycles that were cut short
+fn from_gnome_pomodoro(rows: &Value) -> Result<Vec<HistoryEvent>, String> {
+    let rows = rows
+        .as_array()
+        .ok_or("GNOME Pomodoro entries are not a list")?;
+    let mut events = Vec::new();
+    for row in rows {
+        let cycle = match row["state_name"].as_str() {
+            Some("pomodoro") => CycleType::Work,
+            Some("short-break") => CycleType::ShortBreak,
+            Some("long-break") => CycleType::LongBreak,
+            _ => continue,
+        };
+        let (Some(duration), Some(elapsed)) =
+            (row["state_duration"].as_f64(), row["elapsed"].as_f64())
+        else {
+            continue;
+        };
+        let Some(started) = row["datetime_string"].as_str().and_then(parse_time) else {
+            continue;
+        };
+        // a second of slack, it stores fractions of one
+        if elapsed + 1.0 < duration {
+            continue;
+        }
+        events.push(cycle_event(cycle, started, started + elapsed as u64, None));
+    }
+    Ok(events)
+}
+
+/// Rows of a CSV export, each a completed cycle. Times are RFC 3339, `YYYY-MM-DD HH:MM`
+/// in local time or unix seconds, and durations are minutes
+fn from_csv(text: &str) -> Result<Vec<HistoryEvent>, String> {
+    let mut lines = text.lines().filter(|line| !line.trim().is_empty());
+    let header: Vec<String> = split_csv_line(lines.next().ok_or("The CSV file is empty")?)
+        .into_iter()
+        .map(|name| name.trim().to_lowercase())
+        .collect();
+    let column = |names: &[&str]| {
+        header
+            .iter()
+            .position(|name| names.contains(&name.as_str()))
+    };
+    let start = column(&["start", "started"]).ok_or("The CSV file has no start column")?;
+    let end = column(&["end", "ended"]);
+    let duration = column(&["duration"]);
+    let task = column(&["task"]);
+    let kind = column(&["type", "cycle"]);
+    if end.is_none() && duration.is_none() {
+        return Err("The CSV file needs an end or a duration column".to_string());
+    }
+
+    let mut events = Vec::new();
+    for (number, line) in lines.enumerate() {
+        let fields = split_csv_line(line);
+        let field = |index: Option<usize>| {
+            index
+                .and_then(|index| fields.get(index))
+                .map(|field| field.trim())
+                .filter(|field| !field.is_empty())
+        };
+        let row = number + 2;
+
+        let started = field(Some(start))
+            .and_then(parse_time)
+            .ok_or_else(|| format!("Row {row}: cannot read the start time"))?;
+        let ended = match (field(end), field(duration)) {
+            (Some(end), _) => parse_time(end),
+            (None, Some(minutes)) => minutes
+                .parse::<f64>()
+                .ok()
+                .map(|minutes| started + (minutes * 60.0) as u64),
+            (None, None) => None,
+        }
+        .filter(|ended| *ended >= started)
+        .ok_or_else(|| format!("Row {row}: cannot read the end time or duration"))?;
+        let cycle = match field(kind).map(str::to_lowercase).as_deref() {
+            None | Some("work" | "pomodoro") => CycleType::Work,
+            Some("short-break" | "short break" | "break") => CycleType::ShortBreak,
+            Some("long-break" | "long break") => CycleType::LongBreak,
+            Some(other) => return Err(format!("Row {row}: unknown cycle type '{other}'")),
+        };
+
+        events.push(cycle_event(
+            cycle,
+            started,
+            ended,
+            field(task).map(str::to_string),
+        ));
+    }
+    Ok(events)
+}
+
+fn cycle_event(cycle: CycleType, started: u64, ended: u64, task: Option<String>) -> HistoryEvent {
+    HistoryEvent::Cycle {
+        cycle,
+        started,
+        ended,
+        task,
+        apps: BTreeMap::new(),
+        activity: None,
+        notes: Vec::new(),
+    }
+}
+
+/// Splits a CSV line on commas outside double quotes, `""` being a quote within them
+fn split_csv_line(line: &str) -> Vec<String> {
+    let mut fields = vec![String::new()];
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                chars.next();
+                fields.last_mut().unwrap().push('"');
+            }
+            '"' => quoted = !quoted,
+            ',' if !quoted => fields.push(String::new()),
+            c => fields.last_mut().unwrap().push(c),
+        }
+    }
+    fields
+}
+
+/// Unix seconds from RFC 3339, a local `YYYY-MM-DD HH:MM[:SS]` or unix seconds
+fn parse_time(text: &str) -> Option<u64> {
+    if let Ok(seconds) = text.parse::<u64>() {
+        return Some(seconds);
+    }
+    if let Ok(time) = DateTime::parse_from_rfc3339(text) {
+        return time.timestamp().try_into().ok();
+    }
+    let text = text.replacen('T', " ", 1);
+    let time = NaiveDateTime::parse_from_str(&text, "%Y-%m-%d %H:%M:%S")
+        .or_else(|_| NaiveDateTime::parse_from_str(&text, "%Y-%m-%d %H:%M"))
+        .ok()?;
+    Local
+        .from_local_datetime(&time)
+        .earliest()?
+        .timestamp()
+        .try_into()
+        .ok()
+}
+
+/// The imported cycles that aren't in the history already, by kind and times, oldest first
+pub fn new_events(imported: Vec<HistoryEvent>, existing: &[HistoryEvent]) -> Vec<HistoryEvent> {
+    let key = |event: &HistoryEvent| match event {
+        HistoryEvent::Cycle {
+            cycle,
+            started,
+            ended,
+            ..
+        } => Some((*cycle as u8, *started, *ended)),
+        _ => None,
+    };
+    let mut seen: HashSet<_> = existing.iter().filter_map(key).collect();
+    let mut events: Vec<_> = imported
+        .into_iter()
+        .filter(|event| key(event).is_some_and(|key| seen.insert(key)))
+        .collect();
+    events.sort_by_key(HistoryEvent::ended);
+    events
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_from_gnome_pomodoro() {
+        let rows = json!([
+            {"state_name": "pomodoro", "state_duration": 1500.0, "elapsed": 1500.4,
+             "datetime_string": "2023-11-14T22:13:20.123456Z"},
+            {"state_name": "short-break", "state_duration": 300.0, "elapsed": 299.6,
+             "datetime_string": "2023-11-14T22:38:20+00:00"},
+            // skipped half way through
+            {"state_name": "pomodoro", "state_duration": 1500.0, "elapsed": 700.0,
+             "datetime_string": "2023-11-14T22:43:20Z"},
+            {"state_name": "null", "state_duration": 0.0, "elapsed": 0.0,
+             "datetime_string": "2023-11-14T23:00:00Z"},
+        ]);
+        let events = from_gnome_pomodoro(&rows).unwrap();
+
+        assert_eq!(
+            events,
+            vec![
+                cycle_event(CycleType::Work, 1_700_000_000, 1_700_001_500, None),
+                cycle_event(CycleType::ShortBreak, 1_700_001_500, 1_700_001_799, None),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_from_csv() {
+        let csv = "Start,Duration,Task,Type\n\
+                   1700000000,25,\"RFC, draft \"\"v2\"\"\",work\n\
+                   2023-11-14T22:38:20Z,5,,short break\n";
+        let events = from_csv(csv).unwrap();
+        assert_eq!(
+            events,
+            vec![
+                cycle_event(
+                    CycleType::Work,
+                    1_700_000_000,
+                    1_700_001_500,
+                    Some("RFC, draft \"v2\"".to_string())
+                ),
+                cycle_event(CycleType::ShortBreak, 1_700_001_500, 1_700_001_800, None),
+            ]
+        );
+
+        let csv = "start,end\n1700000000,1700001500\n";
+        assert_eq!(from_csv(csv).unwrap().len(), 1);
+        assert!(from_csv("start,task\n1700000000,RFC\n").is_err());
+        assert_eq!(
+            from_csv("start,end\nyesterday,1700001500\n").unwrap_err(),
+            "Row 2: cannot read the start time"
+        );
+    }
+
+    #[test]
+    fn test_new_events() {
+        let existing = vec![cycle_event(CycleType::Work, 100, 1600, Some("RFC".into()))];
+        let imported = vec![
+            cycle_event(CycleType::Work, 5000, 6500, None),
+            cycle_event(CycleType::Work, 100, 1600, None),
+            cycle_event(CycleType::Work, 2000, 3500, None),
+            cycle_event(CycleType::Work, 2000, 3500, None),
+        ];
+
+        assert_eq!(
+            new_events(imported, &existing),
+            vec![
+                cycle_event(CycleType::Work, 2000, 3500, None),
+                cycle_event(CycleType::Work, 5000, 6500, None),
+            ]
+        );
+    }
+}
@@ -0,0 +1,86 @@
+use std::{
+    fs::{File, OpenOptions, TryLockError},
+    path::{Path, PathBuf},
+    thread,
+    time::Instant,
+};
+
+use tracing::info;
+
+use crate::{
+    models::message::Message,
+    utils::consts::{REPLACE_TIMEOUT, SLEEP_DURATION},
+};
+
+use super::module::send_message_socket;
+
+/// Lock file guarding the instance that owns `socket_path`
+pub fn lock_path(socket_path: &Path) -> PathBuf {
+    socket_path.with_extension("lock")
+}
+
+/// Takes the instance lock so two modules can't fight over the same socket. With `replace`
+/// a running module is asked to exit first, otherwise it is an error. The lock is held for
+/// as long as the returned file is open.
+pub fn acquire(socket_path: &Path, replace: bool) -> Result<File, String> {
+    let path = lock_path(socket_path);
+    let file = OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(&path)
+        .map_err(|e| format!("Cannot open lock file {}: {}", path.display(), e))?;
+
+    if try_lock(&file, &path)? {
+        return Ok(file);
+    }
+
+    if !replace {
+        return Err(format!(
+            "Another module is already running on {} (use --replace to take over)",
+            socket_path.display()
+        ));
+    }
+
+    info!("Asking the running module to exit");
+    let _ = send_message_socket(&socket_path.to_string_lossy(), &Message::Exit.encode());
+
+    let started = Instant::now();
+    while started.elapsed() < REPLACE_TIMEOUT {
+        thread::sleep(SLEEP_DURATION);
+        if try_lock(&file, &path)? {
+            return Ok(file);
+        }
+    }
+    Err(format!(
+        "The module running on {} did not exit",
+        socket_path.display()
+    ))
+}
+
+fn try_lock(file: &File, path: &Path) -> Result<bool, String> {
+    match file.try_lock() {
+        Ok(()) => Ok(true),
+        Err(TryLockError::WouldBlock) => Ok(false),
+        Err(TryLockError::Error(e)) => Err(format!("Cannot lock {}: {}", path.display(), e)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_acquire_is_exclusive() {
+        let dir = tempdir().unwrap();
+        let socket_path = dir.path().join("module0.socket");
+
+        let lock = acquire(&socket_path, false).unwrap();
+        assert!(lock_path(&socket_path).exists());
+        assert!(acquire(&socket_path, false).is_err());
+
+        drop(lock);
+        assert!(acquire(&socket_path, false).is_ok());
+    }
+}
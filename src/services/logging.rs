@@ -0,0 +1,186 @@
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex, OnceLock,
+    },
+};
+
+use tracing::info;
+use tracing_subscriber::{reload, EnvFilter, Registry};
+
+use crate::models::message::{LogLevel, Switch};
+
+pub type ReloadHandle = reload::Handle<EnvFilter, Registry>;
+
+static RELOAD_HANDLE: OnceLock<ReloadHandle> = OnceLock::new();
+
+/// Most recently requested level, restored when logging is switched back on
+static LOG_LEVEL: Mutex<LogLevel> = Mutex::new(LogLevel::Debug);
+static LOGGING_ENABLED: AtomicBool = AtomicBool::new(true);
+
+fn filter_for(level: LogLevel, enabled: bool) -> EnvFilter {
+    if enabled {
+        env_filter(level)
+    } else {
+        EnvFilter::new("off")
+    }
+}
+
+/// Builds the module's log filter, honouring RUST_LOG for everything but our own crate
+pub fn env_filter(level: LogLevel) -> EnvFilter {
+    EnvFilter::from_default_env().add_directive(
+        format!("waybar_module_pomodoro={}", level.as_str())
+            .parse()
+            .unwrap(),
+    )
+}
+
+/// Remembers the handle used to swap the log filter at runtime, set once during startup
+pub fn install_reload_handle(handle: ReloadHandle) {
+    if RELOAD_HANDLE.set(handle).is_err() {
+        tracing::warn!("Log reload handle installed twice, keeping the first one");
+    }
+}
+
+fn reload(filter: EnvFilter) -> Result<(), String> {
+    let handle = RELOAD_HANDLE
+        .get()
+        .ok_or_else(|| "Logging is disabled, start the module with --log".to_string())?;
+
+    handle
+        .reload(filter)
+        .map_err(|e| format!("Failed to change log filter: {e}"))
+}
+
+pub fn set_log_level(level: LogLevel) -> Result<(), String> {
+    reload(filter_for(level, LOGGING_ENABLED.load(Ordering::SeqCst)))?;
+    *LOG_LEVEL.lock().unwrap() = level;
+    info!("Log level changed to {}", level.as_str());
+    Ok(())
+}
+
+/// Turns logging off entirely, or back on at the last level that was set
+pub fn set_logging(switch: Switch) -> Result<(), String> {
+    let enabled = switch.apply(LOGGING_ENABLED.load(Ordering::SeqCst));
+    let level = *LOG_LEVEL.lock().unwrap();
+
+    if !enabled {
+        info!("Logging disabled");
+    }
+    reload(filter_for(level, enabled))?;
+    LOGGING_ENABLED.store(enabled, Ordering::SeqCst);
+    if enabled {
+        info!("Logging enabled at {}", level.as_str());
+    }
+    Ok(())
+}
+
+/// Log file writer that rotates once the file grows past `max_bytes`, keeping `keep` old
+/// files around as `<name>.1` (newest) to `<name>.<keep>` (oldest)
+pub struct SizeRotatingWriter {
+    path: PathBuf,
+    max_bytes: u64,
+    keep: usize,
+    file: File,
+    size: u64,
+}
+
+impl SizeRotatingWriter {
+    pub fn new(path: impl AsRef<Path>, max_bytes: u64, keep: usize) -> io::Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        let size = file.metadata()?.len();
+        Ok(Self {
+            path,
+            max_bytes,
+            keep,
+            file,
+            size,
+        })
+    }
+
+    fn rotated_path(&self, index: usize) -> PathBuf {
+        let mut name = self.path.as_os_str().to_owned();
+        name.push(format!(".{index}"));
+        PathBuf::from(name)
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        self.file.flush()?;
+        if self.keep > 0 {
+            // shift every old file up by one, the oldest falls off the end
+            for index in (1..self.keep).rev() {
+                let from = self.rotated_path(index);
+                if from.exists() {
+                    fs::rename(&from, self.rotated_path(index + 1))?;
+                }
+            }
+            fs::rename(&self.path, self.rotated_path(1))?;
+        }
+
+        self.file = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .truncate(true)
+            .open(&self.path)?;
+        self.size = 0;
+        Ok(())
+    }
+}
+
+impl Write for SizeRotatingWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.size > 0 && self.size + buf.len() as u64 > self.max_bytes {
+            self.rotate()?;
+        }
+        let written = self.file.write(buf)?;
+        self.size += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_set_log_level_without_logging() {
+        // no subscriber is installed in tests, so there is nothing to reload
+        assert!(set_log_level(LogLevel::Info).is_err());
+        assert!(set_logging(Switch::Off).is_err());
+    }
+
+    #[test]
+    fn test_size_rotating_writer() -> io::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("pomodoro.log");
+        let mut writer = SizeRotatingWriter::new(&path, 10, 2)?;
+
+        writer.write_all(b"first-123\n")?;
+        writer.write_all(b"second-12\n")?;
+        writer.write_all(b"third-123\n")?;
+        writer.write_all(b"fourth-12\n")?;
+        writer.flush()?;
+
+        assert_eq!(fs::read_to_string(&path)?, "fourth-12\n");
+        assert_eq!(
+            fs::read_to_string(dir.path().join("pomodoro.log.1"))?,
+            "third-123\n"
+        );
+        assert_eq!(
+            fs::read_to_string(dir.path().join("pomodoro.log.2"))?,
+            "second-12\n"
+        );
+        // only `keep` rotated files are retained
+        assert!(!dir.path().join("pomodoro.log.3").exists());
+
+        Ok(())
+    }
+}
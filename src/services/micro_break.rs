@@ -0,0 +1,99 @@
+use std::time::Duration;
+
+use crate::models::config::Config;
+
+/// A short eye break on its own schedule, e.g. looking away for 20 seconds every 20
+/// minutes, that leaves the pomodoro cycles alone
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MicroBreak {
+    /// Work time since the last micro-break, in milliseconds
+    worked_millis: u64,
+    /// Milliseconds left of the current micro-break
+    remaining_millis: Option<u64>,
+}
+
+impl MicroBreak {
+    /// Moves the schedule on by `millis`, returning true when a micro-break begins
+    ///
+    /// Only running work counts towards the next micro-break, and a pomodoro break rests
+    /// the eyes well enough to start the count over.
+    pub fn advance(&mut self, millis: u32, working: bool, in_break: bool, config: &Config) -> bool {
+        let Some(every) = config.micro_break_every else {
+            return false;
+        };
+        if in_break {
+            *self = MicroBreak::default();
+            return false;
+        }
+
+        if let Some(remaining) = self.remaining_millis {
+            let remaining = remaining.saturating_sub(millis as u64);
+            self.remaining_millis = (remaining > 0).then_some(remaining);
+            return false;
+        }
+        if !working {
+            return false;
+        }
+
+        self.worked_millis += millis as u64;
+        if self.worked_millis < every.as_millis() as u64 {
+            return false;
+        }
+        self.worked_millis = 0;
+        self.remaining_millis = Some(config.micro_break_length.as_millis() as u64);
+        true
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.remaining_millis.is_some()
+    }
+
+    /// Time left of the current micro-break, rounded up to whole seconds
+    pub fn remaining(&self) -> Option<Duration> {
+        self.remaining_millis
+            .map(|millis| Duration::from_secs(millis.div_ceil(1000)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> Config {
+        Config {
+            micro_break_every: Some(Duration::from_secs(60)),
+            micro_break_length: Duration::from_secs(20),
+            ..Config::default()
+        }
+    }
+
+    #[test]
+    fn test_micro_break_schedule() {
+        let config = config();
+        let mut micro_break = MicroBreak::default();
+
+        assert!(!micro_break.advance(59_000, true, false, &config));
+        // paused time doesn't count
+        assert!(!micro_break.advance(5_000, false, false, &config));
+        assert!(micro_break.advance(1_000, true, false, &config));
+        assert_eq!(micro_break.remaining(), Some(Duration::from_secs(20)));
+
+        assert!(!micro_break.advance(19_500, true, false, &config));
+        assert_eq!(micro_break.remaining(), Some(Duration::from_secs(1)));
+        assert!(!micro_break.advance(500, true, false, &config));
+        assert!(!micro_break.is_active());
+    }
+
+    #[test]
+    fn test_micro_break_reset_by_break() {
+        let config = config();
+        let mut micro_break = MicroBreak::default();
+
+        micro_break.advance(50_000, true, false, &config);
+        micro_break.advance(1_000, false, true, &config);
+        assert!(!micro_break.advance(50_000, true, false, &config));
+
+        // disabled by default
+        assert!(!micro_break.advance(u32::MAX, true, false, &Config::default()));
+    }
+}
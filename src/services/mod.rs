@@ -1,3 +1,25 @@
 pub mod cache;
+pub mod call;
+pub mod cleanup;
+pub mod config_file;
+pub mod duck;
+pub mod focus;
+pub mod history;
+pub mod hooks;
+pub mod ical;
+pub mod import;
+pub mod lock;
+pub mod logging;
+pub mod micro_break;
 pub mod module;
+pub mod power;
+pub mod reminders;
+pub mod report;
+pub mod session;
+pub mod sink;
+pub mod sound;
+pub mod state_env;
+pub mod stats;
+pub mod storage;
 pub mod timer;
+pub mod workspace;
@@ -1,49 +1,79 @@
 use std::{
+    collections::VecDeque,
     fs,
-    io::{BufReader, Error, Read, Write},
-    os::unix::net::{UnixListener, UnixStream},
+    io::{Error, Read, Write},
+    net::Shutdown,
+    os::unix::{
+        io::AsRawFd,
+        net::{UnixListener, UnixStream},
+    },
+    panic::{self, AssertUnwindSafe},
     path::{Path, PathBuf},
+    process,
+    str::FromStr,
     sync::{
-        mpsc::{Receiver, Sender},
-        LazyLock,
+        atomic::{AtomicBool, Ordering},
+        mpsc::{Receiver, RecvTimeoutError, Sender},
+        Arc,
     },
     thread,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
 };
 
-use notify_rust::Notification;
-use regex::Regex;
-use rodio::{Decoder, OutputStream, Sink};
-use tracing::{debug, info, warn};
-use xdg::BaseDirectories;
+use chrono::{DateTime, Local};
+#[cfg(feature = "notifications")]
+use notify_rust::{Notification, Urgency};
+use tracing::{debug, error, info, info_span, warn, Span};
 
 use crate::{
+    client::PomodoroClient,
     models::{
-        config::Config,
-        message::{Message, TimeValue},
+        config::{AutosaveEvent, Config},
+        message::{
+            protocol_schema, ErrorKind, Message, Response, ScrollDirection, TimeValue,
+            PROTOCOL_VERSION,
+        },
     },
     utils::{
         self,
-        consts::{HOUR, MINUTE, SLEEP_DURATION},
+        consts::{CLIENT_READ_TIMEOUT, HOUR, MAX_ITERATIONS, MINUTE, POWER_SAVE_TICK_RATE},
     },
 };
 
 use super::{
-    cache,
-    timer::{CycleType, Timer},
+    cache::{self, Snapshot},
+    call::CallWatch,
+    cleanup,
+    focus::{AppTracker, Compositor},
+    history::{self, HistoryEvent},
+    hooks::BreakHooks,
+    logging,
+    power::PowerWatch,
+    reminders::Reminders,
+    session,
+    sink::{self, Subscribers},
+    sound, stats,
+    storage::Storage,
+    timer::{CycleType, PauseCause, SetTally, Task, Timer, TimerEvent},
 };
 
-// Shared regex for matching socket filenames with trailing numbers
-static SOCKET_NUMBER_REGEX: LazyLock<Regex> =
-    LazyLock::new(|| Regex::new(r"^module(\d+)$").unwrap());
+/// The instance number in a socket's file stem, `module3` -> 3
+pub(crate) fn socket_number<T: FromStr>(stem: &str) -> Option<T> {
+    stem.strip_prefix("module")
+        .filter(|digits| !digits.is_empty() && digits.bytes().all(|b| b.is_ascii_digit()))
+        .and_then(|digits| digits.parse().ok())
+}
 
-pub fn play_sound(file_path: Option<&str>) {
+/// Plays `file_path`, with other apps turned down to `duck` percent of their volume
+pub fn play_sound(file_path: Option<&str>, duck: Option<u8>) {
     debug!("play_sound called with file_path: {:?}", file_path);
 
-    // Return early if no sound file is specified
+    // Return early if no sound file is specified, cutting off the previous cycle's sound
     let file_path = match file_path {
         Some(path) => path,
         None => {
             debug!("Skipping sound playback: no sound file specified");
+            sound::stop();
             return;
         }
     };
@@ -55,76 +85,225 @@ pub fn play_sound(file_path: Option<&str>) {
     }
 
     debug!("Starting sound playback for: {}", file_path);
+    sound::play(file_path, duck);
+}
 
-    // Spawn a thread for non-blocking audio playback
-    let file_path = file_path.to_string();
-    thread::spawn(move || match play_audio_file(&file_path) {
-        Ok(_) => debug!("Successfully played sound: {}", file_path),
-        Err(e) => warn!("Failed to play sound {}: {}", file_path, e),
-    });
+/// Announces the start of `cycle_type` after `from`, showing a desktop notification when
+/// --notify-on covers the transition
+pub fn send_notification(
+    from: CycleType,
+    cycle_type: CycleType,
+    activity: Option<&str>,
+    config: &Config,
+) {
+    debug!("send_notification called for cycle_type: {:?}", cycle_type);
+
+    // Check if notifications are enabled
+    if config.with_notifications && config.notify_on.covers(from, cycle_type) {
+        let mut body = match cycle_type {
+            CycleType::Work => "Time to work!",
+            CycleType::ShortBreak => "Time for a short break!",
+            CycleType::LongBreak => "Time for a long break!",
+        }
+        .to_string();
+        if let Some(activity) = activity {
+            body.push_str(&format!(" How about: {activity}"));
+        }
+        show_notification(&body, None);
+    } else {
+        debug!("Notifications disabled, skipping desktop notification");
+    }
+    play_cycle_sound(cycle_type, config);
 }
 
-fn play_audio_file(file_path: &str) -> Result<(), Box<dyn std::error::Error>> {
-    debug!("play_audio_file: Creating audio output stream");
+/// Sums up a set that ended with its long break, in place of the notification for the
+/// work cycle after it
+pub fn send_set_notification(tally: &SetTally, config: &Config) {
+    let notify = config
+        .notify_on
+        .covers(CycleType::LongBreak, CycleType::Work);
+    if config.with_notifications && notify {
+        show_notification(&set_summary(tally), None);
+    } else {
+        debug!("Notifications disabled, skipping set summary");
+    }
+    play_cycle_sound(CycleType::Work, config);
+}
 
-    // Create audio output stream
-    let (_stream, stream_handle) = OutputStream::try_default()?;
-    debug!("play_audio_file: Audio output stream created successfully");
+/// Notifies about what the timer did, from the first instance only so several bars
+/// don't all ring at once
+fn announce(events: Vec<TimerEvent>, socket_nr: i32, config: &Config) {
+    if socket_nr != 0 {
+        if !events.is_empty() {
+            debug!(socket_nr, "didn't send a notification");
+        }
+        return;
+    }
+    for event in events {
+        match event {
+            TimerEvent::CycleStarted {
+                from,
+                cycle,
+                activity,
+            } => send_notification(from, cycle, activity.as_deref(), config),
+            TimerEvent::SetCompleted(tally) => send_set_notification(&tally, config),
+        }
+    }
+}
 
-    debug!("play_audio_file: Opening file: {}", file_path);
+/// `Set complete: 4 pomodoros, 1h40m focused, 2 interruptions`
+fn set_summary(tally: &SetTally) -> String {
+    let plural = |count: u16| if count == 1 { "" } else { "s" };
+    format!(
+        "Set complete: {} pomodoro{}, {} focused, {} interruption{}",
+        tally.pomodoros,
+        plural(tally.pomodoros),
+        format_focus(tally.focus_millis / 1000),
+        tally.interruptions,
+        plural(tally.interruptions)
+    )
+}
 
-    // Open and decode the audio file
-    let file = fs::File::open(file_path)?;
-    let buf_reader = BufReader::new(file);
+fn play_cycle_sound(cycle_type: CycleType, config: &Config) {
+    if !config.with_sounds {
+        debug!("Sounds disabled, skipping sound playback");
+        sound::stop();
+        return;
+    }
 
-    debug!("play_audio_file: Decoding audio file");
-    let source = Decoder::new(buf_reader)?;
-    debug!("play_audio_file: Audio file decoded successfully");
+    let sound_file = match cycle_type {
+        CycleType::Work => config.work_sound.as_deref(),
+        CycleType::ShortBreak | CycleType::LongBreak => config.break_sound.as_deref(),
+    };
 
-    debug!("play_audio_file: Creating audio sink");
+    debug!("send_notification: Using sound file: {:?}", sound_file);
+    play_sound(sound_file, config.duck)
+}
 
-    // Create a sink and play the audio
-    let sink = Sink::try_new(&stream_handle)?;
-    sink.append(source);
-    debug!("play_audio_file: Audio appended to sink, starting playback");
+/// Shows a desktop notification with `body`, if notifications are enabled
+pub fn send_text_notification(body: &str, config: &Config) {
+    if !config.with_notifications {
+        debug!("Notifications disabled, skipping '{}'", body);
+        return;
+    }
+    show_notification(body, None);
+}
 
-    // Wait for playback to finish
-    sink.sleep_until_end();
-    debug!("play_audio_file: Playback finished");
+/// Shows a desktop notification, low-urgency and closing itself when given a `timeout`
+#[cfg(feature = "notifications")]
+fn show_notification(body: &str, timeout: Option<Duration>) {
+    let mut notification = Notification::new();
+    notification.summary("Pomodoro").body(body);
+    if let Some(timeout) = timeout {
+        notification.urgency(Urgency::Low).timeout(timeout);
+    }
+    if let Err(e) = notification.show() {
+        warn!("Failed to show notification '{}': {}", body, e);
+    }
+}
 
-    Ok(())
+#[cfg(not(feature = "notifications"))]
+fn show_notification(body: &str, _timeout: Option<Duration>) {
+    debug!("Built without notifications, skipping '{}'", body);
 }
 
-pub fn send_notification(cycle_type: CycleType, config: &Config) {
-    debug!("send_notification called for cycle_type: {:?}", cycle_type);
+/// Asks whether to resume or reset a timer left paused, sending the answer back to the
+/// module at `socket_path`
+pub fn send_pause_reminder(paused: Duration, socket_path: &Path, config: &Config) {
+    if !config.with_notifications {
+        debug!("Notifications disabled, skipping pause reminder");
+        return;
+    }
+    let body = format!(
+        "Pomodoro paused for {} min. Resume or reset?",
+        paused.as_secs() / MINUTE as u64
+    );
+    ask_resume(&body, "Reset", socket_path);
+}
 
-    // Check if notifications are enabled
-    if config.with_notifications {
-        if let Err(e) = Notification::new()
-            .summary("Pomodoro")
-            .body(match cycle_type {
-                CycleType::Work => "Time to work!",
-                CycleType::ShortBreak => "Time for a short break!",
-                CycleType::LongBreak => "Time for a long break!",
-            })
-            .show()
-        {
-            warn!("send_notification failed: {}", e);
-        }
-    } else {
-        debug!("Notifications disabled, skipping desktop notification");
+/// Offers to resume or discard the session a crash or power loss interrupted, `start` and
+/// `reset` from ctl do the same
+pub fn send_resume_prompt(state: &Timer, socket_path: &Path, config: &Config) {
+    if !config.with_notifications {
+        debug!("Notifications disabled, skipping resume prompt");
+        return;
     }
+    let body = format!(
+        "The last session was interrupted {} into {}. Resume or discard it?",
+        format_time(0, state.elapsed_time),
+        state.current_cycle().name()
+    );
+    ask_resume(&body, "Discard", socket_path);
+}
 
-    let sound_file = match cycle_type {
-        CycleType::Work => config.work_sound.as_deref(),
-        CycleType::ShortBreak | CycleType::LongBreak => config.break_sound.as_deref(),
+/// Shows `body` with buttons sending `start` or `reset`, labelled Resume and `reset_label`,
+/// to the module at `socket_path`
+#[cfg(feature = "notifications")]
+fn ask_resume(body: &str, reset_label: &str, socket_path: &Path) {
+    let handle = match Notification::new()
+        .summary("Pomodoro")
+        .body(body)
+        .action("start", "Resume")
+        .action("reset", reset_label)
+        .show()
+    {
+        Ok(handle) => handle,
+        Err(e) => {
+            warn!("Failed to show '{}': {}", body, e);
+            return;
+        }
     };
 
-    debug!("send_notification: Using sound file: {:?}", sound_file);
-    play_sound(sound_file)
+    let socket_path = socket_path.to_string_lossy().into_owned();
+    // waiting for a click blocks, so do it away from the timer
+    thread::spawn(move || {
+        handle.wait_for_action(|action| {
+            if let "start" | "reset" = action {
+                if let Err(e) = send_message_socket(&socket_path, action) {
+                    warn!("Failed to {} from the notification: {}", action, e);
+                }
+            }
+        })
+    });
+}
+
+#[cfg(not(feature = "notifications"))]
+fn ask_resume(body: &str, _reset_label: &str, _socket_path: &Path) {
+    debug!("Built without notifications, skipping '{}'", body);
+}
+
+/// Shows a low-urgency notification that closes itself after `timeout`, for reminders
+/// that shouldn't interrupt
+pub fn send_quiet_notification(body: &str, timeout: Duration, config: &Config) {
+    if !config.with_notifications {
+        debug!("Notifications disabled, skipping '{}'", body);
+        return;
+    }
+    show_notification(body, Some(timeout));
 }
 
-fn format_time(elapsed_time: u16, max_time: u16) -> String {
+/// The furthest of `checkpoints`, percentages of a cycle `length` seconds long, passed
+/// while the cycle moved from `before` to `after` seconds in
+fn checkpoint_passed(before: u16, after: u16, length: u16, checkpoints: &[u8]) -> Option<u8> {
+    let reached =
+        |elapsed: u16, percent: u8| elapsed as u32 * 100 >= length as u32 * percent as u32;
+    checkpoints
+        .iter()
+        .filter(|percent| !reached(before, **percent) && reached(after, **percent))
+        .max()
+        .copied()
+}
+
+/// Focus time like `3h25m`, or `25m` under an hour
+fn format_focus(secs: u64) -> String {
+    let minutes = secs / 60;
+    match minutes / 60 {
+        0 => format!("{minutes}m"),
+        hours => format!("{hours}h{:02}m", minutes % 60),
+    }
+}
+
+pub(crate) fn format_time(elapsed_time: u16, max_time: u16) -> String {
     let time = max_time - elapsed_time;
 
     let hour = time / HOUR;
@@ -138,96 +317,987 @@ fn format_time(elapsed_time: u16, max_time: u16) -> String {
     format!("{minute:02}:{second:02}")
 }
 
-fn create_message(value: String, tooltip: &str, class: &str) -> String {
-    format!(
-        r#"{{"text": "{value}", "tooltip": "{tooltip}", "class": "{class}", "alt": "{class}"}}"#
-    )
+fn create_message(value: String, tooltip: &str, classes: &[&str], alt: &str) -> String {
+    // user supplied text such as task names may contain quotes or newlines
+    let value = serde_json::Value::from(value);
+    let tooltip = serde_json::Value::from(tooltip);
+    let class = serde_json::Value::from(classes);
+    format!(r#"{{"text": {value}, "tooltip": {tooltip}, "class": {class}, "alt": "{alt}"}}"#)
+}
+
+/// Flips once per wall-clock second, drives the blinking urgent class
+fn blink_on() -> bool {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() % 2 == 0)
+        .unwrap_or(true)
+}
+
+/// The duration, in seconds, `time` would give a cycle lasting `current` seconds
+fn resulting_seconds(current: u16, time: &TimeValue) -> i32 {
+    match time {
+        TimeValue::Set(minutes) => *minutes as i32 * MINUTE as i32,
+        TimeValue::Add(delta) => current as i32 + *delta as i32 * MINUTE as i32,
+        TimeValue::Subtract(delta) => current as i32 - *delta as i32 * MINUTE as i32,
+    }
 }
 
-fn handle_time_value(state: &mut Timer, cycle: CycleType, time: &TimeValue) {
+fn handle_time_value(
+    state: &mut Timer,
+    cycle: CycleType,
+    time: &TimeValue,
+    config: &Config,
+) -> Result<(), String> {
+    let seconds = resulting_seconds(state.times[cycle as usize], time);
+    config.bounds(cycle).check(cycle, seconds)?;
     match time {
         TimeValue::Set(minutes) => state.set_time(cycle, *minutes),
         TimeValue::Add(delta) => state.add_delta_time(cycle, *delta),
         TimeValue::Subtract(delta) => state.add_delta_time(cycle, -*delta),
     }
+    if let (CycleType::Work, Some(ratio)) = (cycle, config.break_ratio) {
+        state.scale_breaks(ratio);
+    }
+    Ok(())
 }
 
-fn handle_current_time_value(state: &mut Timer, time: &TimeValue) {
+fn handle_current_time_value(
+    state: &mut Timer,
+    time: &TimeValue,
+    config: &Config,
+) -> Result<(), String> {
+    let cycle = state.current_cycle();
+    let seconds = resulting_seconds(state.get_current_time(), time);
+    config.bounds(cycle).check(cycle, seconds)?;
     match time {
         TimeValue::Set(minutes) => state.set_current_duration(*minutes),
         TimeValue::Add(delta) => state.add_current_delta_time(*delta),
         TimeValue::Subtract(delta) => state.add_current_delta_time(-*delta),
     }
+    Ok(())
 }
 
-fn process_message(state: &mut Timer, message: &str, config: &Config) {
-    debug!("process_message called with: '{}'", message);
+/// How many recent errors `dump` reports
+const MAX_RECENT_ERRORS: usize = 10;
+
+/// Bookkeeping about the running module that isn't part of the timer itself
+pub(crate) struct Runtime {
+    started: Instant,
+    socket_path: PathBuf,
+    recent_errors: VecDeque<String>,
+    /// Where history is appended, `None` unless --history is given
+    history_path: Option<PathBuf>,
+    apps: Option<AppTracker>,
+    /// Where totals and achievements are kept, `None` unless --achievements is given
+    stats_path: Option<PathBuf>,
+    /// Where other machines' stats are found with --sync-dir
+    storage: Storage,
+    reminders: Reminders,
+    /// When `idle` paused the timer, for `active` to resume it
+    idle_since: Option<Instant>,
+    /// Start of the pause the pause reminder was last sent for
+    pause_reminded: Option<Instant>,
+    /// Watches the microphone, with --call-pause
+    call: Option<CallWatch>,
+    /// Watches the battery, with --power-save
+    power: Option<PowerWatch>,
+    /// Whether the call paused a running timer, for its end to resume it
+    call_paused: bool,
+    /// Notification and sound switches from before the call, restored when it ends
+    call_muted: Option<(bool, bool)>,
+    /// When `snooze-until` resumes the timer
+    snooze: Option<Snooze>,
+}
 
-    match Message::decode(message) {
-        Ok(msg) => {
-            debug!("Decoded message: {:?}", msg);
-            match msg {
-                // Simple commands
-                Message::Start => {
-                    debug!("Setting running to true");
-                    state.running = true;
-                }
-                Message::Stop => {
-                    debug!("Setting running to false");
-                    state.running = false;
-                }
-                Message::Toggle => {
-                    debug!(
-                        "Toggling running state from {} to {}",
-                        state.running, !state.running
-                    );
-                    state.running = !state.running;
-                }
-                Message::Reset => {
-                    debug!("Resetting timer");
-                    state.reset();
-                }
-                Message::NextState => {
-                    debug!("Moving to next state");
-                    state.next_state(config);
-                }
-                // Duration commands
-                Message::SetWork { time } => {
-                    handle_time_value(state, CycleType::Work, &time);
-                }
-                Message::SetShort { time } => {
-                    handle_time_value(state, CycleType::ShortBreak, &time);
-                }
-                Message::SetLong { time } => {
-                    handle_time_value(state, CycleType::LongBreak, &time);
-                }
-                Message::SetCurrent { time } => {
-                    handle_current_time_value(state, &time);
+/// A pause `snooze-until` ends by itself
+struct Snooze {
+    until: DateTime<Local>,
+    /// Whether the heads-up a minute before went out
+    warned: bool,
+}
+
+impl Runtime {
+    pub(crate) fn new(socket_path: impl Into<PathBuf>) -> Self {
+        Self {
+            started: Instant::now(),
+            socket_path: socket_path.into(),
+            recent_errors: VecDeque::new(),
+            history_path: None,
+            apps: None,
+            stats_path: None,
+            storage: Storage::Local,
+            reminders: Reminders::default(),
+            idle_since: None,
+            pause_reminded: None,
+            call: None,
+            power: None,
+            call_paused: false,
+            call_muted: None,
+            snooze: None,
+        }
+    }
+
+    fn record_history(&mut self, event: HistoryEvent) {
+        let Some(path) = &self.history_path else {
+            return;
+        };
+        if let Err(e) = history::append(path, &event) {
+            let error = format!("Failed to write history: {e}");
+            warn!("{}", error);
+            self.record_error(error);
+        }
+    }
+
+    /// Counts a completed pomodoro towards the stats, announcing any achievements it unlocks
+    fn record_pomodoro(&mut self, config: &Config) {
+        let Some(path) = &self.stats_path else {
+            return;
+        };
+        let today = stats::day_of(Local::now(), config.day_start.unwrap_or(0));
+        let result = stats::load(path).and_then(|mut stats| {
+            stats.count_pomodoro(today);
+            // achievements are earned by all machines together, but only recorded here
+            let mut combined = stats.clone();
+            for other in self.storage.stats_paths()?.iter().filter(|p| *p != path) {
+                combined.merge(&stats::load(other)?);
+            }
+            let unlocked = stats.unlock(&combined, today, history::now());
+            stats::save(path, &stats)?;
+            Ok(unlocked)
+        });
+        match result {
+            Ok(unlocked) => {
+                for achievement in unlocked {
+                    info!("Achievement unlocked: {}", achievement.title);
+                    send_text_notification(achievement.title, config);
                 }
             }
+            Err(e) => self.record_error(format!("Failed to update stats: {e}")),
+        }
+    }
+
+    /// Pauses and mutes the timer when a call starts, and picks up where it left off once
+    /// the call ends
+    fn follow_call(&mut self, in_call: bool, state: &mut Timer, config: &mut Config) {
+        if in_call {
+            info!("Microphone in use, pausing for the call");
+            self.call_muted = Some((config.with_notifications, config.with_sounds));
+            config.with_notifications = false;
+            config.with_sounds = false;
+            sound::stop();
+            if state.running {
+                state.pause(PauseCause::Call);
+                self.call_paused = true;
+            }
+            return;
+        }
+
+        info!("Call over");
+        if let Some((notifications, sounds)) = self.call_muted.take() {
+            config.with_notifications = notifications;
+            config.with_sounds = sounds;
+        }
+        // unless it was started or reset by hand in the meantime
+        if std::mem::take(&mut self.call_paused) && !state.running && !state.awaiting_start {
+            state.running = true;
+        }
+    }
+
+    /// Resumes a snoozed timer once the snooze is over, with a heads-up a minute before
+    fn follow_snooze(&mut self, state: &mut Timer, config: &Config, now: DateTime<Local>) {
+        let Some(snooze) = &mut self.snooze else {
+            return;
+        };
+        if state.running {
+            debug!("Started by hand, dropping the snooze");
+            self.snooze = None;
+        } else if now >= snooze.until {
+            info!("Snooze over, resuming");
+            self.snooze = None;
+            state.running = true;
+            state.awaiting_start = false;
+            state.grace_millis = None;
+        } else if !snooze.warned && snooze.until - now <= chrono::TimeDelta::minutes(1) {
+            snooze.warned = true;
+            send_text_notification("Pomodoro resuming in a minute", config);
+        }
+    }
+
+    /// Sends the pause reminder once per pause, when a work cycle has been left paused too long
+    fn remind_paused(&mut self, state: &Timer, config: &Config) {
+        let (Some(threshold), Some(paused)) = (config.pause_reminder, state.paused_mid_work_for())
+        else {
+            return;
+        };
+        if self.snooze.is_some() {
+            return;
+        }
+        if paused >= threshold && self.pause_reminded != state.paused_since {
+            self.pause_reminded = state.paused_since;
+            info!("Timer paused for {:?}, sending a reminder", paused);
+            send_pause_reminder(paused, &self.socket_path, config);
+        }
+    }
+
+    /// Gives up on a work cycle left paused past --abandon-after, starting a fresh one
+    fn abandon_stale(&mut self, state: &mut Timer, config: &Config) {
+        let (Some(threshold), Some(paused)) = (config.abandon_after, state.paused_mid_work_for())
+        else {
+            return;
+        };
+        if paused < threshold || self.snooze.is_some() {
+            return;
+        }
+
+        info!("Abandoning the work cycle after {:?} paused", paused);
+        self.record_history(HistoryEvent::Abandoned {
+            cycle: state.current_cycle(),
+            worked: state.elapsed_time as u64,
+            paused: paused.as_secs(),
+            ended: history::now(),
+            task: state.task.as_ref().map(|task| task.name.clone()),
+        });
+        state.reset();
+        send_text_notification(
+            &format!(
+                "Pomodoro abandoned after {} min paused",
+                paused.as_secs() / MINUTE as u64
+            ),
+            config,
+        );
+    }
+
+    /// Notes where a cycle cut short stood, for `resume-last` to pick it up again
+    fn record_interruption(&mut self, state: &Timer) {
+        if state.elapsed_time == 0 {
+            return;
+        }
+        self.record_history(HistoryEvent::Interrupted {
+            cycle: state.current_cycle(),
+            elapsed: state.elapsed_time as u64,
+            duration: state.get_current_time() as u64,
+            iterations: state.iterations,
+            ended: history::now(),
+            task: state.task.as_ref().map(|task| task.name.clone()),
+        });
+    }
+
+    /// Puts the timer back where the latest interrupted cycle in the history stood
+    fn resume_last(&mut self, state: &mut Timer) -> Result<serde_json::Value, String> {
+        let Some(path) = &self.history_path else {
+            return Err("resume-last needs --history".to_string());
+        };
+        let events = history::read(path).map_err(|e| format!("Failed to read history: {e}"))?;
+        let Some(HistoryEvent::Interrupted {
+            cycle,
+            elapsed,
+            duration,
+            iterations,
+            task,
+            ..
+        }) = history::last_interrupted(&events).cloned()
+        else {
+            return Err("No interrupted cycle in the history to resume".to_string());
+        };
+
+        let clamp = |seconds: u64| seconds.min(u16::MAX as u64) as u16;
+        state.resume_at(cycle, clamp(elapsed), clamp(duration), iterations);
+        if let Some(name) = task {
+            if state.task.as_ref().map(|task| &task.name) != Some(&name) {
+                let previous = state.task.replace(Task::new(name, None));
+                self.finish_task(previous);
+            }
+        }
+        info!("Resumed {} at {}s from the history", cycle.name(), elapsed);
+        Ok(serde_json::json!({
+            "cycle": state.current_cycle(),
+            "time": format_time(state.elapsed_time, state.get_current_time()),
+            "task": state.task.as_ref().map(|task| &task.name),
+        }))
+    }
+
+    /// Logs the task being put down along with how it went against its estimate
+    fn finish_task(&mut self, task: Option<Task>) {
+        if let Some(task) = task {
+            self.record_history(HistoryEvent::task(&task, history::now()));
+        }
+    }
+
+    fn record_error(&mut self, error: impl Into<String>) {
+        if self.recent_errors.len() == MAX_RECENT_ERRORS {
+            self.recent_errors.pop_front();
+        }
+        self.recent_errors.push_back(error.into());
+    }
+
+    fn export_state(&self, state: &Timer) -> Result<serde_json::Value, String> {
+        let stats = match &self.stats_path {
+            Some(path) => {
+                Some(stats::load(path).map_err(|e| format!("Failed to read stats: {e}"))?)
+            }
+            None => None,
+        };
+        let snapshot = Snapshot {
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            timer: state.clone(),
+            stats,
+        };
+        serde_json::to_value(snapshot).map_err(|e| e.to_string())
+    }
+
+    fn import_state(
+        &mut self,
+        state: &mut Timer,
+        snapshot: Snapshot,
+        config: &Config,
+    ) -> Result<(), String> {
+        check_snapshot(&snapshot.timer, config)?;
+        if let Some(imported) = &snapshot.stats {
+            match &self.stats_path {
+                Some(path) => stats::save(path, imported)
+                    .map_err(|e| format!("Failed to write stats: {e}"))?,
+                None => info!("Ignoring imported stats, --achievements is off"),
+            }
+        }
+
+        let previous = state.task.take();
+        cache::apply(state, snapshot.timer);
+        if previous.as_ref().map(|task| &task.name) != state.task.as_ref().map(|task| &task.name) {
+            self.finish_task(previous);
         }
+        info!("Imported state from version {}", snapshot.version);
+        Ok(())
+    }
+
+    fn ping(&self, state: &Timer, config: &Config) -> serde_json::Value {
+        serde_json::json!({
+            "version": env!("CARGO_PKG_VERSION"),
+            "protocol": PROTOCOL_VERSION,
+            "uptime_secs": self.started.elapsed().as_secs(),
+            "instance": state.socket_nr,
+            "name": config.name,
+            "groups": config.groups,
+        })
+    }
+
+    fn dump(&self, state: &Timer, config: &Config) -> serde_json::Value {
+        serde_json::json!({
+            "version": env!("CARGO_PKG_VERSION"),
+            "socket_path": self.socket_path,
+            "uptime_secs": self.started.elapsed().as_secs(),
+            "config": config,
+            "timer": state,
+            "timer_internals": {
+                "current_cycle": state.current_cycle().name(),
+                "current_time": state.get_current_time(),
+                "current_override": state.current_override,
+                "grace_millis": state.grace_millis,
+                "paused_for_secs": state.paused_for().map(|paused| paused.as_secs()),
+            },
+            "reminders": self.reminders,
+            "recent_errors": self.recent_errors,
+        })
+    }
+}
+
+pub(crate) fn process_message(
+    state: &mut Timer,
+    message: &str,
+    config: &mut Config,
+    runtime: &mut Runtime,
+) -> Response {
+    debug!("process_message called with: '{}'", message);
+
+    let msg = match Message::decode(message) {
+        Ok(msg) => msg,
         Err(e) => {
             debug!("Failed to decode message '{}': {}", message, e);
+            let error = format!("Invalid command '{}': {}", message.trim(), e);
+            runtime.record_error(error.clone());
+            return Response::error(ErrorKind::from_decode_error(&e), error);
+        }
+    };
+
+    debug!("Decoded message: {:?}", msg);
+    match apply_message(state, msg, config, runtime) {
+        Ok(None) => Response::ok(),
+        Ok(Some(data)) => Response::with_data(data),
+        Err(e) => {
+            debug!("Rejected message '{}': {}", message, e);
+            runtime.record_error(e.clone());
+            Response::error(ErrorKind::InvalidValue, e)
         }
     }
 }
 
+/// Applies a decoded command, returning any data the client asked for
+fn apply_message(
+    state: &mut Timer,
+    msg: Message,
+    config: &mut Config,
+    runtime: &mut Runtime,
+) -> Result<Option<serde_json::Value>, String> {
+    match msg {
+        // Simple commands
+        Message::Start => {
+            debug!("Setting running to true");
+            state.running = true;
+            state.awaiting_start = false;
+            state.grace_millis = None;
+        }
+        Message::Stop => {
+            debug!("Setting running to false");
+            // paused on purpose, so returning activity mustn't resume it
+            runtime.idle_since = None;
+            state.pause(PauseCause::Manual);
+            state.awaiting_start = false;
+            state.grace_millis = None;
+        }
+        Message::Toggle => {
+            debug!(
+                "Toggling running state from {} to {}",
+                state.running, !state.running
+            );
+            // toggling during a grace countdown starts the cycle right away
+            if state.running && state.grace_millis.is_none() {
+                state.pause(PauseCause::Manual);
+            } else {
+                state.running = true;
+            }
+            state.awaiting_start = false;
+            state.grace_millis = None;
+        }
+        Message::Reset => {
+            debug!("Resetting timer");
+            runtime.record_interruption(state);
+            state.reset();
+        }
+        Message::NextState => {
+            debug!("Moving to next state");
+            state.next_state(config);
+        }
+        Message::Idle | Message::Lock => {
+            if state.running {
+                debug!("Pausing while idle or locked");
+                state.pause(match msg {
+                    Message::Lock => PauseCause::Locked,
+                    _ => PauseCause::Idle,
+                });
+                runtime.idle_since = Some(Instant::now());
+            }
+        }
+        Message::Active => {
+            if let Some(since) = runtime.idle_since.take() {
+                let within = config
+                    .idle_resume
+                    .is_some_and(|window| since.elapsed() <= window);
+                if within && !state.running && !state.awaiting_start {
+                    debug!("Activity is back, resuming");
+                    state.running = true;
+                }
+            }
+        }
+        Message::YieldFocus => {
+            if state.running && !state.is_break() {
+                info!("Another instance started working, pausing");
+                runtime.idle_since = None;
+                state.pause(PauseCause::OtherInstance);
+            }
+        }
+        Message::CancelAuto => {
+            debug!("Cancelling pending auto-start");
+            state.cancel_auto_start();
+        }
+        // Duration commands
+        Message::SetWork { time } => {
+            handle_time_value(state, CycleType::Work, &time, config)?;
+        }
+        Message::SetShort { time } => {
+            handle_time_value(state, CycleType::ShortBreak, &time, config)?;
+        }
+        Message::SetLong { time } => {
+            handle_time_value(state, CycleType::LongBreak, &time, config)?;
+        }
+        Message::SetCurrent { time } => {
+            handle_current_time_value(state, &time, config)?;
+        }
+        // Runtime switches
+        Message::Notifications { state: switch } => {
+            config.with_notifications = switch.apply(config.with_notifications);
+            debug!("Notifications enabled: {}", config.with_notifications);
+        }
+        Message::Sounds { state: switch } => {
+            config.with_sounds = switch.apply(config.with_sounds);
+            debug!("Sounds enabled: {}", config.with_sounds);
+            if !config.with_sounds {
+                sound::stop();
+            }
+        }
+        Message::StopSound => sound::stop(),
+        Message::SetAuto {
+            cycle,
+            state: switch,
+        } => {
+            config.auto_start.switch(cycle, switch);
+            debug!("Auto-start rules now: {:?}", config.auto_start);
+        }
+        // Appearance commands
+        Message::SetIcon { icon, value } => {
+            debug!("Setting {:?} icon to '{}'", icon, value);
+            config.set_icon(icon, value);
+        }
+        Message::SetFormat { format } => {
+            debug!("Setting format to '{}'", format);
+            config.format = format;
+        }
+        Message::ToggleDisplay => {
+            config.count_up = !config.count_up;
+            debug!("Counting up: {}", config.count_up);
+        }
+        // Diagnostics
+        Message::LogLevel { level } => logging::set_log_level(level)?,
+        Message::Logging { state: switch } => logging::set_logging(switch)?,
+        Message::Dump => return Ok(Some(runtime.dump(state, config))),
+        Message::Ping => return Ok(Some(runtime.ping(state, config))),
+        Message::Remaining => return Ok(Some(remaining(state, config))),
+        Message::ShowConfig => {
+            let mut shown = serde_json::to_value(&*config)
+                .map_err(|e| format!("Cannot serialize the config: {e}"))?;
+            // set-work and friends change the timer's lengths rather than the config
+            let [work, short, long] = state.times;
+            shown["work_time"] = work.into();
+            shown["short_break"] = short.into();
+            shown["long_break"] = long.into();
+            return Ok(Some(shown));
+        }
+        Message::Schema => return Ok(Some(protocol_schema())),
+        Message::FastForward { time } => {
+            let events = state.fast_forward(time.as_millis(), config);
+            announce(events, state.socket_nr, config);
+        }
+        Message::Seek { to } => {
+            state.seek(to.seconds(state.get_current_time()));
+            let events = state.update_state(config);
+            announce(events, state.socket_nr, config);
+        }
+        Message::Sprint { pomodoros } => {
+            if pomodoros == 0 {
+                return Err("A sprint needs at least one pomodoro".to_string());
+            }
+            state.sprint(pomodoros);
+        }
+        Message::FocusUntil { until } => {
+            let now = Local::now();
+            let until = until.next_after(now);
+            let pomodoros = state.focus_until(until, now, config);
+            info!("Focusing until {}, {} pomodoros fit", until, pomodoros);
+            return Ok(Some(serde_json::json!({
+                "pomodoros": pomodoros,
+                "until": until.to_rfc3339(),
+            })));
+        }
+        Message::SnoozeUntil { until } => {
+            let until = until.next_after(Local::now());
+            info!("Snoozing until {}", until);
+            runtime.idle_since = None;
+            state.pause(PauseCause::Snooze);
+            state.grace_millis = None;
+            runtime.snooze = Some(Snooze {
+                until,
+                warned: false,
+            });
+            return Ok(Some(serde_json::json!({ "until": until.to_rfc3339() })));
+        }
+        Message::Scroll { direction, step } => {
+            let delta = step as i32 * MINUTE as i32;
+            let delta = match direction {
+                ScrollDirection::Up => delta,
+                ScrollDirection::Down => -delta,
+            };
+            let bounds = config.bounds(state.current_cycle());
+            state.nudge_current(delta, bounds.min * MINUTE, bounds.max * MINUTE);
+        }
+        Message::SetTask { name, estimate } => match &mut state.task {
+            // re-setting the current task only revises its estimate
+            Some(task) if task.name == name => task.estimate = estimate,
+            _ => {
+                let previous = state.task.replace(Task::new(name, estimate));
+                runtime.finish_task(previous);
+            }
+        },
+        Message::ClearTask => {
+            let previous = state.task.take();
+            runtime.finish_task(previous);
+        }
+        Message::Annotate { note } => {
+            if state.is_break() {
+                return Err("Notes can only be added to work cycles".to_string());
+            }
+            state.notes.push(note);
+        }
+        Message::Reminder {
+            name,
+            state: switch,
+        } => runtime.reminders.switch(&name, switch)?,
+        Message::ResumeLast => return runtime.resume_last(state).map(Some),
+        Message::ExportState => return runtime.export_state(state).map(Some),
+        Message::ImportState { snapshot } => runtime.import_state(state, *snapshot, config)?,
+        // the listener shuts down before the timer loop ever sees it
+        Message::Exit => return Err("Exit is only accepted over the module's socket".to_string()),
+        Message::Subscribe => {
+            return Err("Subscribe is only accepted over the module's socket".to_string())
+        }
+    }
+    Ok(None)
+}
+
+/// Checks an imported timer is in a cycle there is, lasting what --work-bounds and friends
+/// allow, and not past its end
+fn check_snapshot(timer: &Timer, config: &Config) -> Result<(), String> {
+    let cycles = [CycleType::Work, CycleType::ShortBreak, CycleType::LongBreak];
+    let Some(&length) = timer.times.get(timer.current_index) else {
+        return Err(format!(
+            "Invalid snapshot: no cycle number {}",
+            timer.current_index
+        ));
+    };
+    for (cycle, seconds) in cycles.into_iter().zip(timer.times) {
+        // --longbreak 0 turns long breaks off
+        if cycle == CycleType::LongBreak && seconds == 0 {
+            continue;
+        }
+        config.bounds(cycle).check(cycle, seconds as i32)?;
+    }
+    if timer.elapsed_time > length || timer.elapsed_millis >= 1000 {
+        return Err(format!(
+            "Invalid snapshot: {} seconds into a cycle of {}",
+            timer.elapsed_time, length
+        ));
+    }
+    // the long break follows the last work cycle of a set, and only that
+    let long_break = timer.current_index == CycleType::LongBreak as usize;
+    if timer.iterations > MAX_ITERATIONS || long_break != (timer.iterations == MAX_ITERATIONS) {
+        return Err(format!(
+            "Invalid snapshot: {} work cycles into a set of {} during a {}",
+            timer.iterations,
+            MAX_ITERATIONS,
+            timer.current_cycle().name()
+        ));
+    }
+    Ok(())
+}
+
+/// The time left in the current cycle, in seconds, in minutes rounded up, as a percentage
+/// of the cycle and as the bar shows it, along with the cycle it's left of
+fn remaining(state: &Timer, config: &Config) -> serde_json::Value {
+    let seconds = state.remaining();
+    let percent = (seconds as u32 * 100)
+        .checked_div(state.get_current_time() as u32)
+        .unwrap_or(0);
+    serde_json::json!({
+        "seconds": seconds,
+        "minutes": seconds.div_ceil(MINUTE),
+        "percent": percent,
+        "time": format_time(state.elapsed_time, state.get_current_time()),
+        "cycle": state.current_cycle(),
+        "icon": config.get_cycle_icon(state.current_cycle()),
+        "running": state.running,
+        "paused": state.pause_cause.filter(|_| !state.running),
+    })
+}
+
 /// Extract socket number from a socket path by looking only at the filename
 /// Only matches numbers at the end of the base filename (before extension)
 fn extract_socket_number(socket_path: &Path) -> i32 {
     socket_path
         .file_stem() // without extension
         .and_then(|name| name.to_str())
-        .and_then(|name| {
-            SOCKET_NUMBER_REGEX
-                .captures(name)
-                .and_then(|caps| caps.get(1))
-                .and_then(|m| m.as_str().parse::<i32>().ok())
-        })
+        .and_then(socket_number::<i32>)
         .unwrap_or(0)
 }
 
-fn handle_client(rx: Receiver<String>, socket_path: impl AsRef<Path>, config: Config) {
+/// Moves the timer on by `delta` milliseconds and renders the line Waybar displays
+pub(crate) fn tick(state: &mut Timer, config: &Config, delta: u32) -> String {
+    // after a reset or restart too, not only when the cycle moves on
+    state.plan_cycle(config);
+    state.fit_focus(Local::now());
+    let before = state.elapsed_time;
+    if state.running {
+        state.advance(delta);
+    } else {
+        state.tick_grace(delta);
+    }
+    let (working, in_break) = (state.running, state.is_break());
+    if working && !in_break && state.socket_nr == 0 {
+        let length = state.get_current_time();
+        if let Some(percent) =
+            checkpoint_passed(before, state.elapsed_time, length, &config.checkpoints)
+        {
+            let body = format!(
+                "{percent}% of the work cycle done, {} to go",
+                format_time(state.elapsed_time, length)
+            );
+            send_quiet_notification(&body, Duration::from_secs(5), config);
+            if config.with_sounds && config.checkpoint_sound.is_some() {
+                play_sound(config.checkpoint_sound.as_deref(), None);
+            }
+        }
+    }
+    if let Some(sprint) = state.end_sprint() {
+        send_text_notification(
+            &format!("Sprint complete: {} pomodoros done", sprint.completed),
+            config,
+        );
+    }
+    if let Some(focus) = state.end_focus(Local::now()) {
+        send_text_notification(
+            &format!(
+                "Focus session over: {} of {} pomodoros done",
+                focus.completed, focus.planned
+            ),
+            config,
+        );
+    }
+    // only the first instance reminds, like the cycle notifications
+    if state.micro_break.advance(delta, working, in_break, config) && state.socket_nr == 0 {
+        let body = format!(
+            "Look at something 20 feet away for {} seconds",
+            config.micro_break_length.as_secs()
+        );
+        send_quiet_notification(&body, config.micro_break_length, config);
+    }
+
+    state.roll_focus(stats::day_of(Local::now(), config.day_start.unwrap_or(0)));
+    let focus_today = format_focus(state.focus_millis / 1000);
+    let sprint = state.sprint_progress();
+    let (next_cycle, next_length) = state.upcoming(config);
+    let next = format!("{} {}", next_cycle.name(), format_focus(next_length as u64));
+    let until_long = state
+        .until_long()
+        .map(|left| left.to_string())
+        .unwrap_or_default();
+    let value = if config.saving_power {
+        // rounded up, so the minute shown is the one running
+        let seconds = match config.count_up {
+            true => state.elapsed_time,
+            false => state.remaining(),
+        };
+        format_focus(seconds.div_ceil(MINUTE) as u64 * MINUTE as u64)
+    } else if config.count_up {
+        format_time(0, state.elapsed_time)
+    } else {
+        format_time(state.elapsed_time, state.get_current_time())
+    };
+    let progress = config.progress_glyphs.render(
+        state.elapsed_time as f64 / state.get_current_time().max(1) as f64,
+        config.progress_width,
+    );
+    let value_prefix = config.get_play_pause_icon(state.running);
+    state.track_pause();
+    let task = state
+        .task
+        .as_ref()
+        .map(|task| task.name.clone())
+        .unwrap_or_default();
+    let task_progress = state
+        .task
+        .as_ref()
+        .map(|task| task.progress())
+        .unwrap_or_default();
+    let pause_cause = state
+        .pause_cause
+        .filter(|_| !state.running)
+        .map(PauseCause::description)
+        .unwrap_or_default();
+    let tooltip = match &config.tooltip_format {
+        Some(template) => utils::helper::fill_sections(
+            template,
+            &[
+                ("icon", value_prefix),
+                ("time", &value),
+                ("cycle", state.current_cycle().name()),
+                ("progress", &progress),
+                ("focus_today", &focus_today),
+                ("sprint", &sprint),
+                ("until_long", &until_long),
+                ("next", &next),
+                ("completed", &state.session_completed.to_string()),
+                ("pomodoros_today", &state.pomodoros_today.to_string()),
+                ("interruptions", &state.interruptions_today.to_string()),
+                ("task", &task),
+                ("task_progress", &task_progress),
+                ("activity", state.activity.as_deref().unwrap_or_default()),
+                ("pause_cause", pause_cause),
+            ],
+        ),
+        None => default_tooltip(state, config),
+    };
+    let classes = state.get_classes(config, blink_on());
+    let alt = state.get_alt();
+    let cycle_icon = config.get_cycle_icon(state.current_cycle());
+    let events = state.update_state(config);
+    announce(events, state.socket_nr, config);
+    let render = |task: &str| {
+        utils::helper::trim_whitespace(&utils::helper::fill_template(
+            &config.format,
+            &[
+                ("icon", value_prefix),
+                ("time", &value),
+                ("cycle_icon", cycle_icon),
+                ("progress", &progress),
+                ("focus_today", &focus_today),
+                ("sprint", &sprint),
+                ("until_long", &until_long),
+                ("next", &next),
+                ("task", task),
+            ],
+        ))
+    };
+    let text = match state.grace_remaining() {
+        Some(remaining) => {
+            let text = format!(
+                "{} starting in {}",
+                state.current_cycle().name(),
+                format_time(0, remaining)
+            );
+            fit_text(|_| text.clone(), "", config)
+        }
+        None => fit_text(render, &task, config),
+    };
+    create_message(text, tooltip.as_str(), &classes, alt)
+}
+
+/// The text rendered with `task` shortened as far as it takes to fit in --max-length, then
+/// padded to --min-length. Only the task is escaped for markup, and widths count what the
+/// bar shows, leaving out the markup the format may have.
+fn fit_text(render: impl Fn(&str) -> String, task: &str, config: &Config) -> String {
+    let mut task = task.to_string();
+    let mut text = render(&utils::helper::escape_markup(&task));
+    let mut width = utils::helper::strip_markup(&text).chars().count();
+    if let Some(max) = config.max_length.map(usize::from) {
+        let over = width.saturating_sub(max);
+        if over > 0 && !task.is_empty() {
+            let keep = task.chars().count().saturating_sub(over).max(1);
+            task = utils::helper::ellipsize(&task, keep);
+            text = render(&utils::helper::escape_markup(&task));
+            width = utils::helper::strip_markup(&text).chars().count();
+        }
+        if width > max {
+            // the rest of the format is too long by itself, so it's cut as plain text
+            let plain = utils::helper::strip_markup(&text);
+            text = utils::helper::escape_markup(&utils::helper::ellipsize(&plain, max));
+            width = max;
+        }
+    }
+    let padding = (config.min_length as usize).saturating_sub(width);
+    text + &" ".repeat(padding)
+}
+
+/// The tooltip without --tooltip-format, a line for each thing worth knowing right now
+fn default_tooltip(state: &Timer, config: &Config) -> String {
+    let mut tooltip = format!(
+        "{} pomodoro{} completed this session",
+        state.session_completed,
+        if state.session_completed > 1 || state.session_completed == 0 {
+            "s"
+        } else {
+            ""
+        }
+    );
+    if let Some(task) = &state.task {
+        tooltip.push_str(&format!("\n{}: {} pomodoros", task.name, task.progress()));
+    }
+    if state.focus_millis >= 60_000 {
+        tooltip.push_str(&format!(
+            "\nFocused today: {}",
+            format_focus(state.focus_millis / 1000)
+        ));
+    }
+    if state.done_for_today(config) {
+        tooltip.push_str(&format!(
+            "\nDone for today: {} pomodoros, well earned!",
+            state.pomodoros_today
+        ));
+    }
+    if let Some(sprint) = &state.sprint {
+        tooltip.push_str(&format!(
+            "\nSprint: {} of {} pomodoros",
+            sprint.completed, sprint.planned
+        ));
+    }
+    if let Some(focus) = &state.focus {
+        tooltip.push_str(&format!(
+            "\nFocusing until {}: {} of {} pomodoros",
+            focus.until.format("%H:%M"),
+            focus.completed,
+            focus.planned
+        ));
+    }
+    if config.jitter > 0 {
+        tooltip.push_str(&format!(
+            "\nThis cycle: {}",
+            format_time(0, state.get_current_time())
+        ));
+    }
+    if let Some(remaining) = state.micro_break.remaining() {
+        tooltip.push_str(&format!(
+            "\nMicro-break: look away for {}s",
+            remaining.as_secs()
+        ));
+    }
+    if let Some(activity) = &state.activity {
+        tooltip.push_str(&format!("\nBreak activity: {activity}"));
+    }
+    if let Some(cause) = state.pause_cause.filter(|_| !state.running) {
+        tooltip.push_str(&format!("\nPaused: {}", cause.description()));
+    }
+    if config.stale_tooltip && state.is_stale(config.stale_after) {
+        if let Some(paused) = state.paused_for() {
+            tooltip.push_str(&format!(
+                " (paused for {} min)",
+                paused.as_secs() / MINUTE as u64
+            ));
+        }
+    }
+    tooltip
+}
+
+/// Moves cycles the timer finished, and the pauses it came back from, into the history
+/// and stats
+pub(crate) fn record_completed(state: &mut Timer, runtime: &mut Runtime, config: &Config) {
+    for (cause, paused) in state.take_pauses() {
+        runtime.record_history(HistoryEvent::Pause {
+            cause,
+            paused,
+            ended: history::now(),
+            task: state.task.as_ref().map(|task| task.name.clone()),
+        });
+    }
+    for completed in state.take_completed() {
+        if completed.cycle == CycleType::Work {
+            runtime.record_pomodoro(config);
+        }
+        let apps = match (completed.cycle, &mut runtime.apps) {
+            (CycleType::Work, Some(apps)) => apps.take(),
+            _ => Default::default(),
+        };
+        let event = HistoryEvent::cycle(completed, history::now(), state.task.as_ref(), apps);
+        runtime.record_history(event);
+    }
+}
+
+/// A command received over the socket, along with where to send its reply
+struct Request {
+    message: String,
+    reply: Sender<Response>,
+}
+
+fn handle_client(
+    rx: Receiver<Request>,
+    socket_path: impl AsRef<Path>,
+    mut config: Config,
+    subscribers: Subscribers,
+) {
     let socket_path = socket_path.as_ref();
     let socket_nr = extract_socket_number(socket_path);
 
@@ -238,49 +1308,202 @@ fn handle_client(rx: Receiver<String>, socket_path: impl AsRef<Path>, config: Co
         socket_nr,
     );
 
+    let mut runtime = Runtime::new(socket_path);
+    runtime.reminders = Reminders::new(&config.reminders);
+    if config.speed > 1 {
+        info!("Simulating at {}x speed", config.speed);
+    }
+
+    let storage = Storage::new(config.sync_dir.as_deref());
+    runtime.storage = storage.clone();
+
+    if config.history {
+        match storage.history_path() {
+            Ok(path) => runtime.history_path = Some(path),
+            Err(e) => runtime.record_error(format!("History disabled: {e}")),
+        }
+    }
+
+    if config.achievements {
+        match storage.stats_path() {
+            Ok(path) => runtime.stats_path = Some(path),
+            Err(e) => runtime.record_error(format!("Achievements disabled: {e}")),
+        }
+    }
+
+    if config.call_pause {
+        runtime.call = Some(CallWatch::default());
+    }
+    if config.power_save {
+        runtime.power = Some(PowerWatch::default());
+    }
+
+    if config.track_apps {
+        match Compositor::detect() {
+            Some(compositor) => runtime.apps = Some(AppTracker::new(compositor)),
+            None => runtime.record_error("App tracking needs Hyprland or Sway"),
+        }
+    }
+
+    // with --sync-dir the state is kept per machine, picking up from whichever ran last
+    let state_path = match storage.state_path() {
+        Ok(path) => path,
+        Err(e) => {
+            runtime.record_error(format!("Sync directory unavailable: {e}"));
+            None
+        }
+    };
+    if let Some(path) = &state_path {
+        cleanup::persist_to(path);
+    }
+
+    let mut autosave = cache::Autosave::new(&config);
+    let store = |state: &Timer, runtime: &mut Runtime| {
+        let stored = match &state_path {
+            Some(path) => cache::store_to_path(state, path),
+            None => cache::store(state),
+        };
+        if let Err(e) = stored {
+            runtime.record_error(format!("Failed to store state: {e}"));
+        }
+    };
+
     if config.persist {
-        let _ = cache::restore(&mut state, &config);
+        let restored = match storage.latest_state_path() {
+            Ok(Some(path)) => cache::restore_from_path(&mut state, &config, &path),
+            Ok(None) if state_path.is_some() => Ok(()),
+            Ok(None) => cache::restore(&mut state, &config),
+            Err(e) => Err(e),
+        };
+        if let Err(e) = restored {
+            runtime.record_error(format!("Failed to restore state: {e}"));
+        }
+        // the module that saved it never got to mark it clean
+        if std::mem::replace(&mut state.dirty, true) {
+            warn!("The last session ended without a clean shutdown, pausing it");
+            state.pause(PauseCause::Crash);
+            send_resume_prompt(&state, socket_path, &config);
+        }
     }
 
+    let mut sinks = sink::sinks(&config, socket_path, socket_nr, subscribers);
+
+    let mut break_hooks = BreakHooks::default();
+    // whether a work cycle was running, to notice one starting with --exclusive-focus
+    let mut focused = false;
+    let mut last_tick = Instant::now();
     loop {
-        if let Ok(message) = rx.try_recv() {
-            debug!("Processing message: '{}'", message);
-            process_message(&mut state, &message, &config);
-        }
-
-        let value = format_time(state.elapsed_time, state.get_current_time());
-        let value_prefix = config.get_play_pause_icon(state.running);
-        let tooltip = format!(
-            "{} pomodoro{} completed this session",
-            state.session_completed,
-            if state.session_completed > 1 || state.session_completed == 0 {
-                "s"
-            } else {
-                ""
-            }
-        );
-        let class = state.get_class();
-        let cycle_icon = config.get_cycle_icon(state.is_break());
-        state.update_state(&config, true);
-        println!(
-            "{}",
-            create_message(
-                utils::helper::trim_whitespace(&format!("{value_prefix} {value} {cycle_icon}")),
-                tooltip.as_str(),
-                class,
-            )
-        );
+        let now = Instant::now();
+        // --simulate runs timer time faster than the wall clock
+        let delta =
+            (now.duration_since(last_tick).as_millis() as u32).saturating_mul(config.speed as u32);
+        last_tick = now;
+
+        if let Some(on_battery) = runtime.power.as_mut().and_then(PowerWatch::sample) {
+            info!("On battery: {}", on_battery);
+            config.saving_power = on_battery;
+        }
+        if config.saving_power {
+            sound::release();
+        }
 
-        if state.running {
-            state.increment_time();
+        if let Some(day_start) = config.day_start {
+            if state.roll_day(stats::day_of(Local::now(), day_start)) {
+                info!("A new day has started, resetting the timer");
+            }
+        }
+        let cycle = state.current_index;
+        let line = tick(&mut state, &config, delta);
+        let now_focused = state.running && !state.is_break();
+        if config.exclusive_focus && now_focused && !focused {
+            info!("Work cycle started, pausing the other instances");
+            claim_focus(socket_path);
+        }
+        focused = now_focused;
+        for reminder in runtime.reminders.advance(delta) {
+            send_text_notification(&reminder, &config);
+        }
+        if let Some(in_call) = runtime.call.as_mut().and_then(CallWatch::sample) {
+            runtime.follow_call(in_call, &mut state, &mut config);
+        }
+        runtime.follow_snooze(&mut state, &config, Local::now());
+        runtime.remind_paused(&state, &config);
+        runtime.abandon_stale(&mut state, &config);
+        if let Some(apps) = &mut runtime.apps {
+            apps.sample(state.running && !state.is_break());
+        }
+        record_completed(&mut state, &mut runtime, &config);
+        break_hooks.update(state.is_break(), &config);
+        if state.current_index != cycle {
+            autosave.event(AutosaveEvent::Cycle);
         }
 
         if config.persist {
-            let _ = cache::store(&state);
+            if autosave.due(now) {
+                store(&state, &mut runtime);
+                autosave.saved(now);
+            }
+            cleanup::remember_state(&state);
+        }
+
+        for sink in &mut sinks {
+            if let Err(e) = sink.emit(&line, &state) {
+                runtime.record_error(e);
+            }
+        }
+
+        // wake up for the next tick, or straight away to render the effect of a command
+        let tick_rate = match config.saving_power {
+            true => Duration::from_millis(POWER_SAVE_TICK_RATE as u64).max(config.tick_rate),
+            false => config.tick_rate,
+        };
+        match rx.recv_timeout(state.next_tick_in(tick_rate)) {
+            Ok(request) => {
+                debug!("Processing message: '{}'", request.message);
+                let response =
+                    process_message(&mut state, &request.message, &mut config, &mut runtime);
+                autosave.event(AutosaveEvent::Command);
+                // the client may have hung up without waiting for the reply
+                let _ = request.reply.send(response);
+            }
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => break,
         }
+    }
 
-        std::thread::sleep(SLEEP_DURATION);
+    runtime.record_interruption(&state);
+    // whatever happened since the last autosave, marked as left behind on purpose
+    if config.persist {
+        state.dirty = false;
+        store(&state, &mut runtime);
     }
+    break_hooks.finish(&config);
+    for sink in &mut sinks {
+        sink.finish();
+    }
+}
+
+/// Pauses the work cycles of the session's other instances, with --exclusive-focus
+fn claim_focus(socket_path: &Path) {
+    let Some(dir) = socket_path.parent() else {
+        return;
+    };
+    let own = socket_path
+        .canonicalize()
+        .unwrap_or_else(|_| socket_path.to_owned());
+    let others: Vec<_> = sockets_in(dir)
+        .into_iter()
+        .filter(|other| *other != own)
+        .collect();
+    // their replies wait on their timer loops, which mustn't hold up this one
+    thread::spawn(move || {
+        for other in others {
+            match PomodoroClient::new(&other).send(&Message::YieldFocus) {
+                Ok(_) => debug!("Asked {} to yield focus", other.display()),
+                Err(e) => warn!("Failed to ask {} to yield focus: {}", other.display(), e),
+            }
+        }
+    });
 }
 
 fn delete_socket(socket_path: &Path) {
@@ -289,44 +1512,182 @@ fn delete_socket(socket_path: &Path) {
     }
 }
 
+/// Reads a single command from a client, giving up once `timeout` passes without data
+pub(crate) fn read_client(stream: &mut UnixStream, timeout: Duration) -> Result<String, Error> {
+    stream.set_read_timeout(Some(timeout))?;
+    let mut message = String::new();
+    stream.read_to_string(&mut message)?;
+    Ok(message)
+}
+
+/// The user id of the process on the other end of `stream`
+fn peer_uid(stream: &UnixStream) -> Result<u32, Error> {
+    let mut cred = libc::ucred {
+        pid: 0,
+        uid: 0,
+        gid: 0,
+    };
+    let mut len = std::mem::size_of::<libc::ucred>() as libc::socklen_t;
+    // SAFETY: `cred` is a ucred and `len` is its size, as SO_PEERCRED expects
+    let result = unsafe {
+        libc::getsockopt(
+            stream.as_raw_fd(),
+            libc::SOL_SOCKET,
+            libc::SO_PEERCRED,
+            (&mut cred as *mut libc::ucred).cast(),
+            &mut len,
+        )
+    };
+    match result {
+        0 => Ok(cred.uid),
+        _ => Err(Error::last_os_error()),
+    }
+}
+
+/// Whether the client on `stream` may shut the module down, only the user running it can
+fn may_exit(stream: &UnixStream) -> bool {
+    match peer_uid(stream) {
+        // SAFETY: geteuid has no preconditions and cannot fail
+        Ok(uid) => uid == unsafe { libc::geteuid() },
+        Err(e) => {
+            warn!("Cannot tell who sent exit: {}", e);
+            false
+        }
+    }
+}
+
+fn handle_connection(
+    mut stream: UnixStream,
+    tx: Sender<Request>,
+    shutdown: Arc<AtomicBool>,
+    socket_path: &Path,
+    subscribers: &Subscribers,
+) {
+    let message = match read_client(&mut stream, CLIENT_READ_TIMEOUT) {
+        Ok(message) => message,
+        Err(e) => {
+            warn!("Dropping client connection: {}", e);
+            return;
+        }
+    };
+
+    debug!("Received message: '{}'", message);
+
+    if let Ok(Message::Exit) = Message::decode(&message) {
+        let response = if may_exit(&stream) {
+            info!("Received exit signal, shutting down module");
+            shutdown.store(true, Ordering::SeqCst);
+            Response::ok()
+        } else {
+            warn!("Refusing exit from a client run by another user");
+            Response::error(
+                ErrorKind::PermissionDenied,
+                "Only the user running the module can shut it down",
+            )
+        };
+        if let Err(e) = stream.write_all(response.encode().as_bytes()) {
+            debug!("Failed to send reply: {}", e);
+        }
+        if shutdown.load(Ordering::SeqCst) {
+            // wake up the accept loop so it notices the shutdown
+            let _ = UnixStream::connect(socket_path);
+        }
+        return;
+    }
+
+    if let Ok(Message::Subscribe) = Message::decode(&message) {
+        debug!("Client subscribed to the module's output");
+        subscribers.add(stream);
+        return;
+    }
+
+    let (reply_tx, reply_rx) = std::sync::mpsc::channel();
+    let request = Request {
+        message,
+        reply: reply_tx,
+    };
+    if tx.send(request).is_err() {
+        warn!("Timer loop has stopped, dropping message");
+        return;
+    }
+
+    match reply_rx.recv_timeout(CLIENT_READ_TIMEOUT) {
+        Ok(response) => {
+            if let Err(e) = stream.write_all(response.encode().as_bytes()) {
+                debug!("Failed to send reply: {}", e);
+            }
+        }
+        Err(e) => warn!("No reply from timer loop: {}", e),
+    }
+}
+
 pub fn spawn_module(socket_path: impl AsRef<Path>, config: Config) {
     let socket_path = socket_path.as_ref();
     delete_socket(socket_path);
 
-    let listener = UnixListener::bind(&socket_path).unwrap();
+    // tag everything logged by this module, including its threads, with the instance
+    let span = info_span!("module", instance = extract_socket_number(socket_path));
+    let _enter = span.enter();
+
+    let listener = UnixListener::bind(socket_path).unwrap();
     info!("Socket bound successfully");
-    let (tx, rx): (Sender<String>, Receiver<String>) = std::sync::mpsc::channel();
+    cleanup::install_panic_hook(socket_path);
+    let (tx, rx): (Sender<Request>, Receiver<Request>) = std::sync::mpsc::channel();
+    let subscribers = Subscribers::default();
+    let timer_thread;
     {
         let socket_path = socket_path.to_owned();
-        thread::spawn(|| handle_client(rx, socket_path, config));
+        let subscribers = subscribers.clone();
+        let span = Span::current();
+        timer_thread = thread::Builder::new()
+            .name(cleanup::TIMER_THREAD.to_string())
+            .spawn(move || {
+                let result = panic::catch_unwind(AssertUnwindSafe(|| {
+                    span.in_scope(|| handle_client(rx, socket_path, config, subscribers))
+                }));
+                if result.is_err() {
+                    // the panic hook has already cleaned up, there is nothing left to serve
+                    error!("Timer loop panicked, shutting down module");
+                    process::exit(1);
+                }
+            })
+            .expect("Failed to spawn timer thread");
     }
 
+    let shutdown = Arc::new(AtomicBool::new(false));
     for stream in listener.incoming() {
+        if shutdown.load(Ordering::SeqCst) {
+            break;
+        }
+
         match stream {
-            Ok(mut stream) => {
-                // read incoming data
-                let mut message = String::new();
-                stream
-                    .read_to_string(&mut message)
-                    .expect("Failed to read UNIX stream");
-
-                debug!("Received message: '{}'", message);
-
-                if message.contains("exit") {
-                    info!("Received exit signal, shutting down module");
-                    delete_socket(socket_path);
-                    break;
-                }
-                tx.send(message.to_string()).unwrap();
+            Ok(stream) => {
+                // handle every client on its own thread so a slow one can't stall the others
+                let tx = tx.clone();
+                let shutdown = Arc::clone(&shutdown);
+                let socket_path = socket_path.to_owned();
+                let subscribers = subscribers.clone();
+                let span = Span::current();
+                thread::spawn(move || {
+                    span.in_scope(|| {
+                        handle_connection(stream, tx, shutdown, &socket_path, &subscribers)
+                    })
+                });
             }
             Err(err) => warn!("Socket error: {}", err),
         }
     }
+
+    delete_socket(socket_path);
+
+    // let the timer loop run its exit hooks once the last client is done with it
+    drop(tx);
+    let _ = timer_thread.join();
 }
 
-/// Find the next available instance number by looking at existing sockets
-pub fn find_next_instance_number(binary_name: &str) -> u16 {
-    let sockets = get_existing_sockets(binary_name);
+/// Find the next available instance number by looking at the session's existing sockets
+pub fn find_next_instance_number(binary_name: &str, session: Option<&str>) -> u16 {
+    let sockets = get_existing_sockets(binary_name, session);
 
     // If no sockets exist, return 0 for the first instance
     if sockets.is_empty() {
@@ -339,12 +1700,7 @@ pub fn find_next_instance_number(binary_name: &str) -> u16 {
             socket
                 .file_stem() // Get filename without extension
                 .and_then(|name| name.to_str())
-                .and_then(|name| {
-                    SOCKET_NUMBER_REGEX
-                        .captures(name)
-                        .and_then(|caps| caps.get(1))
-                        .and_then(|m| m.as_str().parse::<u16>().ok())
-                })
+                .and_then(socket_number::<u16>)
         })
         .max()
         .unwrap_or(0);
@@ -353,17 +1709,26 @@ pub fn find_next_instance_number(binary_name: &str) -> u16 {
     max_instance.saturating_add(1)
 }
 
-pub fn get_existing_sockets(binary_name: &str) -> Vec<PathBuf> {
-    let mut files: Vec<PathBuf> = vec![];
-
+pub fn get_existing_sockets(binary_name: &str, session: Option<&str>) -> Vec<PathBuf> {
     // Use XDG runtime directory for socket discovery
-    let xdg_dirs = BaseDirectories::with_prefix(binary_name);
+    let Some(dir) = session::socket_dir(binary_name, session) else {
+        warn!("No runtime directory to look for sockets in");
+        return Vec::new();
+    };
+
+    sockets_in(&dir)
+}
 
-    debug!("Looking for socket files using XDG list_runtime_files");
+/// The sockets in `dir`, made canonical where possible
+fn sockets_in(dir: &Path) -> Vec<PathBuf> {
+    let mut files: Vec<PathBuf> = vec![];
+
+    debug!("Looking for socket files in {}", dir.display());
 
-    // Use list_runtime_files to get all files in our XDG runtime directory
-    let paths = xdg_dirs.list_runtime_files(".");
-    for path in paths {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return files;
+    };
+    for path in entries.filter_map(|entry| entry.ok().map(|entry| entry.path())) {
         if let Some(file_name) = path.file_name() {
             if let Some(file_name_str) = file_name.to_str() {
                 debug!("Found file: {}", file_name_str);
@@ -388,14 +1753,48 @@ pub fn get_existing_sockets(binary_name: &str) -> Vec<PathBuf> {
     files
 }
 
-pub fn send_message_socket(socket_path: &str, msg: &str) -> Result<(), Error> {
+/// Sends `msg` to the module listening on `socket_path` and returns its raw reply, which
+/// is empty if the module closed the connection without answering
+pub fn send_message_socket(socket_path: &str, msg: &str) -> Result<String, Error> {
+    send_message_retrying(socket_path, msg, CLIENT_READ_TIMEOUT, 0)
+}
+
+/// [`send_message_socket`], waiting `timeout` for the reply and trying to connect up to
+/// `retries` more times, backing off from 100ms
+///
+/// Only connecting is retried: once the message is written, sending it again could apply
+/// it twice.
+pub fn send_message_retrying(
+    socket_path: &str,
+    msg: &str,
+    timeout: Duration,
+    retries: u32,
+) -> Result<String, Error> {
     debug!("Attempting to connect to socket: {}", socket_path);
     debug!("Message to send: '{}'", msg);
-    let mut stream = UnixStream::connect(socket_path)?;
+    let mut backoff = Duration::from_millis(100);
+    let mut attempt = 0;
+    let mut stream = loop {
+        match UnixStream::connect(socket_path) {
+            Ok(stream) => break stream,
+            Err(e) if attempt < retries => {
+                debug!("Failed to connect ({}), retrying in {:?}", e, backoff);
+                thread::sleep(backoff);
+                backoff *= 2;
+                attempt += 1;
+            }
+            Err(e) => return Err(e),
+        }
+    };
     debug!("Connected to socket successfully");
     stream.write_all(msg.as_bytes())?;
+    // signal the end of the message so the module can reply
+    stream.shutdown(Shutdown::Write)?;
     debug!("Message written successfully");
-    Ok(())
+
+    let reply = read_client(&mut stream, timeout)?;
+    debug!("Received reply: '{}'", reply);
+    Ok(reply)
 }
 
 #[cfg(test)]
@@ -403,7 +1802,7 @@ mod tests {
     use crate::utils::consts::{LONG_BREAK_TIME, SHORT_BREAK_TIME, WORK_TIME};
 
     use super::*;
-    use crate::services::module::CycleType;
+    use crate::services::{module::CycleType, sink::OutputSink};
 
     fn create_timer() -> Timer {
         Timer::new(WORK_TIME, SHORT_BREAK_TIME, LONG_BREAK_TIME, 0)
@@ -420,19 +1819,37 @@ mod tests {
     #[test]
     fn test_send_notification_work() {
         let config = Config::default();
-        send_notification(CycleType::Work, &config);
+        send_notification(CycleType::ShortBreak, CycleType::Work, None, &config);
     }
 
     #[test]
     fn test_send_notification_short_break() {
         let config = Config::default();
-        send_notification(CycleType::ShortBreak, &config);
+        send_notification(CycleType::Work, CycleType::ShortBreak, None, &config);
     }
 
     #[test]
     fn test_send_notification_long_break() {
         let config = Config::default();
-        send_notification(CycleType::LongBreak, &config);
+        send_notification(CycleType::Work, CycleType::LongBreak, None, &config);
+    }
+
+    #[test]
+    fn test_checkpoint_passed() {
+        let checkpoints = [50, 90];
+        assert_eq!(checkpoint_passed(0, 749, 1500, &checkpoints), None);
+        assert_eq!(checkpoint_passed(749, 750, 1500, &checkpoints), Some(50));
+        assert_eq!(checkpoint_passed(750, 1349, 1500, &checkpoints), None);
+        // a long stall only cues once, for the furthest checkpoint
+        assert_eq!(checkpoint_passed(0, 1400, 1500, &checkpoints), Some(90));
+        assert_eq!(checkpoint_passed(0, 1500, 1500, &[]), None);
+    }
+
+    #[test]
+    fn test_format_focus() {
+        assert_eq!(format_focus(59), "0m");
+        assert_eq!(format_focus(25 * 60), "25m");
+        assert_eq!(format_focus(3 * 3600 + 25 * 60), "3h25m");
     }
 
     #[test]
@@ -446,11 +1863,11 @@ mod tests {
     fn test_create_message() {
         let message = "Pomodoro";
         let tooltip = "Tooltip";
-        let class = "Class";
+        let alt = "work-running";
 
-        let result = create_message(message.to_string(), tooltip, class);
+        let result = create_message(message.to_string(), tooltip, &["work", "running"], alt);
         let expected = format!(
-            r#"{{"text": "{message}", "tooltip": "{tooltip}", "class": "{class}", "alt": "{class}"}}"#,
+            r#"{{"text": "{message}", "tooltip": "{tooltip}", "class": ["work","running"], "alt": "{alt}"}}"#,
         );
         assert!(result == expected);
     }
@@ -458,33 +1875,118 @@ mod tests {
     #[test]
     fn test_process_message_set_work() {
         let mut timer = create_timer();
-        let config = Config::default();
-        process_message(&mut timer, r#"{"set-work":{"time":"30"}}"#, &config);
+        let mut config = Config::default();
+        let mut runtime = Runtime::new("/tmp/test.socket");
+        process_message(
+            &mut timer,
+            r#"{"set-work":{"time":"30"}}"#,
+            &mut config,
+            &mut runtime,
+        );
         assert_eq!(get_time(&timer, CycleType::Work), 30 * MINUTE);
     }
 
     #[test]
     fn test_process_message_set_short() {
         let mut timer = create_timer();
-        let config = Config::default();
-        process_message(&mut timer, r#"{"set-short":{"time":"3"}}"#, &config);
+        let mut config = Config::default();
+        let mut runtime = Runtime::new("/tmp/test.socket");
+        process_message(
+            &mut timer,
+            r#"{"set-short":{"time":"3"}}"#,
+            &mut config,
+            &mut runtime,
+        );
         assert_eq!(get_time(&timer, CycleType::ShortBreak), 3 * MINUTE);
     }
 
     #[test]
     fn test_process_message_set_long() {
         let mut timer = create_timer();
-        let config = Config::default();
-        process_message(&mut timer, r#"{"set-long":{"time":"10"}}"#, &config);
+        let mut config = Config::default();
+        let mut runtime = Runtime::new("/tmp/test.socket");
+        process_message(
+            &mut timer,
+            r#"{"set-long":{"time":"10"}}"#,
+            &mut config,
+            &mut runtime,
+        );
         assert_eq!(get_time(&timer, CycleType::LongBreak), 10 * MINUTE);
     }
 
+    #[test]
+    fn test_process_message_set_work_scales_breaks() {
+        let mut timer = create_timer();
+        let mut config = Config {
+            break_ratio: Some(Default::default()),
+            ..Default::default()
+        };
+        let mut runtime = Runtime::new("/tmp/test.socket");
+        process_message(
+            &mut timer,
+            r#"{"set-work":{"time":"50"}}"#,
+            &mut config,
+            &mut runtime,
+        );
+        assert_eq!(get_time(&timer, CycleType::ShortBreak), 10 * MINUTE);
+        assert_eq!(get_time(&timer, CycleType::LongBreak), 25 * MINUTE);
+
+        // breaks set on their own stay put until work changes again
+        process_message(
+            &mut timer,
+            r#"{"set-short":{"time":"3"}}"#,
+            &mut config,
+            &mut runtime,
+        );
+        assert_eq!(get_time(&timer, CycleType::ShortBreak), 3 * MINUTE);
+        process_message(
+            &mut timer,
+            r#"{"set-work":{"time":"-10"}}"#,
+            &mut config,
+            &mut runtime,
+        );
+        assert_eq!(get_time(&timer, CycleType::ShortBreak), 8 * MINUTE);
+    }
+
+    #[test]
+    fn test_process_message_duration_bounds() {
+        let mut timer = create_timer();
+        let mut config = Config::default();
+        config.bounds[0] = "20-60".parse().unwrap();
+        let mut runtime = Runtime::new("/tmp/test.socket");
+        let mut send =
+            |message: &str| match process_message(&mut timer, message, &mut config, &mut runtime) {
+                Response::Error { kind, message } => {
+                    assert_eq!(kind, ErrorKind::InvalidValue);
+                    Some(message)
+                }
+                _ => None,
+            };
+
+        assert_eq!(
+            send(r#"{"set-work":{"time":"0"}}"#).as_deref(),
+            Some("Duration out of range: 0 minutes of work (allowed 20 to 60)")
+        );
+        assert!(send(r#"{"set-work":{"time":"-10"}}"#).is_some());
+        assert!(send(r#"{"set-work":{"time":"+40"}}"#).is_some());
+        assert_eq!(send(r#"{"set-work":{"time":"+35"}}"#), None);
+        // the current cycle is work, held to the same bounds
+        assert!(send(r#"{"set-current":{"time":"15"}}"#).is_some());
+        // breaks keep the default bounds, which still refuse 0 minutes
+        assert!(send(r#"{"set-short":{"time":"-5"}}"#).is_some());
+        assert_eq!(send(r#"{"set-short":{"time":"-4"}}"#), None);
+
+        assert_eq!(get_time(&timer, CycleType::Work), 60 * MINUTE);
+        assert_eq!(get_time(&timer, CycleType::ShortBreak), MINUTE);
+    }
+
     #[test]
     fn test_process_message_start() {
         let mut timer = create_timer();
         // Test backward compatibility - plain string should work
-        let config = Config::default();
-        process_message(&mut timer, "start", &config);
+        let mut config = Config::default();
+        let mut runtime = Runtime::new("/tmp/test.socket");
+        process_message(&mut timer, "start", &mut config, &mut runtime);
         assert!(timer.running);
     }
 
@@ -493,39 +1995,907 @@ mod tests {
         let mut timer = create_timer();
         timer.running = true;
         // Test backward compatibility - plain string should work
-        let config = Config::default();
-        process_message(&mut timer, "stop", &config);
+        let mut config = Config::default();
+        let mut runtime = Runtime::new("/tmp/test.socket");
+        process_message(&mut timer, "stop", &mut config, &mut runtime);
         assert!(!timer.running);
     }
 
+    #[test]
+    fn test_process_message_start_clears_urgent() {
+        let mut timer = create_timer();
+        timer.awaiting_start = true;
+        let mut config = Config::default();
+        let mut runtime = Runtime::new("/tmp/test.socket");
+        process_message(&mut timer, "start", &mut config, &mut runtime);
+        assert!(timer.running);
+        assert!(!timer.awaiting_start);
+    }
+
+    #[test]
+    fn test_process_message_switches() {
+        let mut timer = create_timer();
+        let mut config = Config::default();
+        let mut runtime = Runtime::new("/tmp/test.socket");
+
+        process_message(
+            &mut timer,
+            r#"{"notifications":{"state":"on"}}"#,
+            &mut config,
+            &mut runtime,
+        );
+        assert!(config.with_notifications);
+        process_message(
+            &mut timer,
+            r#"{"notifications":{"state":"toggle"}}"#,
+            &mut config,
+            &mut runtime,
+        );
+        assert!(!config.with_notifications);
+
+        assert!(config.with_sounds);
+        process_message(
+            &mut timer,
+            r#"{"sounds":{"state":"off"}}"#,
+            &mut config,
+            &mut runtime,
+        );
+        assert!(!config.with_sounds);
+    }
+
+    #[test]
+    fn test_process_message_set_auto() {
+        let mut timer = create_timer();
+        let mut config = Config::default();
+        let mut runtime = Runtime::new("/tmp/test.socket");
+
+        process_message(
+            &mut timer,
+            r#"{"set-auto":{"cycle":"break","state":"on"}}"#,
+            &mut config,
+            &mut runtime,
+        );
+        assert!(config.auto_start.short_break);
+        assert!(config.auto_start.long_break);
+        assert!(!config.auto_start.work_after_short);
+
+        process_message(
+            &mut timer,
+            r#"{"set-auto":{"cycle":"work","state":"toggle"}}"#,
+            &mut config,
+            &mut runtime,
+        );
+        assert!(config.auto_start.work_after_short);
+        assert!(config.auto_start.work_after_long);
+    }
+
+    #[test]
+    fn test_process_message_appearance() {
+        let mut timer = create_timer();
+        let mut config = Config::default();
+        let mut runtime = Runtime::new("/tmp/test.socket");
+
+        process_message(
+            &mut timer,
+            r#"{"set-icon":{"icon":"pause","value":"zz"}}"#,
+            &mut config,
+            &mut runtime,
+        );
+        assert_eq!(config.pause_icon, "zz");
+
+        process_message(
+            &mut timer,
+            r#"{"set-format":{"format":"{time}"}}"#,
+            &mut config,
+            &mut runtime,
+        );
+        assert_eq!(config.format, "{time}");
+    }
+
+    #[test]
+    fn test_process_message_toggle_during_grace() {
+        let mut timer = create_timer();
+        timer.grace_millis = Some(5000);
+        let mut config = Config::default();
+        let mut runtime = Runtime::new("/tmp/test.socket");
+        process_message(&mut timer, "toggle", &mut config, &mut runtime);
+        assert!(timer.running);
+        assert_eq!(timer.grace_millis, None);
+    }
+
     #[test]
     fn test_process_message_set_current() {
         let mut timer = create_timer();
 
         // Test setting current work time
         timer.current_index = 0;
-        let config = Config::default();
-        process_message(&mut timer, r#"{"set-current":{"time":"30"}}"#, &config);
+        let mut config = Config::default();
+        let mut runtime = Runtime::new("/tmp/test.socket");
+        process_message(
+            &mut timer,
+            r#"{"set-current":{"time":"30"}}"#,
+            &mut config,
+            &mut runtime,
+        );
         assert_eq!(timer.get_current_time(), 30 * 60);
         // Original time should remain unchanged
         assert_eq!(timer.times[0], WORK_TIME);
 
         // Test setting current break time
         timer.current_index = 1;
-        process_message(&mut timer, r#"{"set-current":{"time":"10"}}"#, &config);
+        process_message(
+            &mut timer,
+            r#"{"set-current":{"time":"10"}}"#,
+            &mut config,
+            &mut runtime,
+        );
         assert_eq!(timer.get_current_time(), 10 * 60);
         // Original time should remain unchanged
         assert_eq!(timer.times[1], SHORT_BREAK_TIME);
 
         // Test delta on current
-        process_message(&mut timer, r#"{"set-current":{"time":"+5"}}"#, &config);
+        process_message(
+            &mut timer,
+            r#"{"set-current":{"time":"+5"}}"#,
+            &mut config,
+            &mut runtime,
+        );
         assert_eq!(timer.get_current_time(), 15 * 60);
 
         // Test negative delta
-        process_message(&mut timer, r#"{"set-current":{"time":"-2"}}"#, &config);
+        process_message(
+            &mut timer,
+            r#"{"set-current":{"time":"-2"}}"#,
+            &mut config,
+            &mut runtime,
+        );
         assert_eq!(timer.get_current_time(), 13 * 60);
     }
 
+    #[test]
+    fn test_read_client() {
+        let (mut client, server) = UnixStream::pair().unwrap();
+        client.write_all(b"start").unwrap();
+        drop(client);
+
+        let mut server = server;
+        assert_eq!(
+            read_client(&mut server, Duration::from_millis(100)).unwrap(),
+            "start"
+        );
+    }
+
+    #[test]
+    fn test_read_client_times_out() {
+        // a client that connects and never writes must not block forever
+        let (_client, mut server) = UnixStream::pair().unwrap();
+        assert!(read_client(&mut server, Duration::from_millis(50)).is_err());
+    }
+
+    #[test]
+    fn test_handle_connection_replies() {
+        let (mut client, server) = UnixStream::pair().unwrap();
+        client.write_all(b"toggle").unwrap();
+        client.shutdown(Shutdown::Write).unwrap();
+
+        let (tx, rx) = std::sync::mpsc::channel::<Request>();
+        let shutdown = Arc::new(AtomicBool::new(false));
+        let responder = thread::spawn(move || {
+            let request = rx.recv().unwrap();
+            assert_eq!(request.message, "toggle");
+            request.reply.send(Response::ok()).unwrap();
+        });
+        handle_connection(
+            server,
+            tx,
+            Arc::clone(&shutdown),
+            Path::new("/nonexistent"),
+            &Subscribers::default(),
+        );
+        responder.join().unwrap();
+
+        let mut reply = String::new();
+        client.read_to_string(&mut reply).unwrap();
+        assert_eq!(Response::decode(&reply).unwrap(), Response::ok());
+        assert!(!shutdown.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn test_handle_connection_exit() {
+        let (mut client, server) = UnixStream::pair().unwrap();
+        client.write_all(b"exit\n").unwrap();
+        client.shutdown(Shutdown::Write).unwrap();
+
+        let (tx, rx) = std::sync::mpsc::channel::<Request>();
+        let shutdown = Arc::new(AtomicBool::new(false));
+        handle_connection(
+            server,
+            tx,
+            Arc::clone(&shutdown),
+            Path::new("/nonexistent"),
+            &Subscribers::default(),
+        );
+
+        let mut reply = String::new();
+        client.read_to_string(&mut reply).unwrap();
+        assert_eq!(Response::decode(&reply).unwrap(), Response::ok());
+        assert!(shutdown.load(Ordering::SeqCst));
+        // the timer loop never sees it
+        assert!(rx.try_recv().is_err());
+    }
+
+    #[test]
+    fn test_handle_connection_subscribe() {
+        let (mut client, server) = UnixStream::pair().unwrap();
+        client.write_all(b"subscribe").unwrap();
+        client.shutdown(Shutdown::Write).unwrap();
+
+        let (tx, rx) = std::sync::mpsc::channel::<Request>();
+        let mut subscribers = Subscribers::default();
+        let shutdown = Arc::new(AtomicBool::new(false));
+        handle_connection(
+            server,
+            tx,
+            Arc::clone(&shutdown),
+            Path::new("/nonexistent"),
+            &subscribers,
+        );
+        // kept for the sink rather than passed to the timer loop
+        assert!(rx.try_recv().is_err());
+
+        subscribers.emit("{}", &create_timer()).unwrap();
+        subscribers.finish();
+        let mut lines = String::new();
+        client.read_to_string(&mut lines).unwrap();
+        assert_eq!(lines, "{}\n");
+    }
+
+    #[test]
+    fn test_handle_connection_ignores_exit_in_arguments() {
+        let message = r#"{"set-task":{"name":"exited tests"}}"#;
+        let (mut client, server) = UnixStream::pair().unwrap();
+        client.write_all(message.as_bytes()).unwrap();
+        client.shutdown(Shutdown::Write).unwrap();
+
+        let (tx, rx) = std::sync::mpsc::channel::<Request>();
+        let shutdown = Arc::new(AtomicBool::new(false));
+        let responder = thread::spawn(move || {
+            let request = rx.recv().unwrap();
+            assert_eq!(request.message, message);
+            request.reply.send(Response::ok()).unwrap();
+        });
+        handle_connection(
+            server,
+            tx,
+            Arc::clone(&shutdown),
+            Path::new("/nonexistent"),
+            &Subscribers::default(),
+        );
+        responder.join().unwrap();
+        assert!(!shutdown.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn test_process_message_errors() {
+        let mut timer = create_timer();
+        let mut config = Config::default();
+        let mut runtime = Runtime::new("/tmp/test.socket");
+
+        assert_eq!(
+            process_message(&mut timer, "start", &mut config, &mut runtime),
+            Response::ok()
+        );
+
+        match process_message(&mut timer, "launch", &mut config, &mut runtime) {
+            Response::Error { kind, .. } => assert_eq!(kind, ErrorKind::UnknownCommand),
+            other => panic!("unexpected response {other:?}"),
+        }
+
+        // 2000 minutes doesn't fit the timer, it must be rejected rather than overflow
+        match process_message(
+            &mut timer,
+            r#"{"set-work":{"time":"2000"}}"#,
+            &mut config,
+            &mut runtime,
+        ) {
+            Response::Error { kind, .. } => assert_eq!(kind, ErrorKind::InvalidValue),
+            other => panic!("unexpected response {other:?}"),
+        }
+        assert_eq!(timer.times[0], WORK_TIME);
+
+        match process_message(
+            &mut timer,
+            r#"{"set-current":{"time":"+1500"}}"#,
+            &mut config,
+            &mut runtime,
+        ) {
+            Response::Error { kind, .. } => assert_eq!(kind, ErrorKind::InvalidValue),
+            other => panic!("unexpected response {other:?}"),
+        }
+        assert_eq!(runtime.recent_errors.len(), 3);
+    }
+
+    #[test]
+    fn test_process_message_tasks() {
+        let dir = tempfile::tempdir().unwrap();
+        let history_path = dir.path().join("history.jsonl");
+        let mut timer = create_timer();
+        let mut config = Config::default();
+        let mut runtime = Runtime::new("/tmp/test.socket");
+        runtime.history_path = Some(history_path.clone());
+
+        process_message(
+            &mut timer,
+            r#"{"set-task":{"name":"RFC","estimate":4}}"#,
+            &mut config,
+            &mut runtime,
+        );
+        process_message(
+            &mut timer,
+            r#"{"annotate":{"note":"outline"}}"#,
+            &mut config,
+            &mut runtime,
+        );
+        timer.elapsed_time = timer.get_current_time();
+        timer.update_state(&config);
+        record_completed(&mut timer, &mut runtime, &config);
+        assert_eq!(timer.task.as_ref().unwrap().progress(), "1/4");
+        // breaks can't be annotated
+        assert!(matches!(
+            process_message(
+                &mut timer,
+                r#"{"annotate":{"note":"coffee"}}"#,
+                &mut config,
+                &mut runtime
+            ),
+            Response::Error { .. }
+        ));
+
+        process_message(&mut timer, "clear-task", &mut config, &mut runtime);
+        assert_eq!(timer.task, None);
+
+        let events = history::read(&history_path).unwrap();
+        assert_eq!(events.len(), 2);
+        assert!(matches!(
+            &events[0],
+            HistoryEvent::Cycle { cycle: CycleType::Work, task: Some(task), notes, .. }
+                if task == "RFC" && notes == &["outline"]
+        ));
+        assert!(matches!(
+            &events[1],
+            HistoryEvent::Task {
+                estimate: Some(4),
+                completed: 1,
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn test_process_message_resume_last() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut timer = create_timer();
+        let mut config = Config::default();
+        let mut runtime = Runtime::new("/tmp/test.socket");
+
+        let response = process_message(&mut timer, "resume-last", &mut config, &mut runtime);
+        assert!(matches!(response, Response::Error { .. }));
+
+        runtime.history_path = Some(dir.path().join("history.jsonl"));
+        let response = process_message(&mut timer, "resume-last", &mut config, &mut runtime);
+        assert!(matches!(response, Response::Error { .. }));
+
+        process_message(
+            &mut timer,
+            r#"{"set-task":{"name":"RFC"}}"#,
+            &mut config,
+            &mut runtime,
+        );
+        process_message(&mut timer, "start", &mut config, &mut runtime);
+        tick(&mut timer, &config, 600_000);
+        process_message(&mut timer, "reset", &mut config, &mut runtime);
+        process_message(&mut timer, "clear-task", &mut config, &mut runtime);
+        assert_eq!(timer.elapsed_time, 0);
+
+        let response = process_message(&mut timer, "resume-last", &mut config, &mut runtime);
+        assert_eq!(
+            response,
+            Response::with_data(serde_json::json!({
+                "cycle": "work",
+                "time": "15:00",
+                "task": "RFC",
+            }))
+        );
+        assert_eq!(timer.elapsed_time, 600);
+        assert!(!timer.running);
+    }
+
+    #[test]
+    fn test_set_summary() {
+        let tally = SetTally {
+            pomodoros: 4,
+            focus_millis: 100 * 60 * 1000,
+            interruptions: 1,
+        };
+        assert_eq!(
+            set_summary(&tally),
+            "Set complete: 4 pomodoros, 1h40m focused, 1 interruption"
+        );
+    }
+
+    #[test]
+    fn test_process_message_yield_focus() {
+        let mut timer = create_timer();
+        let mut config = Config::default();
+        let mut runtime = Runtime::new("/tmp/test.socket");
+
+        process_message(&mut timer, "start", &mut config, &mut runtime);
+        process_message(&mut timer, "yield-focus", &mut config, &mut runtime);
+        assert!(!timer.running);
+
+        // breaks carry on, there is nothing to focus on in them
+        timer.next_state(&config);
+        process_message(&mut timer, "start", &mut config, &mut runtime);
+        process_message(&mut timer, "yield-focus", &mut config, &mut runtime);
+        assert!(timer.running);
+    }
+
+    #[test]
+    fn test_process_message_idle_resume() {
+        let mut timer = create_timer();
+        let mut config = Config {
+            idle_resume: Some(Duration::from_secs(60)),
+            ..Config::default()
+        };
+        let mut runtime = Runtime::new("/tmp/test.socket");
+
+        process_message(&mut timer, "start", &mut config, &mut runtime);
+        process_message(&mut timer, "idle", &mut config, &mut runtime);
+        assert!(!timer.running);
+        process_message(&mut timer, "active", &mut config, &mut runtime);
+        assert!(timer.running);
+
+        // back too late
+        process_message(&mut timer, "idle", &mut config, &mut runtime);
+        runtime.idle_since = Some(Instant::now() - Duration::from_secs(120));
+        process_message(&mut timer, "active", &mut config, &mut runtime);
+        assert!(!timer.running);
+
+        // a timer that was already paused stays paused
+        process_message(&mut timer, "idle", &mut config, &mut runtime);
+        process_message(&mut timer, "active", &mut config, &mut runtime);
+        assert!(!timer.running);
+    }
+
+    #[test]
+    fn test_follow_call() {
+        let mut timer = create_timer();
+        let mut config = Config {
+            with_notifications: true,
+            ..Config::default()
+        };
+        let mut runtime = Runtime::new("/tmp/test.socket");
+
+        timer.running = true;
+        runtime.follow_call(true, &mut timer, &mut config);
+        assert!(!timer.running);
+        assert!(!config.with_notifications);
+        runtime.follow_call(false, &mut timer, &mut config);
+        assert!(timer.running);
+        assert!(config.with_notifications);
+
+        // a timer that was already paused stays paused
+        timer.running = false;
+        runtime.follow_call(true, &mut timer, &mut config);
+        runtime.follow_call(false, &mut timer, &mut config);
+        assert!(!timer.running);
+    }
+
+    #[test]
+    fn test_follow_snooze() {
+        let mut timer = create_timer();
+        let mut config = Config::default();
+        let mut runtime = Runtime::new("/tmp/test.socket");
+
+        process_message(&mut timer, "start", &mut config, &mut runtime);
+        timer.elapsed_time = 300;
+        let snooze = Message::SnoozeUntil {
+            until: "14:00".parse().unwrap(),
+        };
+        process_message(&mut timer, &snooze.encode(), &mut config, &mut runtime);
+        assert!(!timer.running);
+        let until = runtime.snooze.as_ref().unwrap().until;
+
+        runtime.follow_snooze(&mut timer, &config, until - chrono::TimeDelta::minutes(5));
+        assert!(!runtime.snooze.as_ref().unwrap().warned);
+        runtime.follow_snooze(&mut timer, &config, until - chrono::TimeDelta::seconds(30));
+        assert!(runtime.snooze.as_ref().unwrap().warned);
+        assert!(!timer.running);
+
+        runtime.follow_snooze(&mut timer, &config, until);
+        assert!(timer.running);
+        assert_eq!(timer.elapsed_time, 300);
+        assert!(runtime.snooze.is_none());
+    }
+
+    #[test]
+    fn test_abandon_stale() {
+        let dir = tempfile::tempdir().unwrap();
+        let history_path = dir.path().join("history.jsonl");
+        let mut timer = create_timer();
+        let config = Config {
+            abandon_after: Some(Duration::from_secs(600)),
+            ..Config::default()
+        };
+        let mut runtime = Runtime::new("/tmp/test.socket");
+        runtime.history_path = Some(history_path.clone());
+
+        timer.elapsed_time = 300;
+        timer.paused_since = Some(Instant::now() - Duration::from_secs(300));
+        runtime.abandon_stale(&mut timer, &config);
+        assert_eq!(timer.elapsed_time, 300);
+
+        timer.paused_since = Some(Instant::now() - Duration::from_secs(900));
+        runtime.abandon_stale(&mut timer, &config);
+        assert_eq!(timer.elapsed_time, 0);
+        assert!(matches!(
+            history::read(&history_path).unwrap()[..],
+            [HistoryEvent::Abandoned {
+                cycle: CycleType::Work,
+                worked: 300,
+                ..
+            }]
+        ));
+    }
+
+    #[test]
+    fn test_pause_cause() {
+        let mut timer = create_timer();
+        let mut config = Config {
+            idle_resume: Some(Duration::from_secs(60)),
+            ..Config::default()
+        };
+        let mut runtime = Runtime::new("/tmp/test.socket");
+        let tooltip = |timer: &mut Timer, config: &Config| {
+            let line: serde_json::Value = serde_json::from_str(&tick(timer, config, 0)).unwrap();
+            line["tooltip"].as_str().unwrap().to_string()
+        };
+
+        process_message(&mut timer, "start", &mut config, &mut runtime);
+        tick(&mut timer, &config, 60_000);
+        process_message(&mut timer, "lock", &mut config, &mut runtime);
+        assert!(tooltip(&mut timer, &config).ends_with("\nPaused: screen locked"));
+        assert_eq!(remaining(&timer, &config)["paused"], "locked");
+
+        process_message(&mut timer, "active", &mut config, &mut runtime);
+        assert!(!tooltip(&mut timer, &config).contains("Paused"));
+        assert_eq!(
+            remaining(&timer, &config)["paused"],
+            serde_json::Value::Null
+        );
+        assert_eq!(timer.take_pauses(), [(PauseCause::Locked, 0)]);
+
+        process_message(&mut timer, "toggle", &mut config, &mut runtime);
+        assert!(tooltip(&mut timer, &config).ends_with("\nPaused: paused by hand"));
+    }
+
+    #[test]
+    fn test_text_width() {
+        let mut timer = create_timer();
+        let mut config = Config {
+            format: "{time} {task}".to_string(),
+            max_length: Some(16),
+            min_length: 12,
+            ..Config::default()
+        };
+        let mut runtime = Runtime::new("/tmp/test.socket");
+        let text = |timer: &mut Timer, config: &Config| {
+            let line: serde_json::Value = serde_json::from_str(&tick(timer, config, 0)).unwrap();
+            line["text"].as_str().unwrap().to_string()
+        };
+
+        assert_eq!(text(&mut timer, &config), "25:00       ");
+        process_message(
+            &mut timer,
+            r#"{"set-task": {"name": "Write the RFC draft"}}"#,
+            &mut config,
+            &mut runtime,
+        );
+        // the task gives way, the time stays
+        assert_eq!(text(&mut timer, &config), "25:00 Write the…");
+
+        // widths count the task as written, it's escaped for markup after it's been cut
+        process_message(
+            &mut timer,
+            r#"{"set-task": {"name": "Q&A"}}"#,
+            &mut config,
+            &mut runtime,
+        );
+        assert_eq!(text(&mut timer, &config), "25:00 Q&amp;A   ");
+        process_message(
+            &mut timer,
+            r#"{"set-task": {"name": "R&D <review>"}}"#,
+            &mut config,
+            &mut runtime,
+        );
+        assert_eq!(text(&mut timer, &config), "25:00 R&amp;D &lt;revi…");
+
+        // markup in the format is kept and takes up no room
+        config.format = "<span color='red'>{time}</span> {task}".to_string();
+        assert_eq!(
+            text(&mut timer, &config),
+            "<span color='red'>25:00</span> R&amp;D &lt;revi…"
+        );
+
+        config.format = "{time} of the current work cycle".to_string();
+        assert_eq!(text(&mut timer, &config), "25:00 of the cu…");
+        config.format = "<b>{time} of the current work cycle & more</b>".to_string();
+        assert_eq!(text(&mut timer, &config), "25:00 of the cu…");
+    }
+
+    #[test]
+    fn test_process_message_toggle_display() {
+        let mut timer = create_timer();
+        let mut config = Config::default();
+        let mut runtime = Runtime::new("/tmp/test.socket");
+
+        process_message(&mut timer, "start", &mut config, &mut runtime);
+        assert!(tick(&mut timer, &config, 60_000).contains("24:00"));
+        process_message(&mut timer, "toggle-display", &mut config, &mut runtime);
+        assert!(tick(&mut timer, &config, 0).contains("01:00"));
+        process_message(&mut timer, "toggle-display", &mut config, &mut runtime);
+        assert!(tick(&mut timer, &config, 0).contains("24:00"));
+    }
+
+    #[test]
+    fn test_power_save_rendering() {
+        let mut timer = create_timer();
+        let mut config = Config {
+            saving_power: true,
+            ..Config::default()
+        };
+        let mut runtime = Runtime::new("/tmp/test.socket");
+
+        process_message(&mut timer, "start", &mut config, &mut runtime);
+        let line = tick(&mut timer, &config, 30_000);
+        assert!(line.contains("25m"));
+        assert!(line.contains("power-save"));
+        // the next tick lands where the minute shown changes
+        assert_eq!(
+            timer.next_tick_in(Duration::from_millis(POWER_SAVE_TICK_RATE as u64)),
+            Duration::from_secs(30)
+        );
+        assert!(tick(&mut timer, &config, 30_000).contains("24m"));
+    }
+
+    #[test]
+    fn test_create_message_escapes_text() {
+        let message = create_message("a \"b\"".to_string(), "x\ny", &["work"], "work-running");
+        let parsed: serde_json::Value = serde_json::from_str(&message).unwrap();
+        assert_eq!(parsed["text"], "a \"b\"");
+        assert_eq!(parsed["tooltip"], "x\ny");
+    }
+
+    #[test]
+    fn test_process_message_dump() {
+        let mut timer = create_timer();
+        let mut config = Config::default();
+        let mut runtime = Runtime::new("/tmp/test.socket");
+        for _ in 0..MAX_RECENT_ERRORS + 2 {
+            process_message(&mut timer, "launch", &mut config, &mut runtime);
+        }
+
+        let data = match process_message(&mut timer, "dump", &mut config, &mut runtime) {
+            Response::Ok { data: Some(data) } => data,
+            other => panic!("unexpected response {other:?}"),
+        };
+        assert_eq!(data["socket_path"], "/tmp/test.socket");
+        assert_eq!(data["config"]["work_time"], config.work_time);
+        assert_eq!(data["timer"]["current_index"], 0);
+        assert_eq!(data["timer_internals"]["current_cycle"], "work");
+        assert_eq!(
+            data["recent_errors"].as_array().unwrap().len(),
+            MAX_RECENT_ERRORS
+        );
+    }
+
+    #[test]
+    fn test_process_message_show_config() {
+        let mut timer = create_timer();
+        let mut config = Config::default();
+        let mut runtime = Runtime::new("/tmp/test.socket");
+
+        // changes made at runtime show
+        let sounds_off = Message::Sounds {
+            state: crate::models::message::Switch::Off,
+        }
+        .encode();
+        process_message(&mut timer, &sounds_off, &mut config, &mut runtime);
+        let data = match process_message(&mut timer, "show-config", &mut config, &mut runtime) {
+            Response::Ok { data: Some(data) } => data,
+            other => panic!("unexpected response {other:?}"),
+        };
+        assert_eq!(data["with_sounds"], false);
+        assert_eq!(data["work_time"], timer.times[0]);
+        assert_eq!(data["with_notifications"], config.with_notifications);
+    }
+
+    #[test]
+    fn test_process_message_ping() {
+        let mut timer = create_timer();
+        timer.socket_nr = 2;
+        let mut config = Config {
+            name: Some("coding".to_string()),
+            groups: vec!["monitors".to_string()],
+            ..Config::default()
+        };
+        let mut runtime = Runtime::new("/tmp/test.socket");
+
+        let data = match process_message(&mut timer, "ping", &mut config, &mut runtime) {
+            Response::Ok { data: Some(data) } => data,
+            other => panic!("unexpected response {other:?}"),
+        };
+        assert_eq!(data["version"], env!("CARGO_PKG_VERSION"));
+        assert_eq!(data["protocol"], PROTOCOL_VERSION);
+        assert_eq!(data["instance"], 2);
+        assert_eq!(data["name"], "coding");
+        assert_eq!(data["groups"], serde_json::json!(["monitors"]));
+        assert!(data["uptime_secs"].is_u64());
+    }
+
+    #[test]
+    fn test_process_message_remaining() {
+        let mut timer = create_timer();
+        let mut config = Config::default();
+        let mut runtime = Runtime::new("/tmp/test.socket");
+        timer.elapsed_time = 5 * MINUTE + 30;
+
+        let data = match process_message(&mut timer, "remaining", &mut config, &mut runtime) {
+            Response::Ok { data: Some(data) } => data,
+            other => panic!("unexpected response {other:?}"),
+        };
+        assert_eq!(data["seconds"], 19 * MINUTE + 30);
+        assert_eq!(data["minutes"], 20);
+        assert_eq!(data["percent"], 78);
+        assert_eq!(data["time"], "19:30");
+        assert_eq!(data["cycle"], "work");
+        assert_eq!(data["running"], false);
+    }
+
+    #[test]
+    fn test_process_message_seek() {
+        let mut timer = create_timer();
+        let mut config = Config::default();
+        let mut runtime = Runtime::new("/tmp/test.socket");
+
+        process_message(
+            &mut timer,
+            r#"{"seek":{"to":"10:00"}}"#,
+            &mut config,
+            &mut runtime,
+        );
+        assert_eq!(timer.elapsed_time, 10 * MINUTE);
+        assert_eq!(timer.get_current_time(), WORK_TIME);
+
+        process_message(
+            &mut timer,
+            r#"{"seek":{"to":"100%"}}"#,
+            &mut config,
+            &mut runtime,
+        );
+        assert_eq!(timer.current_index, 1);
+        assert_eq!(timer.elapsed_time, 0);
+    }
+
+    #[test]
+    fn test_process_message_export_and_import_state() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut config = Config::default();
+
+        let mut desktop = create_timer();
+        let mut desktop_runtime = Runtime::new("/tmp/test.socket");
+        desktop_runtime.stats_path = Some(dir.path().join("desktop.json"));
+        process_message(&mut desktop, "start", &mut config, &mut desktop_runtime);
+        process_message(
+            &mut desktop,
+            r#"{"set-task":{"name":"RFC"}}"#,
+            &mut config,
+            &mut desktop_runtime,
+        );
+        desktop.elapsed_time = 300;
+        desktop_runtime.record_pomodoro(&config);
+
+        let snapshot = match process_message(
+            &mut desktop,
+            "export-state",
+            &mut config,
+            &mut desktop_runtime,
+        ) {
+            Response::Ok { data: Some(data) } => data,
+            other => panic!("unexpected response {other:?}"),
+        };
+        assert_eq!(snapshot["stats"]["total_pomodoros"], 1);
+
+        let mut laptop = Timer::new(WORK_TIME, SHORT_BREAK_TIME, LONG_BREAK_TIME, 3);
+        let mut laptop_runtime = Runtime::new("/tmp/test3.socket");
+        laptop_runtime.stats_path = Some(dir.path().join("laptop.json"));
+        let message = serde_json::json!({ "import-state": { "snapshot": snapshot } });
+        assert_eq!(
+            process_message(
+                &mut laptop,
+                &message.to_string(),
+                &mut config,
+                &mut laptop_runtime
+            ),
+            Response::ok()
+        );
+
+        assert!(laptop.running);
+        assert_eq!(laptop.elapsed_time, 300);
+        assert_eq!(laptop.task.as_ref().unwrap().name, "RFC");
+        assert_eq!(laptop.socket_nr, 3);
+        let stats = stats::load(laptop_runtime.stats_path.as_ref().unwrap()).unwrap();
+        assert_eq!(stats.total_pomodoros, 1);
+    }
+
+    #[test]
+    fn test_process_message_import_state_rejects_broken_snapshots() {
+        let mut config = Config::default();
+        config.bounds[CycleType::Work as usize] = "5-90".parse().unwrap();
+        let mut runtime = Runtime::new("/tmp/test.socket");
+        let mut timer = create_timer();
+        timer.elapsed_time = 60;
+
+        for (field, value) in [
+            ("current_index", serde_json::json!(3)),
+            ("elapsed_time", serde_json::json!(WORK_TIME + 1)),
+            ("times", serde_json::json!([WORK_TIME, 0, LONG_BREAK_TIME])),
+            (
+                "times",
+                serde_json::json!([1000 * MINUTE, SHORT_BREAK_TIME, 0]),
+            ),
+            ("iterations", serde_json::json!(MAX_ITERATIONS + 1)),
+            ("iterations", serde_json::json!(MAX_ITERATIONS)),
+            ("current_index", serde_json::json!(2)),
+        ] {
+            let mut imported = serde_json::to_value(create_timer()).unwrap();
+            imported[field] = value.clone();
+            let message = serde_json::json!({
+                "import-state": { "snapshot": { "version": "0.3.0", "timer": imported } }
+            });
+            match process_message(&mut timer, &message.to_string(), &mut config, &mut runtime) {
+                Response::Error { kind, .. } => assert_eq!(kind, ErrorKind::InvalidValue),
+                other => panic!("{field} = {value} was accepted: {other:?}"),
+            }
+            assert_eq!(timer.elapsed_time, 60);
+        }
+
+        // no long breaks is fine
+        let mut imported = serde_json::to_value(create_timer()).unwrap();
+        imported["times"] = serde_json::json!([WORK_TIME, SHORT_BREAK_TIME, 0]);
+        let message = serde_json::json!({
+            "import-state": { "snapshot": { "version": "0.3.0", "timer": imported } }
+        });
+        assert_eq!(
+            process_message(&mut timer, &message.to_string(), &mut config, &mut runtime),
+            Response::ok()
+        );
+        assert!(!timer.long_breaks());
+
+        // nor is the long break at the end of a set
+        let mut imported = serde_json::to_value(create_timer()).unwrap();
+        imported["current_index"] = serde_json::json!(2);
+        imported["iterations"] = serde_json::json!(MAX_ITERATIONS);
+        let message = serde_json::json!({
+            "import-state": { "snapshot": { "version": "0.3.0", "timer": imported } }
+        });
+        assert_eq!(
+            process_message(&mut timer, &message.to_string(), &mut config, &mut runtime),
+            Response::ok()
+        );
+        assert_eq!(timer.until_long(), Some(0));
+    }
+
     // TODO:
     // #[tokio::test]
     // async fn test_spawn_module() {
@@ -547,7 +2917,7 @@ mod tests {
         std::fs::File::create(socket_path).unwrap();
         assert!(std::path::Path::new(socket_path).exists());
 
-        delete_socket(socket_path);
+        delete_socket(Path::new(socket_path));
         assert!(!std::path::Path::new(socket_path).exists());
     }
 
@@ -563,39 +2933,49 @@ mod tests {
     #[test]
     fn test_extract_socket_number() {
         // Test with just filename - valid module names
-        assert_eq!(extract_socket_number("module0.socket"), 0);
-        assert_eq!(extract_socket_number("module1.socket"), 1);
-        assert_eq!(extract_socket_number("module123.socket"), 123);
+        assert_eq!(extract_socket_number(Path::new("module0.socket")), 0);
+        assert_eq!(extract_socket_number(Path::new("module1.socket")), 1);
+        assert_eq!(extract_socket_number(Path::new("module123.socket")), 123);
 
         // Test with full paths
         assert_eq!(
-            extract_socket_number("/run/user/1000/waybar-module-pomodoro/module0.socket"),
+            extract_socket_number(Path::new(
+                "/run/user/1000/waybar-module-pomodoro/module0.socket"
+            )),
             0
         );
-        assert_eq!(extract_socket_number("/var/tmp/module42.socket"), 42);
+        assert_eq!(
+            extract_socket_number(Path::new("/var/tmp/module42.socket")),
+            42
+        );
 
         // Test with paths containing numbers
         assert_eq!(
-            extract_socket_number("/run/user/1000/waybar-module-pomodoro/module5.socket"),
+            extract_socket_number(Path::new(
+                "/run/user/1000/waybar-module-pomodoro/module5.socket"
+            )),
             5
         );
         assert_eq!(
-            extract_socket_number("/home/user123/sockets/module7.socket"),
+            extract_socket_number(Path::new("/home/user123/sockets/module7.socket")),
             7
         );
 
         // Test edge cases - these should all return 0 because they don't match the pattern
-        assert_eq!(extract_socket_number("module.socket"), 0); // No number at end
-        assert_eq!(extract_socket_number("custom99name88.socket"), 0); // Not "module" prefix
-        assert_eq!(extract_socket_number("99module.socket"), 0); // Wrong pattern
-        assert_eq!(extract_socket_number("/path/to/nowhere"), 0); // No extension
-        assert_eq!(extract_socket_number(""), 0); // Empty string
+        assert_eq!(extract_socket_number(Path::new("module.socket")), 0); // No number at end
+        assert_eq!(extract_socket_number(Path::new("custom99name88.socket")), 0); // Not "module" prefix
+        assert_eq!(extract_socket_number(Path::new("99module.socket")), 0); // Wrong pattern
+        assert_eq!(extract_socket_number(Path::new("/path/to/nowhere")), 0); // No extension
+        assert_eq!(extract_socket_number(Path::new("")), 0); // Empty string
 
         // Test various filenames that don't match the pattern
-        assert_eq!(extract_socket_number("socket1.socket"), 0); // Wrong prefix
-        assert_eq!(extract_socket_number("my-socket-15.socket"), 0); // Wrong prefix
-        assert_eq!(extract_socket_number("test_socket_999.socket"), 0); // Wrong prefix
-        assert_eq!(extract_socket_number("modules123.socket"), 0); // Wrong prefix (plural)
-        assert_eq!(extract_socket_number("module_123.socket"), 0); // Has underscore
+        assert_eq!(extract_socket_number(Path::new("socket1.socket")), 0); // Wrong prefix
+        assert_eq!(extract_socket_number(Path::new("my-socket-15.socket")), 0); // Wrong prefix
+        assert_eq!(
+            extract_socket_number(Path::new("test_socket_999.socket")),
+            0
+        ); // Wrong prefix
+        assert_eq!(extract_socket_number(Path::new("modules123.socket")), 0); // Wrong prefix (plural)
+        assert_eq!(extract_socket_number(Path::new("module_123.socket")), 0); // Has underscore
     }
 }
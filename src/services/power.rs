@@ -0,0 +1,59 @@
+use std::time::{Duration, Instant};
+
+#[cfg(feature = "power")]
+use tracing::debug;
+
+/// How often UPower is asked whether the machine runs on battery
+const SAMPLE_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Whether the machine runs on battery, `None` if UPower can't be asked
+#[cfg(feature = "power")]
+pub fn on_battery() -> Option<bool> {
+    let ask = || -> zbus::Result<bool> {
+        let connection = zbus::blocking::Connection::system()?;
+        let upower = zbus::blocking::Proxy::new(
+            &connection,
+            "org.freedesktop.UPower",
+            "/org/freedesktop/UPower",
+            "org.freedesktop.UPower",
+        )?;
+        upower.get_property("OnBattery")
+    };
+    ask()
+        .inspect_err(|e| debug!("Cannot ask UPower about the battery: {}", e))
+        .ok()
+}
+
+#[cfg(not(feature = "power"))]
+pub fn on_battery() -> Option<bool> {
+    None
+}
+
+/// Follows whether the machine runs on battery
+#[derive(Debug, Default)]
+pub struct PowerWatch {
+    last_sample: Option<Instant>,
+    on_battery: bool,
+}
+
+impl PowerWatch {
+    /// Asks UPower once the sample interval has passed, returning whether the machine
+    /// runs on battery when that changed
+    pub fn sample(&mut self) -> Option<bool> {
+        let now = Instant::now();
+        if self
+            .last_sample
+            .is_some_and(|last| now.duration_since(last) < SAMPLE_INTERVAL)
+        {
+            return None;
+        }
+        self.last_sample = Some(now);
+
+        let on_battery = on_battery().unwrap_or(false);
+        if on_battery == self.on_battery {
+            return None;
+        }
+        self.on_battery = on_battery;
+        Some(on_battery)
+    }
+}
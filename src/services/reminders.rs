@@ -0,0 +1,168 @@
+use std::str::FromStr;
+
+use serde::Serialize;
+
+use crate::{
+    models::message::{DurationValue, Switch},
+    utils::helper::fill_template,
+};
+
+use super::report::format_duration;
+
+const DEFAULT_TEMPLATE: &str = "Time for {name}";
+
+/// A recurring reminder given as `name:every[:template]`, e.g. `water:45m:Drink some water`
+///
+/// The template may use `{name}` and `{every}`.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ReminderSpec {
+    pub name: String,
+    pub every: DurationValue,
+    pub template: String,
+}
+
+impl FromStr for ReminderSpec {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.splitn(3, ':');
+        let name = parts.next().unwrap_or_default().trim();
+        if name.is_empty() {
+            return Err(format!(
+                "Invalid reminder: {s} (expected name:every[:message], e.g. water:45m)"
+            ));
+        }
+        let every: DurationValue = parts
+            .next()
+            .ok_or_else(|| format!("Reminder {name} needs an interval, e.g. {name}:45m"))?
+            .parse()?;
+        if every.0 == 0 {
+            return Err(format!("Reminder {name} can't repeat every 0 seconds"));
+        }
+        Ok(ReminderSpec {
+            name: name.to_string(),
+            every,
+            template: parts.next().unwrap_or(DEFAULT_TEMPLATE).to_string(),
+        })
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct Reminder {
+    #[serde(flatten)]
+    spec: ReminderSpec,
+    enabled: bool,
+    #[serde(skip)]
+    elapsed_millis: u64,
+}
+
+impl Reminder {
+    fn every_millis(&self) -> u64 {
+        self.spec.every.as_millis()
+    }
+
+    fn body(&self) -> String {
+        fill_template(
+            &self.spec.template,
+            &[
+                ("name", &self.spec.name),
+                ("every", &format_duration(self.spec.every.0 as u64)),
+            ],
+        )
+    }
+}
+
+/// Reminders that repeat on their own schedules, independently of the pomodoro cycles
+#[derive(Debug, Default, Serialize)]
+#[serde(transparent)]
+pub struct Reminders {
+    reminders: Vec<Reminder>,
+}
+
+impl Reminders {
+    pub fn new(specs: &[ReminderSpec]) -> Self {
+        Self {
+            reminders: specs
+                .iter()
+                .map(|spec| Reminder {
+                    spec: spec.clone(),
+                    enabled: true,
+                    elapsed_millis: 0,
+                })
+                .collect(),
+        }
+    }
+
+    /// Moves every enabled reminder on by `millis`, returning the messages of those now due
+    pub fn advance(&mut self, millis: u32) -> Vec<String> {
+        let mut due = Vec::new();
+        for reminder in self.reminders.iter_mut().filter(|r| r.enabled) {
+            reminder.elapsed_millis += millis as u64;
+            if reminder.elapsed_millis >= reminder.every_millis() {
+                // a long stall only reminds once
+                reminder.elapsed_millis %= reminder.every_millis();
+                due.push(reminder.body());
+            }
+        }
+        due
+    }
+
+    /// Enables or disables the reminder called `name`, a re-enabled one starts a fresh interval
+    pub fn switch(&mut self, name: &str, switch: Switch) -> Result<(), String> {
+        let reminder = self
+            .reminders
+            .iter_mut()
+            .find(|reminder| reminder.spec.name == name)
+            .ok_or_else(|| format!("No reminder called '{name}'"))?;
+        let enabled = switch.apply(reminder.enabled);
+        if enabled && !reminder.enabled {
+            reminder.elapsed_millis = 0;
+        }
+        reminder.enabled = enabled;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_spec() {
+        assert_eq!(
+            "water:45m:Drink some water: now".parse(),
+            Ok(ReminderSpec {
+                name: "water".to_string(),
+                every: DurationValue(2700),
+                template: "Drink some water: now".to_string(),
+            })
+        );
+        assert_eq!(
+            "posture:30".parse::<ReminderSpec>().unwrap().template,
+            DEFAULT_TEMPLATE
+        );
+        assert!("posture".parse::<ReminderSpec>().is_err());
+        assert!(":30".parse::<ReminderSpec>().is_err());
+        assert!("posture:0".parse::<ReminderSpec>().is_err());
+    }
+
+    #[test]
+    fn test_reminders_due() {
+        let specs = [
+            "water:45m:Drink some water ({every})".parse().unwrap(),
+            "posture:30m".parse().unwrap(),
+        ];
+        let mut reminders = Reminders::new(&specs);
+
+        assert!(reminders.advance(29 * 60_000).is_empty());
+        assert_eq!(reminders.advance(60_000), ["Time for posture"]);
+        assert_eq!(reminders.advance(15 * 60_000), ["Drink some water (45m)"]);
+
+        reminders.switch("posture", Switch::Off).unwrap();
+        assert!(reminders.advance(30 * 60_000).is_empty());
+        // switched back on, it waits a whole interval again
+        reminders.switch("posture", Switch::Toggle).unwrap();
+        assert_eq!(reminders.advance(29 * 60_000), ["Drink some water (45m)"]);
+        assert!(reminders.switch("stretch", Switch::On).is_err());
+    }
+}
@@ -0,0 +1,544 @@
+use std::collections::BTreeMap;
+
+use chrono::{DateTime, Datelike, Days, Local, NaiveDate, TimeZone};
+use clap::ValueEnum;
+
+use super::{history::HistoryEvent, timer::CycleType};
+
+/// Output formats for `report --export`
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum ReportFormat {
+    Md,
+    Html,
+}
+
+/// Heatmap cell colours, from no pomodoros up to the busiest day's share
+const HEAT_COLOURS: [&str; 5] = ["#ebedf0", "#9be9a8", "#40c463", "#30a14e", "#216e39"];
+/// Heatmap cell size and distance between cells, in pixels
+const HEAT_CELL: i64 = 11;
+const HEAT_PITCH: i64 = 13;
+/// Room for the weekday and month labels
+const HEAT_LEFT: i64 = 28;
+const HEAT_TOP: i64 = 16;
+
+/// Pomodoros and focus seconds for one row of a report
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Tally {
+    pub pomodoros: u32,
+    pub focus_secs: u64,
+}
+
+impl Tally {
+    fn add(&mut self, focus_secs: u64) {
+        self.pomodoros += 1;
+        self.focus_secs += focus_secs;
+    }
+}
+
+/// A titled table of a report
+struct Table {
+    title: &'static str,
+    headers: Vec<&'static str>,
+    rows: Vec<Vec<String>>,
+}
+
+impl Table {
+    fn new(title: &'static str, headers: Vec<&'static str>, rows: Vec<Vec<String>>) -> Self {
+        Self {
+            title,
+            headers,
+            rows,
+        }
+    }
+}
+
+/// Work done over a span of days, summarised from the history log
+#[derive(Debug, Default)]
+pub struct Report {
+    pub from: Option<NaiveDate>,
+    pub to: Option<NaiveDate>,
+    pub total: Tally,
+    /// Work cycles given up on after a long pause
+    pub abandoned: u32,
+    pub days: BTreeMap<NaiveDate, Tally>,
+    pub tasks: BTreeMap<String, Tally>,
+    /// Latest estimate recorded for each task
+    pub estimates: BTreeMap<String, u16>,
+    pub tags: BTreeMap<String, Tally>,
+    /// Seconds each app was focused
+    pub apps: BTreeMap<String, u64>,
+    /// How many times and for how many seconds work was paused, by cause
+    pub pauses: BTreeMap<&'static str, (u32, u64)>,
+    /// Notes from `annotate` with the day and task of their work cycle
+    pub notes: Vec<(NaiveDate, Option<String>, String)>,
+}
+
+pub fn local_date(timestamp: u64) -> Option<NaiveDate> {
+    Local
+        .timestamp_opt(timestamp as i64, 0)
+        .single()
+        .map(|time: DateTime<Local>| time.date_naive())
+}
+
+/// `#words` in a task name
+fn tags(task: &str) -> impl Iterator<Item = &str> {
+    task.split_whitespace()
+        .filter_map(|word| word.strip_prefix('#'))
+        .filter(|tag| !tag.is_empty())
+}
+
+impl Report {
+    /// Summarises the work cycles that ended on or after `since`
+    pub fn new(events: &[HistoryEvent], since: NaiveDate) -> Self {
+        let mut report = Report::default();
+
+        for event in events {
+            match event {
+                HistoryEvent::Cycle {
+                    cycle: CycleType::Work,
+                    started,
+                    ended,
+                    task,
+                    apps,
+                    notes,
+                    ..
+                } => {
+                    let Some(day) = local_date(*ended).filter(|day| *day >= since) else {
+                        continue;
+                    };
+                    let focus = ended.saturating_sub(*started);
+
+                    report.from = Some(report.from.map_or(day, |from| from.min(day)));
+                    report.to = Some(report.to.map_or(day, |to| to.max(day)));
+                    report.total.add(focus);
+                    report.days.entry(day).or_default().add(focus);
+                    if let Some(task) = task {
+                        report.tasks.entry(task.clone()).or_default().add(focus);
+                        for tag in tags(task) {
+                            report.tags.entry(tag.to_string()).or_default().add(focus);
+                        }
+                    }
+                    for (app, secs) in apps {
+                        *report.apps.entry(app.clone()).or_default() += secs;
+                    }
+                    for note in notes {
+                        report.notes.push((day, task.clone(), note.clone()));
+                    }
+                }
+                HistoryEvent::Abandoned {
+                    cycle: CycleType::Work,
+                    ended,
+                    ..
+                } if local_date(*ended).is_some_and(|day| day >= since) => {
+                    report.abandoned += 1;
+                }
+                HistoryEvent::Pause {
+                    cause,
+                    paused,
+                    ended,
+                    ..
+                } if local_date(*ended).is_some_and(|day| day >= since) => {
+                    let (count, secs) = report.pauses.entry(cause.description()).or_default();
+                    *count += 1;
+                    *secs += paused;
+                }
+                HistoryEvent::Task {
+                    name,
+                    estimate: Some(estimate),
+                    ..
+                } => {
+                    report.estimates.insert(name.clone(), *estimate);
+                }
+                _ => {}
+            }
+        }
+        report
+    }
+
+    fn title(&self) -> String {
+        match (self.from, self.to) {
+            (Some(from), Some(to)) if from != to => format!("Pomodoro report {from} – {to}"),
+            (Some(day), _) => format!("Pomodoro report {day}"),
+            _ => "Pomodoro report".to_string(),
+        }
+    }
+
+    fn summary(&self) -> String {
+        let mut summary = format!(
+            "{} pomodoro{}, {} of focus",
+            self.total.pomodoros,
+            if self.total.pomodoros == 1 { "" } else { "s" },
+            format_duration(self.total.focus_secs)
+        );
+        if self.abandoned > 0 {
+            summary.push_str(&format!(", {} abandoned", self.abandoned));
+        }
+        summary
+    }
+
+    /// Every non-empty table, in display order
+    fn tables(&self) -> Vec<Table> {
+        let tally_rows = |rows: &mut dyn Iterator<Item = (String, &Tally)>| {
+            rows.map(|(name, tally)| {
+                vec![
+                    name,
+                    tally.pomodoros.to_string(),
+                    format_duration(tally.focus_secs),
+                ]
+            })
+            .collect::<Vec<_>>()
+        };
+
+        let mut tables = vec![Table::new(
+            "By day",
+            vec!["Day", "Pomodoros", "Focus"],
+            tally_rows(&mut self.days.iter().map(|(day, t)| (day.to_string(), t))),
+        )];
+
+        let tasks = self
+            .tasks
+            .iter()
+            .map(|(task, tally)| {
+                let estimate = self
+                    .estimates
+                    .get(task)
+                    .map(|estimate| estimate.to_string())
+                    .unwrap_or_default();
+                vec![
+                    task.clone(),
+                    tally.pomodoros.to_string(),
+                    estimate,
+                    format_duration(tally.focus_secs),
+                ]
+            })
+            .collect();
+        tables.push(Table::new(
+            "By task",
+            vec!["Task", "Pomodoros", "Estimate", "Focus"],
+            tasks,
+        ));
+        tables.push(Table::new(
+            "By tag",
+            vec!["Tag", "Pomodoros", "Focus"],
+            tally_rows(&mut self.tags.iter().map(|(tag, t)| (format!("#{tag}"), t))),
+        ));
+
+        let app_total: u64 = self.apps.values().sum();
+        let mut apps: Vec<_> = self.apps.iter().collect();
+        apps.sort_by(|a, b| b.1.cmp(a.1));
+        tables.push(Table::new(
+            "By app",
+            vec!["App", "Share", "Focus"],
+            apps.into_iter()
+                .map(|(app, secs)| {
+                    vec![
+                        app.clone(),
+                        format!("{}%", secs * 100 / app_total.max(1)),
+                        format_duration(*secs),
+                    ]
+                })
+                .collect(),
+        ));
+
+        tables.push(Table::new(
+            "Pauses",
+            vec!["Cause", "Pauses", "Paused"],
+            self.pauses
+                .iter()
+                .map(|(cause, (count, secs))| {
+                    vec![cause.to_string(), count.to_string(), format_duration(*secs)]
+                })
+                .collect(),
+        ));
+
+        tables.push(Table::new(
+            "Notes",
+            vec!["Day", "Task", "Note"],
+            self.notes
+                .iter()
+                .map(|(day, task, note)| {
+                    vec![
+                        day.to_string(),
+                        task.clone().unwrap_or_default(),
+                        note.clone(),
+                    ]
+                })
+                .collect(),
+        ));
+
+        tables.retain(|table| !table.rows.is_empty());
+        tables
+    }
+
+    pub fn render(&self, format: ReportFormat) -> String {
+        match format {
+            ReportFormat::Md => self.to_markdown(),
+            ReportFormat::Html => self.to_html(),
+        }
+    }
+
+    pub fn to_markdown(&self) -> String {
+        let mut out = format!("# {}\n\n{}\n", self.title(), self.summary());
+        for table in self.tables() {
+            out.push_str(&format!("\n## {}\n\n", table.title));
+            out.push_str(&format!("| {} |\n", table.headers.join(" | ")));
+            out.push_str(&format!("|{}\n", " --- |".repeat(table.headers.len())));
+            for row in table.rows {
+                let cells: Vec<String> = row.iter().map(|cell| cell.replace('|', "\\|")).collect();
+                out.push_str(&format!("| {} |\n", cells.join(" | ")));
+            }
+        }
+        out
+    }
+
+    pub fn to_html(&self) -> String {
+        let title = escape_html(&self.title());
+        let mut out = format!(
+            "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{title}</title>\n\
+             <style>body {{ font-family: sans-serif; }} table {{ border-collapse: collapse; }} \
+             th, td {{ border: 1px solid #ccc; padding: 4px 8px; text-align: left; }}</style>\n\
+             </head>\n<body>\n<h1>{title}</h1>\n<p>{}</p>\n",
+            escape_html(&self.summary())
+        );
+        for table in self.tables() {
+            out.push_str(&format!("<h2>{}</h2>\n<table>\n<tr>", table.title));
+            for header in table.headers {
+                out.push_str(&format!("<th>{header}</th>"));
+            }
+            out.push_str("</tr>\n");
+            for row in table.rows {
+                out.push_str("<tr>");
+                for cell in row {
+                    out.push_str(&format!("<td>{}</td>", escape_html(&cell)));
+                }
+                out.push_str("</tr>\n");
+            }
+            out.push_str("</table>\n");
+        }
+        out.push_str("</body>\n</html>\n");
+        out
+    }
+
+    /// A calendar of pomodoros per day from `since` to `until` as an SVG, a column per
+    /// week starting on Monday, shaded like GitHub's contributions graph
+    pub fn heatmap_svg(&self, since: NaiveDate, until: NaiveDate) -> String {
+        let monday = since - Days::new(since.weekday().num_days_from_monday() as u64);
+        let weeks = (until - monday).num_days().max(0) / 7 + 1;
+        let busiest = self
+            .days
+            .range(since..=until)
+            .map(|(_, tally)| tally.pomodoros)
+            .max()
+            .unwrap_or(0);
+
+        let mut out = format!(
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{}\" height=\"{}\" \
+             font-family=\"sans-serif\" font-size=\"9\" fill=\"#767676\">\n",
+            HEAT_LEFT + weeks * HEAT_PITCH,
+            HEAT_TOP + 7 * HEAT_PITCH
+        );
+        for (row, weekday) in [(0, "Mon"), (2, "Wed"), (4, "Fri")] {
+            out.push_str(&format!(
+                "<text x=\"0\" y=\"{}\">{weekday}</text>\n",
+                HEAT_TOP + row * HEAT_PITCH + 9
+            ));
+        }
+
+        let mut labelled_month = None;
+        for day in since.iter_days().take_while(|day| *day <= until) {
+            let offset = (day - monday).num_days();
+            let x = HEAT_LEFT + offset / 7 * HEAT_PITCH;
+            let y = HEAT_TOP + offset % 7 * HEAT_PITCH;
+            // a month is labelled above the first column that starts in it
+            if labelled_month != Some(day.month()) && (offset % 7 == 0 || labelled_month.is_none())
+            {
+                labelled_month = Some(day.month());
+                out.push_str(&format!(
+                    "<text x=\"{x}\" y=\"{}\">{}</text>\n",
+                    HEAT_TOP - 5,
+                    day.format("%b")
+                ));
+            }
+            let pomodoros = self.days.get(&day).map_or(0, |tally| tally.pomodoros);
+            out.push_str(&format!(
+                "<rect x=\"{x}\" y=\"{y}\" width=\"{HEAT_CELL}\" height=\"{HEAT_CELL}\" rx=\"2\" \
+                 fill=\"{}\"><title>{day}: {pomodoros} pomodoro{}</title></rect>\n",
+                HEAT_COLOURS[heat_level(pomodoros, busiest)],
+                if pomodoros == 1 { "" } else { "s" }
+            ));
+        }
+        out.push_str("</svg>\n");
+        out
+    }
+}
+
+/// Which of the heatmap colours a day gets, by its share of the busiest day
+fn heat_level(pomodoros: u32, busiest: u32) -> usize {
+    if pomodoros == 0 || busiest == 0 {
+        return 0;
+    }
+    let last = HEAT_COLOURS.len() as u32 - 1;
+    (pomodoros * last).div_ceil(busiest).clamp(1, last) as usize
+}
+
+/// `1h 05m`, or `25m` under an hour
+pub fn format_duration(secs: u64) -> String {
+    let minutes = secs / 60;
+    match minutes / 60 {
+        0 => format!("{minutes}m"),
+        hours => format!("{hours}h {:02}m", minutes % 60),
+    }
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::timer::PauseCause;
+
+    fn work(ended: u64, task: Option<&str>, apps: &[(&str, u64)]) -> HistoryEvent {
+        HistoryEvent::Cycle {
+            cycle: CycleType::Work,
+            started: ended - 1500,
+            ended,
+            task: task.map(str::to_string),
+            apps: apps.iter().map(|(app, s)| (app.to_string(), *s)).collect(),
+            activity: None,
+            notes: Vec::new(),
+        }
+    }
+
+    fn events() -> Vec<HistoryEvent> {
+        let day = 1_700_000_000;
+        vec![
+            work(
+                day,
+                Some("RFC #writing"),
+                &[("kitty", 1200), ("firefox", 300)],
+            ),
+            HistoryEvent::Cycle {
+                cycle: CycleType::ShortBreak,
+                started: day,
+                ended: day + 300,
+                task: None,
+                apps: BTreeMap::new(),
+                activity: Some("stretch".to_string()),
+                notes: Vec::new(),
+            },
+            HistoryEvent::Cycle {
+                cycle: CycleType::Work,
+                started: day + 500,
+                ended: day + 2000,
+                task: Some("RFC #writing".to_string()),
+                apps: BTreeMap::new(),
+                activity: None,
+                notes: vec!["debugging | the parser".to_string()],
+            },
+            work(day + 4000, None, &[]),
+            HistoryEvent::Abandoned {
+                cycle: CycleType::Work,
+                worked: 600,
+                paused: 3600,
+                ended: day + 8000,
+                task: None,
+            },
+            HistoryEvent::Task {
+                name: "RFC #writing".to_string(),
+                estimate: Some(4),
+                completed: 2,
+                ended: day + 4100,
+            },
+            HistoryEvent::Pause {
+                cause: PauseCause::Locked,
+                paused: 300,
+                ended: day + 1800,
+                task: None,
+            },
+            HistoryEvent::Pause {
+                cause: PauseCause::Locked,
+                paused: 600,
+                ended: day + 3800,
+                task: None,
+            },
+        ]
+    }
+
+    #[test]
+    fn test_report_totals() {
+        let report = Report::new(&events(), NaiveDate::MIN);
+
+        assert_eq!(report.total.pomodoros, 3);
+        assert_eq!(report.total.focus_secs, 4500);
+        assert_eq!(report.tasks["RFC #writing"].pomodoros, 2);
+        assert_eq!(report.tags["writing"].pomodoros, 2);
+        assert_eq!(report.estimates["RFC #writing"], 4);
+        assert_eq!(report.apps["kitty"], 1200);
+
+        // nothing happened after the cut-off
+        let report = Report::new(&events(), NaiveDate::MAX);
+        assert_eq!(report.total, Tally::default());
+    }
+
+    #[test]
+    fn test_report_markdown() {
+        let markdown = Report::new(&events(), NaiveDate::MIN).to_markdown();
+
+        assert!(markdown.contains("3 pomodoros, 1h 15m of focus, 1 abandoned"));
+        assert!(markdown.contains("| Task | Pomodoros | Estimate | Focus |"));
+        assert!(markdown.contains("| RFC #writing | 2 | 4 | 50m |"));
+        assert!(markdown.contains("| #writing | 2 | 50m |"));
+        assert!(markdown.contains("| kitty | 80% | 20m |"));
+        assert!(markdown.contains("| RFC #writing | debugging \\| the parser |"));
+        assert!(markdown.contains("| screen locked | 2 | 15m |"));
+    }
+
+    #[test]
+    fn test_report_html_escapes() {
+        let events = vec![work(1_700_000_000, Some("<b>"), &[])];
+        let html = Report::new(&events, NaiveDate::MIN).to_html();
+
+        assert!(html.contains("<td>&lt;b&gt;</td>"));
+        assert!(!html.contains("<td><b></td>"));
+    }
+
+    #[test]
+    fn test_heatmap_svg() {
+        let day = local_date(1_700_000_000).unwrap();
+        let events = vec![
+            work(1_700_000_000, None, &[]),
+            work(1_700_002_000, None, &[]),
+            work(1_700_000_000 + 86_400, None, &[]),
+        ];
+        let svg = Report::new(&events, NaiveDate::MIN).heatmap_svg(day - Days::new(27), day);
+
+        assert!(svg.starts_with("<svg "));
+        assert!(svg.ends_with("</svg>\n"));
+        assert_eq!(svg.matches("<rect ").count(), 28);
+        assert!(svg.contains(&format!("<title>{day}: 2 pomodoros</title>")));
+        // the day after is outside the span
+        assert!(!svg.contains(&format!("<title>{}", day + Days::new(1))));
+        assert!(svg.contains(&format!("fill=\"{}\"><title>{day}:", HEAT_COLOURS[4])));
+    }
+
+    #[test]
+    fn test_heat_level() {
+        assert_eq!(heat_level(0, 0), 0);
+        assert_eq!(heat_level(0, 8), 0);
+        assert_eq!(heat_level(1, 8), 1);
+        assert_eq!(heat_level(5, 8), 3);
+        assert_eq!(heat_level(8, 8), 4);
+    }
+
+    #[test]
+    fn test_format_duration() {
+        assert_eq!(format_duration(59), "0m");
+        assert_eq!(format_duration(1500), "25m");
+        assert_eq!(format_duration(3900), "1h 05m");
+    }
+}
@@ -0,0 +1,144 @@
+use std::{env, fs, path::PathBuf};
+
+#[cfg(feature = "xdg")]
+use xdg::BaseDirectories;
+
+use super::module::get_existing_sockets;
+
+/// The desktop session this process runs in, from `$WAYLAND_DISPLAY` or else
+/// `$XDG_SESSION_ID`
+///
+/// Each session's modules keep their sockets in their own directory, so two sessions of
+/// the same user, e.g. a second compositor or a TTY login, number their instances
+/// independently and ctl only reaches the timers of its own bar.
+pub fn current() -> Option<String> {
+    from_env(
+        env::var("WAYLAND_DISPLAY").ok(),
+        env::var("XDG_SESSION_ID").ok(),
+    )
+}
+
+fn from_env(wayland_display: Option<String>, session_id: Option<String>) -> Option<String> {
+    let name = match (wayland_display, session_id) {
+        // may be an absolute path to the compositor's socket
+        (Some(display), _) if !display.is_empty() => display.trim_matches('/').replace('/', "_"),
+        (_, Some(id)) if !id.is_empty() => format!("session-{id}"),
+        _ => return None,
+    };
+    Some(name)
+}
+
+/// Where the sockets of `session`'s modules live, relative to the runtime directory
+pub fn socket_prefix(binary_name: &str, session: Option<&str>) -> PathBuf {
+    let prefix = PathBuf::from(binary_name);
+    match session {
+        Some(session) => prefix.join(session),
+        None => prefix,
+    }
+}
+
+/// `$XDG_RUNTIME_DIR`, as long as only the user can get into it
+#[cfg(feature = "xdg")]
+fn runtime_dir() -> Option<PathBuf> {
+    BaseDirectories::new().get_runtime_directory().ok().cloned()
+}
+
+/// `$XDG_RUNTIME_DIR`
+#[cfg(not(feature = "xdg"))]
+fn runtime_dir() -> Option<PathBuf> {
+    dirs::runtime_dir()
+}
+
+/// The directory holding the sockets of `session`'s modules, `None` without a runtime
+/// directory
+pub fn socket_dir(binary_name: &str, session: Option<&str>) -> Option<PathBuf> {
+    runtime_dir().map(|dir| dir.join(socket_prefix(binary_name, session)))
+}
+
+/// Every session with a running module, `None` for modules outside of any session
+pub fn with_sockets(binary_name: &str) -> Vec<Option<String>> {
+    let mut sessions = Vec::new();
+    if !get_existing_sockets(binary_name, None).is_empty() {
+        sessions.push(None);
+    }
+    let Some(dir) = socket_dir(binary_name, None) else {
+        return sessions;
+    };
+    let Ok(entries) = fs::read_dir(dir) else {
+        return sessions;
+    };
+    for entry in entries.filter_map(Result::ok) {
+        if !entry.path().is_dir() {
+            continue;
+        }
+        let Some(name) = entry.file_name().to_str().map(str::to_string) else {
+            continue;
+        };
+        if !get_existing_sockets(binary_name, Some(&name)).is_empty() {
+            sessions.push(Some(name));
+        }
+    }
+    sessions.sort();
+    sessions
+}
+
+/// The session ctl talks to: `preferred` if it runs any modules, otherwise the only one that
+/// does, e.g. when ctl runs from an ssh login
+pub fn resolve(
+    preferred: Option<String>,
+    running: Vec<Option<String>>,
+) -> Result<Option<String>, String> {
+    if running.contains(&preferred) {
+        return Ok(preferred);
+    }
+    match &running[..] {
+        [] => Ok(preferred),
+        [only] => Ok(only.clone()),
+        _ => Err(format!(
+            "Modules run in several sessions ({}), pick one with --session",
+            running
+                .iter()
+                .map(|session| session.as_deref().unwrap_or("none"))
+                .collect::<Vec<_>>()
+                .join(", ")
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_env() {
+        let s = |value: &str| Some(value.to_string());
+        assert_eq!(from_env(s("wayland-1"), s("3")), s("wayland-1"));
+        assert_eq!(
+            from_env(s("/run/user/1000/wayland-0"), None),
+            s("run_user_1000_wayland-0")
+        );
+        assert_eq!(from_env(s(""), s("3")), s("session-3"));
+        assert_eq!(from_env(None, None), None);
+        assert_eq!(
+            socket_prefix("waybar-module-pomodoro", Some("wayland-1")),
+            PathBuf::from("waybar-module-pomodoro/wayland-1")
+        );
+    }
+
+    #[test]
+    fn test_resolve() {
+        let s = |value: &str| Some(value.to_string());
+        assert_eq!(
+            resolve(s("wayland-1"), vec![s("wayland-0"), s("wayland-1")]),
+            Ok(s("wayland-1"))
+        );
+        // ssh logins have no display, but there's only one session to pick
+        assert_eq!(
+            resolve(s("session-7"), vec![s("wayland-0")]),
+            Ok(s("wayland-0"))
+        );
+        assert_eq!(resolve(None, vec![None]), Ok(None));
+        assert_eq!(resolve(s("wayland-1"), vec![]), Ok(s("wayland-1")));
+        assert!(resolve(None, vec![s("wayland-0"), s("wayland-1")]).is_err());
+    }
+}
@@ -0,0 +1,150 @@
+use std::{
+    io::Write,
+    os::unix::net::UnixStream,
+    path::Path,
+    sync::{Arc, Mutex},
+};
+
+use clap::ValueEnum;
+use serde::Serialize;
+use tracing::debug;
+
+use crate::{
+    models::config::Config,
+    utils::{consts::SUBSCRIBER_WRITE_TIMEOUT, helper::strip_markup},
+};
+
+use super::{state_env::StateEnvSink, timer::Timer};
+
+/// Somewhere the timer is shown, given each tick's rendering of it
+pub trait OutputSink {
+    /// Shows the timer, `line` being the JSON Waybar reads
+    fn emit(&mut self, line: &str, state: &Timer) -> Result<(), String>;
+
+    /// Cleans up as the module shuts down
+    fn finish(&mut self) {}
+}
+
+/// What the module prints on standard output
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum OutputFormat {
+    /// A JSON object per line for Waybar's custom module
+    #[default]
+    Waybar,
+    /// Plain text per line for polybar's custom/script with `tail = true`
+    Polybar,
+}
+
+/// Waybar's custom module reads a JSON line per update from standard output
+pub struct WaybarSink;
+
+impl OutputSink for WaybarSink {
+    fn emit(&mut self, line: &str, _state: &Timer) -> Result<(), String> {
+        println!("{line}");
+        Ok(())
+    }
+}
+
+/// Polybar shows each line a `tail = true` script prints as it is, so the markup is stripped first
+pub struct PolybarSink;
+
+impl OutputSink for PolybarSink {
+    fn emit(&mut self, line: &str, _state: &Timer) -> Result<(), String> {
+        println!("{}", polybar_text(line)?);
+        Ok(())
+    }
+}
+
+/// The text of a Waybar line without its Pango markup
+fn polybar_text(line: &str) -> Result<String, String> {
+    let value: serde_json::Value =
+        serde_json::from_str(line).map_err(|e| format!("Cannot read the rendered line: {e}"))?;
+    Ok(strip_markup(value["text"].as_str().unwrap_or_default()))
+}
+
+/// Clients that sent `subscribe`, each sent every line the module renders until it hangs up
+#[derive(Debug, Clone, Default)]
+pub struct Subscribers(Arc<Mutex<Vec<UnixStream>>>);
+
+impl Subscribers {
+    pub fn add(&self, stream: UnixStream) {
+        // a client that stops reading must not hold up the timer
+        if let Err(e) = stream.set_write_timeout(Some(SUBSCRIBER_WRITE_TIMEOUT)) {
+            debug!("Dropping subscriber: {}", e);
+            return;
+        }
+        self.lock().push(stream);
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Vec<UnixStream>> {
+        self.0.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl OutputSink for Subscribers {
+    fn emit(&mut self, line: &str, _state: &Timer) -> Result<(), String> {
+        self.lock()
+            .retain_mut(|stream| writeln!(stream, "{line}").is_ok());
+        Ok(())
+    }
+
+    fn finish(&mut self) {
+        // hanging up tells them the module is gone
+        self.lock().clear();
+    }
+}
+
+/// Every output the config asks for, standard output first
+pub fn sinks(
+    config: &Config,
+    socket_path: &Path,
+    socket_nr: i32,
+    subscribers: Subscribers,
+) -> Vec<Box<dyn OutputSink>> {
+    let mut sinks: Vec<Box<dyn OutputSink>> = match config.output {
+        OutputFormat::Waybar => vec![Box::new(WaybarSink)],
+        OutputFormat::Polybar => vec![Box::new(PolybarSink)],
+    };
+    sinks.push(Box::new(subscribers));
+    if config.state_env {
+        sinks.push(Box::new(StateEnvSink::new(socket_path, socket_nr)));
+    }
+    sinks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader};
+
+    #[test]
+    fn test_polybar_text() {
+        let line = r#"{"text": "<span color='red'>󰔟</span> 24:59 RFC &amp; review", "class": []}"#;
+        assert_eq!(polybar_text(line).unwrap(), "󰔟 24:59 RFC & review");
+        assert!(polybar_text("not json").is_err());
+    }
+
+    #[test]
+    fn test_subscribers() {
+        let state = Timer::new(25 * 60, 5 * 60, 15 * 60, 0);
+        let mut subscribers = Subscribers::default();
+        let (client, server) = UnixStream::pair().unwrap();
+        let (gone, gone_server) = UnixStream::pair().unwrap();
+        subscribers.add(server);
+        subscribers.add(gone_server);
+        drop(gone);
+
+        subscribers.emit("first", &state).unwrap();
+        subscribers.emit("second", &state).unwrap();
+        // the one that hung up is dropped once writing to it fails
+        assert_eq!(subscribers.lock().len(), 1);
+
+        subscribers.finish();
+        let lines: Vec<String> = BufReader::new(client)
+            .lines()
+            .map(|line| line.unwrap())
+            .collect();
+        assert_eq!(lines, ["first", "second"]);
+    }
+}
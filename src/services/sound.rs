@@ -0,0 +1,142 @@
+#[cfg(feature = "sounds")]
+use std::{
+    fs,
+    io::BufReader,
+    sync::{
+        mpsc::{self, RecvTimeoutError, Sender},
+        LazyLock, Mutex,
+    },
+    thread,
+    time::Duration,
+};
+
+#[cfg(feature = "sounds")]
+use rodio::{Decoder, OutputStream, OutputStreamHandle, Sink};
+#[cfg(feature = "sounds")]
+use tracing::debug;
+use tracing::warn;
+
+#[cfg(feature = "sounds")]
+use super::duck::{self, Ducked};
+
+/// How often the player looks whether the sound ended while other apps are turned down
+#[cfg(feature = "sounds")]
+const DUCK_POLL: Duration = Duration::from_millis(100);
+
+#[cfg(feature = "sounds")]
+enum Command {
+    /// Play a file, turning other apps down to the given percentage of their volume
+    Play(String, Option<u8>),
+    Stop,
+    /// Close the audio output once nothing plays, to let the device sleep
+    Release,
+}
+
+/// The one thread playing sounds, so a new sound cuts off the one before instead of
+/// playing over it
+#[cfg(feature = "sounds")]
+static PLAYER: LazyLock<Mutex<Sender<Command>>> = LazyLock::new(|| {
+    let (tx, rx) = mpsc::channel();
+    thread::spawn(move || {
+        // the stream stops playing once dropped, and can't leave this thread
+        let mut output: Option<(OutputStream, OutputStreamHandle)> = None;
+        let mut playing: Option<Sink> = None;
+        // other apps turned down while the sound plays
+        let mut ducked: Option<Ducked> = None;
+        loop {
+            let command = if ducked.is_some() {
+                match rx.recv_timeout(DUCK_POLL) {
+                    Ok(command) => command,
+                    Err(RecvTimeoutError::Timeout) => {
+                        if playing.as_ref().is_none_or(Sink::empty) {
+                            // dropping it turns them back up
+                            ducked = None;
+                        }
+                        continue;
+                    }
+                    Err(RecvTimeoutError::Disconnected) => break,
+                }
+            } else {
+                match rx.recv() {
+                    Ok(command) => command,
+                    Err(_) => break,
+                }
+            };
+            if let Command::Release = command {
+                if playing.as_ref().is_none_or(Sink::empty) {
+                    playing = None;
+                    output = None;
+                }
+                continue;
+            }
+            if let Some(sink) = playing.take() {
+                sink.stop();
+            }
+            ducked = None;
+            let Command::Play(file_path, duck_to) = command else {
+                continue;
+            };
+            if output.is_none() {
+                match OutputStream::try_default() {
+                    Ok(stream) => output = Some(stream),
+                    Err(e) => {
+                        warn!("Failed to open audio output: {}", e);
+                        continue;
+                    }
+                }
+            }
+            let Some((_, handle)) = &output else {
+                continue;
+            };
+            let ducking = duck_to.map(duck::duck);
+            match start(handle, &file_path) {
+                Ok(sink) => {
+                    debug!("Playing sound: {}", file_path);
+                    playing = Some(sink);
+                    ducked = ducking;
+                }
+                Err(e) => warn!("Failed to play sound {}: {}", file_path, e),
+            }
+        }
+    });
+    Mutex::new(tx)
+});
+
+#[cfg(feature = "sounds")]
+fn start(handle: &OutputStreamHandle, file_path: &str) -> Result<Sink, Box<dyn std::error::Error>> {
+    let source = Decoder::new(BufReader::new(fs::File::open(file_path)?))?;
+    let sink = Sink::try_new(handle)?;
+    sink.append(source);
+    Ok(sink)
+}
+
+#[cfg(feature = "sounds")]
+fn send(command: Command) {
+    if let Ok(player) = PLAYER.lock() {
+        let _ = player.send(command);
+    }
+}
+
+/// Plays `file_path` in the background, stopping whatever sound is still playing, with
+/// other apps turned down to `duck` percent of their volume until it's over
+#[cfg(feature = "sounds")]
+pub fn play(file_path: &str, duck: Option<u8>) {
+    send(Command::Play(file_path.to_string(), duck));
+}
+
+#[cfg(not(feature = "sounds"))]
+pub fn play(file_path: &str, _duck: Option<u8>) {
+    warn!("Built without sounds, not playing {}", file_path);
+}
+
+/// Stops the sound that is playing, if any
+pub fn stop() {
+    #[cfg(feature = "sounds")]
+    send(Command::Stop);
+}
+
+/// Closes the audio output once the sound playing is over, it's opened again for the next
+pub fn release() {
+    #[cfg(feature = "sounds")]
+    send(Command::Release);
+}
@@ -0,0 +1,115 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use super::{module::format_time, sink::OutputSink, timer::Timer};
+
+/// Keeps the state file of --state-env up to date, removing it on shutdown
+pub struct StateEnvSink {
+    path: PathBuf,
+    /// What was last written, so the file is only touched when something changed
+    written: String,
+}
+
+impl StateEnvSink {
+    pub fn new(socket_path: &Path, instance: i32) -> Self {
+        Self {
+            path: path(socket_path, instance),
+            written: String::new(),
+        }
+    }
+}
+
+impl OutputSink for StateEnvSink {
+    fn emit(&mut self, _line: &str, state: &Timer) -> Result<(), String> {
+        let env = render(state);
+        if env != self.written {
+            write(&self.path, &env)
+                .map_err(|e| format!("Failed to write {}: {e}", self.path.display()))?;
+            self.written = env;
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+/// `state.env` next to the socket of instance 0, `state<N>.env` for the others
+pub fn path(socket_path: &Path, instance: i32) -> PathBuf {
+    let name = match instance {
+        0 => "state.env".to_string(),
+        n => format!("state{n}.env"),
+    };
+    socket_path.with_file_name(name)
+}
+
+/// The timer as `KEY=VALUE` lines a shell can source
+pub fn render(state: &Timer) -> String {
+    let cycle = serde_json::to_value(state.current_cycle()).unwrap_or_default();
+    format!(
+        "CYCLE={}\nREMAINING={}\nTIME={}\nRUNNING={}\nCOMPLETED={}\n",
+        cycle.as_str().unwrap_or_default(),
+        state.remaining(),
+        format_time(state.elapsed_time, state.get_current_time()),
+        state.running,
+        state.session_completed,
+    )
+}
+
+pub fn write(path: &Path, contents: &str) -> std::io::Result<()> {
+    // readers polling the file must never see it half written
+    let tmp = path.with_extension("env.tmp");
+    fs::write(&tmp, contents)?;
+    fs::rename(tmp, path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_render_and_write() {
+        let mut state = Timer::new(25 * 60, 5 * 60, 15 * 60, 0);
+        state.elapsed_time = 90;
+        state.running = true;
+        state.session_completed = 3;
+        let env = render(&state);
+        assert_eq!(
+            env,
+            "CYCLE=work\nREMAINING=1410\nTIME=23:30\nRUNNING=true\nCOMPLETED=3\n"
+        );
+
+        let dir = tempdir().unwrap();
+        let socket = dir.path().join("module1.socket");
+        assert_eq!(path(&socket, 0), dir.path().join("state.env"));
+        let path = path(&socket, 1);
+        write(&path, &env).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), env);
+    }
+
+    #[test]
+    fn test_sink_writes_changes() {
+        let dir = tempdir().unwrap();
+        let socket = dir.path().join("module.socket");
+        let path = path(&socket, 0);
+        let mut sink = StateEnvSink::new(&socket, 0);
+        let mut state = Timer::new(25 * 60, 5 * 60, 15 * 60, 0);
+
+        sink.emit("", &state).unwrap();
+        assert!(fs::read_to_string(&path).unwrap().contains("RUNNING=false"));
+        // left alone while nothing changes
+        fs::remove_file(&path).unwrap();
+        sink.emit("", &state).unwrap();
+        assert!(!path.exists());
+
+        state.running = true;
+        sink.emit("", &state).unwrap();
+        assert!(fs::read_to_string(&path).unwrap().contains("RUNNING=true"));
+        sink.finish();
+        assert!(!path.exists());
+    }
+}
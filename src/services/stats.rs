@@ -0,0 +1,270 @@
+use std::{
+    collections::BTreeMap,
+    error::Error,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use chrono::{DateTime, Days, Local, NaiveDate, TimeDelta};
+use serde::{Deserialize, Serialize};
+
+/// Running totals kept across sessions, along with the achievements they unlocked
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq)]
+pub struct Stats {
+    pub total_pomodoros: u32,
+    /// Pomodoros completed per local day
+    #[serde(default)]
+    pub days: BTreeMap<NaiveDate, u32>,
+    /// Unlocked achievement ids and when, in unix seconds
+    #[serde(default)]
+    pub achievements: BTreeMap<String, u64>,
+}
+
+/// A milestone worth celebrating
+pub struct Achievement {
+    pub id: &'static str,
+    pub title: &'static str,
+    pub description: &'static str,
+    unlocked: fn(&Stats, NaiveDate) -> bool,
+}
+
+pub const ACHIEVEMENTS: &[Achievement] = &[
+    Achievement {
+        id: "first-pomodoro",
+        title: "First pomodoro!",
+        description: "Complete your first pomodoro",
+        unlocked: |stats, _| stats.total_pomodoros >= 1,
+    },
+    Achievement {
+        id: "pomodoros-10",
+        title: "10th pomodoro!",
+        description: "Complete 10 pomodoros",
+        unlocked: |stats, _| stats.total_pomodoros >= 10,
+    },
+    Achievement {
+        id: "pomodoros-100",
+        title: "100th pomodoro!",
+        description: "Complete 100 pomodoros",
+        unlocked: |stats, _| stats.total_pomodoros >= 100,
+    },
+    Achievement {
+        id: "pomodoros-1000",
+        title: "1000th pomodoro!",
+        description: "Complete 1000 pomodoros",
+        unlocked: |stats, _| stats.total_pomodoros >= 1000,
+    },
+    Achievement {
+        id: "day-4",
+        title: "First 4-pomodoro day",
+        description: "Complete 4 pomodoros in a day",
+        unlocked: |stats, today| stats.on(today) >= 4,
+    },
+    Achievement {
+        id: "day-8",
+        title: "First 8-pomodoro day",
+        description: "Complete 8 pomodoros in a day",
+        unlocked: |stats, today| stats.on(today) >= 8,
+    },
+    Achievement {
+        id: "day-12",
+        title: "First 12-pomodoro day",
+        description: "Complete 12 pomodoros in a day",
+        unlocked: |stats, today| stats.on(today) >= 12,
+    },
+    Achievement {
+        id: "streak-7",
+        title: "7-day streak",
+        description: "Complete a pomodoro 7 days in a row",
+        unlocked: |stats, today| stats.streak(today) >= 7,
+    },
+    Achievement {
+        id: "streak-30",
+        title: "30-day streak",
+        description: "Complete a pomodoro 30 days in a row",
+        unlocked: |stats, today| stats.streak(today) >= 30,
+    },
+];
+
+impl Stats {
+    /// Pomodoros completed on `day`
+    pub fn on(&self, day: NaiveDate) -> u32 {
+        self.days.get(&day).copied().unwrap_or(0)
+    }
+
+    /// Consecutive days with a pomodoro, ending on `today`
+    pub fn streak(&self, today: NaiveDate) -> u32 {
+        let mut streak = 0;
+        let mut day = Some(today);
+        while let Some(current) = day.filter(|day| self.on(*day) > 0) {
+            streak += 1;
+            day = current.checked_sub_days(Days::new(1));
+        }
+        streak
+    }
+
+    /// Counts a pomodoro completed on `today`, returning the achievements it unlocked
+    pub fn record_pomodoro(&mut self, today: NaiveDate, now: u64) -> Vec<&'static Achievement> {
+        self.count_pomodoro(today);
+        let combined = self.clone();
+        self.unlock(&combined, today, now)
+    }
+
+    pub fn count_pomodoro(&mut self, today: NaiveDate) {
+        self.total_pomodoros += 1;
+        *self.days.entry(today).or_default() += 1;
+    }
+
+    /// Unlocks the achievements `combined` has earned but not yet been awarded, recording
+    /// them here
+    pub fn unlock(
+        &mut self,
+        combined: &Stats,
+        today: NaiveDate,
+        now: u64,
+    ) -> Vec<&'static Achievement> {
+        let mut unlocked = Vec::new();
+        for achievement in ACHIEVEMENTS {
+            if !combined.achievements.contains_key(achievement.id)
+                && (achievement.unlocked)(combined, today)
+            {
+                self.achievements.insert(achievement.id.to_string(), now);
+                unlocked.push(achievement);
+            }
+        }
+        unlocked
+    }
+
+    /// Adds another machine's stats to these, keeping the earliest unlock of each achievement
+    pub fn merge(&mut self, other: &Stats) {
+        self.total_pomodoros += other.total_pomodoros;
+        for (day, count) in &other.days {
+            *self.days.entry(*day).or_default() += count;
+        }
+        for (id, unlocked) in &other.achievements {
+            self.achievements
+                .entry(id.clone())
+                .and_modify(|at| *at = (*at).min(*unlocked))
+                .or_insert(*unlocked);
+        }
+    }
+}
+
+/// The day `time` counts towards when days start at `day_start` o'clock, so late nights
+/// still count as the day before
+pub fn day_of(time: DateTime<Local>, day_start: u8) -> NaiveDate {
+    (time - TimeDelta::hours(day_start as i64)).date_naive()
+}
+
+/// Loads the stats, starting afresh if there are none yet
+pub fn load(path: &Path) -> Result<Stats, Box<dyn Error>> {
+    match fs::read_to_string(path) {
+        Ok(content) => Ok(serde_json::from_str(&content)?),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Stats::default()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Loads and merges the stats of every machine
+pub fn load_all(paths: &[PathBuf]) -> Result<Stats, Box<dyn Error>> {
+    let mut combined = Stats::default();
+    for path in paths {
+        combined.merge(&load(path)?);
+    }
+    Ok(combined)
+}
+
+pub fn save(path: &Path, stats: &Stats) -> Result<(), Box<dyn Error>> {
+    // write then rename so a crash can't leave half a file behind
+    let tmp = path.with_extension("json.tmp");
+    fs::write(&tmp, serde_json::to_string_pretty(stats)?)?;
+    fs::rename(tmp, path)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    fn day(d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2025, 3, d).unwrap()
+    }
+
+    #[test]
+    fn test_record_pomodoro_unlocks_once() {
+        let mut stats = Stats::default();
+
+        let unlocked = stats.record_pomodoro(day(1), 100);
+        assert_eq!(
+            unlocked.iter().map(|a| a.id).collect::<Vec<_>>(),
+            ["first-pomodoro"]
+        );
+        assert!(stats.record_pomodoro(day(1), 200).is_empty());
+        stats.record_pomodoro(day(1), 300);
+
+        let unlocked = stats.record_pomodoro(day(1), 400);
+        assert_eq!(unlocked.iter().map(|a| a.id).collect::<Vec<_>>(), ["day-4"]);
+        assert_eq!(stats.achievements["first-pomodoro"], 100);
+    }
+
+    #[test]
+    fn test_streak() {
+        let mut stats = Stats::default();
+        for d in [1, 2, 4, 5, 6] {
+            stats.record_pomodoro(day(d), 0);
+        }
+        assert_eq!(stats.streak(day(6)), 3);
+        assert_eq!(stats.streak(day(3)), 0);
+        assert_eq!(stats.streak(day(2)), 2);
+    }
+
+    #[test]
+    fn test_load_and_save() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("stats.json");
+        assert_eq!(load(&path).unwrap(), Stats::default());
+
+        let mut stats = Stats::default();
+        stats.record_pomodoro(day(1), 100);
+        save(&path, &stats).unwrap();
+        assert_eq!(load(&path).unwrap(), stats);
+    }
+
+    #[test]
+    fn test_day_of() {
+        let time = |h| {
+            NaiveDate::from_ymd_opt(2025, 3, 2)
+                .unwrap()
+                .and_hms_opt(h, 30, 0)
+                .unwrap()
+                .and_local_timezone(Local)
+                .unwrap()
+        };
+        assert_eq!(day_of(time(3), 0), day(2));
+        assert_eq!(day_of(time(3), 4), day(1));
+        assert_eq!(day_of(time(4), 4), day(2));
+    }
+
+    #[test]
+    fn test_merge_machines() {
+        let mut desktop = Stats::default();
+        let mut laptop = Stats::default();
+        desktop.record_pomodoro(day(1), 100);
+        desktop.record_pomodoro(day(1), 200);
+        laptop.record_pomodoro(day(1), 50);
+
+        let mut combined = desktop.clone();
+        combined.merge(&laptop);
+        assert_eq!(combined.total_pomodoros, 3);
+        assert_eq!(combined.on(day(1)), 3);
+        assert_eq!(combined.achievements["first-pomodoro"], 50);
+
+        // the fourth pomodoro of the day counts the laptop's too
+        desktop.count_pomodoro(day(1));
+        laptop.count_pomodoro(day(1));
+        let mut combined = desktop.clone();
+        combined.merge(&laptop);
+        let unlocked = laptop.unlock(&combined, day(1), 300);
+        assert_eq!(unlocked.iter().map(|a| a.id).collect::<Vec<_>>(), ["day-4"]);
+    }
+}
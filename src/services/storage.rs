@@ -0,0 +1,243 @@
+use std::{
+    error::Error,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use tracing::{info, warn};
+
+const MODULE: &str = env!("CARGO_PKG_NAME");
+
+/// Where history, stats and persisted state live
+///
+/// A synced directory may be shared between machines by Syncthing or similar, so every
+/// machine only ever writes files carrying its own name and readers merge all of them.
+/// That way two machines never edit the same file and the sync tool has no conflicts to
+/// resolve.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Storage {
+    /// The local state directory
+    Local,
+    Synced(PathBuf),
+}
+
+impl Storage {
+    pub fn new(sync_dir: Option<&Path>) -> Self {
+        match sync_dir {
+            Some(dir) => Storage::Synced(dir.to_owned()),
+            None => Storage::Local,
+        }
+    }
+
+    fn dir(&self) -> Result<PathBuf, Box<dyn Error>> {
+        let dir = match self {
+            Storage::Local => return local_dir(),
+            Storage::Synced(dir) => dir.clone(),
+        };
+        fs::create_dir_all(&dir)?;
+        Ok(dir)
+    }
+
+    /// The file this machine writes, `history.jsonl` locally or `history-<host>.jsonl` synced
+    fn own_file(&self, stem: &str, extension: &str) -> Result<PathBuf, Box<dyn Error>> {
+        let name = match self {
+            Storage::Local => format!("{stem}.{extension}"),
+            Storage::Synced(_) => format!("{stem}-{}.{extension}", machine_name()),
+        };
+        Ok(self.dir()?.join(name))
+    }
+
+    /// Every machine's copy of a file, sorted by name, leaving out Syncthing's conflict copies
+    /// whose contents the machine's own file has already
+    fn all_files(&self, stem: &str, extension: &str) -> Result<Vec<PathBuf>, Box<dyn Error>> {
+        if *self == Storage::Local {
+            return Ok(vec![self.own_file(stem, extension)?]);
+        }
+
+        let prefix = format!("{stem}-");
+        let suffix = format!(".{extension}");
+        let mut files: Vec<PathBuf> = fs::read_dir(self.dir()?)?
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| {
+                path.file_name()
+                    .and_then(|name| name.to_str())
+                    .is_some_and(|name| {
+                        name.starts_with(&prefix)
+                            && name.ends_with(&suffix)
+                            && !name.contains(".sync-conflict-")
+                    })
+            })
+            .collect();
+        files.sort();
+        Ok(files)
+    }
+
+    pub fn history_path(&self) -> Result<PathBuf, Box<dyn Error>> {
+        self.own_file("history", "jsonl")
+    }
+
+    pub fn history_paths(&self) -> Result<Vec<PathBuf>, Box<dyn Error>> {
+        self.all_files("history", "jsonl")
+    }
+
+    pub fn stats_path(&self) -> Result<PathBuf, Box<dyn Error>> {
+        self.own_file("stats", "json")
+    }
+
+    pub fn stats_paths(&self) -> Result<Vec<PathBuf>, Box<dyn Error>> {
+        self.all_files("stats", "json")
+    }
+
+    /// Where the timer state is persisted, `None` for the versioned file in the local dir
+    pub fn state_path(&self) -> Result<Option<PathBuf>, Box<dyn Error>> {
+        match self {
+            Storage::Local => Ok(None),
+            Storage::Synced(_) => self.own_file("state", "json").map(Some),
+        }
+    }
+
+    /// The most recently written timer state of any machine, to carry on where it left off
+    pub fn latest_state_path(&self) -> Result<Option<PathBuf>, Box<dyn Error>> {
+        if *self == Storage::Local {
+            return Ok(None);
+        }
+        Ok(self
+            .all_files("state", "json")?
+            .into_iter()
+            .filter_map(|path| {
+                let modified = fs::metadata(&path).and_then(|m| m.modified()).ok()?;
+                Some((modified, path))
+            })
+            .max()
+            .map(|(_, path)| path))
+    }
+}
+
+/// `$XDG_STATE_HOME/waybar-module-pomodoro`, where history, stats and the persisted timer
+/// state live unless they are synced
+///
+/// Older versions kept history and stats in the data directory and the timer state in the
+/// cache directory, where cache cleaners would wipe it, so their files are moved over the
+/// first time the directory is created.
+pub fn local_dir() -> Result<PathBuf, Box<dyn Error>> {
+    let dir = dirs::state_dir()
+        .or_else(dirs::data_dir)
+        .ok_or("unable to get state dir")?
+        .join(MODULE);
+    if !dir.exists() {
+        fs::create_dir_all(&dir)?;
+        for old in [dirs::data_dir(), dirs::cache_dir()].into_iter().flatten() {
+            let old = old.join(MODULE);
+            if old != dir {
+                migrate(&old, &dir);
+            }
+        }
+    }
+    Ok(dir)
+}
+
+/// Moves the files of `old` into `new`, leaving any `new` already has alone
+fn migrate(old: &Path, new: &Path) {
+    let Ok(entries) = fs::read_dir(old) else {
+        return;
+    };
+    for path in entries.filter_map(|entry| entry.ok().map(|entry| entry.path())) {
+        let Some(name) = path.file_name() else {
+            continue;
+        };
+        let target = new.join(name);
+        if !path.is_file() || target.exists() {
+            continue;
+        }
+        // rename can't cross filesystems, e.g. a tmpfs cache
+        let moved = fs::rename(&path, &target)
+            .or_else(|_| fs::copy(&path, &target).and_then(|_| fs::remove_file(&path)));
+        match moved {
+            Ok(()) => info!("Moved {:?} to {:?}", path, target),
+            Err(e) => warn!("Failed to move {:?} to {:?}: {}", path, target, e),
+        }
+    }
+}
+
+/// Name of this machine, used to keep its files apart in a synced directory
+pub fn machine_name() -> String {
+    fs::read_to_string("/proc/sys/kernel/hostname")
+        .ok()
+        .or_else(|| std::env::var("HOSTNAME").ok())
+        .map(|name| name.trim().to_string())
+        .filter(|name| !name.is_empty())
+        .unwrap_or_else(|| "localhost".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_synced_files() {
+        let dir = tempdir().unwrap();
+        let storage = Storage::new(Some(dir.path()));
+
+        let own = storage.history_path().unwrap();
+        assert_eq!(
+            own.file_name().unwrap().to_str().unwrap(),
+            format!("history-{}.jsonl", machine_name())
+        );
+
+        fs::write(&own, "").unwrap();
+        fs::write(dir.path().join("history-laptop.jsonl"), "").unwrap();
+        fs::write(dir.path().join("stats-laptop.json"), "").unwrap();
+        fs::write(
+            dir.path().join("history-laptop.sync-conflict.jsonl.tmp"),
+            "",
+        )
+        .unwrap();
+        fs::write(
+            dir.path()
+                .join("history-laptop.sync-conflict-20250302-101500-ABCDEFG.jsonl"),
+            "",
+        )
+        .unwrap();
+
+        let paths = storage.history_paths().unwrap();
+        assert_eq!(paths.len(), 2);
+        assert!(paths.contains(&own));
+        assert!(paths.contains(&dir.path().join("history-laptop.jsonl")));
+    }
+
+    #[test]
+    fn test_migrate() {
+        let old = tempdir().unwrap();
+        let new = tempdir().unwrap();
+        fs::write(old.path().join("history.jsonl"), "old").unwrap();
+        fs::write(old.path().join("stats.json"), "old").unwrap();
+        fs::write(new.path().join("stats.json"), "new").unwrap();
+
+        migrate(old.path(), new.path());
+        assert_eq!(
+            fs::read_to_string(new.path().join("history.jsonl")).unwrap(),
+            "old"
+        );
+        assert_eq!(
+            fs::read_to_string(new.path().join("stats.json")).unwrap(),
+            "new"
+        );
+        assert!(!old.path().join("history.jsonl").exists());
+
+        // nothing to migrate from
+        migrate(&old.path().join("missing"), new.path());
+    }
+
+    #[test]
+    fn test_latest_state_path() {
+        let dir = tempdir().unwrap();
+        let storage = Storage::new(Some(dir.path()));
+        assert_eq!(storage.latest_state_path().unwrap(), None);
+
+        let own = storage.state_path().unwrap().unwrap();
+        fs::write(&own, "{}").unwrap();
+        assert_eq!(storage.latest_state_path().unwrap(), Some(own));
+        assert_eq!(Storage::Local.state_path().unwrap(), None);
+    }
+}
@@ -1,11 +1,18 @@
+use std::{
+    collections::hash_map::RandomState,
+    hash::{BuildHasher, Hasher},
+    time::{Duration, Instant},
+};
+
+use chrono::{DateTime, Local, NaiveDate};
 use serde::{Deserialize, Serialize};
 
 use crate::{
-    models::config::Config,
-    utils::consts::{MAX_ITERATIONS, SLEEP_TIME},
+    models::config::{ActivityOrder, BreakRatio, CompletionCount, Config},
+    utils::consts::{MAX_ITERATIONS, MINUTE, SLEEP_TIME},
 };
 
-use super::module::send_notification;
+use super::micro_break::MicroBreak;
 
 use tracing::debug;
 
@@ -14,15 +21,151 @@ const CLASS_EMPTY: &str = "";
 const CLASS_PAUSE: &str = "pause";
 const CLASS_WORK: &str = "work";
 const CLASS_BREAK: &str = "break";
-
-#[derive(Debug)]
+const CLASS_URGENT: &str = "urgent";
+const CLASS_STALE: &str = "stale";
+const CLASS_GRACE: &str = "grace";
+const CLASS_MICRO_BREAK: &str = "micro-break";
+const CLASS_RUNNING: &str = "running";
+const CLASS_PAUSED: &str = "paused";
+const CLASS_WARNING: &str = "warning";
+const CLASS_DONE_FOR_TODAY: &str = "done-for-today";
+const CLASS_POWER_SAVE: &str = "power-save";
+
+/// Seconds before the end of a running cycle during which the warning class is added
+const WARNING_TIME: u16 = 60;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
 pub enum CycleType {
     Work,
     ShortBreak,
     LongBreak,
 }
 
-#[derive(Serialize, Deserialize, Debug, PartialEq)]
+impl CycleType {
+    pub fn name(&self) -> &'static str {
+        match self {
+            CycleType::Work => "work",
+            CycleType::ShortBreak => "short break",
+            CycleType::LongBreak => "long break",
+        }
+    }
+}
+
+/// What the user is working on, with an optional estimate in pomodoros
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Task {
+    pub name: String,
+    pub estimate: Option<u16>,
+    /// Work cycles finished while this task was set
+    pub completed: u16,
+}
+
+impl Task {
+    pub fn new(name: String, estimate: Option<u16>) -> Self {
+        Self {
+            name,
+            estimate,
+            completed: 0,
+        }
+    }
+
+    /// Progress as `2/4`, or just `2` without an estimate
+    pub fn progress(&self) -> String {
+        match self.estimate {
+            Some(estimate) => format!("{}/{}", self.completed, estimate),
+            None => self.completed.to_string(),
+        }
+    }
+}
+
+/// A cycle that ran to its end, waiting to be recorded in the history
+#[derive(Debug, Clone, PartialEq)]
+pub struct CompletedCycle {
+    pub cycle: CycleType,
+    /// Length of the cycle in seconds
+    pub duration: u16,
+    /// What the break was suggested to be spent on
+    pub activity: Option<String>,
+    /// Notes added with `annotate` while the cycle ran
+    pub notes: Vec<String>,
+}
+
+/// Pomodoros run back to back until a deadline, with `focus-until`
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct FocusSession {
+    pub until: DateTime<Local>,
+    /// Pomodoros that fit when the session began
+    pub planned: u16,
+    pub completed: u16,
+}
+
+/// A block of pomodoros planned with `sprint`, after which the timer stops
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct Sprint {
+    pub planned: u16,
+    pub completed: u16,
+}
+
+/// What the work cycles since the last long break added up to, for the summary shown
+/// once the set is done
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+pub struct SetTally {
+    pub pomodoros: u16,
+    pub focus_millis: u64,
+    pub interruptions: u16,
+}
+
+/// Why the timer was paused, shown while it is and recorded in the history
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum PauseCause {
+    Manual,
+    Idle,
+    Locked,
+    Call,
+    /// Another instance started a work cycle, with --exclusive-focus
+    OtherInstance,
+    Snooze,
+    /// The module came back from a crash or power loss
+    Crash,
+}
+
+impl PauseCause {
+    pub fn description(self) -> &'static str {
+        match self {
+            PauseCause::Manual => "paused by hand",
+            PauseCause::Idle => "idle",
+            PauseCause::Locked => "screen locked",
+            PauseCause::Call => "in a call",
+            PauseCause::OtherInstance => "another instance is working",
+            PauseCause::Snooze => "snoozed",
+            PauseCause::Crash => "restored after a crash",
+        }
+    }
+}
+
+/// What happened as `update_state` moved the timer on, for the module to notify about,
+/// so the timer itself stays free of side effects
+#[derive(Debug, Clone, PartialEq)]
+pub enum TimerEvent {
+    /// A cycle ran to its end and the next one began
+    CycleStarted {
+        from: CycleType,
+        cycle: CycleType,
+        activity: Option<String>,
+    },
+    /// The long break ended, closing a set of pomodoros
+    SetCompleted(SetTally),
+}
+
+impl Sprint {
+    pub fn is_complete(&self) -> bool {
+        self.completed >= self.planned
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct Timer {
     pub current_index: usize,
     pub elapsed_millis: u16,
@@ -34,6 +177,64 @@ pub struct Timer {
     pub socket_nr: i32,
     #[serde(skip)]
     pub current_override: Option<u16>,
+    /// A cycle ran out and the next one is waiting to be started by the user
+    #[serde(default)]
+    pub awaiting_start: bool,
+    #[serde(skip)]
+    pub paused_since: Option<Instant>,
+    /// Why the timer is paused, when something paused it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pause_cause: Option<PauseCause>,
+    /// Causes and seconds of the pauses part way through a work cycle that have ended,
+    /// not yet taken by `take_pauses`
+    #[serde(skip)]
+    pub pauses: Vec<(PauseCause, u64)>,
+    /// Milliseconds left before a pending auto-start kicks in
+    #[serde(skip)]
+    pub grace_millis: Option<u32>,
+    #[serde(default)]
+    pub task: Option<Task>,
+    /// Activity suggested for the current break
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub activity: Option<String>,
+    /// Breaks an activity has been suggested for, to take turns through them
+    #[serde(default)]
+    pub activities_given: usize,
+    /// Notes on the current work cycle
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub notes: Vec<String>,
+    /// Work time on `focus_day`, partial cycles included
+    #[serde(default)]
+    pub focus_millis: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub focus_day: Option<NaiveDate>,
+    /// Work cycles completed on `focus_day`, for --daily-cap
+    #[serde(default)]
+    pub pomodoros_today: u16,
+    /// Work cycles paused part way through on `focus_day`
+    #[serde(default)]
+    pub interruptions_today: u16,
+    /// Work cycles completed in a row today, for --adaptive-long-break
+    #[serde(default)]
+    pub streak: u16,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub focus: Option<FocusSession>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sprint: Option<Sprint>,
+    /// Day the timer's progress belongs to, with --day-start
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub day: Option<NaiveDate>,
+    /// Set while a persisting module runs on this state and cleared when it exits, so a
+    /// restored state that still has it was left behind by a crash or power loss
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub dirty: bool,
+    #[serde(default)]
+    pub set_tally: SetTally,
+    /// Finished cycles not yet taken by `take_completed`
+    #[serde(skip)]
+    pub completed: Vec<CompletedCycle>,
+    #[serde(skip)]
+    pub micro_break: MicroBreak,
 }
 
 impl Timer {
@@ -48,6 +249,27 @@ impl Timer {
             running: false,
             socket_nr: socker_nr,
             current_override: None,
+            awaiting_start: false,
+            paused_since: None,
+            pause_cause: None,
+            pauses: Vec::new(),
+            grace_millis: None,
+            task: None,
+            activity: None,
+            activities_given: 0,
+            notes: Vec::new(),
+            focus_millis: 0,
+            focus_day: None,
+            pomodoros_today: 0,
+            interruptions_today: 0,
+            streak: 0,
+            focus: None,
+            sprint: None,
+            day: None,
+            dirty: false,
+            set_tally: SetTally::default(),
+            completed: Vec::new(),
+            micro_break: MicroBreak::default(),
         }
     }
 
@@ -58,21 +280,200 @@ impl Timer {
         self.iterations = 0;
         self.running = false;
         self.current_override = None;
+        self.awaiting_start = false;
+        self.grace_millis = None;
+        self.notes.clear();
+        self.streak = 0;
+        self.focus = None;
+        self.sprint = None;
+        self.set_tally = SetTally::default();
+    }
+
+    /// Puts the timer back `elapsed` seconds into a `cycle` lasting `duration` seconds,
+    /// paused and `iterations` work cycles towards the long break
+    pub fn resume_at(&mut self, cycle: CycleType, elapsed: u16, duration: u16, iterations: u8) {
+        self.reset();
+        self.current_index = match cycle {
+            CycleType::LongBreak if !self.long_breaks() => CycleType::ShortBreak as usize,
+            cycle => cycle as usize,
+        };
+        self.iterations = iterations.min(MAX_ITERATIONS);
+        // pinned, so the cycle isn't planned afresh at a different length
+        let duration = duration.max(MINUTE);
+        self.current_override = Some(duration);
+        self.elapsed_time = elapsed.min(duration);
+    }
+
+    /// Starts afresh once `today` is a different day, returning whether it did
+    pub fn roll_day(&mut self, today: NaiveDate) -> bool {
+        let rolled = self.day.is_some_and(|day| day != today);
+        if rolled {
+            self.reset();
+            self.session_completed = 0;
+        }
+        self.day = Some(today);
+        rolled
+    }
+
+    /// Whether work cycles build up to a long break, `--longbreak 0` turns them off
+    pub fn long_breaks(&self) -> bool {
+        self.times[CycleType::LongBreak as usize] > 0
     }
 
     pub fn is_break(&self) -> bool {
         self.current_index != 0
     }
 
+    pub fn current_cycle(&self) -> CycleType {
+        match self.current_index {
+            0 => CycleType::Work,
+            1 => CycleType::ShortBreak,
+            2 => CycleType::LongBreak,
+            _ => panic!("Invalid cycle type"),
+        }
+    }
+
+    /// Sets how long `cycle` lasts, keeping the progress of the cycle that's running. If
+    /// that's the one changed and it is already past its new length, it ends.
     pub fn set_time(&mut self, cycle: CycleType, input: u16) {
+        self.set_seconds(cycle, input * 60);
+    }
+
+    /// Sizes the current cycle, unless its length was already changed: work from
+    /// --work-sequence by how far into the set it is, long breaks from
+    /// --adaptive-long-break, then drawn at random within --jitter
+    pub fn plan_cycle(&mut self, config: &Config) {
+        if self.current_override.is_some() {
+            return;
+        }
+        let base = self.times[self.current_index];
+        let mut length =
+            self.cycle_length(self.current_cycle(), self.iterations, self.streak, config);
+        if config.jitter > 0 && length > 0 {
+            let draw = random_u64() % (2 * config.jitter as u64 + 1);
+            let offset = draw as i32 - config.jitter as i32;
+            length = (length as i32 + offset).clamp(MINUTE as i32, u16::MAX as i32) as u16;
+            debug!("Cycle drawn at {} seconds", length);
+        } else if length == base {
+            return;
+        }
+        // a restored or imported cycle may already be further in than a fresh draw
+        self.current_override = Some(length.max(self.elapsed_time));
+    }
+
+    /// How many work cycles would run to their end within `seconds`, going on from the
+    /// current one
+    pub fn pomodoros_within(&self, seconds: u64, config: &Config) -> u16 {
+        let mut plan = self.clone();
+        plan.focus = None;
+        let mut left = seconds;
+        let mut pomodoros = 0;
+        loop {
+            let cycle_left = plan.get_current_time().saturating_sub(plan.elapsed_time) as u64;
+            // nothing left to fill the time with
+            if cycle_left > left || (cycle_left == 0 && plan.elapsed_time == 0) {
+                return pomodoros;
+            }
+            left -= cycle_left;
+            if plan.current_cycle() == CycleType::Work {
+                pomodoros += 1;
+            }
+            plan.next_state(config);
+        }
+    }
+
+    /// Starts a focus session running until `until`, returning how many pomodoros fit
+    pub fn focus_until(
+        &mut self,
+        until: DateTime<Local>,
+        now: DateTime<Local>,
+        config: &Config,
+    ) -> u16 {
+        let seconds = (until - now).num_seconds().max(0) as u64;
+        let planned = self.pomodoros_within(seconds, config);
+        self.focus = Some(FocusSession {
+            until,
+            planned,
+            completed: 0,
+        });
+        self.running = true;
+        self.awaiting_start = false;
+        self.grace_millis = None;
+        self.fit_focus(now);
+        planned
+    }
+
+    /// Cuts the current cycle short so it ends at the focus session's deadline
+    pub fn fit_focus(&mut self, now: DateTime<Local>) {
+        let Some(focus) = &self.focus else {
+            return;
+        };
+        let left = (focus.until - now).num_seconds().clamp(0, u16::MAX as i64) as u16;
+        if self.get_current_time().saturating_sub(self.elapsed_time) > left {
+            self.current_override = Some(self.elapsed_time.saturating_add(left));
+        }
+    }
+
+    /// Ends the focus session once its deadline has passed, returning it
+    pub fn end_focus(&mut self, now: DateTime<Local>) -> Option<FocusSession> {
+        if self.focus.as_ref()?.until > now {
+            return None;
+        }
+        let focus = self.focus.take();
         self.reset();
+        focus
+    }
 
-        match cycle {
-            CycleType::Work => self.times[0] = input * 60,
-            CycleType::ShortBreak => self.times[1] = input * 60,
-            CycleType::LongBreak => self.times[2] = input * 60,
+    /// Plans a sprint of `pomodoros` and gets the timer going
+    pub fn sprint(&mut self, pomodoros: u16) {
+        self.sprint = Some(Sprint {
+            planned: pomodoros,
+            completed: 0,
+        });
+        self.running = true;
+        self.awaiting_start = false;
+        self.grace_millis = None;
+    }
+
+    /// Ends the sprint once its last pomodoro is done, leaving the timer stopped
+    pub fn end_sprint(&mut self) -> Option<Sprint> {
+        if !self.sprint?.is_complete() {
+            return None;
+        }
+        self.running = false;
+        self.awaiting_start = false;
+        self.grace_millis = None;
+        self.sprint.take()
+    }
+
+    /// Sprint progress like `2/4`, empty outside of a sprint
+    pub fn sprint_progress(&self) -> String {
+        self.sprint
+            .map(|sprint| format!("{}/{}", sprint.completed, sprint.planned))
+            .unwrap_or_default()
+    }
+
+    /// Sizes the breaks from the work length with --break-ratio, leaving long breaks off if
+    /// they are
+    pub fn scale_breaks(&mut self, ratio: BreakRatio) {
+        let (short, long) = ratio.breaks(self.times[CycleType::Work as usize]);
+        self.set_seconds(CycleType::ShortBreak, short);
+        if self.long_breaks() {
+            self.set_seconds(CycleType::LongBreak, long);
         }
-        println!("{:?}", self.times);
+    }
+
+    fn set_seconds(&mut self, cycle: CycleType, seconds: u16) {
+        let index = cycle as usize;
+        self.times[index] = seconds;
+        if self.current_index == index
+            && self.current_override.is_none()
+            && self.elapsed_time > self.times[index]
+        {
+            self.elapsed_time = self.times[index];
+            self.elapsed_millis = 0;
+        }
+        debug!("Cycle times set to {:?}", self.times);
     }
 
     pub fn add_delta_time(&mut self, cycle: CycleType, delta: i16) {
@@ -82,20 +483,19 @@ impl Timer {
             CycleType::LongBreak => 2,
         };
 
-        let delta_seconds = delta * 60;
+        let delta_seconds = delta as i32 * 60;
         let current_time = self.times[index] as i32;
-        let new_time = (current_time + delta_seconds as i32).max(0) as u16;
+        let new_time = (current_time + delta_seconds).max(0) as u16;
 
         // If we're modifying the current active cycle and the time goes to zero
         if new_time == 0 && self.current_index == index {
             // Gracefully transition to next state by setting elapsed time to max
-            self.elapsed_time = self.times[index];
+            self.elapsed_time = self.get_current_time();
             self.elapsed_millis = 0;
+            debug!("Cycle times set to {:?}", self.times);
         } else {
-            self.times[index] = new_time;
+            self.set_seconds(cycle, new_time);
         }
-
-        println!("{:?}", self.times);
     }
 
     pub fn set_current_duration(&mut self, minutes: u16) {
@@ -109,16 +509,28 @@ impl Timer {
         debug!("Current cycle overridden to {} seconds", new_duration);
     }
 
+    /// Moves the current cycle's duration by `delta` seconds, staying within `min..=max`.
+    /// Shortening never ends the cycle outright, at least a minute is left to run.
+    pub fn nudge_current(&mut self, delta: i32, min: u16, max: u16) {
+        let current = self.get_current_time();
+        let floor = min
+            .max(self.elapsed_time.saturating_add(MINUTE))
+            .min(current);
+        let new_time = (current as i32 + delta).clamp(floor as i32, max.max(floor) as i32) as u16;
+
+        self.current_override = Some(new_time);
+        debug!("Current cycle nudged to {} seconds", new_time);
+    }
+
     pub fn add_current_delta_time(&mut self, delta: i16) {
-        let delta_seconds = delta * 60;
+        let delta_seconds = delta as i32 * 60;
         let current_time = self.get_current_time() as i32;
-        let new_time = (current_time + delta_seconds as i32).max(0) as u16;
+        let new_time = (current_time + delta_seconds).max(0) as u16;
 
         // If the time goes to zero, gracefully transition
         if new_time == 0 {
             self.elapsed_time = self.get_current_time();
             self.elapsed_millis = 0;
-            self.current_override = Some(0);
         } else {
             self.current_override = Some(new_time);
             // Adjust elapsed time if necessary
@@ -132,12 +544,19 @@ impl Timer {
             "Current cycle adjusted by {} to {} seconds",
             delta_seconds, new_time
         );
-        println!("{:?}", self.times);
     }
 
     pub fn get_class(&self) -> &'static str {
+        // the next cycle is about to start on its own
+        if self.grace_millis.is_some() {
+            CLASS_GRACE
+        }
+        // a cycle finished and nobody has started the next one yet
+        else if self.awaiting_start {
+            CLASS_URGENT
+        }
         // timer hasn't been started yet
-        if self.elapsed_millis == 0
+        else if self.elapsed_millis == 0
             && self.elapsed_time == 0
             && self.iterations == 0
             && self.session_completed == 0
@@ -160,54 +579,243 @@ impl Timer {
         }
     }
 
-    pub fn update_state(&mut self, config: &Config, send_notifications: bool) {
-        if (self.get_current_time() - self.elapsed_time) == 0 {
-            // Clear any override when transitioning to a new cycle
-            self.current_override = None;
+    /// Waybar `alt` value combining the cycle and whether it is running, for `format-icons`
+    pub fn get_alt(&self) -> &'static str {
+        match (self.current_cycle(), self.running) {
+            (CycleType::Work, true) => "work-running",
+            (CycleType::Work, false) => "work-paused",
+            (CycleType::ShortBreak, true) => "shortbreak-running",
+            (CycleType::ShortBreak, false) => "shortbreak-paused",
+            (CycleType::LongBreak, true) => "longbreak-running",
+            (CycleType::LongBreak, false) => "longbreak-paused",
+        }
+    }
 
-            // if we're on the third iteration and first work, then we want a long break
-            if self.current_index == 0 && self.iterations == MAX_ITERATIONS - 1 {
-                self.current_index = self.times.len() - 1;
-                self.iterations = MAX_ITERATIONS;
+    /// Stops the timer, noting why
+    pub fn pause(&mut self, cause: PauseCause) {
+        self.running = false;
+        self.pause_cause = Some(cause);
+    }
+
+    /// Keeps `paused_since` in step with the running state, call once per tick
+    pub fn track_pause(&mut self) {
+        if self.running || self.get_class() == CLASS_EMPTY {
+            if let Some(paused) = self.paused_mid_work_for().filter(|_| self.running) {
+                let cause = self.pause_cause.unwrap_or(PauseCause::Manual);
+                self.pauses.push((cause, paused.as_secs()));
             }
-            // if we've had our long break, reset everything and start over
-            else if self.current_index == self.times.len() - 1
-                && self.iterations == MAX_ITERATIONS
-            {
-                self.current_index = 0;
-                self.iterations = 0;
-                // since we've gone through a long break, we've also completed a single pomodoro!
-                self.session_completed += 1;
+            self.paused_since = None;
+            self.pause_cause = None;
+        } else if self.paused_since.is_none() {
+            self.paused_since = Some(Instant::now());
+            if self.paused_mid_work_for().is_some() {
+                self.interruptions_today += 1;
+                self.set_tally.interruptions += 1;
             }
-            // otherwise, run as normal
-            else {
-                self.current_index = (self.current_index + 1) % 2;
-                if self.current_index == 0 {
-                    self.iterations += 1;
+        }
+    }
+
+    pub fn paused_for(&self) -> Option<Duration> {
+        self.paused_since.map(|since| since.elapsed())
+    }
+
+    /// How long a work cycle has been paused part way through, `None` otherwise
+    pub fn paused_mid_work_for(&self) -> Option<Duration> {
+        let started = self.elapsed_time > 0 || self.elapsed_millis > 0;
+        let waiting = self.awaiting_start || self.grace_millis.is_some();
+        if self.is_break() || !started || waiting {
+            return None;
+        }
+        self.paused_for()
+    }
+
+    pub fn is_stale(&self, stale_after: Option<Duration>) -> bool {
+        match (stale_after, self.paused_for()) {
+            (Some(threshold), Some(paused)) => paused >= threshold,
+            _ => false,
+        }
+    }
+
+    /// The class shown on the bar, taking the blink and stale options into account
+    pub fn get_display_class(&self, config: &Config, blink_on: bool) -> &'static str {
+        if self.micro_break.is_active() {
+            CLASS_MICRO_BREAK
+        } else if self.is_stale(config.stale_after) {
+            CLASS_STALE
+        } else if config.blink {
+            self.get_blinking_class(blink_on)
+        } else {
+            self.get_class()
+        }
+    }
+
+    /// The display class followed by classes for the cycle, whether the timer runs and
+    /// whether the cycle is about to end, so each can be styled on its own
+    pub fn get_classes(&self, config: &Config, blink_on: bool) -> Vec<&'static str> {
+        let mut classes = vec![self.get_display_class(config, blink_on)];
+        classes.retain(|class| *class != CLASS_EMPTY);
+        classes.push(match self.current_cycle() {
+            CycleType::Work => "cycle-work",
+            CycleType::ShortBreak => "cycle-short-break",
+            CycleType::LongBreak => "cycle-long-break",
+        });
+        classes.push(if self.running {
+            CLASS_RUNNING
+        } else {
+            CLASS_PAUSED
+        });
+        classes.push(self.remaining_class());
+        if self.running && self.remaining() <= WARNING_TIME {
+            classes.push(CLASS_WARNING);
+        }
+        if self.done_for_today(config) {
+            classes.push(CLASS_DONE_FOR_TODAY);
+        }
+        if config.saving_power {
+            classes.push(CLASS_POWER_SAVE);
+        }
+        classes
+    }
+
+    /// `p100` down to `p10`, the smallest bucket the percentage of the cycle left fits in,
+    /// for styling a gradient as the cycle runs out
+    fn remaining_class(&self) -> &'static str {
+        let percent = (self.remaining() as u32 * 100)
+            .checked_div(self.get_current_time() as u32)
+            .unwrap_or(0);
+        match percent {
+            76.. => "p100",
+            51..=75 => "p75",
+            26..=50 => "p50",
+            11..=25 => "p25",
+            _ => "p10",
+        }
+    }
+
+    /// Like `get_class`, but alternates `urgent` with `pause` when `blink_on` is false,
+    /// so the bar can flash while a finished cycle waits for the user
+    pub fn get_blinking_class(&self, blink_on: bool) -> &'static str {
+        match self.get_class() {
+            CLASS_URGENT if !blink_on => CLASS_PAUSE,
+            class => class,
+        }
+    }
+
+    /// Moves on to the next cycle once the current one has run to its end
+    pub fn update_state(&mut self, config: &Config) -> Vec<TimerEvent> {
+        if (self.get_current_time() - self.elapsed_time) == 0 {
+            let cycle = self.current_cycle();
+            self.completed.push(CompletedCycle {
+                cycle,
+                duration: self.get_current_time(),
+                activity: self.activity.take(),
+                notes: std::mem::take(&mut self.notes),
+            });
+            if cycle == CycleType::Work {
+                self.streak += 1;
+                self.pomodoros_today += 1;
+                self.set_tally.pomodoros += 1;
+                if config.count_completed == CompletionCount::Work {
+                    self.session_completed = self.session_completed.saturating_add(1);
+                }
+                if let Some(focus) = &mut self.focus {
+                    focus.completed += 1;
+                }
+                if let Some(sprint) = &mut self.sprint {
+                    sprint.completed += 1;
+                }
+                if let Some(task) = &mut self.task {
+                    task.completed += 1;
                 }
             }
 
-            self.elapsed_time = 0;
-
-            // if the user has passed either auto flag, we want to keep ticking the timer
-            // NOTE: the is_break() seems to be flipped..?
-            self.running = (config.autob && self.is_break()) || (config.autow && !self.is_break());
-
-            // only send a notification for the first instance of the module and if send_notifications is true
-            if self.socket_nr == 0 && send_notifications {
-                send_notification(
-                    match self.current_index {
-                        0 => CycleType::Work,
-                        1 => CycleType::ShortBreak,
-                        2 => CycleType::LongBreak,
-                        _ => panic!("Invalid cycle type"),
-                    },
-                    config,
-                );
-            } else {
-                debug!(socket_nr = self.socket_nr, send_notifications, "didn't send a notification");
-            }
+            let event = match self.start_next_cycle(config) {
+                Some(tally) => TimerEvent::SetCompleted(tally),
+                None => TimerEvent::CycleStarted {
+                    from: cycle,
+                    cycle: self.current_cycle(),
+                    activity: self.activity.clone(),
+                },
+            };
+            return vec![event];
+        }
+        Vec::new()
+    }
+
+    /// Cycles that ran to their end since the last call
+    pub fn take_completed(&mut self) -> Vec<CompletedCycle> {
+        std::mem::take(&mut self.completed)
+    }
+
+    /// Pauses in the middle of work that ended since the last call, with their causes
+    pub fn take_pauses(&mut self) -> Vec<(PauseCause, u64)> {
+        std::mem::take(&mut self.pauses)
+    }
+
+    /// Returns the tally of the set the long break closed, when it was one
+    fn start_next_cycle(&mut self, config: &Config) -> Option<SetTally> {
+        let previous = self.current_cycle();
+        // notes on a skipped cycle go with it
+        self.notes.clear();
+
+        // Clear any override when transitioning to a new cycle
+        self.current_override = None;
+
+        let (next, iterations) = self.following();
+        // if we've had our long break, we've also completed a single pomodoro!
+        let set_done =
+            self.long_breaks() && previous == CycleType::LongBreak && next == CycleType::Work;
+        // a long break of 0 minutes turns them off, so every work cycle is a pomodoro
+        let pomodoro_done = set_done || (!self.long_breaks() && previous == CycleType::Work);
+        self.current_index = next as usize;
+        self.iterations = iterations;
+        if pomodoro_done && config.count_completed == CompletionCount::Set {
+            self.session_completed = self.session_completed.saturating_add(1);
+        }
+        let finished_set = set_done.then(|| std::mem::take(&mut self.set_tally));
+
+        self.elapsed_time = 0;
+        // sized once, as the cycle begins
+        self.plan_cycle(config);
+
+        // keep ticking the timer if the user asked for this transition to auto-start
+        // a focus session runs on by itself until its deadline, a sprint stops once done,
+        // and nothing starts by itself past the daily cap
+        let auto_start = if self.done_for_today(config)
+            || self.sprint.is_some_and(|sprint| sprint.is_complete())
+        {
+            false
+        } else {
+            self.focus.is_some() || config.auto_start.covers(previous, self.current_cycle())
+        };
+        if auto_start && config.auto_start_grace > 0 {
+            // give the user a chance to cancel before the next cycle starts
+            self.running = false;
+            self.grace_millis = Some(config.auto_start_grace as u32 * 1000);
+        } else {
+            self.running = auto_start;
+        }
+        self.awaiting_start = !auto_start;
+        self.activity = if self.is_break() {
+            self.pick_activity(config)
+        } else {
+            None
+        };
+        finished_set
+    }
+
+    /// Suggests one of the configured break activities, if there are any
+    fn pick_activity(&mut self, config: &Config) -> Option<String> {
+        let activities = &config.break_activities;
+        if activities.is_empty() {
+            return None;
         }
+        let index = match config.activity_order {
+            ActivityOrder::RoundRobin => self.activities_given,
+            ActivityOrder::Random => random_u64() as usize,
+        };
+        self.activities_given = self.activities_given.wrapping_add(1);
+        Some(activities[index % activities.len()].clone())
     }
 
     pub fn get_current_time(&self) -> u16 {
@@ -215,28 +823,223 @@ impl Timer {
             .unwrap_or(self.times[self.current_index])
     }
 
+    /// Whole seconds left in the current cycle
+    pub fn remaining(&self) -> u16 {
+        self.get_current_time().saturating_sub(self.elapsed_time)
+    }
+
     pub fn increment_time(&mut self) {
-        self.elapsed_millis += SLEEP_TIME;
-        if self.elapsed_millis >= 1000 {
+        self.advance(SLEEP_TIME as u32);
+    }
+
+    /// Moves the running cycle forward by `millis`, never past the end of the cycle
+    pub fn advance(&mut self, millis: u32) {
+        let before = self.elapsed_time as u64 * 1000 + self.elapsed_millis as u64;
+        let total = self.elapsed_millis as u32 + millis;
+        let elapsed_time = self.elapsed_time as u32 + total / 1000;
+
+        if elapsed_time >= self.get_current_time() as u32 {
+            self.elapsed_time = self.get_current_time();
+            self.elapsed_millis = 0;
+        } else {
+            self.elapsed_time = elapsed_time as u16;
+            self.elapsed_millis = (total % 1000) as u16;
+        }
+
+        if !self.is_break() {
+            let after = self.elapsed_time as u64 * 1000 + self.elapsed_millis as u64;
+            self.focus_millis += after.saturating_sub(before);
+            self.set_tally.focus_millis += after.saturating_sub(before);
+        }
+    }
+
+    /// Moves to `seconds` into the current cycle, forward or back. Time skipped in a work cycle
+    /// counts as focus, as with fast-forward
+    pub fn seek(&mut self, seconds: u16) {
+        let target = seconds.min(self.get_current_time()) as u32 * 1000;
+        let position = self.elapsed_time as u32 * 1000 + self.elapsed_millis as u32;
+        if target >= position {
+            self.advance(target - position);
+        } else {
+            self.elapsed_time = (target / 1000) as u16;
             self.elapsed_millis = 0;
-            self.elapsed_time += 1;
+        }
+    }
+
+    /// The cycle that follows the current one and how long it will last, before any
+    /// --jitter
+    pub fn upcoming(&self, config: &Config) -> (CycleType, u16) {
+        let (cycle, iterations) = self.following();
+        // counting the work cycle that leads up to it
+        let length = self.cycle_length(cycle, iterations, self.streak.saturating_add(1), config);
+        (cycle, length)
+    }
+
+    /// The cycle that comes after the current one, and how far into the set it is
+    fn following(&self) -> (CycleType, u8) {
+        if !self.long_breaks() {
+            match self.current_cycle() {
+                CycleType::Work => (CycleType::ShortBreak, 0),
+                _ => (CycleType::Work, 0),
+            }
+        }
+        // if we're on the third iteration and first work, then we want a long break
+        else if self.current_index == 0 && self.iterations == MAX_ITERATIONS - 1 {
+            (CycleType::LongBreak, MAX_ITERATIONS)
+        }
+        // if we've had our long break, reset everything and start over
+        else if self.current_index == 2 && self.iterations == MAX_ITERATIONS {
+            (CycleType::Work, 0)
+        }
+        // otherwise, run as normal
+        else if self.current_index == 0 {
+            (CycleType::ShortBreak, self.iterations)
+        } else {
+            (CycleType::Work, self.iterations + 1)
+        }
+    }
+
+    /// How long `cycle` lasts `iterations` work cycles into a set, before any --jitter: work
+    /// from --work-sequence, long breaks from --adaptive-long-break after a `streak`
+    fn cycle_length(&self, cycle: CycleType, iterations: u8, streak: u16, config: &Config) -> u16 {
+        let base = self.times[cycle as usize];
+        match cycle {
+            CycleType::Work if !config.work_sequence.is_empty() => {
+                let step = (iterations as usize).min(config.work_sequence.len() - 1);
+                config.work_sequence[step]
+            }
+            CycleType::LongBreak => config
+                .long_break_curve
+                .as_ref()
+                .map_or(base, |curve| curve.length(streak, base)),
+            _ => base,
+        }
+    }
+
+    /// Work cycles left before the long break, the current one included, `None` without
+    /// long breaks
+    pub fn until_long(&self) -> Option<u8> {
+        if !self.long_breaks() {
+            return None;
+        }
+        let left = match self.current_cycle() {
+            CycleType::Work => MAX_ITERATIONS - self.iterations,
+            // the work cycle just done counts once the break is over
+            CycleType::ShortBreak => MAX_ITERATIONS - self.iterations - 1,
+            CycleType::LongBreak => 0,
+        };
+        Some(left)
+    }
+
+    /// Whether today's pomodoros reached --daily-cap
+    pub fn done_for_today(&self, config: &Config) -> bool {
+        config
+            .daily_cap
+            .is_some_and(|cap| self.pomodoros_today >= cap)
+    }
+
+    /// Starts counting focus time afresh once `today` is a different day
+    pub fn roll_focus(&mut self, today: NaiveDate) {
+        if self.focus_day != Some(today) {
+            self.focus_day = Some(today);
+            self.focus_millis = 0;
+            self.pomodoros_today = 0;
+            self.interruptions_today = 0;
+        }
+    }
+
+    /// Skips `millis` of timer time, carrying on into the following cycles for as long as
+    /// they start by themselves
+    pub fn fast_forward(&mut self, mut millis: u64, config: &Config) -> Vec<TimerEvent> {
+        let mut events = Vec::new();
+        let mut empty_cycles = 0;
+        loop {
+            let left = (self.get_current_time().saturating_sub(self.elapsed_time) as u64 * 1000)
+                .saturating_sub(self.elapsed_millis as u64);
+            let step = millis.min(left);
+            self.advance(step as u32);
+            millis -= step;
+            if millis == 0 {
+                break;
+            }
+            // every cycle lasting no time would leave nothing to skip through but loop forever
+            empty_cycles = if step == 0 { empty_cycles + 1 } else { 0 };
+            if empty_cycles > self.times.len() {
+                break;
+            }
+
+            events.extend(self.update_state(config));
+            if !self.running {
+                break;
+            }
+        }
+        events
+    }
+
+    /// How long to wait for the next tick, aligned so that ticks land on the timer's own
+    /// second boundaries (or grace countdown boundaries) rather than drifting
+    pub fn next_tick_in(&self, tick_rate: Duration) -> Duration {
+        let tick = (tick_rate.as_millis() as u64).max(1);
+        let into_tick = if self.running {
+            (self.elapsed_time as u64 * 1000 + self.elapsed_millis as u64) % tick
+        } else if let Some(grace) = self.grace_millis {
+            (tick - grace as u64 % tick) % tick
+        } else {
+            0
+        };
+        Duration::from_millis(tick - into_tick)
+    }
+
+    /// Counts down a pending auto-start, starting the cycle once the grace period is over
+    pub fn tick_grace(&mut self, millis: u32) {
+        if let Some(remaining) = self.grace_millis {
+            let remaining = remaining.saturating_sub(millis);
+            if remaining == 0 {
+                self.grace_millis = None;
+                self.running = true;
+            } else {
+                self.grace_millis = Some(remaining);
+            }
+        }
+    }
+
+    /// Whole seconds left before a pending auto-start, rounded up
+    pub fn grace_remaining(&self) -> Option<u16> {
+        self.grace_millis.map(|millis| millis.div_ceil(1000) as u16)
+    }
+
+    /// Drops a pending auto-start, leaving the next cycle waiting for the user
+    pub fn cancel_auto_start(&mut self) {
+        if self.grace_millis.take().is_some() {
+            self.awaiting_start = true;
         }
     }
 
     pub fn next_state(&mut self, config: &Config) {
-        // Skip to end of current timer
-        self.elapsed_time = self.get_current_time();
+        // a skipped cycle isn't completed, so move on without recording it or notifying
         self.elapsed_millis = 0;
-
-        // Trigger state transition without notifications
-        self.update_state(config, false);
+        if !self.is_break() {
+            self.streak = 0;
+        }
+        self.start_next_cycle(config);
+        // the user skipped ahead themselves, no need to demand their attention
+        self.awaiting_start = false;
     }
 }
 
+/// A number from a randomly seeded hasher, which is all the randomness the timer needs
+fn random_u64() -> u64 {
+    RandomState::new().build_hasher().finish()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::utils::consts::{LONG_BREAK_TIME, SHORT_BREAK_TIME, SLEEP_DURATION, WORK_TIME};
+    use crate::models::{config::Transitions, message::AutoTarget};
+    use crate::utils::consts::{
+        LONG_BREAK_TIME, MAX_MINUTES, SHORT_BREAK_TIME, SLEEP_DURATION, WORK_TIME,
+    };
+    use proptest::prelude::*;
 
     fn create_timer() -> Timer {
         Timer::new(WORK_TIME, SHORT_BREAK_TIME, LONG_BREAK_TIME, 0)
@@ -284,6 +1087,18 @@ mod tests {
         assert!(timer.is_break());
     }
 
+    #[test]
+    fn test_current_cycle() {
+        let mut timer = create_timer();
+        assert_eq!(timer.current_cycle(), CycleType::Work);
+
+        timer.current_index = 1;
+        assert_eq!(timer.current_cycle(), CycleType::ShortBreak);
+
+        timer.current_index = 2;
+        assert_eq!(timer.current_cycle(), CycleType::LongBreak);
+    }
+
     #[test]
     fn test_set_time() {
         let mut timer = create_timer();
@@ -298,6 +1113,25 @@ mod tests {
         assert_eq!(timer.times[2], 20 * 60);
     }
 
+    #[test]
+    fn test_set_time_keeps_progress() {
+        let mut timer = create_timer();
+        timer.running = true;
+        timer.iterations = 2;
+        timer.elapsed_time = 600;
+
+        timer.set_time(CycleType::LongBreak, 30);
+        assert_eq!(timer.elapsed_time, 600);
+        assert_eq!(timer.iterations, 2);
+        assert!(timer.running);
+
+        // shortened below the time already spent, the work cycle ends
+        timer.set_time(CycleType::Work, 5);
+        assert_eq!(timer.elapsed_time, 300);
+        timer.update_state(&Config::default());
+        assert_eq!(timer.current_cycle(), CycleType::ShortBreak);
+    }
+
     #[test]
     fn test_get_class() {
         let mut timer = create_timer();
@@ -315,6 +1149,322 @@ mod tests {
         assert_eq!(timer.get_class(), CLASS_PAUSE);
     }
 
+    #[test]
+    fn test_get_class_urgent() {
+        let mut timer = create_timer();
+        let config = Config::default();
+
+        // run out the work cycle without any auto flags
+        timer.running = true;
+        timer.elapsed_time = timer.get_current_time();
+        timer.update_state(&config);
+
+        assert!(!timer.running);
+        assert!(timer.awaiting_start);
+        assert_eq!(timer.get_class(), CLASS_URGENT);
+        assert_eq!(timer.get_blinking_class(true), CLASS_URGENT);
+        assert_eq!(timer.get_blinking_class(false), CLASS_PAUSE);
+
+        timer.reset();
+        assert_eq!(timer.get_class(), CLASS_EMPTY);
+    }
+
+    #[test]
+    fn test_no_urgent_when_auto_started() {
+        let mut timer = create_timer();
+        let mut config = Config::default();
+        config.auto_start.short_break = true;
+
+        timer.running = true;
+        timer.elapsed_time = timer.get_current_time();
+        timer.update_state(&config);
+
+        assert!(timer.running);
+        assert!(!timer.awaiting_start);
+        assert_ne!(timer.get_class(), CLASS_URGENT);
+    }
+
+    #[test]
+    fn test_get_alt() {
+        let mut timer = create_timer();
+        assert_eq!(timer.get_alt(), "work-paused");
+
+        timer.running = true;
+        assert_eq!(timer.get_alt(), "work-running");
+
+        timer.current_index = 1;
+        assert_eq!(timer.get_alt(), "shortbreak-running");
+
+        timer.current_index = 2;
+        timer.running = false;
+        assert_eq!(timer.get_alt(), "longbreak-paused");
+    }
+
+    #[test]
+    fn test_auto_start_grace() {
+        let mut timer = create_timer();
+        let mut config = Config {
+            auto_start_grace: 1,
+            ..Default::default()
+        };
+        config.auto_start.short_break = true;
+
+        timer.running = true;
+        timer.elapsed_time = timer.get_current_time();
+        timer.update_state(&config);
+
+        assert!(!timer.running);
+        assert_eq!(timer.grace_remaining(), Some(1));
+        assert_eq!(timer.get_class(), CLASS_GRACE);
+
+        timer.tick_grace(600);
+        assert!(!timer.running);
+        timer.tick_grace(400);
+        assert!(timer.running);
+        assert_eq!(timer.grace_remaining(), None);
+    }
+
+    #[test]
+    fn test_cancel_auto_start() {
+        let mut timer = create_timer();
+        let mut config = Config {
+            auto_start_grace: 10,
+            ..Default::default()
+        };
+        config.auto_start.short_break = true;
+
+        timer.elapsed_time = timer.get_current_time();
+        timer.update_state(&config);
+        timer.cancel_auto_start();
+
+        assert!(!timer.running);
+        assert_eq!(timer.grace_remaining(), None);
+        assert_eq!(timer.get_class(), CLASS_URGENT);
+    }
+
+    #[test]
+    fn test_track_pause() {
+        let mut timer = create_timer();
+
+        // not started yet, so there is nothing to be stale about
+        timer.track_pause();
+        assert!(timer.paused_since.is_none());
+
+        timer.elapsed_time = 10;
+        timer.track_pause();
+        assert!(timer.paused_since.is_some());
+        // the pause interrupted the work cycle, counted once however long it lasts
+        timer.track_pause();
+        assert_eq!(timer.interruptions_today, 1);
+
+        timer.running = true;
+        timer.track_pause();
+        assert!(timer.paused_since.is_none());
+        assert_eq!(timer.take_pauses(), [(PauseCause::Manual, 0)]);
+
+        timer.pause(PauseCause::Locked);
+        timer.track_pause();
+        assert_eq!(timer.pause_cause, Some(PauseCause::Locked));
+        timer.running = true;
+        timer.track_pause();
+        assert_eq!(timer.pause_cause, None);
+        assert_eq!(timer.take_pauses(), [(PauseCause::Locked, 0)]);
+        assert_eq!(timer.take_pauses(), []);
+
+        // a break paused and resumed didn't interrupt any work
+        timer.current_index = 1;
+        timer.pause(PauseCause::Idle);
+        timer.track_pause();
+        timer.running = true;
+        timer.track_pause();
+        assert_eq!(timer.take_pauses(), []);
+    }
+
+    #[test]
+    fn test_roll_day() {
+        let mut timer = create_timer();
+        let today = NaiveDate::from_ymd_opt(2025, 3, 1).unwrap();
+        timer.running = true;
+        timer.advance(5000);
+        timer.session_completed = 2;
+
+        assert!(!timer.roll_day(today));
+        assert!(!timer.roll_day(today));
+        assert!(timer.running);
+
+        assert!(timer.roll_day(today.succ_opt().unwrap()));
+        assert!(!timer.running);
+        assert_eq!(timer.elapsed_millis, 0);
+        assert_eq!(timer.session_completed, 0);
+    }
+
+    #[test]
+    fn test_focus_time() {
+        let mut timer = create_timer();
+        let today = NaiveDate::from_ymd_opt(2025, 3, 1).unwrap();
+        timer.roll_focus(today);
+
+        timer.advance(90_500);
+        timer.advance(WORK_TIME as u32 * 1000);
+        assert_eq!(timer.focus_millis, WORK_TIME as u64 * 1000);
+
+        // breaks and other days don't count
+        timer.current_index = 1;
+        timer.elapsed_time = 0;
+        timer.advance(60_000);
+        timer.roll_focus(today);
+        assert_eq!(timer.focus_millis, WORK_TIME as u64 * 1000);
+        timer.roll_focus(today.succ_opt().unwrap());
+        assert_eq!(timer.focus_millis, 0);
+    }
+
+    #[test]
+    fn test_paused_mid_work_for() {
+        let mut timer = create_timer();
+        timer.track_pause();
+        assert_eq!(timer.paused_mid_work_for(), None);
+
+        timer.running = true;
+        timer.advance(5000);
+        timer.running = false;
+        timer.track_pause();
+        assert!(timer.paused_mid_work_for().is_some());
+
+        // a finished cycle waiting to be started isn't mid-cycle
+        timer.awaiting_start = true;
+        assert_eq!(timer.paused_mid_work_for(), None);
+    }
+
+    #[test]
+    fn test_get_display_class_stale() {
+        let mut timer = create_timer();
+        let config = Config {
+            stale_after: Some(Duration::from_secs(60)),
+            ..Default::default()
+        };
+
+        timer.elapsed_time = 10;
+        timer.paused_since = Some(Instant::now());
+        assert_eq!(timer.get_display_class(&config, true), CLASS_PAUSE);
+
+        timer.paused_since = Instant::now().checked_sub(Duration::from_secs(61));
+        assert_eq!(timer.get_display_class(&config, true), CLASS_STALE);
+
+        // without a threshold the timer never goes stale
+        assert_eq!(
+            timer.get_display_class(&Config::default(), true),
+            CLASS_PAUSE
+        );
+    }
+
+    #[test]
+    fn test_get_classes() {
+        let mut timer = create_timer();
+        let config = Config::default();
+        assert_eq!(
+            timer.get_classes(&config, true),
+            ["cycle-work", CLASS_PAUSED, "p100"]
+        );
+
+        timer.running = true;
+        timer.elapsed_time = WORK_TIME - WARNING_TIME;
+        assert_eq!(
+            timer.get_classes(&config, true),
+            [
+                CLASS_WORK,
+                "cycle-work",
+                CLASS_RUNNING,
+                "p10",
+                CLASS_WARNING
+            ]
+        );
+
+        timer.current_index = 2;
+        timer.elapsed_time = 1;
+        assert_eq!(
+            timer.get_classes(&config, true),
+            [CLASS_BREAK, "cycle-long-break", CLASS_RUNNING, "p100"]
+        );
+
+        // run past the end of the cycle, e.g. before the tick that ends it
+        timer.current_index = 0;
+        timer.elapsed_time = WORK_TIME + 5;
+        assert!(timer.get_classes(&config, true).contains(&CLASS_WARNING));
+    }
+
+    #[test]
+    fn test_remaining_class() {
+        let mut timer = create_timer();
+        timer.times = [100, 100, 100];
+        for (elapsed, class) in [
+            (0, "p100"),
+            (24, "p100"),
+            (25, "p75"),
+            (50, "p50"),
+            (74, "p50"),
+            (75, "p25"),
+            (89, "p25"),
+            (90, "p10"),
+            (100, "p10"),
+        ] {
+            timer.elapsed_time = elapsed;
+            assert_eq!(timer.remaining_class(), class, "{elapsed}s in");
+        }
+    }
+
+    #[test]
+    fn test_set_tally() {
+        let mut timer = create_timer();
+        let config = Config::default();
+        timer.times = [2, 1, 1];
+
+        for _ in 0..MAX_ITERATIONS {
+            assert_eq!(timer.current_cycle(), CycleType::Work);
+            timer.advance(2000);
+            timer.update_state(&config);
+            timer.advance(1000);
+            if timer.current_cycle() == CycleType::LongBreak {
+                break;
+            }
+            timer.update_state(&config);
+        }
+        assert_eq!(timer.current_cycle(), CycleType::LongBreak);
+        assert_eq!(timer.set_tally.pomodoros, MAX_ITERATIONS as u16);
+        assert_eq!(timer.set_tally.focus_millis, 8000);
+
+        // the summary goes out as the long break ends, starting a new tally
+        let tally = timer.set_tally;
+        assert_eq!(
+            timer.update_state(&config),
+            [TimerEvent::SetCompleted(tally)]
+        );
+        assert_eq!(timer.current_cycle(), CycleType::Work);
+        assert_eq!(timer.set_tally, SetTally::default());
+    }
+
+    #[test]
+    fn test_count_completed_work() {
+        let mut timer = create_timer();
+        let config = Config {
+            count_completed: CompletionCount::Work,
+            ..Config::default()
+        };
+        timer.times = [2, 1, 1];
+
+        // three pomodoros, no long break reached
+        for _ in 0..3 {
+            timer.advance(2000);
+            timer.update_state(&config);
+            timer.advance(1000);
+            timer.update_state(&config);
+        }
+        assert_eq!(timer.session_completed, 3);
+
+        // a skipped work cycle doesn't count
+        timer.next_state(&config);
+        assert_eq!(timer.session_completed, 3);
+    }
+
     #[test]
     fn test_update_state() {
         let mut timer = create_timer();
@@ -335,15 +1485,25 @@ mod tests {
             timer.increment_time();
             std::thread::sleep(SLEEP_DURATION);
         }
-        timer.update_state(&config, false);
+        assert_eq!(
+            timer.update_state(&config),
+            [TimerEvent::CycleStarted {
+                from: CycleType::Work,
+                cycle: CycleType::ShortBreak,
+                activity: None
+            }]
+        );
         assert_eq!(timer.current_index, 1); // Move to short break
 
+        // nothing more happens until the break has run too
+        assert_eq!(timer.update_state(&config), []);
+
         // Update state after short break is completed
         for _ in 0..time * 1000 / SLEEP_TIME {
             timer.increment_time();
             std::thread::sleep(SLEEP_DURATION);
         }
-        timer.update_state(&config, false);
+        timer.update_state(&config);
 
         // we need to trigger a long break
         timer.iterations = MAX_ITERATIONS - 1;
@@ -354,10 +1514,45 @@ mod tests {
             std::thread::sleep(SLEEP_DURATION);
         }
 
-        timer.update_state(&config, false);
+        timer.update_state(&config);
         assert_eq!(timer.current_index, 2); // Move to long break
     }
 
+    #[test]
+    fn test_break_activities() {
+        let mut timer = create_timer();
+        let config = Config {
+            break_activities: vec!["stretch".to_string(), "water".to_string()],
+            ..Config::default()
+        };
+
+        let mut suggested = Vec::new();
+        for _ in 0..6 {
+            timer.elapsed_time = timer.get_current_time();
+            timer.update_state(&config);
+            suggested.push(timer.activity.clone());
+        }
+        let stretch = Some("stretch".to_string());
+        let water = Some("water".to_string());
+        assert_eq!(
+            suggested,
+            [
+                stretch.clone(),
+                None,
+                water.clone(),
+                None,
+                stretch.clone(),
+                None
+            ]
+        );
+
+        // the break remembers what it was spent on
+        let completed = timer.take_completed();
+        assert_eq!(completed[1].cycle, CycleType::ShortBreak);
+        assert_eq!(completed[1].activity, stretch);
+        assert_eq!(completed[2].activity, None);
+    }
+
     #[test]
     fn test_increment_elapsed_time() {
         let mut timer = create_timer();
@@ -376,6 +1571,108 @@ mod tests {
         assert_eq!(timer.elapsed_time, 10);
     }
 
+    #[test]
+    fn test_advance() {
+        let mut timer = create_timer();
+
+        timer.advance(1500);
+        assert_eq!(timer.elapsed_time, 1);
+        assert_eq!(timer.elapsed_millis, 500);
+
+        timer.advance(2500);
+        assert_eq!(timer.elapsed_time, 4);
+        assert_eq!(timer.elapsed_millis, 0);
+
+        // a late wake-up never runs past the end of the cycle
+        timer.advance(WORK_TIME as u32 * 1000);
+        assert_eq!(timer.elapsed_time, WORK_TIME);
+        assert_eq!(timer.elapsed_millis, 0);
+    }
+
+    #[test]
+    fn test_nudge_current() {
+        let mut timer = create_timer();
+        let max = 30 * MINUTE;
+
+        timer.nudge_current(5 * MINUTE as i32, MINUTE, max);
+        assert_eq!(timer.get_current_time(), WORK_TIME + 5 * MINUTE);
+        timer.nudge_current(10 * MINUTE as i32, MINUTE, max);
+        assert_eq!(timer.get_current_time(), max);
+
+        // scrolling down never finishes the cycle
+        timer.elapsed_time = 10 * MINUTE;
+        timer.nudge_current(-60 * MINUTE as i32, MINUTE, max);
+        assert_eq!(timer.get_current_time(), 11 * MINUTE);
+        assert_eq!(timer.elapsed_time, 10 * MINUTE);
+    }
+
+    #[test]
+    fn test_fast_forward() {
+        let mut timer = create_timer();
+        let mut config = Config::default();
+
+        timer.fast_forward(90_000, &config);
+        assert_eq!(timer.elapsed_time, 90);
+
+        // stops at the end of the cycle when the break has to be started by hand
+        timer.fast_forward(WORK_TIME as u64 * 1000, &config);
+        assert_eq!(timer.current_index, 1);
+        assert_eq!(timer.elapsed_time, 0);
+        assert!(!timer.running);
+
+        // but carries on into cycles that start by themselves
+        config.auto_start.enable(AutoTarget::Work);
+        timer.fast_forward((SHORT_BREAK_TIME + 60) as u64 * 1000, &config);
+        assert_eq!(timer.current_index, 0);
+        assert_eq!(timer.elapsed_time, 60);
+        assert!(timer.running);
+
+        // and stops when every cycle lasts no time at all
+        config.auto_start.enable(AutoTarget::Break);
+        timer.times = [0, 0, 0];
+        timer.fast_forward(60_000, &config);
+        assert_eq!(timer.elapsed_time, 0);
+    }
+
+    #[test]
+    fn test_seek() {
+        let mut timer = create_timer();
+        timer.advance(90_500);
+
+        timer.seek(10 * MINUTE);
+        assert_eq!(timer.elapsed_time, 10 * MINUTE);
+        assert_eq!(timer.elapsed_millis, 0);
+        assert_eq!(timer.focus_millis, 600_000);
+
+        timer.seek(5 * MINUTE);
+        assert_eq!(timer.elapsed_time, 5 * MINUTE);
+        assert_eq!(timer.focus_millis, 600_000);
+
+        timer.seek(u16::MAX);
+        assert_eq!(timer.elapsed_time, WORK_TIME);
+    }
+
+    #[test]
+    fn test_next_tick_in() {
+        let mut timer = create_timer();
+        let second = Duration::from_millis(1000);
+
+        // paused, nothing to align to
+        assert_eq!(timer.next_tick_in(second), second);
+
+        timer.running = true;
+        timer.elapsed_millis = 300;
+        assert_eq!(timer.next_tick_in(second), Duration::from_millis(700));
+        assert_eq!(
+            timer.next_tick_in(Duration::from_millis(250)),
+            Duration::from_millis(200)
+        );
+
+        timer.running = false;
+        timer.grace_millis = Some(4700);
+        assert_eq!(timer.next_tick_in(second), Duration::from_millis(700));
+    }
+
     #[test]
     fn test_next_state() {
         let mut timer = create_timer();
@@ -403,4 +1700,359 @@ mod tests {
         assert_eq!(timer.iterations, 0);
         assert_eq!(timer.session_completed, 1); // One session completed
     }
+
+    #[test]
+    fn test_adaptive_long_break() {
+        let mut timer = create_timer();
+        let config = Config {
+            long_break_curve: Some("8:20,12:30".parse().unwrap()),
+            ..Default::default()
+        };
+        let finish = |timer: &mut Timer| {
+            timer.elapsed_time = timer.get_current_time();
+            timer.update_state(&config);
+        };
+
+        // the first long break comes after 4 in a row, short of the first step
+        while timer.current_cycle() != CycleType::LongBreak {
+            finish(&mut timer);
+        }
+        assert_eq!(timer.streak, 4);
+        assert_eq!(timer.get_current_time(), LONG_BREAK_TIME);
+
+        while timer.streak < 8 || timer.current_cycle() != CycleType::LongBreak {
+            finish(&mut timer);
+        }
+        assert_eq!(timer.get_current_time(), 20 * MINUTE);
+        finish(&mut timer);
+        assert_eq!(timer.get_current_time(), WORK_TIME);
+
+        // skipping work breaks the streak
+        timer.next_state(&config);
+        assert_eq!(timer.streak, 0);
+    }
+
+    #[test]
+    fn test_work_sequence() {
+        let mut timer = create_timer();
+        let config = Config {
+            work_sequence: vec![25 * MINUTE, 30 * MINUTE, 35 * MINUTE],
+            ..Default::default()
+        };
+        let mut lengths = Vec::new();
+        for _ in 0..MAX_ITERATIONS {
+            timer.plan_cycle(&config);
+            lengths.push(timer.get_current_time() / MINUTE);
+            timer.next_state(&config);
+            timer.next_state(&config);
+        }
+        // the last length repeats, and the set starts over after the long break
+        assert_eq!(lengths, [25, 30, 35, 35]);
+        assert_eq!(timer.current_cycle(), CycleType::Work);
+        assert_eq!(timer.get_current_time(), 25 * MINUTE);
+
+        // a length set by hand stays
+        timer.set_current_duration(40);
+        timer.plan_cycle(&config);
+        assert_eq!(timer.get_current_time(), 40 * MINUTE);
+    }
+
+    #[test]
+    fn test_jitter() {
+        let mut timer = create_timer();
+        let config = Config {
+            jitter: 3 * MINUTE,
+            ..Default::default()
+        };
+        for _ in 0..20 {
+            timer.next_state(&config);
+            let base = timer.times[timer.current_index];
+            let drawn = timer.get_current_time();
+            assert!((base - 3 * MINUTE..=base + 3 * MINUTE).contains(&drawn));
+            // drawn once per cycle
+            timer.plan_cycle(&config);
+            assert_eq!(timer.get_current_time(), drawn);
+        }
+    }
+
+    #[test]
+    fn test_focus_until() {
+        let mut timer = create_timer();
+        let config = Config::default();
+        let now = Local::now();
+        let until = now + chrono::TimeDelta::minutes(70);
+
+        // 25 + 5 + 25 + 5 + 10 of a third work cycle
+        assert_eq!(timer.focus_until(until, now, &config), 2);
+        assert!(timer.running);
+        for _ in 0..4 {
+            timer.elapsed_time = timer.get_current_time();
+            timer.update_state(&config);
+            assert!(timer.running);
+        }
+        assert_eq!(timer.focus.as_ref().unwrap().completed, 2);
+
+        // the third work cycle is cut short at the deadline
+        timer.fit_focus(until - chrono::TimeDelta::minutes(10));
+        assert_eq!(timer.get_current_time(), 10 * MINUTE);
+        assert_eq!(timer.end_focus(until - chrono::TimeDelta::seconds(1)), None);
+        let focus = timer.end_focus(until).unwrap();
+        assert_eq!((focus.planned, focus.completed), (2, 2));
+        assert!(timer.focus.is_none());
+        assert!(!timer.running);
+    }
+
+    #[test]
+    fn test_sprint() {
+        let mut timer = create_timer();
+        let config = Config {
+            auto_start: Transitions {
+                short_break: true,
+                work_after_short: true,
+                ..Default::default()
+            },
+            auto_start_grace: 0,
+            ..Default::default()
+        };
+        timer.sprint(2);
+        assert!(timer.running);
+        assert_eq!(timer.sprint_progress(), "0/2");
+
+        for _ in 0..2 {
+            timer.elapsed_time = timer.get_current_time();
+            timer.update_state(&config);
+            assert!(timer.running);
+            assert_eq!(timer.end_sprint(), None);
+        }
+        assert_eq!(timer.sprint_progress(), "1/2");
+
+        // the last pomodoro stops the timer, auto-start or not
+        timer.elapsed_time = timer.get_current_time();
+        timer.update_state(&config);
+        assert!(!timer.running);
+        assert_eq!(
+            timer.end_sprint(),
+            Some(Sprint {
+                planned: 2,
+                completed: 2
+            })
+        );
+        assert_eq!(timer.sprint_progress(), "");
+        assert!(!timer.awaiting_start);
+    }
+
+    #[test]
+    fn test_daily_cap() {
+        let mut timer = create_timer();
+        let config = Config {
+            auto_start: Transitions {
+                short_break: true,
+                work_after_short: true,
+                ..Default::default()
+            },
+            auto_start_grace: 0,
+            daily_cap: Some(2),
+            ..Default::default()
+        };
+        let today = NaiveDate::from_ymd_opt(2025, 3, 1).unwrap();
+        timer.roll_focus(today);
+        timer.running = true;
+
+        for _ in 0..2 {
+            timer.elapsed_time = timer.get_current_time();
+            timer.update_state(&config);
+            assert!(timer.running);
+        }
+        assert!(!timer.done_for_today(&config));
+
+        // the second pomodoro reaches the cap, the break waits for the user
+        timer.elapsed_time = timer.get_current_time();
+        timer.update_state(&config);
+        assert_eq!(timer.pomodoros_today, 2);
+        assert!(!timer.running);
+        assert!(timer
+            .get_classes(&config, true)
+            .contains(&CLASS_DONE_FOR_TODAY));
+
+        timer.roll_focus(today.succ_opt().unwrap());
+        assert!(!timer.done_for_today(&config));
+    }
+
+    #[test]
+    fn test_upcoming() {
+        let config = Config {
+            work_sequence: vec![20 * MINUTE, 30 * MINUTE],
+            ..Default::default()
+        };
+        for long_break in [LONG_BREAK_TIME, 0] {
+            let mut timer = Timer::new(WORK_TIME, SHORT_BREAK_TIME, long_break, 0);
+            for _ in 0..2 * MAX_ITERATIONS + 2 {
+                let upcoming = timer.upcoming(&config);
+                timer.next_state(&config);
+                assert_eq!(upcoming, (timer.current_cycle(), timer.get_current_time()));
+            }
+        }
+    }
+
+    #[test]
+    fn test_until_long() {
+        let mut timer = create_timer();
+        let config = Config::default();
+        let mut left = Vec::new();
+        for _ in 0..2 * MAX_ITERATIONS + 1 {
+            left.push(timer.until_long().unwrap());
+            timer.next_state(&config);
+        }
+        assert_eq!(left, [4, 3, 3, 2, 2, 1, 1, 0, 4]);
+
+        let timer = Timer::new(WORK_TIME, SHORT_BREAK_TIME, 0, 0);
+        assert_eq!(timer.until_long(), None);
+    }
+
+    #[test]
+    fn test_next_state_without_long_breaks() {
+        let mut timer = Timer::new(WORK_TIME, SHORT_BREAK_TIME, 0, 0);
+        let config = Config::default();
+        assert!(!timer.long_breaks());
+
+        for completed in 1..=MAX_ITERATIONS + 1 {
+            timer.next_state(&config);
+            assert_eq!(timer.current_cycle(), CycleType::ShortBreak);
+            assert_eq!(timer.session_completed, completed);
+            timer.next_state(&config);
+            assert_eq!(timer.current_cycle(), CycleType::Work);
+        }
+        assert_eq!(timer.iterations, 0);
+    }
+
+    #[test]
+    fn test_resume_at() {
+        let mut timer = create_timer();
+        timer.running = true;
+        timer.resume_at(CycleType::ShortBreak, 120, 420, 2);
+        assert_eq!(timer.current_cycle(), CycleType::ShortBreak);
+        assert_eq!(timer.get_current_time(), 420);
+        assert_eq!(timer.elapsed_time, 120);
+        assert_eq!(timer.iterations, 2);
+        assert!(!timer.running);
+
+        let mut timer = Timer::new(WORK_TIME, SHORT_BREAK_TIME, 0, 0);
+        timer.resume_at(CycleType::LongBreak, 9000, 900, 9);
+        assert_eq!(timer.current_cycle(), CycleType::ShortBreak);
+        assert_eq!(timer.elapsed_time, 900);
+        assert_eq!(timer.iterations, MAX_ITERATIONS);
+    }
+
+    #[derive(Debug, Clone)]
+    enum Op {
+        Advance(u32),
+        Update,
+        Next,
+        Toggle,
+        Reset,
+        SetTime(CycleType, u16),
+        AddDelta(CycleType, i16),
+        SetCurrent(u16),
+        AddCurrentDelta(i16),
+        FastForward(u64),
+        TickGrace(u32),
+        CancelAuto,
+        Sprint(u16),
+    }
+
+    fn cycle() -> impl Strategy<Value = CycleType> {
+        prop_oneof![
+            Just(CycleType::Work),
+            Just(CycleType::ShortBreak),
+            Just(CycleType::LongBreak),
+        ]
+    }
+
+    fn op() -> impl Strategy<Value = Op> {
+        prop_oneof![
+            (0u32..3_000_000).prop_map(Op::Advance),
+            Just(Op::Update),
+            Just(Op::Next),
+            Just(Op::Toggle),
+            Just(Op::Reset),
+            (cycle(), 1..=MAX_MINUTES).prop_map(|(cycle, minutes)| Op::SetTime(cycle, minutes)),
+            (cycle(), -90i16..90).prop_map(|(cycle, delta)| Op::AddDelta(cycle, delta)),
+            (1..=MAX_MINUTES).prop_map(Op::SetCurrent),
+            (-90i16..90).prop_map(Op::AddCurrentDelta),
+            (0u64..20_000_000).prop_map(Op::FastForward),
+            (0u32..20_000).prop_map(Op::TickGrace),
+            Just(Op::CancelAuto),
+            (1u16..10).prop_map(Op::Sprint),
+        ]
+    }
+
+    fn apply(timer: &mut Timer, op: Op, config: &Config) {
+        match op {
+            Op::Advance(millis) => timer.advance(millis),
+            Op::Update => {
+                timer.update_state(config);
+            }
+            Op::Next => timer.next_state(config),
+            Op::Toggle => {
+                // as the toggle command does
+                timer.running = !timer.running || timer.grace_millis.is_some();
+                timer.awaiting_start = false;
+                timer.grace_millis = None;
+            }
+            Op::Reset => timer.reset(),
+            Op::SetTime(cycle, minutes) => timer.set_time(cycle, minutes),
+            Op::AddDelta(cycle, delta) => timer.add_delta_time(cycle, delta),
+            Op::SetCurrent(minutes) => timer.set_current_duration(minutes),
+            Op::AddCurrentDelta(delta) => timer.add_current_delta_time(delta),
+            Op::FastForward(millis) => {
+                timer.fast_forward(millis, config);
+            }
+            Op::TickGrace(millis) => timer.tick_grace(millis),
+            Op::CancelAuto => timer.cancel_auto_start(),
+            Op::Sprint(pomodoros) => timer.sprint(pomodoros),
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn test_fsm_invariants(
+            ops in prop::collection::vec(op(), 1..200),
+            long_breaks in any::<bool>(),
+            auto in any::<bool>(),
+            grace in prop_oneof![Just(0u16), Just(5)],
+        ) {
+            let long_break = if long_breaks { LONG_BREAK_TIME } else { 0 };
+            let mut timer = Timer::new(WORK_TIME, SHORT_BREAK_TIME, long_break, 0);
+            let config = Config {
+                auto_start: Transitions {
+                    short_break: auto,
+                    long_break: auto,
+                    work_after_short: auto,
+                    work_after_long: auto,
+                },
+                auto_start_grace: grace,
+                ..Config::default()
+            };
+
+            for op in ops {
+                let completed = timer.session_completed;
+                apply(&mut timer, op.clone(), &config);
+
+                prop_assert!(
+                    timer.elapsed_time <= timer.get_current_time(),
+                    "{op:?} left {}s of a {}s cycle elapsed",
+                    timer.elapsed_time,
+                    timer.get_current_time()
+                );
+                prop_assert!(timer.elapsed_millis < 1000);
+                prop_assert!(timer.current_index < timer.times.len());
+                prop_assert!(timer.iterations <= MAX_ITERATIONS);
+                if !timer.long_breaks() {
+                    prop_assert_ne!(timer.current_cycle(), CycleType::LongBreak);
+                }
+                prop_assert!(timer.session_completed >= completed);
+                prop_assert!(!(timer.running && timer.grace_millis.is_some()));
+            }
+        }
+    }
 }
@@ -0,0 +1,85 @@
+use std::process::Command;
+
+use serde_json::Value;
+use tracing::{debug, warn};
+
+/// A Hyprland special workspace shown for the length of a break, e.g. one holding a
+/// break checklist
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpecialWorkspace {
+    name: String,
+}
+
+impl SpecialWorkspace {
+    pub fn new(name: impl Into<String>) -> Self {
+        Self { name: name.into() }
+    }
+
+    pub fn show(&self) {
+        // toggling is the only way in, so only toggle if the user hasn't opened it already
+        if self.is_shown() == Some(false) {
+            self.toggle();
+        }
+    }
+
+    pub fn hide(&self) {
+        if self.is_shown() == Some(true) {
+            self.toggle();
+        }
+    }
+
+    fn toggle(&self) {
+        debug!("Toggling special workspace {}", self.name);
+        match Command::new("hyprctl")
+            .args(["dispatch", "togglespecialworkspace", &self.name])
+            .output()
+        {
+            Ok(output) if !output.status.success() => {
+                warn!("hyprctl exited with {}", output.status)
+            }
+            Ok(_) => {}
+            Err(e) => warn!("Failed to run hyprctl: {}", e),
+        }
+    }
+
+    /// Whether any monitor shows the workspace, `None` if Hyprland can't be asked
+    fn is_shown(&self) -> Option<bool> {
+        let output = Command::new("hyprctl")
+            .args(["monitors", "-j"])
+            .output()
+            .inspect_err(|e| warn!("Failed to run hyprctl: {}", e))
+            .ok()?;
+        if !output.status.success() {
+            warn!("hyprctl exited with {}", output.status);
+            return None;
+        }
+        let monitors: Value = serde_json::from_slice(&output.stdout).ok()?;
+        Some(shown_on(&monitors, &self.name))
+    }
+}
+
+fn shown_on(monitors: &Value, name: &str) -> bool {
+    let special = format!("special:{name}");
+    monitors.as_array().is_some_and(|monitors| {
+        monitors
+            .iter()
+            .any(|monitor| monitor["specialWorkspace"]["name"].as_str() == Some(&special))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_shown_on() {
+        let monitors = json!([
+            {"name": "DP-1", "specialWorkspace": {"id": 0, "name": ""}},
+            {"name": "DP-2", "specialWorkspace": {"id": -98, "name": "special:break"}}
+        ]);
+        assert!(shown_on(&monitors, "break"));
+        assert!(!shown_on(&monitors, "scratch"));
+        assert!(!shown_on(&json!({}), "break"));
+    }
+}
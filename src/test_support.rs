@@ -0,0 +1,264 @@
+//! Drives a module's timer and command protocol in-process, without real sockets, sleeping
+//! or XDG directories. Enable the `test-support` feature to use it from integration tests.
+
+use std::{
+    io::{self, Write},
+    net::Shutdown,
+    os::unix::net::UnixStream,
+    time::Duration,
+};
+
+use crate::{
+    models::{
+        config::Config,
+        message::{Message, Response},
+    },
+    services::{
+        module::{self, Runtime},
+        timer::Timer,
+    },
+    utils::consts::CLIENT_READ_TIMEOUT,
+};
+
+/// A clock that only moves when told to
+#[derive(Debug, Default, Clone, Copy)]
+pub struct VirtualClock {
+    elapsed: Duration,
+}
+
+impl VirtualClock {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn advance(&mut self, by: Duration) {
+        self.elapsed += by;
+    }
+
+    /// Time passed since the clock was created
+    pub fn elapsed(&self) -> Duration {
+        self.elapsed
+    }
+}
+
+/// A module whose commands travel over an in-memory socket pair and whose time is virtual
+pub struct Harness {
+    state: Timer,
+    config: Config,
+    runtime: Runtime,
+    clock: VirtualClock,
+}
+
+impl Harness {
+    /// Creates a module for `config`. Nothing is persisted; notifications are still sent if
+    /// the config enables them.
+    pub fn new(mut config: Config) -> Self {
+        config.persist = false;
+        let state = Timer::new(config.work_time, config.short_break, config.long_break, 0);
+        Self {
+            state,
+            config,
+            runtime: Runtime::new("in-memory"),
+            clock: VirtualClock::new(),
+        }
+    }
+
+    /// Sends a raw command over a fresh connection and decodes the module's reply
+    pub fn request(&mut self, message: &str) -> io::Result<Response> {
+        let (mut client, mut server) = UnixStream::pair()?;
+        client.write_all(message.as_bytes())?;
+        client.shutdown(Shutdown::Write)?;
+
+        let received = module::read_client(&mut server, CLIENT_READ_TIMEOUT)?;
+        let response = module::process_message(
+            &mut self.state,
+            &received,
+            &mut self.config,
+            &mut self.runtime,
+        );
+        server.write_all(response.encode().as_bytes())?;
+        drop(server);
+
+        let reply = module::read_client(&mut client, CLIENT_READ_TIMEOUT)?;
+        Response::decode(&reply).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    pub fn send(&mut self, message: &Message) -> io::Result<Response> {
+        self.request(&message.encode())
+    }
+
+    /// Moves virtual time on, ticking at the configured tick rate, and returns the last
+    /// line the module rendered
+    pub fn advance(&mut self, by: Duration) -> String {
+        self.clock.advance(by);
+
+        let tick_rate = self.config.tick_rate.max(Duration::from_millis(1));
+        let mut remaining = by;
+        let mut output = self.render();
+        while !remaining.is_zero() {
+            let step = remaining.min(tick_rate);
+            remaining -= step;
+            output = module::tick(&mut self.state, &self.config, step.as_millis() as u32);
+            module::record_completed(&mut self.state, &mut self.runtime, &self.config);
+        }
+        output
+    }
+
+    /// Renders the current state without moving time
+    pub fn render(&mut self) -> String {
+        module::tick(&mut self.state, &self.config, 0)
+    }
+
+    pub fn timer(&self) -> &Timer {
+        &self.state
+    }
+
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    pub fn clock(&self) -> &VirtualClock {
+        &self.clock
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        cli::ModuleCli,
+        models::message::ErrorKind,
+        utils::consts::{MAX_ITERATIONS, SHORT_BREAK_TIME},
+    };
+    use clap::Parser;
+    use proptest::prelude::*;
+
+    #[test]
+    fn test_harness_runs_a_work_cycle() {
+        let cli = ModuleCli::parse_from(["waybar-module-pomodoro"]);
+        let mut harness = Harness::new(Config::from_module_cli(&cli));
+        let work_time = harness.config().work_time;
+
+        assert_eq!(harness.send(&Message::Start).unwrap(), Response::ok());
+        let output = harness.advance(Duration::from_secs(60));
+        assert!(output.contains(r#""class": ["work","cycle-work","running","p100"]"#));
+        assert_eq!(harness.timer().elapsed_time, 60);
+
+        harness.advance(Duration::from_secs(work_time as u64));
+        assert!(harness.timer().is_break());
+        assert_eq!(harness.timer().get_current_time(), SHORT_BREAK_TIME);
+        assert_eq!(
+            harness.clock().elapsed(),
+            Duration::from_secs(60 + work_time as u64)
+        );
+    }
+
+    #[test]
+    fn test_harness_replies_with_errors() {
+        let mut harness = Harness::new(Config::default());
+        match harness.request("launch").unwrap() {
+            Response::Error { kind, .. } => assert_eq!(kind, ErrorKind::UnknownCommand),
+            other => panic!("unexpected response {other:?}"),
+        }
+    }
+
+    /// The inputs of a fuzz target's corpus, see fuzz/
+    fn corpus(target: &str) -> Vec<String> {
+        let dir = std::path::Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("fuzz/corpus")
+            .join(target);
+        let inputs: Vec<_> = std::fs::read_dir(dir)
+            .unwrap()
+            .filter_map(|entry| std::fs::read(entry.unwrap().path()).ok())
+            .map(|bytes| String::from_utf8_lossy(&bytes).into_owned())
+            .collect();
+        assert!(!inputs.is_empty(), "empty {target} corpus");
+        inputs
+    }
+
+    /// Replays the fuzz corpora, checking what the fuzz targets check
+    #[test]
+    fn test_fuzz_corpus() {
+        for input in corpus("decode_message") {
+            if let Ok(message) = Message::decode(&input) {
+                assert_eq!(Message::decode(&message.encode()).ok(), Some(message));
+            }
+        }
+        for input in corpus("decode_response") {
+            if let Ok(response) = Response::decode(&input) {
+                assert!(Response::decode(&response.encode()).is_ok());
+            }
+        }
+        for input in corpus("process_message") {
+            let mut harness = Harness::new(Config::default());
+            for command in input.split('\n') {
+                harness.request(command).unwrap();
+            }
+            harness.advance(Duration::from_secs(90));
+        }
+    }
+
+    /// A command as a client would send it, with arbitrary arguments
+    fn command() -> impl Strategy<Value = String> {
+        let cycle = prop_oneof![Just("set-work"), Just("set-short"), Just("set-long")];
+        let sign = prop_oneof![Just(""), Just("+"), Just("-")];
+        prop_oneof![
+            prop_oneof![
+                Just("start"),
+                Just("stop"),
+                Just("toggle"),
+                Just("reset"),
+                Just("next-state"),
+                Just("cancel-auto"),
+                Just("idle"),
+                Just("active"),
+            ]
+            .prop_map(str::to_string),
+            (cycle, sign.clone(), 0u16..200)
+                .prop_map(|(cycle, sign, n)| format!(r#"{{"{cycle}":{{"time":"{sign}{n}"}}}}"#)),
+            (sign, 0u16..200)
+                .prop_map(|(sign, n)| format!(r#"{{"set-current":{{"time":"{sign}{n}"}}}}"#)),
+            (0u32..100_000).prop_map(|n| format!(r#"{{"fast-forward":{{"time":"{n}s"}}}}"#)),
+            (any::<bool>(), 0u16..30).prop_map(|(up, step)| {
+                let direction = if up { "up" } else { "down" };
+                format!(r#"{{"scroll":{{"direction":"{direction}","step":{step}}}}}"#)
+            }),
+            (0u16..10).prop_map(|n| format!(r#"{{"sprint":{{"pomodoros":{n}}}}}"#)),
+            (any::<bool>(), any::<bool>()).prop_map(|(work, on)| {
+                let cycle = if work { "work" } else { "break" };
+                let state = if on { "on" } else { "off" };
+                format!(r#"{{"set-auto":{{"cycle":"{cycle}","state":"{state}"}}}}"#)
+            }),
+        ]
+    }
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(64))]
+
+        /// Whatever clients send, the timer stays in a state it can render and run from
+        #[test]
+        fn test_commands_leave_valid_state(
+            steps in prop::collection::vec((command(), 0u64..120), 1..60),
+        ) {
+            let cli = ModuleCli::parse_from(["waybar-module-pomodoro"]);
+            let mut harness = Harness::new(Config::from_module_cli(&cli));
+            for (command, seconds) in steps {
+                let completed = harness.timer().session_completed;
+                harness.request(&command).unwrap();
+                harness.advance(Duration::from_secs(seconds));
+
+                let timer = harness.timer();
+                prop_assert!(
+                    timer.elapsed_time <= timer.get_current_time(),
+                    "{command} left {}s of a {}s cycle elapsed",
+                    timer.elapsed_time,
+                    timer.get_current_time()
+                );
+                prop_assert!(timer.elapsed_millis < 1000);
+                prop_assert!(timer.iterations <= MAX_ITERATIONS);
+                prop_assert!(timer.session_completed >= completed);
+                prop_assert!(!(timer.running && timer.grace_millis.is_some()));
+            }
+        }
+    }
+}
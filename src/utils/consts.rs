@@ -2,13 +2,26 @@ use std::time::Duration;
 
 pub const SLEEP_TIME: u16 = 100;
 pub const SLEEP_DURATION: Duration = Duration::from_millis(SLEEP_TIME as u64);
+pub const TICK_RATE: u16 = 1000;
+/// Tick rate while --power-save has the module on battery, rendering whole minutes
+pub const POWER_SAVE_TICK_RATE: u16 = 60000;
+pub const CLIENT_READ_TIMEOUT: Duration = Duration::from_secs(2);
+/// How long a `subscribe` client may keep the module waiting to take a line
+pub const SUBSCRIBER_WRITE_TIMEOUT: Duration = Duration::from_millis(100);
 pub const MINUTE: u16 = 60;
 pub const HOUR: u16 = 60 * MINUTE;
+/// Largest duration, in minutes, that fits the timer's seconds counter
+pub const MAX_MINUTES: u16 = u16::MAX / MINUTE;
 pub const MAX_ITERATIONS: u8 = 4;
 pub const WORK_TIME: u16 = 25 * MINUTE;
 pub const SHORT_BREAK_TIME: u16 = 5 * MINUTE;
 pub const LONG_BREAK_TIME: u16 = 15 * MINUTE;
+pub const MICRO_BREAK_LENGTH: u16 = 20;
+pub const PROGRESS_WIDTH: u8 = 5;
 pub const PLAY_ICON: &str = "▶";
 pub const PAUSE_ICON: &str = "⏸";
 pub const WORK_ICON: &str = "󰔟";
 pub const BREAK_ICON: &str = "";
+pub const LONG_BREAK_ICON: &str = "󰒲";
+pub const DEFAULT_FORMAT: &str = "{icon} {time} {sprint} {cycle_icon}";
+pub const REPLACE_TIMEOUT: Duration = Duration::from_secs(5);
@@ -8,3 +8,162 @@ pub fn trim_whitespace(input: &str) -> String {
     });
     result
 }
+
+/// Replaces every `{name}` placeholder in `template` with its value, unknown placeholders are left as-is
+pub fn fill_template(template: &str, values: &[(&str, &str)]) -> String {
+    // a single pass, so a value that looks like a placeholder is never filled in itself
+    let mut result = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        result.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let found = rest.find('}').and_then(|end| {
+            values
+                .iter()
+                .find(|(name, _)| *name == &rest[1..end])
+                .map(|(_, value)| (end, *value))
+        });
+        match found {
+            Some((end, value)) => {
+                result.push_str(value);
+                rest = &rest[end + 1..];
+            }
+            None => {
+                result.push('{');
+                rest = &rest[1..];
+            }
+        }
+    }
+    result.push_str(rest);
+    result
+}
+
+/// Fills a multi-line template like `fill_template`, one line at a time, leaving out the
+/// lines whose placeholders all came out empty so a section disappears when there's
+/// nothing to show. `\n` starts a new line, and values are escaped for Pango markup so
+/// the template itself may use it.
+pub fn fill_sections(template: &str, values: &[(&str, &str)]) -> String {
+    let escaped: Vec<String> = values
+        .iter()
+        .map(|(_, value)| escape_markup(value))
+        .collect();
+    let values: Vec<(&str, &str)> = values
+        .iter()
+        .zip(&escaped)
+        .map(|((name, _), value)| (*name, value.as_str()))
+        .collect();
+    template
+        .replace("\\n", "\n")
+        .lines()
+        .filter_map(|line| {
+            let mut used = values
+                .iter()
+                .filter(|(name, _)| line.contains(&format!("{{{name}}}")))
+                .peekable();
+            if used.peek().is_some() && used.all(|(_, value)| value.is_empty()) {
+                return None;
+            }
+            Some(fill_template(line, &values))
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// `markup` as plain text, Pango tags left out and escaped characters turned back
+pub fn strip_markup(markup: &str) -> String {
+    let mut text = String::with_capacity(markup.len());
+    let mut in_tag = false;
+    for c in markup.chars() {
+        match c {
+            '<' => in_tag = true,
+            '>' if in_tag => in_tag = false,
+            c if !in_tag => text.push(c),
+            _ => {}
+        }
+    }
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&#39;", "'")
+        .replace("&quot;", "\"")
+        .replace("&amp;", "&")
+}
+
+/// `text` cut down to `max` characters, the last of them an ellipsis
+pub fn ellipsize(text: &str, max: usize) -> String {
+    if text.chars().count() <= max {
+        return text.to_string();
+    }
+    let mut result: String = text.chars().take(max.saturating_sub(1)).collect();
+    result.push('…');
+    result
+}
+
+/// Escapes `text` for use in Pango markup
+pub fn escape_markup(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('\'', "&#39;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_strip_markup() {
+        assert_eq!(
+            strip_markup("<span color='red'>RFC &amp; <b>review</b></span> &lt;3"),
+            "RFC & review <3"
+        );
+        assert_eq!(strip_markup(&escape_markup("a &lt; b")), "a &lt; b");
+    }
+
+    #[test]
+    fn test_fill_template() {
+        let result = fill_template(
+            "{icon} {time} {cycle_icon} {unknown}",
+            &[("icon", ">"), ("time", "25:00"), ("cycle_icon", "W")],
+        );
+        assert_eq!(result, "> 25:00 W {unknown}");
+
+        // values are never read as placeholders themselves
+        let result = fill_template("{{task}} {time}", &[("task", "{time}"), ("time", "25:00")]);
+        assert_eq!(result, "{{time}} 25:00");
+    }
+
+    #[test]
+    fn test_ellipsize() {
+        assert_eq!(ellipsize("write the RFC", 9), "write th…");
+        assert_eq!(ellipsize("write", 9), "write");
+        assert_eq!(ellipsize("▶ 25:00 󰔟", 9), "▶ 25:00 󰔟");
+    }
+
+    #[test]
+    fn test_fill_sections() {
+        let template = "<b>{task}</b> {task_progress}\\nToday: {pomodoros_today}\nNo placeholders";
+        let result = fill_sections(
+            template,
+            &[
+                ("task", "R&D"),
+                ("task_progress", "2/4"),
+                ("pomodoros_today", "3"),
+            ],
+        );
+        assert_eq!(result, "<b>R&amp;D</b> 2/4\nToday: 3\nNo placeholders");
+
+        let result = fill_sections(
+            template,
+            &[
+                ("task", ""),
+                ("task_progress", ""),
+                ("pomodoros_today", "3"),
+            ],
+        );
+        assert_eq!(result, "Today: 3\nNo placeholders");
+
+        let result = fill_sections("{task} at {time}", &[("task", "{time}"), ("time", "25:00")]);
+        assert_eq!(result, "{time} at 25:00");
+    }
+}